@@ -1,5 +1,14 @@
+use crate::config::{Auth, MessageCompat, Provider};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use crate::error::HalError;
+use std::time::{Duration, Instant};
+use ureq::unversioned::resolver::{DefaultResolver, ResolvedSocketAddrs, Resolver};
+use ureq::unversioned::transport::{Buffers, ConnectionDetails, Connector, DefaultConnector, NextTimeout, Transport};
 
 #[derive(Debug, Serialize)]
 struct ChatRequest<'a> {
@@ -7,12 +16,18 @@ struct ChatRequest<'a> {
     messages: &'a [Value],
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<&'a [Value]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prompt_cache_key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ChatResponse {
     choices: Vec<Choice>,
     usage: Option<Usage>,
+    #[serde(default)]
+    system_fingerprint: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -21,6 +36,198 @@ pub struct Usage {
     pub completion_tokens: u32,
     #[allow(dead_code)]
     pub total_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    // Anthropic-style names, passed through by some gateways
+    #[serde(default)]
+    cache_read_input_tokens: Option<u32>,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: Option<u32>,
+    #[serde(default)]
+    cache_write_tokens: Option<u32>,
+}
+
+impl Usage {
+    /// Prompt tokens served from the provider's cache
+    pub fn cache_read(&self) -> u32 {
+        self.prompt_tokens_details
+            .as_ref()
+            .and_then(|d| d.cached_tokens)
+            .or(self.cache_read_input_tokens)
+            .unwrap_or(0)
+    }
+
+    /// Prompt tokens written to the provider's cache
+    pub fn cache_write(&self) -> u32 {
+        self.prompt_tokens_details
+            .as_ref()
+            .and_then(|d| d.cache_write_tokens)
+            .or(self.cache_creation_input_tokens)
+            .unwrap_or(0)
+    }
+}
+
+/// Anthropic's compatibility layer ignores `seed`; don't record one it never used
+pub fn supports_seed(provider: &Provider) -> bool {
+    !provider.base_url.contains("anthropic.com")
+}
+
+fn message_compat(provider: &Provider) -> MessageCompat {
+    provider.compat.unwrap_or(if provider.base_url.contains("generativelanguage.googleapis.com") {
+        MessageCompat::Gemini
+    } else if provider.gateway.is_some() {
+        MessageCompat::Strict
+    } else {
+        MessageCompat::Openai
+    })
+}
+
+/// Adapt tool and assistant messages to what the backend accepts. The stored
+/// history always uses the OpenAI shape.
+fn adapt_messages(compat: MessageCompat, messages: &[Value]) -> Cow<'_, [Value]> {
+    if compat == MessageCompat::Openai {
+        return Cow::Borrowed(messages);
+    }
+    let strict = compat == MessageCompat::Strict;
+
+    let mut names: HashMap<String, String> = HashMap::new();
+    let mut adapted = messages.to_vec();
+    for message in &mut adapted {
+        match message["role"].as_str() {
+            Some("assistant") => {
+                let Some(calls) = message.get_mut("tool_calls").and_then(|c| c.as_array_mut()) else {
+                    continue;
+                };
+                for call in calls.iter_mut() {
+                    if let (Some(id), Some(name)) = (call["id"].as_str(), call["function"]["name"].as_str()) {
+                        names.insert(id.to_string(), name.to_string());
+                    }
+                    if strict && call["function"]["arguments"].as_str().is_some_and(|a| a.trim().is_empty()) {
+                        call["function"]["arguments"] = Value::from("{}");
+                    }
+                }
+                if strict && message["content"].is_null() {
+                    message["content"] = Value::from("");
+                }
+            }
+            Some("tool") => {
+                if let Some(name) = message["tool_call_id"].as_str().and_then(|id| names.get(id)) {
+                    message["name"] = Value::from(name.as_str());
+                }
+                if strict && message["content"].as_str().is_none_or(|c| c.is_empty()) {
+                    message["content"] = Value::from("(no output)");
+                }
+            }
+            _ => {}
+        }
+    }
+    Cow::Owned(adapted)
+}
+
+/// Whether an API error looks like the backend rejecting the shape of the history
+pub fn is_history_error(error: &HalError) -> bool {
+    let HalError::Api { status: 400, message } = error else {
+        return false;
+    };
+    let message = message.to_lowercase();
+    ["tool_call", "tool call", "tool_use", "function response", "messages[", "role"]
+        .iter()
+        .any(|needle| message.contains(needle))
+}
+
+/// Drop dangling tool calls, orphaned tool results and empty assistant turns, and
+/// merge consecutive assistant texts. Returns a description of each fix.
+pub fn repair_messages(messages: &mut Vec<Value>) -> Vec<String> {
+    let mut fixes = Vec::new();
+
+    // Tool call ids that have a result somewhere after their assistant message
+    let answered: std::collections::HashSet<String> = messages
+        .iter()
+        .filter(|m| m["role"] == "tool")
+        .filter_map(|m| m["tool_call_id"].as_str().map(String::from))
+        .collect();
+    let mut dangling = 0;
+    let mut requested = std::collections::HashSet::new();
+    for message in messages.iter_mut().filter(|m| m["role"] == "assistant") {
+        let Some(calls) = message.get_mut("tool_calls").and_then(|c| c.as_array_mut()) else {
+            continue;
+        };
+        let before = calls.len();
+        calls.retain(|c| c["id"].as_str().is_some_and(|id| answered.contains(id)));
+        dangling += before - calls.len();
+        requested.extend(calls.iter().filter_map(|c| c["id"].as_str().map(String::from)));
+        if calls.is_empty() {
+            message.as_object_mut().map(|m| m.remove("tool_calls"));
+        }
+    }
+    if dangling > 0 {
+        fixes.push(format!("dropped {} dangling tool call(s)", dangling));
+    }
+
+    let before = messages.len();
+    messages.retain(|m| m["role"] != "tool" || m["tool_call_id"].as_str().is_some_and(|id| requested.contains(id)));
+    if messages.len() < before {
+        fixes.push(format!("dropped {} orphaned tool result(s)", before - messages.len()));
+    }
+
+    let is_empty_text = |m: &Value| m["content"].as_str().is_none_or(|c| c.trim().is_empty());
+    let before = messages.len();
+    messages.retain(|m| m["role"] != "assistant" || m.get("tool_calls").is_some() || !is_empty_text(m));
+    if messages.len() < before {
+        fixes.push(format!("dropped {} empty assistant turn(s)", before - messages.len()));
+    }
+
+    let mut merged = 0;
+    let mut i = 1;
+    while i < messages.len() {
+        let (prev, cur) = (&messages[i - 1], &messages[i]);
+        if prev["role"] == "assistant" && cur["role"] == "assistant" && prev.get("tool_calls").is_none() && cur.get("tool_calls").is_none() {
+            let text = format!("{}\n\n{}", prev["content"].as_str().unwrap_or(""), cur["content"].as_str().unwrap_or(""));
+            messages[i - 1]["content"] = Value::from(text);
+            messages.remove(i);
+            merged += 1;
+        } else {
+            i += 1;
+        }
+    }
+    if merged > 0 {
+        fixes.push(format!("merged {} consecutive assistant turn(s)", merged));
+    }
+
+    fixes
+}
+
+/// Whether to add Anthropic-style cache_control breakpoints. OpenAI caches automatically.
+fn uses_cache_control(provider: &Provider) -> bool {
+    provider.prompt_caching.unwrap_or_else(|| {
+        provider.base_url.contains("anthropic.com")
+            || (provider.base_url.contains("openrouter.ai") && provider.model.starts_with("anthropic/"))
+    })
+}
+
+/// Mark the system prompt and the latest user message as cache breakpoints, so the
+/// stable prefix of the conversation is reused on the next request
+fn with_cache_control(messages: &[Value]) -> Vec<Value> {
+    let mut messages = messages.to_vec();
+    let last_user = messages.iter().rposition(|m| m["role"] == "user");
+    for (i, message) in messages.iter_mut().enumerate() {
+        if ((i == 0 && message["role"] == "system") || Some(i) == last_user)
+            && let Some(text) = message["content"].as_str()
+        {
+            message["content"] = serde_json::json!([{
+                "type": "text",
+                "text": text,
+                "cache_control": { "type": "ephemeral" }
+            }]);
+        }
+    }
+    messages
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,55 +245,391 @@ pub struct ApiResponse {
     pub content: Option<String>,
     pub tool_calls: Option<Vec<Value>>,
     pub usage: Option<Usage>,
+    pub fingerprint: Option<String>,
+}
+
+/// Rate limiting shared by every tab: requests to the same provider (keyed by base URL)
+/// are capped at `max_concurrent_requests`, and a 429 pauses all of them until the
+/// provider's Retry-After has passed.
+#[derive(Default)]
+struct ProviderSlots {
+    in_flight: usize,
+    blocked_until: Option<Instant>,
+}
+
+static LIMITER: OnceLock<(Mutex<HashMap<String, ProviderSlots>>, Condvar)> = OnceLock::new();
+static MAX_CONCURRENT: AtomicUsize = AtomicUsize::new(2);
+
+const MAX_RATE_LIMIT_RETRIES: usize = 2;
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(20);
+
+pub fn set_max_concurrent_requests(max: usize) {
+    MAX_CONCURRENT.store(max.max(1), Ordering::SeqCst);
+}
+
+fn limiter() -> &'static (Mutex<HashMap<String, ProviderSlots>>, Condvar) {
+    LIMITER.get_or_init(|| (Mutex::new(HashMap::new()), Condvar::new()))
+}
+
+/// Holds a request slot for a provider; releases it on drop
+struct SlotGuard<'a> {
+    key: &'a str,
+}
+
+impl<'a> SlotGuard<'a> {
+    fn acquire(key: &'a str) -> Self {
+        let (lock, cvar) = limiter();
+        let mut slots = lock.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let max = MAX_CONCURRENT.load(Ordering::SeqCst);
+            let slot = slots.entry(key.to_string()).or_default();
+            let now = Instant::now();
+            match slot.blocked_until {
+                Some(until) if until > now => {
+                    slots = cvar.wait_timeout(slots, until - now).unwrap_or_else(|e| e.into_inner()).0;
+                }
+                _ if slot.in_flight >= max => {
+                    slots = cvar.wait(slots).unwrap_or_else(|e| e.into_inner());
+                }
+                _ => {
+                    slot.in_flight += 1;
+                    return SlotGuard { key };
+                }
+            }
+        }
+    }
+
+    fn back_off(&self, duration: Duration) {
+        let (lock, _) = limiter();
+        let mut slots = lock.lock().unwrap_or_else(|e| e.into_inner());
+        slots.entry(self.key.to_string()).or_default().blocked_until = Some(Instant::now() + duration);
+    }
+}
+
+impl Drop for SlotGuard<'_> {
+    fn drop(&mut self) {
+        let (lock, cvar) = limiter();
+        let mut slots = lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(slot) = slots.get_mut(self.key) {
+            slot.in_flight = slot.in_flight.saturating_sub(1);
+        }
+        cvar.notify_all();
+    }
+}
+
+/// Where a request stands (connecting, waiting for the model, receiving the reply) and
+/// since when, shared with the UI so network stalls can be told apart from model latency
+#[derive(Debug, Clone, Default)]
+pub struct Progress(Arc<Mutex<Option<(&'static str, Instant)>>>);
+
+impl Progress {
+    pub fn set(&self, phase: &'static str) {
+        let mut current = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if current.is_none_or(|(p, _)| p != phase) {
+            *current = Some((phase, Instant::now()));
+        }
+    }
+
+    pub fn clear(&self) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// The current phase and how long it has lasted
+    pub fn current(&self) -> Option<(&'static str, Duration)> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).map(|(phase, since)| (phase, since.elapsed()))
+    }
+}
+
+/// ureq's resolver, reporting DNS lookups
+#[derive(Debug)]
+struct ProgressResolver(Progress, DefaultResolver);
+
+impl Resolver for ProgressResolver {
+    fn resolve(&self, uri: &ureq::http::Uri, config: &ureq::config::Config, timeout: NextTimeout) -> Result<ResolvedSocketAddrs, ureq::Error> {
+        self.0.set("resolving host");
+        self.1.resolve(uri, config, timeout)
+    }
+}
+
+/// First in the connector chain: a new connection (TCP, then TLS) is being set up
+#[derive(Debug)]
+struct ConnectStart(Progress);
+
+impl Connector<()> for ConnectStart {
+    type Out = ();
+
+    fn connect(&self, details: &ConnectionDetails, chained: Option<()>) -> Result<Option<()>, ureq::Error> {
+        self.0.set(if details.needs_tls() { "connecting (TCP + TLS)" } else { "connecting" });
+        Ok(chained)
+    }
+}
+
+/// Last in the connector chain: wraps the connection to report sending and receiving
+#[derive(Debug)]
+struct ConnectDone(Progress);
+
+impl<In: Transport> Connector<In> for ConnectDone {
+    type Out = ProgressTransport<In>;
+
+    fn connect(&self, _: &ConnectionDetails, chained: Option<In>) -> Result<Option<Self::Out>, ureq::Error> {
+        Ok(chained.map(|inner| ProgressTransport { inner, progress: self.0.clone() }))
+    }
+}
+
+#[derive(Debug)]
+struct ProgressTransport<T> {
+    inner: T,
+    progress: Progress,
+}
+
+impl<T: Transport> Transport for ProgressTransport<T> {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        self.inner.buffers()
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), ureq::Error> {
+        self.progress.set("sending request");
+        let result = self.inner.transmit_output(amount, timeout);
+        // Until the first byte comes back, the time is the gateway's and the model's
+        self.progress.set("waiting for the model");
+        result
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, ureq::Error> {
+        let result = self.inner.await_input(timeout);
+        if matches!(result, Ok(true)) {
+            self.progress.set("receiving response");
+        }
+        result
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.inner.is_open()
+    }
+
+    fn is_tls(&self) -> bool {
+        self.inner.is_tls()
+    }
 }
 
 pub fn chat(
-    base_url: &str,
+    provider: &Provider,
     api_key: &str,
-    model: &str,
+    session_id: &str,
+    seed: Option<u64>,
     messages: &[Value],
     tools: &[Value],
-) -> Result<ApiResponse, String> {
+    progress: &Progress,
+) -> Result<ApiResponse, HalError> {
+    let base_url = provider.base_url.as_str();
     let url = format!("{}/chat/completions", base_url.trim_end_matches('/'));
 
+    let mut messages = adapt_messages(message_compat(provider), messages);
+    if uses_cache_control(provider) {
+        messages = Cow::Owned(with_cache_control(&messages));
+    }
+    let is_openai = base_url.contains("api.openai.com");
+    let model = match &provider.gateway {
+        Some(gateway) => gateway.route(&provider.model),
+        None => provider.model.clone(),
+    };
+
+    let tools = if provider.compact_tools {
+        Cow::Owned(crate::tools::compact_definitions(tools))
+    } else {
+        Cow::Borrowed(tools)
+    };
+
     let request = ChatRequest {
-        model,
-        messages,
-        tools: if tools.is_empty() { None } else { Some(tools) },
+        model: &model,
+        messages: &messages,
+        tools: if tools.is_empty() { None } else { Some(&tools) },
+        // Routes requests from the same session to the same cache
+        prompt_cache_key: if is_openai { Some(session_id) } else { None },
+        seed: seed.filter(|_| supports_seed(provider)),
     };
 
-    let agent = ureq::Agent::config_builder()
-        .http_status_as_error(false)
-        .build()
-        .new_agent();
+    let config = ureq::Agent::config_builder().http_status_as_error(false).build();
+    let connector = ConnectStart(progress.clone()).chain(DefaultConnector::new()).chain(ConnectDone(progress.clone()));
+    let agent = ureq::Agent::with_parts(config, connector, ProgressResolver(progress.clone(), DefaultResolver::default()));
 
-    let response = agent.post(&url)
-        .header("Authorization", &format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .send_json(&request)
-        .map_err(|e| e.to_string())?;
+    let secrets = [api_key];
+    if crate::debug_log::enabled() {
+        let body = serde_json::to_string_pretty(&request).unwrap_or_default();
+        crate::debug_log::log(&format!("request POST {}", url), &body, &secrets);
+    }
 
-    let status = response.status().as_u16();
-    if status >= 400 {
-        let body: String = response.into_body().read_to_string()
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        if let Ok(json) = serde_json::from_str::<Value>(&body) {
-            if let Some(msg) = json["error"]["message"].as_str() {
-                return Err(format!("{}: {}", status, msg));
+    let mut attempt = 0;
+    let mut retry_after = DEFAULT_RETRY_AFTER;
+    let started = Instant::now();
+    let response = loop {
+        progress.set("waiting for a request slot");
+        let slot = SlotGuard::acquire(base_url);
+
+        let mut builder = agent.post(&url).header("Content-Type", "application/json");
+        match provider.auth {
+            Auth::None => {}
+            Auth::Bearer => builder = builder.header("Authorization", &format!("Bearer {}", api_key)),
+            Auth::Header => {
+                let name = provider.auth_header.as_deref().unwrap_or("api-key");
+                builder = builder.header(name, &format!("{}{}", provider.auth_prefix.as_deref().unwrap_or(""), api_key));
+            }
+        }
+        if let Some(gateway) = &provider.gateway {
+            for (name, value) in &gateway.headers {
+                builder = builder.header(name, value);
+            }
+            if let Some(header) = &gateway.user_header
+                && let Some(user) = gateway.user_id()
+            {
+                builder = builder.header(header, &user);
+            }
+            if let Some(header) = &gateway.session_header {
+                builder = builder.header(header, session_id);
             }
         }
-        return Err(format!("{}: {}", status, body));
+        if let Some(org) = &provider.organization {
+            builder = builder.header("OpenAI-Organization", org);
+        }
+        if let Some(project) = &provider.project {
+            builder = builder.header("OpenAI-Project", project);
+        }
+        let response = builder.send_json(&request).map_err(|e| {
+            crate::debug_log::log("request failed", &e.to_string(), &secrets);
+            HalError::Network(e.to_string())
+        })?;
+
+        if response.status().as_u16() == 429 {
+            retry_after = response
+                .headers()
+                .get("retry-after")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_RETRY_AFTER);
+        }
+        if response.status().as_u16() == 429 && attempt < MAX_RATE_LIMIT_RETRIES {
+            crate::debug_log::log(&format!("response 429, retrying in {}s", retry_after.as_secs()), "", &secrets);
+            progress.set("rate limited, retrying");
+            slot.back_off(retry_after);
+            attempt += 1;
+            continue;
+        }
+
+        break response;
+    };
+
+    let status = response.status().as_u16();
+    let body = response.into_body().read_to_string();
+    if crate::debug_log::enabled() {
+        let text = match &body {
+            Ok(text) => serde_json::from_str::<Value>(text)
+                .and_then(|json| serde_json::to_string_pretty(&json))
+                .unwrap_or_else(|_| text.clone()),
+            Err(e) => format!("Failed to read body: {}", e),
+        };
+        let title = format!("response {} after {} ms", status, started.elapsed().as_millis());
+        crate::debug_log::log(&title, &text, &secrets);
+    }
+    if status >= 400 {
+        let body = body.unwrap_or_else(|_| "Unknown error".to_string());
+        let message = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|json| json["error"]["message"].as_str().map(str::to_string))
+            .unwrap_or(body);
+        return Err(HalError::from_status(status, message, &provider.api_key_env, retry_after));
     }
 
-    let body: ChatResponse = response.into_body().read_json()
-        .map_err(|e| format!("Failed to parse response: {}", e))?;
+    let body: ChatResponse = body
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+        .map_err(|e| HalError::Parse(format!("Failed to parse response: {}", e)))?;
 
     let choice = body.choices.into_iter().next()
-        .ok_or("No response choices")?;
+        .ok_or_else(|| HalError::Parse("No response choices".to_string()))?;
 
     Ok(ApiResponse {
         content: choice.message.content,
         tool_calls: choice.message.tool_calls,
         usage: body.usage,
+        fingerprint: body.system_fingerprint,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cache_control_marks_system_and_last_user_message() {
+        let messages = vec![
+            json!({ "role": "system", "content": "sys" }),
+            json!({ "role": "user", "content": "first" }),
+            json!({ "role": "assistant", "content": "ok" }),
+            json!({ "role": "user", "content": "second" }),
+            json!({ "role": "tool", "tool_call_id": "1", "content": "out" }),
+        ];
+        let marked = with_cache_control(&messages);
+        assert_eq!(marked[0]["content"][0]["cache_control"]["type"], "ephemeral");
+        assert_eq!(marked[1]["content"], "first");
+        assert_eq!(marked[3]["content"][0]["text"], "second");
+        assert_eq!(marked[4]["content"], "out");
+    }
+
+    #[test]
+    fn test_repair_messages() {
+        let mut messages = vec![
+            json!({ "role": "system", "content": "sys" }),
+            json!({ "role": "user", "content": "list files" }),
+            json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [
+                    { "id": "c1", "type": "function", "function": { "name": "list_dir", "arguments": "{}" } },
+                    { "id": "c2", "type": "function", "function": { "name": "list_dir", "arguments": "{}" } }
+                ]
+            }),
+            json!({ "role": "tool", "tool_call_id": "c1", "content": "a.rs" }),
+            json!({ "role": "tool", "tool_call_id": "c9", "content": "stale" }),
+            json!({ "role": "assistant", "content": "" }),
+            json!({ "role": "assistant", "content": "one" }),
+            json!({ "role": "assistant", "content": "two" }),
+        ];
+        let fixes = repair_messages(&mut messages);
+        assert_eq!(fixes.len(), 4, "{:?}", fixes);
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[2]["tool_calls"].as_array().unwrap().len(), 1);
+        assert_eq!(messages[3]["tool_call_id"], "c1");
+        assert_eq!(messages[4]["content"], "one\n\ntwo");
+        assert!(repair_messages(&mut messages).is_empty());
+    }
+
+    #[test]
+    fn test_adapt_messages_per_compat() {
+        let messages = vec![
+            json!({ "role": "user", "content": "hi" }),
+            json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{ "id": "c1", "type": "function", "function": { "name": "list_dir", "arguments": "" } }]
+            }),
+            json!({ "role": "tool", "tool_call_id": "c1", "content": "" }),
+        ];
+
+        // (compat, assistant content, arguments, tool name, tool content)
+        let cases = [
+            (MessageCompat::Openai, json!(null), json!(""), json!(null), json!("")),
+            (MessageCompat::Gemini, json!(null), json!(""), json!("list_dir"), json!("")),
+            (MessageCompat::Strict, json!(""), json!("{}"), json!("list_dir"), json!("(no output)")),
+        ];
+        for (compat, content, arguments, name, output) in cases {
+            let adapted = adapt_messages(compat, &messages);
+            assert_eq!(adapted[0], messages[0], "{:?}", compat);
+            assert_eq!(adapted[1]["content"], content, "{:?}", compat);
+            assert_eq!(adapted[1]["tool_calls"][0]["function"]["arguments"], arguments, "{:?}", compat);
+            assert_eq!(adapted[2]["name"], name, "{:?}", compat);
+            assert_eq!(adapted[2]["content"], output, "{:?}", compat);
+        }
+    }
+}
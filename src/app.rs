@@ -1,14 +1,19 @@
 use crate::api;
-use crate::config::{Config, Mode, Provider};
-use crate::sandbox::{self, SandboxConfig};
-use crate::session::{self, Session};
-use crate::tools;
+use crate::clipboard;
+use crate::config::{Config, Keybindings, Mode, Provider};
+use crate::error::HalError;
+use crate::render::{self, PlainTextRenderer};
+use crate::sandbox::{self, NetworkPolicy, SandboxConfig};
+use crate::session::{self, Bookmark, Checkpoint, Session, Turn};
+use crate::tools::{self, ErrorKind, ToolError};
 use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver};
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 
 pub const MAX_PICKER_ITEMS: usize = 10;
 
@@ -35,6 +40,34 @@ pub enum PickerMode {
     Commands,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VimMode {
+    Normal,
+    Insert,
+}
+
+/// A message (or a code block inside one) that can be copied in copy mode
+#[derive(Debug, Clone)]
+pub struct CopyTarget {
+    pub message: usize,
+    pub label: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CopyMode {
+    pub targets: Vec<CopyTarget>,
+    pub selected: usize,
+}
+
+/// Ctrl+R search over past prompts
+#[derive(Debug, Clone)]
+pub struct HistorySearch {
+    pub query: String,
+    pub results: Vec<(String, Vec<usize>)>, // (prompt, matched char indices), most recent first
+    pub selected: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct ProviderModal {
     pub providers: Vec<String>,
@@ -45,32 +78,48 @@ pub struct ProviderModal {
 pub struct DiffModal {
     pub path: String,
     pub diff_text: String,       // The diff to display
-    pub new_content: String,     // Content to write if accepted
-    pub tool_name: String,       // "write_file" or "edit_file"
+    pub writes: Vec<(String, String)>, // (path, content) to write if accepted
+    pub tool_name: String,       // A tools::is_file_change tool
     pub pending_tool_id: String,
     pub scroll_offset: u16,
     pub options: Vec<&'static str>,
     pub selected: usize,
+    pub originals: Vec<Option<String>>, // Each write's file as previewed, to catch changes made meanwhile
+    pub warning: Option<String>,        // Another hal tab or session is about to write the same file
 }
 
 impl DiffModal {
-    pub fn new(path: String, diff_text: String, new_content: String, tool_name: String, tool_id: String) -> Self {
+    pub fn new(path: String, diff_text: String, writes: Vec<(String, String)>, tool_name: String, tool_id: String) -> Self {
+        let originals = writes.iter().map(|(p, _)| std::fs::read_to_string(p).ok()).collect();
         Self {
             path,
             diff_text,
-            new_content,
+            writes,
             tool_name,
             pending_tool_id: tool_id,
             scroll_offset: 0,
             options: vec!["Accept", "Reject"],
             selected: 0,
+            originals,
+            warning: None,
         }
     }
+
+    fn paths(&self) -> Vec<&str> {
+        self.writes.iter().map(|(p, _)| p.as_str()).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PermissionKind {
+    Path,
+    Network,
 }
 
 #[derive(Debug, Clone)]
 pub struct PermissionModal {
-    pub path: String,
+    pub kind: PermissionKind,
+    pub path: String, // The command, for network requests
     pub reason: String,
     pub options: Vec<&'static str>,
     pub selected: usize,
@@ -80,15 +129,95 @@ pub struct PermissionModal {
 impl PermissionModal {
     pub fn new(path: String, reason: String, tool_id: String) -> Self {
         Self {
+            kind: PermissionKind::Path,
             path,
             reason,
-            options: vec!["Allow for project", "Allow globally", "Allow once", "Deny"],
+            // A global grant can't be saved with a read-only config dir
+            options: vec!["Allow for project", if Config::writable() { "Allow globally" } else { "Allow for session" }, "Allow once", "Deny"],
+            selected: 0,
+            pending_tool_id: tool_id,
+        }
+    }
+
+    pub fn network(command: String, tool_id: String) -> Self {
+        Self {
+            kind: PermissionKind::Network,
+            path: command,
+            reason: "This command looks like it needs network access".to_string(),
+            options: vec!["Allow once", "Allow for session", "Deny"],
             selected: 0,
             pending_tool_id: tool_id,
         }
     }
 }
 
+/// Shown when a turn reaches one of its limits: config.max_tool_calls_per_turn,
+/// max_tokens_per_turn or max_turn_minutes
+pub struct ContinueModal {
+    pub reached: String, // The limit hit, e.g. "50 tool calls"
+    pub tool_calls: usize,
+    pub tokens: (u32, u32), // (prompt, completion) spent this turn
+    pub cost: Option<f64>,  // USD for those tokens, when the provider has prices
+    pub elapsed: Duration,
+    pub options: Vec<&'static str>,
+    pub selected: usize,
+}
+
+impl ContinueModal {
+    pub fn new(reached: String, tool_calls: usize, tokens: (u32, u32), cost: Option<f64>, elapsed: Duration) -> Self {
+        Self {
+            reached,
+            tool_calls,
+            tokens,
+            cost,
+            elapsed,
+            options: vec!["Continue", "Stop"],
+            selected: 0,
+        }
+    }
+}
+
+/// Shown when edit_file's old text matches several places, to pick the one meant
+#[derive(Debug, Clone)]
+pub struct MatchModal {
+    pub path: String,
+    pub locations: Vec<(usize, String)>, // (line, first line of the match)
+    pub selected: usize,                 // locations.len() is Reject
+}
+
+impl MatchModal {
+    pub fn options(&self) -> Vec<String> {
+        let mut options: Vec<String> = self.locations.iter().map(|(line, text)| format!("Line {}: {}", line, text)).collect();
+        options.push("Reject".to_string());
+        options
+    }
+}
+
+/// /versions: step through the versions of a file hal changed this session
+#[derive(Debug, Clone)]
+pub struct VersionsModal {
+    pub path: String,
+    pub versions: Vec<crate::undo::Version>, // Oldest first
+    pub selected: usize,
+    pub scroll: u16,
+}
+
+impl VersionsModal {
+    /// The selected version against the one before it, in the diff modal's format
+    pub fn diff(&self) -> String {
+        let version = &self.versions[self.selected];
+        let current = version.content.as_deref().unwrap_or("");
+        match self.selected.checked_sub(1).map(|i| &self.versions[i]) {
+            _ if version.content.is_none() => format!("{} did not exist yet\n", self.path),
+            Some(previous) => crate::tools::format_diff_with_context(&self.path, "Changes in", previous.content.as_deref().unwrap_or(""), current),
+            None => {
+                let numbered: Vec<String> = current.lines().enumerate().map(|(i, line)| format!(" {:>4}│{}", i + 1, line)).collect();
+                format!("Original {}\n{}\n", self.path, numbered.join("\n"))
+            }
+        }
+    }
+}
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -96,7 +225,13 @@ use serde::{Deserialize, Serialize};
 pub enum MessageRole {
     User,
     Assistant,
-    Tool { name: String, path: Option<String> },
+    Tool {
+        name: String,
+        path: Option<String>,
+        /// Why the call failed, if it did
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<ErrorKind>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -109,6 +244,8 @@ pub struct App {
     pub config: Config,
     pub input: String,
     pub input_cursor: usize,
+    pub vim_mode: VimMode,
+    vim_pending: Option<char>, // First key of a two-key vim command (dd, cw)
     pub messages: Vec<ChatMessage>,
     pub api_messages: Vec<Value>,
     pub state: AppState,
@@ -118,56 +255,114 @@ pub struct App {
     pub saved_input: String,
     pub picker_mode: PickerMode,
     pub picker_query: String,
-    pub picker_results: Vec<String>,
+    pub picker_results: Vec<(String, Vec<usize>)>, // (item, matched char indices)
     pub picker_selected: usize,
     pub files_cache: Option<Vec<String>>,
+    recent_files: Vec<String>, // Most recently attached first, boosted in the file picker
+    pub attachments: Vec<String>, // Files from --file/--context, attached to the next message
     pub should_quit: bool,
     pub error: Option<String>,
+    pub notice: Option<String>,
     pub token_usage: Option<(u32, u32)>, // (prompt, completion)
+    pub cache_usage: Option<(u32, u32)>, // (cache read, cache write) prompt tokens, when reported
+    pub api_progress: api::Progress, // Connection phase of the request in flight
     pub permission_modal: Option<PermissionModal>,
     pub diff_modal: Option<DiffModal>,
     pub provider_modal: Option<ProviderModal>,
+    pub continue_modal: Option<ContinueModal>,
+    pub match_modal: Option<MatchModal>,
+    pub versions_modal: Option<VersionsModal>,
+    pub copy_mode: Option<CopyMode>,
+    pub history_search: Option<HistorySearch>,
+    pub expanded_tools: HashSet<usize>, // Indices of tool messages shown with full output
     pub auto_accept: bool,               // Auto-accept file changes without confirmation
     pub temp_allowed_paths: Vec<String>, // Paths allowed for this session only
+    network_allowed: bool,               // "Allow for session" chosen for network access
+    network_allowed_once: Option<String>, // Tool call id granted network access once
     tool_defs: Vec<Value>,
     api_key: String,
     provider: Provider,
-    pending_response: Option<Receiver<Result<api::ApiResponse, String>>>,
+    pub provider_name: String, // The provider in use: config.default_provider unless the session or /fork picked another
+    pending_response: Option<Receiver<Result<api::ApiResponse, HalError>>>,
+    pending_title: Option<(String, Receiver<String>)>, // (session id, generated title)
     pending_tool_calls: Vec<(String, String, String)>, // (id, name, args) waiting for permission
-    pending_tool_execution: Option<Receiver<ToolExecutionResult>>,
+    pending_tool_execution: Option<Receiver<Vec<ToolExecutionResult>>>,
+    pending_shell: Option<(Receiver<tools::ToolResult>, bool)>, // A `!` command the user ran, and whether `!!` attaches it
+    shell_output: Option<String>,       // The last `!` command's output, for a bare `!!`
+    shell_attachments: Vec<String>,     // `!!` outputs attached to the next message
+    reply_start: usize, // Index in messages where the latest reply begins, after the user's attachments
+    turn_tool_calls: usize,
+    turn_tokens: (u32, u32),
+    pause_at_tool_calls: usize, // Ask before the next API call once turn_tool_calls reaches this
+    pause_at_tokens: u64,       // Likewise for the turn's total tokens
+    pause_at_time: Option<Instant>, // And for wall-clock time
+    turn_started: Instant,
+    history_repaired: bool,     // Repair runs at most once per user message
+    require_approval: bool,     // Review file changes this turn even with auto-accept on
+    resolving: Vec<String>,     // Conflicted files from /resolve-conflicts, marked resolved when the turn ends
+    insert_draft: bool,         // Include the draft in the next message
     session: Session,
     cancel_flag: Arc<AtomicBool>,
+    tool_cancel: Arc<AtomicBool>, // Kills the running bash command when set
+    config_snapshot: [Option<String>; 2], // Global and project config file contents as last loaded
+    config_checked: Instant,
+    max_duration: Option<Duration>, // --max-duration, until the first message starts the clock
+    deadline: Option<Instant>, // --max-duration: wrap up once the run has gone past this
+    recovery: Option<crate::recovery::Marker>, // Names this tab's session in case hal crashes
+    lease_owner: String, // Whose leases the files under review are
+    wrapping_up: bool,         // The next reply is the time-limit summary, asked for without tools
+    pub timed_out: bool,
+    pub spell_review: Option<(String, Vec<crate::spell::Fix>)>, // A message held back for its typos
+    pasted_paths: Option<PastedPaths>, // A paste of dropped files, offered as @ references
+    input_estimate: Option<(String, usize, crate::usage::Estimate)>, // For (input, history length)
+    pending_submit: Option<(Submission, Receiver<HashMap<String, String>>)>, // Waiting on @gh: fetches
+    estimator: crate::usage::Estimator,
+    busy_since: Option<Instant>, // When hal last started working without needing the user
+    pub postmortem: Option<String>, // Diagnostic bundle for the last failed turn, until Ctrl+B saves it or a new turn starts
+}
+
+/// Appended to failures that can be captured with Ctrl+B
+const POSTMORTEM_HINT: &str = "Ctrl+B saves a diagnostic bundle for a hal bug report";
+
+/// Pasted text that was only file paths, inserted as is until Tab swaps in @ references
+struct PastedPaths {
+    input: String, // The input right after the paste; any edit drops the offer
+    range: std::ops::Range<usize>,
+    references: Vec<String>,
 }
 
 struct ToolExecutionResult {
     id: String,
     name: String,
+    args: String,
     path: Option<String>,
-    result: String,
+    result: tools::ToolResult,
 }
 
 impl App {
     pub fn new(config: Config, session: Option<Session>) -> Result<Self, String> {
-        let provider = config
-            .get_provider()
-            .ok_or_else(|| format!("Provider '{}' not found", config.default_provider))?
-            .clone();
+        let (provider_name, provider) = match session.as_ref().and_then(|s| pinned_provider(&config, s)) {
+            Some(pinned) => pinned,
+            None => {
+                let provider = config
+                    .get_provider()
+                    .ok_or_else(|| format!("Provider '{}' not found", config.default_provider))?
+                    .clone();
+                (config.default_provider.clone(), provider)
+            }
+        };
 
         let api_key = provider
-            .api_key
-            .clone()
-            .or_else(|| std::env::var(&provider.api_key_env).ok())
+            .resolve_api_key()
             .ok_or_else(|| format!("Set ${} with your API key", provider.api_key_env))?;
 
+        api::set_max_concurrent_requests(config.max_concurrent_requests);
+        crate::glyphs::set(config.glyphs);
+        crate::undo::set_keep_backups(config.keep_backups);
+        crate::github::set_token(config.github_token.clone());
+
         let tool_defs = tools::get_tool_definitions(&config.mode);
-        // Build system prompt with optional HAL.md context
-        let mut system_prompt = get_system_prompt(&config.mode).to_string();
-        if config.mode == Mode::Coding {
-            if let Some(context) = load_context_file() {
-                system_prompt.push_str("\n\n## Project Context\n\n");
-                system_prompt.push_str(&context);
-            }
-        }
+        let system_prompt = build_system_prompt(&config, session.as_ref().map_or("", |s| &s.system_prompt));
 
         // Start with system message
         let mut api_messages = vec![json!({
@@ -176,6 +371,8 @@ impl App {
         })];
 
         // Restore from session if provided
+        let notice = session.as_ref().filter(|s| !s.messages.is_empty()).map(resumed_notice);
+        let notice = if Config::writable() { notice } else { Some(Config::read_only_notice()) };
         let (messages, session) = if let Some(mut s) = session {
             // Restore API messages (skip system prompt from saved, use fresh one)
             if s.api_messages.len() > 1 {
@@ -191,38 +388,86 @@ impl App {
         };
 
         let auto_accept = config.auto_accept;
+        let history = crate::history::load();
         Ok(App {
             config,
             input: String::new(),
             input_cursor: 0,
+            vim_mode: VimMode::Insert,
+            vim_pending: None,
             messages,
             api_messages,
             state: AppState::Idle,
             scroll_offset: 0,
-            history: Vec::new(),
-            history_pos: 0,
+            history_pos: history.len(),
+            history,
             saved_input: String::new(),
             picker_mode: PickerMode::None,
             picker_query: String::new(),
             picker_results: Vec::new(),
             picker_selected: 0,
             files_cache: None,
+            recent_files: Vec::new(),
+            attachments: Vec::new(),
             should_quit: false,
             error: None,
+            notice,
+            api_progress: api::Progress::default(),
             token_usage: None,
+            cache_usage: None,
             permission_modal: None,
             diff_modal: None,
             provider_modal: None,
+            continue_modal: None,
+            match_modal: None,
+            versions_modal: None,
+            copy_mode: None,
+            history_search: None,
+            expanded_tools: HashSet::new(),
             auto_accept,
             temp_allowed_paths: Vec::new(),
+            network_allowed: false,
+            network_allowed_once: None,
             tool_defs,
             api_key,
             provider,
+            provider_name,
             pending_response: None,
+            pending_title: None,
             pending_tool_calls: Vec::new(),
             pending_tool_execution: None,
+            pending_shell: None,
+            shell_output: None,
+            shell_attachments: Vec::new(),
+            reply_start: 0,
+            turn_tool_calls: 0,
+            turn_tokens: (0, 0),
+            pause_at_tool_calls: 0,
+            pause_at_tokens: 0,
+            pause_at_time: None,
+            turn_started: Instant::now(),
+            history_repaired: false,
+            require_approval: false,
+            resolving: Vec::new(),
+            insert_draft: false,
             session,
             cancel_flag: Arc::new(AtomicBool::new(false)),
+            tool_cancel: Arc::new(AtomicBool::new(false)),
+            config_snapshot: Config::files_snapshot(),
+            config_checked: Instant::now(),
+            max_duration: None,
+            deadline: None,
+            recovery: None,
+            lease_owner: crate::lease::owner_id(),
+            wrapping_up: false,
+            timed_out: false,
+            spell_review: None,
+            pasted_paths: None,
+            input_estimate: None,
+            pending_submit: None,
+            estimator: crate::usage::Estimator::default(),
+            busy_since: None,
+            postmortem: None,
         })
     }
 
@@ -253,6 +498,66 @@ impl App {
         }
 
         let _ = self.session.save();
+        if let Some(marker) = &mut self.recovery {
+            marker.set(&self.session.id);
+        }
+    }
+
+    /// Offer this tab's session for recovery if hal doesn't exit cleanly (TUI only)
+    pub fn track_recovery(&mut self) {
+        self.recovery = crate::recovery::Marker::create();
+        if let Some(marker) = &mut self.recovery
+            && !self.messages.is_empty()
+        {
+            marker.set(&self.session.id);
+        }
+    }
+
+    /// Condensed plain-text transcript (prompts, answers, files changed) for printing
+    /// to the normal screen after leaving the alternate screen.
+    pub fn condensed_transcript(&self) -> String {
+        let mut renderer = PlainTextRenderer::new(true);
+        render::render_transcript(&self.messages, &mut renderer);
+        renderer.finish()
+    }
+
+    /// Write the transcript as Markdown or standalone HTML. Returns the path written.
+    fn export_transcript(&self, args: &str) -> Result<String, String> {
+        if self.messages.is_empty() {
+            return Err("Nothing to export yet".to_string());
+        }
+
+        let mut format = None;
+        let mut path = None;
+        for part in args.split_whitespace() {
+            match part {
+                "md" | "markdown" if format.is_none() && path.is_none() => format = Some("md"),
+                "html" if format.is_none() && path.is_none() => format = Some("html"),
+                _ => path = Some(part.to_string()),
+            }
+        }
+        let format = format.unwrap_or_else(|| match &path {
+            Some(p) if p.ends_with(".html") || p.ends_with(".htm") => "html",
+            _ => "md",
+        });
+        let path = path.unwrap_or_else(|| format!("hal-{}.{}", self.session.id, format));
+
+        let title = self.session.display_title();
+        let content = if format == "html" {
+            let mut renderer = render::HtmlRenderer::default();
+            render::render_transcript(&self.messages, &mut renderer);
+            renderer.finish(&title)
+        } else {
+            render::markdown_document(&title, &self.messages)
+        };
+
+        std::fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        Ok(path)
+    }
+
+    /// Tool calls made and (prompt, completion) tokens spent in the current turn
+    pub fn turn_usage(&self) -> (usize, (u32, u32)) {
+        (self.turn_tool_calls, self.turn_tokens)
     }
 
     pub fn submit_input(&mut self) {
@@ -261,6 +566,19 @@ impl App {
             return;
         }
 
+        // `!cmd` runs a shell command for the user; `!!cmd` also attaches its output
+        if let Some(command) = input.strip_prefix('!') {
+            self.remember_input(&input);
+            self.input.clear();
+            self.input_cursor = 0;
+            match command.strip_prefix('!').map(str::trim) {
+                Some("") => self.attach_shell_output(),
+                Some(command) => self.run_shell(command, true),
+                None => self.run_shell(command.trim(), false),
+            }
+            return;
+        }
+
         // Handle commands
         match input.as_str() {
             "/quit" | "/exit" | "/q" => {
@@ -271,10 +589,12 @@ impl App {
             "/clear" => {
                 self.save_session();
                 self.messages.clear();
+                self.expanded_tools.clear();
                 self.api_messages.truncate(1);
                 self.input.clear();
                 self.input_cursor = 0;
                 self.token_usage = None;
+                self.cache_usage = None;
                 self.session = Session::new();
                 return;
             }
@@ -290,11 +610,14 @@ impl App {
                         .iter()
                         .take(10)
                         .map(|s| {
-                            let date = chrono::DateTime::from_timestamp(s.updated_at, 0)
-                                .map(|d| d.format("%Y-%m-%d %H:%M").to_string())
-                                .unwrap_or_default();
-                            let title = if s.title.is_empty() { "(untitled)" } else { &s.title };
-                            format!("**{}** - {} ({})", s.id, title, date)
+                            let date = session::relative_time(s.updated_at);
+                            let title = if s.generated_title.is_empty() && s.title.is_empty() {
+                                "(untitled)".to_string()
+                            } else {
+                                s.display_title()
+                            };
+                            let branch = s.parent_id.as_ref().map(|p| format!(" {} branch of {}", crate::glyphs::get().branch, p)).unwrap_or_default();
+                            format!("**{}** - {} ({}){}", s.id, title, date, branch)
                         })
                         .collect();
                     self.messages.push(ChatMessage {
@@ -306,6 +629,38 @@ impl App {
                 self.input_cursor = 0;
                 return;
             }
+            "/bookmarks" => {
+                let content = if self.session.bookmarks.is_empty() {
+                    "No bookmarks. In copy mode (Ctrl+Y), press `m` on a message to bookmark it — text in the input box becomes the note.".to_string()
+                } else {
+                    let list: Vec<String> = self
+                        .session
+                        .bookmarks
+                        .iter()
+                        .enumerate()
+                        .map(|(i, b)| {
+                            let snippet: String = self
+                                .messages
+                                .get(b.message)
+                                .map(|m| m.content.lines().find(|l| !l.trim().is_empty()).unwrap_or("").chars().take(60).collect())
+                                .unwrap_or_default();
+                            if b.note.is_empty() {
+                                format!("{}. {}", i + 1, snippet)
+                            } else {
+                                format!("{}. **{}** — {}", i + 1, b.note, snippet)
+                            }
+                        })
+                        .collect();
+                    format!("**Bookmarks:**\n{}\n\nCtrl+Y then `n`/`N` jumps between them.", list.join("\n"))
+                };
+                self.messages.push(ChatMessage {
+                    role: MessageRole::Assistant,
+                    content,
+                });
+                self.input.clear();
+                self.input_cursor = 0;
+                return;
+            }
             "/autoaccept" => {
                 self.auto_accept = !self.auto_accept;
                 self.config.auto_accept = self.auto_accept;
@@ -323,6 +678,21 @@ impl App {
                 self.input_cursor = 0;
                 return;
             }
+            "/auto" => {
+                self.config.skip_permissions = !self.config.skip_permissions;
+                let msg = if self.config.skip_permissions {
+                    "Permission checks **off** for this session — sandbox path requests and file changes are approved without asking. `/auto` turns them back on."
+                } else {
+                    "Permission checks **on** — sandbox path requests and file changes ask first"
+                };
+                self.messages.push(ChatMessage {
+                    role: MessageRole::Assistant,
+                    content: msg.to_string(),
+                });
+                self.input.clear();
+                self.input_cursor = 0;
+                return;
+            }
             "/help" => {
                 self.messages.push(ChatMessage {
                     role: MessageRole::Assistant,
@@ -339,7 +709,7 @@ impl App {
                 });
                 self.input.clear();
                 self.input_cursor = 0;
-                match crate::self_update() {
+                match crate::self_update(false) {
                     Ok(msg) => {
                         self.messages.push(ChatMessage {
                             role: MessageRole::Assistant,
@@ -358,7 +728,7 @@ impl App {
             "/key" => {
                 self.messages.push(ChatMessage {
                     role: MessageRole::Assistant,
-                    content: format!("Usage: `/key <key>` — sets the API key for **{}**", self.config.default_provider),
+                    content: format!("Usage: `/key <key>` — sets the API key for **{}**", self.provider_name),
                 });
                 self.input.clear();
                 self.input_cursor = 0;
@@ -376,7 +746,7 @@ impl App {
             "/model" => {
                 let mut names: Vec<String> = self.config.providers.keys().cloned().collect();
                 names.sort();
-                let selected = names.iter().position(|n| n == &self.config.default_provider).unwrap_or(0);
+                let selected = names.iter().position(|n| n == &self.provider_name).unwrap_or(0);
                 self.provider_modal = Some(ProviderModal {
                     providers: names,
                     selected,
@@ -392,11 +762,9 @@ impl App {
         if let Some(name) = input.strip_prefix("/model ") {
             let name = name.trim().to_string();
             if let Some(new_provider) = self.config.providers.get(&name) {
-                let key = new_provider
-                    .api_key
-                    .clone()
-                    .or_else(|| std::env::var(&new_provider.api_key_env).ok());
+                let key = new_provider.resolve_api_key();
                 self.config.default_provider = name.clone();
+                self.provider_name = name.clone();
                 self.provider = new_provider.clone();
                 let _ = self.config.save();
                 if let Some(key) = key {
@@ -430,14 +798,14 @@ impl App {
                 self.error = Some("API key cannot be empty".to_string());
             } else {
                 self.api_key = key.clone();
-                if let Some(provider) = self.config.providers.get_mut(&self.config.default_provider) {
+                if let Some(provider) = self.config.providers.get_mut(&self.provider_name) {
                     provider.api_key = Some(key);
                     self.provider = provider.clone();
                 }
                 let _ = self.config.save();
                 self.messages.push(ChatMessage {
                     role: MessageRole::Assistant,
-                    content: format!("API key updated for **{}**", self.config.default_provider),
+                    content: format!("API key updated for **{}**", self.provider_name),
                 });
             }
             self.input.clear();
@@ -454,6 +822,7 @@ impl App {
 
                     // Restore messages
                     self.messages = s.messages.clone();
+                    self.expanded_tools.clear();
 
                     // Restore API messages (keep system prompt, replace rest)
                     self.api_messages.truncate(1);
@@ -461,8 +830,15 @@ impl App {
                         self.api_messages.extend(s.api_messages[1..].iter().cloned());
                     }
 
+                    self.notice = Some(resumed_notice(&s));
+                    if let Some((name, provider)) = pinned_provider(&self.config, &s) {
+                        self.api_key = provider.resolve_api_key().unwrap_or_default();
+                        self.provider_name = name;
+                        self.provider = provider;
+                    }
                     self.session = s;
                     self.token_usage = None;
+                    self.cache_usage = None;
                 }
                 Err(e) => {
                     self.error = Some(format!("Failed to load session: {}", e));
@@ -473,585 +849,2315 @@ impl App {
             return;
         }
 
-        // Don't send unrecognized slash commands to the LLM
-        if input.starts_with('/') {
+        // Handle /image <path>: attach a screenshot to the next message
+        if input == "/image" || input.starts_with("/image ") {
+            let path = input["/image".len()..].trim();
+            if path.is_empty() {
+                self.error = Some("Usage: /image <path>".to_string());
+            } else {
+                match crate::image::load(path) {
+                    Ok(image) => {
+                        self.notice = Some(format!("Attached {} to the next message", image.label()));
+                        self.attachments.push(path.to_string());
+                    }
+                    Err(e) => self.error = Some(e),
+                }
+            }
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
+
+        // /mode [ask|coding|coach] switches the tool set and system prompt
+        if input == "/mode" || input.starts_with("/mode ") {
+            self.switch_mode(input["/mode".len()..].trim());
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
+
+        // /prompt shows the system prompt as sent
+        if input == "/prompt" {
+            let prompt = self.api_messages.first().and_then(|m| m["content"].as_str()).unwrap_or("");
             self.messages.push(ChatMessage {
                 role: MessageRole::Assistant,
-                content: format!("Unknown command: `{}`. Type `/help` to see available commands.", input.split_whitespace().next().unwrap_or(&input)),
+                content: format!("**System prompt** ({} chars):\n\n```text\n{}\n```", prompt.len(), prompt),
             });
             self.input.clear();
             self.input_cursor = 0;
+            self.scroll_offset = 0;
             return;
         }
 
-        // Add to history
-        if self.history.last().map(|s| s.as_str()) != Some(&input) {
-            self.history.push(input.clone());
+        if input == "/usage" || input.starts_with("/usage ") {
+            self.usage_command(input["/usage".len()..].trim());
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
         }
-        self.history_pos = self.history.len();
-
-        // Expand file references
-        let expanded = expand_file_refs(&input);
-
-        // Add user message
-        self.messages.push(ChatMessage {
-            role: MessageRole::User,
-            content: input,
-        });
-
-        // Add visual feedback for attached files
-        for (path, lines) in &expanded.files_read {
+        if input == "/debug" || input.starts_with("/debug ") {
+            let enable = match input["/debug".len()..].trim() {
+                "" => Some(!crate::debug_log::enabled()),
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => None,
+            };
+            match enable {
+                Some(enable) => {
+                    crate::debug_log::set_enabled(enable);
+                    self.notice = Some(if enable {
+                        format!("Debug logging on — {}", crate::debug_log::path().display())
+                    } else {
+                        "Debug logging off".to_string()
+                    });
+                }
+                None => self.error = Some("Usage: /debug [on|off]".to_string()),
+            }
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
+        if input == "/log" {
+            let state = if crate::debug_log::enabled() { "on" } else { "off — /debug turns it on" };
             self.messages.push(ChatMessage {
-                role: MessageRole::Tool {
-                    name: "read_file".to_string(),
-                    path: Some(path.clone()),
-                },
-                content: "\n".repeat(*lines), // Fake content with right line count
+                role: MessageRole::Assistant,
+                content: format!("Debug log: `{}` (logging {})", crate::debug_log::path().display(), state),
             });
+            self.input.clear();
+            self.input_cursor = 0;
+            self.scroll_offset = 0;
+            return;
+        }
+        if input == "/stats" || input.starts_with("/stats ") {
+            self.stats_command(input["/stats".len()..].trim());
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
         }
 
-        self.api_messages.push(json!({
-            "role": "user",
-            "content": expanded.text
-        }));
-
-        self.input.clear();
-        self.input_cursor = 0;
-        self.state = AppState::Thinking;
-        self.start_api_call();
-    }
-
-    fn start_api_call(&mut self) {
-        // Reset cancel flag for new request
-        self.cancel_flag.store(false, Ordering::SeqCst);
+        if input == "/prefs" || input.starts_with("/prefs ") {
+            self.prefs_command(input["/prefs".len()..].trim());
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
 
-        let (tx, rx) = mpsc::channel();
-        self.pending_response = Some(rx);
+        if input == "/fork" || input.starts_with("/fork ") {
+            self.fork_command(input["/fork".len()..].trim());
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
 
-        let base_url = self.provider.base_url.clone();
-        let api_key = self.api_key.clone();
-        let model = self.provider.model.clone();
-        let messages = self.api_messages.clone();
-        let tool_defs = self.tool_defs.clone();
-        let cancel_flag = self.cancel_flag.clone();
+        // /seed [n|off] fixes the sampling seed for this session
+        if input == "/seed" || input.starts_with("/seed ") {
+            self.seed_command(input["/seed".len()..].trim());
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
 
-        thread::spawn(move || {
-            let result = api::chat(&base_url, &api_key, &model, &messages, &tool_defs);
-            // Only send if not cancelled
-            if !cancel_flag.load(Ordering::SeqCst) {
-                let _ = tx.send(result);
+        // /retry re-sends the last message; /edit-last brings it back for editing
+        if input == "/retry" || input == "/edit-last" {
+            self.input.clear();
+            self.input_cursor = 0;
+            if input == "/retry" {
+                self.retry();
+            } else {
+                self.edit_last();
             }
-        });
-    }
-
-    pub fn abort_request(&mut self) {
-        if self.state == AppState::Idle {
             return;
         }
 
-        // Signal cancellation
-        self.cancel_flag.store(true, Ordering::SeqCst);
-
-        // Clear pending state
-        self.pending_response = None;
-        self.pending_tool_calls.clear();
-        self.pending_tool_execution = None;
-        self.state = AppState::Idle;
+        // Handle /checkpoint [label] and /rewind [n]
+        if input == "/checkpoint" || input.starts_with("/checkpoint ") {
+            self.checkpoint(input["/checkpoint".len()..].trim());
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
+        if input == "/rewind" || input.starts_with("/rewind ") {
+            self.rewind(input["/rewind".len()..].trim());
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
 
-        // Add aborted message to chat
-        self.messages.push(ChatMessage {
-            role: MessageRole::Assistant,
-            content: "*Request aborted*".to_string(),
-        });
-    }
+        // /undo reverts the latest file change; /changes lists changed files
+        if input == "/undo" {
+            match crate::undo::undo() {
+                Ok(message) => self.notice = Some(message),
+                Err(e) => self.error = Some(e),
+            }
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
+        if input == "/versions" || input.starts_with("/versions ") {
+            self.open_versions(input["/versions".len()..].trim());
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
+        if input == "/changes" {
+            let files = crate::undo::changes();
+            let content = if files.is_empty() {
+                "No files changed yet.".to_string()
+            } else {
+                let list: Vec<String> = files
+                    .iter()
+                    .map(|(path, count, created)| {
+                        let what = if *created { "created" } else { "modified" };
+                        format!("- `{}` — {}, {} change(s)", path, what, count)
+                    })
+                    .collect();
+                format!("**Changed files:**\n{}\n\n*`/undo` reverts the latest change; `/versions <path>` steps through a file's versions.*", list.join("\n"))
+            };
+            self.messages.push(ChatMessage { role: MessageRole::Assistant, content });
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
 
-    pub fn poll_api_response(&mut self) {
-        if self.state == AppState::Idle {
+        // Handle /draft [text|insert|clear]: a notes buffer kept across prompts and restarts
+        if input == "/draft" || input.starts_with("/draft ") {
+            self.draft_command(input["/draft".len()..].trim());
+            self.input.clear();
+            self.input_cursor = 0;
             return;
         }
 
-        // Check if cancelled
-        if self.cancel_flag.load(Ordering::SeqCst) {
+        // Handle /export [md|html] [path]
+        if input == "/export" || input.starts_with("/export ") {
+            match self.export_transcript(input["/export".len()..].trim()) {
+                Ok(path) => self.notice = Some(format!("Exported transcript to {}", path)),
+                Err(e) => self.error = Some(e),
+            }
+            self.input.clear();
+            self.input_cursor = 0;
             return;
         }
 
-        let response = match &self.pending_response {
-            Some(rx) => match rx.try_recv() {
-                Ok(result) => result,
-                Err(mpsc::TryRecvError::Empty) => return, // Still waiting
-                Err(mpsc::TryRecvError::Disconnected) => {
-                    // Could be cancelled or crashed - check flag
-                    if self.cancel_flag.load(Ordering::SeqCst) {
+        // /changelog [range] drafts release notes; the CHANGELOG.md write always needs approval
+        let mut require_approval = false;
+        let mut resolving = Vec::new();
+        let prompt = if input == "/changelog" || input.starts_with("/changelog ") {
+            match crate::changelog::prompt(input["/changelog".len()..].trim()) {
+                Ok(prompt) => {
+                    require_approval = true;
+                    Some(prompt)
+                }
+                Err(e) => {
+                    self.error = Some(e);
+                    self.input.clear();
+                    self.input_cursor = 0;
+                    return;
+                }
+            }
+        } else if input == "/digest" || input.starts_with("/digest ") {
+            match crate::digest::prompt(input["/digest".len()..].trim()) {
+                Ok(prompt) => Some(prompt),
+                Err(e) => {
+                    self.error = Some(e);
+                    self.input.clear();
+                    self.input_cursor = 0;
+                    return;
+                }
+            }
+        } else if input == "/resolve-conflicts" {
+            // Each resolution is reviewed as a diff, even with auto-accept on
+            match crate::conflicts::prompt() {
+                Ok((prompt, files)) => {
+                    require_approval = true;
+                    resolving = files;
+                    Some(prompt)
+                }
+                Err(e) => {
+                    self.error = Some(e);
+                    self.input.clear();
+                    self.input_cursor = 0;
+                    return;
+                }
+            }
+        } else if input == "/handoff" {
+            match crate::handoff::prompt(&self.messages) {
+                Ok(prompt) => Some(prompt),
+                Err(e) => {
+                    self.error = Some(e);
+                    self.input.clear();
+                    self.input_cursor = 0;
+                    return;
+                }
+            }
+        } else if input.starts_with('/') {
+            // Custom commands from ~/.config/hal/commands and .hal/commands expand into a prompt
+            crate::commands::expand(&input)
+        } else {
+            None
+        };
+
+        // Don't send unrecognized slash commands to the LLM
+        if input.starts_with('/') && prompt.is_none() {
+            self.messages.push(ChatMessage {
+                role: MessageRole::Assistant,
+                content: format!("Unknown command: `{}`. Type `/help` to see available commands.", input.split_whitespace().next().unwrap_or(&input)),
+            });
+            self.input.clear();
+            self.input_cursor = 0;
+            return;
+        }
+
+        // Outside coding mode, hold a message with obvious typos back once; Enter again sends it as is
+        if !input.starts_with('/')
+            && self.config.mode != Mode::Coding
+            && self.config.spell_assist
+            && self.spell_review.as_ref().is_none_or(|(reviewed, _)| *reviewed != input)
+        {
+            let fixes = crate::spell::check(&input);
+            if !fixes.is_empty() {
+                self.spell_review = Some((input, fixes));
+                return;
+            }
+        }
+        self.spell_review = None;
+
+        self.remember_input(&input);
+        let submission = Submission { input, prompt, require_approval, resolving };
+
+        // @gh: references are fetched on a worker; the message goes out once they're in
+        let references: Vec<(String, u64)> = submission
+            .text()
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix("@gh:").and_then(crate::github::parse_ref))
+            .collect();
+        if references.is_empty() {
+            self.send_input(submission, HashMap::new());
+            return;
+        }
+        let labels: Vec<String> = references.iter().map(|(repo, number)| format!("{}#{}", repo, number)).collect();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let issues = references
+                .into_iter()
+                .map(|(repo, number)| (format!("{}#{}", repo, number), crate::github::fetch_issue(&repo, number).unwrap_or_else(|e| e)))
+                .collect();
+            let _ = tx.send(issues);
+        });
+        self.pending_submit = Some((submission, rx));
+        self.input.clear();
+        self.input_cursor = 0;
+        self.state = AppState::ToolCall(format!("Fetching {}", labels.join(", ")));
+    }
+
+    /// Send the message held for its @gh: references once they're fetched
+    fn poll_pending_submit(&mut self) {
+        let Some((_, rx)) = &self.pending_submit else { return };
+        let issues = match rx.try_recv() {
+            Ok(issues) => issues,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => HashMap::new(), // The references stay as typed
+        };
+        if let Some((submission, _)) = self.pending_submit.take() {
+            self.send_input(submission, issues);
+        }
+    }
+
+    /// Expand a message's references and send it. `issues` holds the fetched text of
+    /// its @gh: references by label.
+    fn send_input(&mut self, submission: Submission, issues: HashMap<String, String>) {
+        let limits = AttachmentLimits {
+            file: self.config.max_file_attachment_bytes,
+            total: self.config.max_total_attachment_bytes,
+        };
+        let mut expanded = expand_file_refs(submission.text(), limits, &issues);
+        let Submission { input, require_approval, resolving, .. } = submission;
+        for output in std::mem::take(&mut self.shell_attachments) {
+            expanded.text.push_str(&format!("\n\n<shell_output>\n{}\n</shell_output>", output.trim_end()));
+        }
+        for path in std::mem::take(&mut self.attachments) {
+            if expanded.files_read.iter().any(|(p, _)| *p == path) || expanded.images.iter().any(|i| i.path == path) {
+                continue;
+            }
+            if crate::image::is_image(&path) {
+                match crate::image::load(&path) {
+                    Ok(image) => expanded.images.push(image),
+                    Err(e) => self.error = Some(e),
+                }
+                continue;
+            }
+            if let Some(content) = read_attachment(&path, None, limits, &mut expanded.attached_bytes, &mut expanded.warnings) {
+                expanded.text.push_str(&file_block(&path, &content));
+                expanded.files_read.push((path, content.lines().count()));
+            }
+        }
+
+        if std::mem::take(&mut self.insert_draft) {
+            let draft = crate::draft::load();
+            if !draft.trim().is_empty() {
+                expanded.text.push_str(&format!("\n\n<draft>\n{}\n</draft>", draft.trim()));
+            }
+        }
+
+        // Add user message
+        self.messages.push(ChatMessage {
+            role: MessageRole::User,
+            content: input,
+        });
+
+        for warning in &expanded.warnings {
+            self.messages.push(ChatMessage {
+                role: MessageRole::Assistant,
+                content: format!("*{} {}*", crate::glyphs::get().warning, warning),
+            });
+        }
+
+        for (path, _) in &expanded.files_read {
+            self.note_recent_file(path);
+        }
+
+        // Add visual feedback for attached files
+        for (path, lines) in &expanded.files_read {
+            self.messages.push(ChatMessage {
+                role: MessageRole::Tool {
+                    name: "read_file".to_string(),
+                    path: Some(path.clone()),
+                    error: None,
+                },
+                content: "\n".repeat(*lines), // Fake content with right line count
+            });
+        }
+        for (label, content) in &expanded.issues_read {
+            self.messages.push(ChatMessage {
+                role: MessageRole::Tool {
+                    name: "gh_fetch".to_string(),
+                    path: Some(label.clone()),
+                    error: None,
+                },
+                content: content.clone(),
+            });
+        }
+
+        for image in &expanded.images {
+            self.messages.push(ChatMessage {
+                role: MessageRole::Tool {
+                    name: "image".to_string(),
+                    path: Some(image.path.clone()),
+                    error: None,
+                },
+                content: format!("Attached {}", image.label()),
+            });
+        }
+
+        let content = if expanded.images.is_empty() {
+            json!(expanded.text)
+        } else {
+            let mut parts = vec![json!({ "type": "text", "text": expanded.text })];
+            parts.extend(expanded.images.iter().map(|i| i.content_part()));
+            json!(parts)
+        };
+        self.api_messages.push(json!({
+            "role": "user",
+            "content": content
+        }));
+
+        self.input.clear();
+        self.input_cursor = 0;
+        self.reply_start = self.messages.len();
+        self.turn_tool_calls = 0;
+        self.turn_tokens = (0, 0);
+        self.history_repaired = false;
+        self.wrapping_up = false;
+        self.postmortem = None;
+        self.require_approval = require_approval;
+        self.resolving = resolving;
+        self.turn_started = Instant::now();
+        if let Some(limit) = self.max_duration.take() {
+            self.deadline = self.turn_started.checked_add(limit);
+        }
+        self.reset_turn_limits();
+        self.state = AppState::Thinking;
+        self.save_session();
+        self.start_api_call();
+    }
+
+    fn start_api_call(&mut self) {
+        // Reset cancel flag for new request
+        self.cancel_flag.store(false, Ordering::SeqCst);
+
+        let (tx, rx) = mpsc::channel();
+        self.pending_response = Some(rx);
+
+        let provider = self.provider.clone();
+        let api_key = self.api_key.clone();
+        let session_id = self.session.id.clone();
+        let seed = self.session.seed;
+        let messages = self.api_messages.clone();
+        let tool_defs = if self.wrapping_up { Vec::new() } else { self.tool_defs.clone() };
+        let cancel_flag = self.cancel_flag.clone();
+        self.api_progress.clear();
+        let progress = self.api_progress.clone();
+
+        thread::spawn(move || {
+            let result = api::chat(&provider, &api_key, &session_id, seed, &messages, &tool_defs, &progress);
+            // Only send if not cancelled
+            if !cancel_flag.load(Ordering::SeqCst) {
+                let _ = tx.send(result);
+            }
+        });
+    }
+
+    /// After the first reply, ask the model for a short session title in the background
+    fn request_title(&mut self) {
+        if !self.config.generate_titles || !self.session.generated_title.is_empty() || self.pending_title.is_some() {
+            return;
+        }
+        let first = |role: &str| {
+            self.api_messages
+                .iter()
+                .find(|m| m["role"] == role && m["content"].is_string())
+                .and_then(|m| m["content"].as_str())
+                .map(|c| c.chars().take(1000).collect::<String>())
+        };
+        let (Some(question), Some(answer)) = (first("user"), first("assistant")) else {
+            return;
+        };
+        let messages = vec![
+            json!({
+                "role": "system",
+                "content": "Write a title of 3 to 6 words for this conversation. Reply with the title only, no quotes or punctuation at the end."
+            }),
+            json!({
+                "role": "user",
+                "content": format!("User:\n{}\n\nAssistant:\n{}", question, answer)
+            }),
+        ];
+
+        let (tx, rx) = mpsc::channel();
+        self.pending_title = Some((self.session.id.clone(), rx));
+        let mut provider = self.provider.clone();
+        if let Some(model) = &self.config.title_model {
+            provider.model = model.clone();
+        }
+        let api_key = self.api_key.clone();
+        let session_id = self.session.id.clone();
+        thread::spawn(move || {
+            if let Ok(resp) = api::chat(&provider, &api_key, &session_id, None, &messages, &[], &api::Progress::default()) {
+                let title = resp.content.unwrap_or_default();
+                let title = title.lines().next().unwrap_or("").trim().trim_matches(['"', '\'', '*', '.']).trim();
+                let _ = tx.send(title.chars().take(60).collect());
+            }
+        });
+    }
+
+    fn poll_title(&mut self) {
+        let Some((session_id, rx)) = &self.pending_title else {
+            return;
+        };
+        match rx.try_recv() {
+            Ok(title) => {
+                // Dropped if the user has moved on to another session
+                if *session_id == self.session.id && !title.is_empty() {
+                    self.session.generated_title = title;
+                    self.save_session();
+                }
+                self.pending_title = None;
+            }
+            Err(mpsc::TryRecvError::Empty) => {}
+            Err(mpsc::TryRecvError::Disconnected) => self.pending_title = None,
+        }
+    }
+
+    /// Add a submitted line to the prompt history
+    fn remember_input(&mut self, input: &str) {
+        if self.history.last().map(|s| s.as_str()) != Some(input) {
+            self.history.push(input.to_string());
+            if let Err(e) = crate::history::append(input) {
+                self.notice = Some(e);
+            }
+        }
+        self.history_pos = self.history.len();
+    }
+
+    /// Run a command the user typed after `!`, sandboxed like the bash tool. The output
+    /// goes into the transcript only; `attach` also adds it to the next message.
+    fn run_shell(&mut self, command: &str, attach: bool) {
+        if command.is_empty() {
+            self.error = Some("Usage: !<command> runs it; !!<command> also attaches the output to your next message".to_string());
+            return;
+        }
+        let args = json!({ "command": command }).to_string();
+        let allowed_paths = self.get_all_allowed_paths();
+        // Typed by the user, so "ask" doesn't need to ask
+        let allow_network = SandboxConfig::network_policy() != NetworkPolicy::Deny;
+        self.tool_cancel.store(false, Ordering::SeqCst);
+        let cancel = self.tool_cancel.clone();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(tools::execute_bash_with_paths(&args, &allowed_paths, allow_network, false, &cancel));
+        });
+        self.pending_shell = Some((rx, attach));
+        self.turn_started = Instant::now();
+        self.state = AppState::ToolCall(format!("$ {}", command));
+    }
+
+    /// `!!` on its own: attach the last `!` command's output to the next message
+    fn attach_shell_output(&mut self) {
+        match self.shell_output.clone() {
+            Some(output) => {
+                self.shell_attachments.push(output);
+                self.notice = Some("Output attached to your next message".to_string());
+            }
+            None => self.error = Some("No command output yet; run one with !<command>".to_string()),
+        }
+    }
+
+    /// `!!` outputs waiting for the next message
+    pub fn shell_attachments(&self) -> usize {
+        self.shell_attachments.len()
+    }
+
+    pub fn poll_shell(&mut self) {
+        let Some((rx, attach)) = &self.pending_shell else { return };
+        let attach = *attach;
+        let (output, error) = match rx.try_recv() {
+            Ok(result) => tools::split_result(result),
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => ("Error: the command thread crashed".to_string(), Some(ErrorKind::Internal)),
+        };
+        self.pending_shell = None;
+        self.state = AppState::Idle;
+        self.messages.push(ChatMessage {
+            role: MessageRole::Tool { name: "bash".to_string(), path: None, error },
+            content: output.clone(),
+        });
+        // What's attached is capped like the bash tool's output to the model
+        let output = tools::limit_output("bash", &output, self.config.tool_output_limit("bash"));
+        if attach {
+            self.shell_attachments.push(output.clone());
+            self.notice = Some("Output attached to your next message".to_string());
+        }
+        self.shell_output = Some(output);
+        self.scroll_offset = 0;
+        self.save_session();
+    }
+
+    pub fn abort_request(&mut self) {
+        if self.state == AppState::Idle {
+            return;
+        }
+
+        // A running tool is killed instead; poll_tool_result reports the interruption
+        if (self.pending_tool_execution.is_some() || self.pending_shell.is_some()) && self.interrupt_tools() {
+            return;
+        }
+
+        // Signal cancellation
+        self.cancel_flag.store(true, Ordering::SeqCst);
+
+        // Clear pending state; a message still waiting on GitHub goes back to the input
+        if let Some((submission, _)) = self.pending_submit.take() {
+            self.input = submission.input;
+            self.input_cursor = self.input.len();
+        }
+        self.pending_response = None;
+        self.pending_tool_calls.clear();
+        self.pending_tool_execution = None;
+        self.continue_modal = None;
+        self.state = AppState::Idle;
+
+        // Add aborted message to chat
+        self.messages.push(ChatMessage {
+            role: MessageRole::Assistant,
+            content: "*Request aborted*".to_string(),
+        });
+    }
+
+    /// Answer from the conversation alone, e.g. for `hal explain`
+    pub fn disable_tools(&mut self) {
+        self.tool_defs.clear();
+    }
+
+    /// Add instructions to this session's system prompt, e.g. the --json-result format
+    pub fn append_system_prompt(&mut self, text: &str) {
+        if let Some(system) = self.api_messages.first_mut() {
+            system["content"] = json!(format!("{}\n\n{}", system["content"].as_str().unwrap_or(""), text));
+        }
+    }
+
+    /// Wrap up once `limit` has passed since the first message: the running tool call
+    /// finishes, a pending reply is dropped, the rest of the calls are skipped and the
+    /// model is asked for a summary without tools
+    pub fn set_max_duration(&mut self, limit: Duration) {
+        self.max_duration = Some(limit);
+    }
+
+    /// Give the turn a fresh allowance of tool calls, tokens and time from where it is now
+    fn reset_turn_limits(&mut self) {
+        let max_calls = self.config.max_tool_calls_per_turn;
+        let max_tokens = self.config.max_tokens_per_turn;
+        let max_minutes = self.config.max_turn_minutes;
+        self.pause_at_tool_calls = if max_calls > 0 { self.turn_tool_calls + max_calls } else { 0 };
+        self.pause_at_tokens = if max_tokens > 0 { self.turn_total_tokens() + max_tokens } else { 0 };
+        self.pause_at_time = (max_minutes > 0).then(|| Instant::now() + Duration::from_secs(max_minutes * 60));
+    }
+
+    fn turn_total_tokens(&self) -> u64 {
+        self.turn_tokens.0 as u64 + self.turn_tokens.1 as u64
+    }
+
+    /// The limit this turn has run into, if any, for the continue modal
+    fn turn_limit_reached(&self) -> Option<String> {
+        if self.pause_at_tool_calls > 0 && self.turn_tool_calls >= self.pause_at_tool_calls {
+            Some(format!("{} tool calls", self.turn_tool_calls))
+        } else if self.pause_at_tokens > 0 && self.turn_total_tokens() >= self.pause_at_tokens {
+            Some(format!("{} tokens", self.turn_total_tokens()))
+        } else if self.pause_at_time.is_some_and(|at| Instant::now() >= at) {
+            Some(format!("{} minutes", self.turn_started.elapsed().as_secs() / 60))
+        } else {
+            None
+        }
+    }
+
+    fn past_deadline(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Typos found in the input as it stands, if it was held back for them
+    pub fn spell_fixes(&self) -> Option<&[crate::spell::Fix]> {
+        self.spell_review
+            .as_ref()
+            .filter(|(reviewed, _)| *reviewed == self.input.trim())
+            .map(|(_, fixes)| fixes.as_slice())
+    }
+
+    /// Tab on a held-back message: apply the suggested fixes, leaving it to send
+    pub fn apply_spell_fixes(&mut self) {
+        let Some(fixes) = self.spell_fixes() else { return };
+        self.input = crate::spell::apply(self.input.trim(), fixes);
+        self.input_cursor = self.input.len();
+        self.spell_review = None;
+    }
+
+    /// Notify once hal needs the user again (turn over, or a change or command to approve)
+    /// after working at least notify_after_secs while the terminal was in the background
+    pub fn check_attention(&mut self) {
+        let waiting = self.state == AppState::Idle || self.has_modal();
+        if !waiting {
+            self.busy_since.get_or_insert_with(Instant::now);
+            return;
+        }
+        let Some(since) = self.busy_since.take() else { return };
+        if crate::notify::focused() || since.elapsed() < Duration::from_secs(self.config.notify_after_secs) {
+            return;
+        }
+        let what = if self.has_modal() {
+            "waiting for your approval"
+        } else if self.error.is_some() {
+            "stopped with an error"
+        } else {
+            "finished"
+        };
+        let title = if self.session.title.is_empty() { String::new() } else { format!(" ({})", self.session.title) };
+        crate::notify::send(self.config.notify, &format!("hal {}{}", what, title));
+    }
+
+    /// End the turn on an error, offering to capture a post-mortem of it
+    fn fail_turn(&mut self, error: String) {
+        self.error = Some(format!("{} ({})", error, POSTMORTEM_HINT));
+        self.capture_postmortem(&error);
+        self.state = AppState::Idle;
+    }
+
+    /// Describe the failed turn now, while the history still holds the request
+    fn capture_postmortem(&mut self, failure: &str) {
+        let bundle = crate::postmortem::bundle(failure, &self.provider, self.config.mode, &self.api_messages, &[&self.api_key]);
+        self.postmortem = Some(bundle);
+    }
+
+    /// Ctrl+B after a failed turn: write the diagnostic bundle and say where it went
+    pub fn save_postmortem(&mut self) {
+        let Some(bundle) = self.postmortem.take() else { return };
+        match crate::postmortem::save(&bundle) {
+            Ok(path) => self.notice = Some(format!("Saved post-mortem to {} — review it before attaching", path.display())),
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Answer a tool call that didn't go to a worker thread: refused, failed or run in place
+    fn push_tool_result(&mut self, id: &str, name: String, path: Option<String>, result: tools::ToolResult) {
+        let (content, error) = tools::split_result(result);
+        self.api_messages.push(json!({
+            "role": "tool",
+            "tool_call_id": id,
+            "content": tools::result_for_model(&content, error)
+        }));
+        self.messages.push(ChatMessage {
+            role: MessageRole::Tool { name, path, error },
+            content,
+        });
+    }
+
+    /// Answer the calls still queued with `error` so the history stays valid
+    fn skip_pending_tools(&mut self, error: ToolError) {
+        for (id, name, _) in std::mem::take(&mut self.pending_tool_calls) {
+            self.push_tool_result(&id, name, None, Err(error.clone()));
+        }
+    }
+
+    fn wrap_up(&mut self) {
+        self.skip_pending_tools(ToolError::new(ErrorKind::TimeLimit, "Skipped: time limit reached"));
+        self.deadline = None;
+        self.wrapping_up = true;
+        self.timed_out = true;
+        self.messages.push(ChatMessage {
+            role: MessageRole::Assistant,
+            content: "*Time limit reached — asking for a summary*".to_string(),
+        });
+        self.api_messages.push(json!({
+            "role": "user",
+            "content": "The time limit for this run has been reached. Stop working and don't call any tools. \
+                        Reply with a short summary: what you did, what state things are in (including anything \
+                        left half-done), and what remains, as a list of concrete next steps."
+        }));
+        self.state = AppState::Thinking;
+        self.start_api_call();
+    }
+
+    /// Reload the config if config.json or .hal/config.json was edited outside hal.
+    /// Checked at most once per CONFIG_POLL_INTERVAL.
+    pub fn poll_config_reload(&mut self) {
+        if self.config_checked.elapsed() < CONFIG_POLL_INTERVAL {
+            return;
+        }
+        self.config_checked = Instant::now();
+        let snapshot = Config::files_snapshot();
+        if snapshot == self.config_snapshot {
+            return;
+        }
+        // Saves from /autoaccept, /model, /key etc. aren't edits to reload
+        let own_write = snapshot[1] == self.config_snapshot[1]
+            && snapshot[0].as_deref().is_some_and(|content| self.config.is_saved_form(content));
+        self.config_snapshot = snapshot;
+        if own_write {
+            return;
+        }
+        match Config::reload() {
+            Ok(config) => self.apply_config(config),
+            Err(e) => self.error = Some(format!("Config not reloaded: {}", e)),
+        }
+    }
+
+    /// Switch to a reloaded config, keeping the mode and provider chosen this session
+    fn apply_config(&mut self, mut config: Config) {
+        let mut added: Vec<&String> = config.providers.keys().filter(|k| !self.config.providers.contains_key(*k)).collect();
+        added.sort();
+        let mut notice = "*Config reloaded".to_string();
+        if !added.is_empty() {
+            notice.push_str(&format!(" — new providers: {}", added.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ")));
+        }
+        notice.push('*');
+
+        config.mode = self.config.mode;
+        config.skip_permissions = self.config.skip_permissions;
+        if !config.providers.contains_key(&self.provider_name) {
+            self.provider_name = config.default_provider.clone();
+        }
+        self.config = config;
+        let pinned = pinned_provider(&self.config, &self.session).filter(|(name, _)| *name == self.provider_name);
+        if let Some(provider) = pinned.map(|(_, p)| p).or_else(|| self.config.providers.get(&self.provider_name).cloned()) {
+            if let Some(key) = provider.resolve_api_key() {
+                self.api_key = key;
+            }
+            self.provider = provider;
+        }
+        self.auto_accept = self.config.auto_accept;
+        api::set_max_concurrent_requests(self.config.max_concurrent_requests);
+        crate::glyphs::set(self.config.glyphs);
+        crate::undo::set_keep_backups(self.config.keep_backups);
+        crate::github::set_token(self.config.github_token.clone());
+        let prompt = build_system_prompt(&self.config, &self.session.system_prompt);
+        if let Some(system) = self.api_messages.first_mut() {
+            system["content"] = json!(prompt);
+        }
+        self.messages.push(ChatMessage {
+            role: MessageRole::Assistant,
+            content: notice,
+        });
+    }
+
+    /// How long the current turn (or `!` command) has been running
+    pub fn busy_for(&self) -> Duration {
+        self.turn_started.elapsed()
+    }
+
+    pub fn tool_running(&self) -> bool {
+        self.pending_tool_execution.is_some() || self.pending_shell.is_some()
+    }
+
+    /// Kill the running bash command. Returns false if it was already asked to stop.
+    pub fn interrupt_tools(&mut self) -> bool {
+        if self.tool_cancel.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        self.state = AppState::ToolCall("interrupting".to_string());
+        true
+    }
+
+    /// The model's plan for the current task, from todo_write
+    pub fn todos(&self) -> &[crate::todo::Todo] {
+        &self.session.todos
+    }
+
+    pub fn poll_api_response(&mut self) {
+        self.poll_title();
+        self.poll_pending_submit();
+        if self.state == AppState::Idle {
+            return;
+        }
+
+        // Check if cancelled
+        if self.cancel_flag.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // A reply that is still coming when time runs out is dropped for the summary
+        if self.pending_response.is_some() && self.past_deadline() {
+            self.pending_response = None;
+            self.wrap_up();
+            return;
+        }
+
+        let response = match &self.pending_response {
+            Some(rx) => match rx.try_recv() {
+                Ok(result) => result,
+                Err(mpsc::TryRecvError::Empty) => return, // Still waiting
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    // Could be cancelled or crashed - check flag
+                    if self.cancel_flag.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    self.fail_turn("API thread crashed".to_string());
+                    self.pending_response = None;
+                    return;
+                }
+            },
+            None => return,
+        };
+
+        self.pending_response = None;
+
+        match response {
+            Ok(resp) => {
+                // Update token usage
+                if let Some(usage) = &resp.usage {
+                    self.token_usage = Some((usage.prompt_tokens, usage.completion_tokens));
+                    let (read, write) = (usage.cache_read(), usage.cache_write());
+                    self.cache_usage = (read > 0 || write > 0).then_some((read, write));
+                    self.turn_tokens.0 += usage.prompt_tokens;
+                    self.turn_tokens.1 += usage.completion_tokens;
+                }
+                self.session.turns.push(Turn {
+                    api_message: self.api_messages.len(),
+                    model: self.provider.model.clone(),
+                    seed: self.session.seed.filter(|_| api::supports_seed(&self.provider)),
+                    fingerprint: resp.fingerprint.clone(),
+                    time: chrono::Utc::now().timestamp(),
+                });
+
+                // The time-limit summary is the last word, even if the model reaches for a tool
+                if let Some(tool_calls) = resp.tool_calls.filter(|_| !self.wrapping_up) {
+                    self.handle_tool_calls(tool_calls);
+                    // process_pending_tools will call start_api_call when done
+                } else {
+                    let content = resp.content.unwrap_or_default();
+                    self.messages.push(ChatMessage {
+                        role: MessageRole::Assistant,
+                        content: content.clone(),
+                    });
+                    self.api_messages.push(json!({
+                        "role": "assistant",
+                        "content": content
+                    }));
+                    if !self.resolving.is_empty() {
+                        let note = crate::conflicts::mark_resolved(&std::mem::take(&mut self.resolving));
+                        self.messages.push(ChatMessage { role: MessageRole::Assistant, content: note });
+                    }
+                    self.state = AppState::Idle;
+                    self.save_session();
+                    self.request_title();
+                }
+            }
+            Err(e) => {
+                // A history the backend rejects (e.g. tool calls orphaned by an abort)
+                // gets one repair pass and a retry
+                if !self.history_repaired && api::is_history_error(&e) {
+                    self.history_repaired = true;
+                    let fixes = api::repair_messages(&mut self.api_messages);
+                    if !fixes.is_empty() {
+                        self.messages.push(ChatMessage {
+                            role: MessageRole::Assistant,
+                            content: format!("*Repaired conversation history ({}), retrying*", fixes.join(", ")),
+                        });
+                        self.start_api_call();
                         return;
                     }
-                    self.error = Some("API thread crashed".to_string());
-                    self.state = AppState::Idle;
-                    self.pending_response = None;
+                }
+                // The post-mortem describes the request that failed, so capture it before dropping it
+                self.fail_turn(format!("API error: {}", e.with_hint()));
+                self.api_messages.pop();
+            }
+        }
+    }
+
+    fn handle_tool_calls(&mut self, tool_calls: Vec<Value>) {
+        let calls: Vec<_> = tool_calls
+            .iter()
+            .map(|call| {
+                (
+                    call["id"].as_str().unwrap_or("").to_string(),
+                    call["function"]["name"].as_str().unwrap_or("").to_string(),
+                    call["function"]["arguments"]
+                        .as_str()
+                        .unwrap_or("{}")
+                        .to_string(),
+                )
+            })
+            .collect();
+
+        self.api_messages.push(json!({
+            "role": "assistant",
+            "content": Value::Null,
+            "tool_calls": tool_calls
+        }));
+
+        // Store pending calls and process them
+        self.turn_tool_calls += calls.len();
+        self.pending_tool_calls = calls;
+        self.process_pending_tools();
+    }
+
+    /// Show a change for review, leasing its files until it's accepted or rejected so other
+    /// tabs and sessions hold off writing them
+    fn open_diff_modal(&mut self, mut modal: DiffModal) {
+        modal.warning = crate::lease::acquire(&modal.paths(), &self.lease_owner).first().map(|lease| lease.describe());
+        self.diff_modal = Some(modal);
+    }
+
+    /// Give up the leases of a change still under review, when the tab closes
+    pub fn release_leases(&self) {
+        if let Some(modal) = &self.diff_modal {
+            crate::lease::release(&modal.paths(), &self.lease_owner);
+        }
+    }
+
+    fn process_pending_tools(&mut self) {
+        // If already executing a tool, wait for it
+        if self.pending_tool_execution.is_some() {
+            return;
+        }
+
+        if self.past_deadline() {
+            self.wrap_up();
+            return;
+        }
+
+        // Get next tool to execute
+        let Some((id, name, args)) = self.pending_tool_calls.first().cloned() else {
+            // Pause long tool loops until the user says to keep going
+            if let Some(reached) = self.turn_limit_reached() {
+                let elapsed = self.turn_started.elapsed();
+                self.continue_modal = Some(ContinueModal::new(reached, self.turn_tool_calls, self.turn_tokens, self.provider.cost(self.turn_tokens), elapsed));
+                return;
+            }
+            // No more tools, continue with API call
+            self.state = AppState::Thinking;
+            self.start_api_call();
+            return;
+        };
+
+        // Calls to tools the current mode doesn't offer (e.g. bash in ask mode) are refused
+        if !offers_tool(&self.tool_defs, &name) {
+            self.pending_tool_calls.remove(0);
+            let content = format!("Error: {} is not available in {} mode", name, mode_name(self.config.mode));
+            self.push_tool_result(&id, name, None, Err(ToolError::new(ErrorKind::Unavailable, content)));
+            self.process_pending_tools();
+            return;
+        }
+
+        // The plan lives on the session, so its tools run here rather than on a worker thread
+        if name == "todo_write" || name == "todo_read" {
+            self.pending_tool_calls.remove(0);
+            let result = if name == "todo_write" {
+                crate::todo::write(&mut self.session.todos, &args)
+            } else {
+                Ok(crate::todo::checklist(&self.session.todos))
+            };
+            let content = result.as_ref().unwrap_or_else(|e| &e.message);
+            crate::debug_log::log(&format!("tool {}", name), &format!("Arguments: {}\n\n{}", args, content), &[]);
+            self.push_tool_result(&id, name, None, result);
+            self.process_pending_tools();
+            return;
+        }
+
+        // A confirmed replace_in_files must repeat one of this session's dry runs
+        if name == "replace_in_files" && !tools::is_dry_run(&name, &args) && !self.session.dry_runs.take(&args) {
+            self.pending_tool_calls.remove(0);
+            let content = "Error: no dry run with these arguments. Call replace_in_files without confirm first and check the diff.";
+            self.push_tool_result(&id, name, None, Err(ToolError::new(ErrorKind::InvalidArguments, content)));
+            self.process_pending_tools();
+            return;
+        }
+
+        // Check if bash tool needs permission
+        if name == "bash" && self.config.skip_permissions {
+            self.grant_bash_permissions(&args, &id);
+        } else if name == "bash"
+            && let Some(modal) = self.check_bash_permission(&args, &id)
+        {
+            self.permission_modal = Some(modal);
+            return; // Wait for user response
+        }
+
+        // Ambiguous edits wait for the user to pick the match, even when auto-accepting
+        if name == "edit_file"
+            && let Some((path, locations)) = tools::ambiguous_edit(&args)
+        {
+            self.match_modal = Some(MatchModal { path, locations, selected: 0 });
+            return;
+        }
+
+        // Check if write/edit needs manual approval (multi-file replace_in_files always does)
+        if tools::is_file_change(&name)
+            && !tools::is_dry_run(&name, &args)
+            && !self.config.skip_permissions
+            && (!self.auto_accept || self.require_approval || name == "replace_in_files")
+        {
+            match tools::preview_file_change(&name, &args) {
+                // Changes confined to the scratch directory are throwaway and run without review
+                Ok((_, writes)) if writes.iter().all(|(path, _)| crate::scratch::contains(path)) => {}
+                Ok((diff_text, writes)) => {
+                    let path = serde_json::from_str::<Value>(&args)
+                        .ok()
+                        .and_then(|v| v["path"].as_str().map(|s| s.to_string()))
+                        .unwrap_or_default();
+                    self.open_diff_modal(DiffModal::new(
+                        path, diff_text, writes, name.clone(), id.clone(),
+                    ));
+                    return; // Wait for user response
+                }
+                Err(e) => {
+                    // Preview failed — send error as tool result
+                    self.pending_tool_calls.remove(0);
+                    self.push_tool_result(&id, name, None, Err(e));
+                    self.process_pending_tools();
                     return;
                 }
-            },
+            }
+        }
+
+        // Remove from pending, along with any following calls that can run alongside it
+        let len = batch_len(&self.pending_tool_calls, &self.tool_defs, self.config.max_parallel_tools);
+        let batch: Vec<_> = self.pending_tool_calls.drain(..len).collect();
+
+        let status = batch
+            .iter()
+            .map(|(_, name, args)| format_tool_call(name, args))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.state = AppState::ToolCall(status);
+
+        // Spawn tool execution in background
+        let (tx, rx) = mpsc::channel();
+        self.pending_tool_execution = Some(rx);
+
+        let allowed_paths = self.get_all_allowed_paths();
+        let allow_network = self.network_access(&id, &args);
+        let bash_quiet = self.config.bash_quiet;
+        self.tool_cancel.store(false, Ordering::SeqCst);
+        let cancel = self.tool_cancel.clone();
+
+        thread::spawn(move || {
+            // Run every call in the batch concurrently, collecting results in call order
+            let results: Vec<ToolExecutionResult> = thread::scope(|scope| {
+                let handles: Vec<_> = batch
+                    .into_iter()
+                    .map(|(id, name, args)| {
+                        let allowed_paths = &allowed_paths;
+                        let cancel = &cancel;
+                        let fallback = (id.clone(), name.clone(), args.clone());
+                        let handle = scope.spawn(move || {
+                            // Extract path from args for tools that have it
+                            let path = serde_json::from_str::<Value>(&args)
+                                .ok()
+                                .and_then(|v| v["path"].as_str().map(|s| s.to_string()));
+
+                            let started = Instant::now();
+                            let result = if name == "bash" {
+                                tools::execute_bash_with_paths(&args, allowed_paths, allow_network, bash_quiet, cancel)
+                            } else {
+                                // For non-bash tools, we need to call them directly
+                                // since we can't send the function pointer across threads
+                                tools::execute_tool_by_name(&name, &args)
+                            };
+                            crate::debug_log::log(
+                                &format!("tool {} ({} ms)", name, started.elapsed().as_millis()),
+                                &format!("Arguments: {}\n\n{}", args, result.as_ref().unwrap_or_else(|e| &e.message)),
+                                &[],
+                            );
+
+                            ToolExecutionResult { id, name, args, path, result }
+                        });
+                        (fallback, handle)
+                    })
+                    .collect();
+
+                // A panicking tool still needs a result so the tool_call_id gets a reply
+                handles
+                    .into_iter()
+                    .map(|((id, name, args), handle)| {
+                        handle.join().unwrap_or_else(|_| ToolExecutionResult {
+                            id,
+                            name,
+                            args,
+                            path: None,
+                            result: Err(ToolError::new(ErrorKind::Internal, "Error: tool execution panicked")),
+                        })
+                    })
+                    .collect()
+            });
+
+            let _ = tx.send(results);
+        });
+    }
+
+    pub fn poll_tool_result(&mut self) {
+        let rx = match &self.pending_tool_execution {
+            Some(rx) => rx,
             None => return,
         };
 
-        self.pending_response = None;
+        match rx.try_recv() {
+            Ok(tool_results) => {
+                self.pending_tool_execution = None;
+
+                for tool_result in tool_results {
+                    let limit = self.config.tool_output_limit(&tool_result.name);
+                    if tool_result.result.is_ok() && tools::is_dry_run(&tool_result.name, &tool_result.args) {
+                        self.session.dry_runs.record(&tool_result.args);
+                    }
+                    let (result, error) = tools::split_result(tool_result.result);
+                    let content = tools::limit_output(&tool_result.name, &tools::result_for_model(&result, error), limit);
+                    self.messages.push(ChatMessage {
+                        role: MessageRole::Tool {
+                            name: tool_result.name,
+                            path: tool_result.path,
+                            error,
+                        },
+                        content: result,
+                    });
+
+                    self.api_messages.push(json!({
+                        "role": "tool",
+                        "tool_call_id": tool_result.id,
+                        "content": content
+                    }));
+                }
+
+                // Interrupted: answer the remaining calls and hand control back to the user
+                if self.tool_cancel.load(Ordering::SeqCst) {
+                    self.skip_pending_tools(ToolError::new(ErrorKind::Interrupted, "Skipped: interrupted by user"));
+                    self.messages.push(ChatMessage {
+                        role: MessageRole::Assistant,
+                        content: "*Tool interrupted*".to_string(),
+                    });
+                    self.state = AppState::Idle;
+                    self.save_session();
+                    return;
+                }
+
+                // Saved after every batch, so a crash mid-turn loses at most the running tool
+                self.save_session();
+                // Process next tool or start API call
+                self.process_pending_tools();
+            }
+            Err(mpsc::TryRecvError::Empty) => {
+                // Still executing, keep waiting
+            }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                // Thread crashed
+                self.pending_tool_execution = None;
+                self.fail_turn("Tool execution thread crashed".to_string());
+            }
+        }
+    }
+
+    fn check_bash_permission(&self, args: &str, tool_id: &str) -> Option<PermissionModal> {
+        let json: Value = serde_json::from_str(args).unwrap_or_default();
+        let command = json["command"].as_str().unwrap_or("");
+
+        if command.is_empty() {
+            return None;
+        }
+
+        // Get missing paths considering temp allowed paths
+        let missing = self.get_missing_paths_for_command(command);
+
+        if let Some(first_missing) = missing.first() {
+            Some(PermissionModal::new(
+                first_missing.path.clone(),
+                first_missing.reason.clone(),
+                tool_id.to_string(),
+            ))
+        } else if SandboxConfig::network_policy() == NetworkPolicy::Ask
+            && sandbox::needs_network(command)
+            && !self.network_access(tool_id, args)
+        {
+            Some(PermissionModal::network(command.to_string(), tool_id.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// With permission checks off, allow what a bash call would have asked for, for this session
+    fn grant_bash_permissions(&mut self, args: &str, tool_id: &str) {
+        let json: Value = serde_json::from_str(args).unwrap_or_default();
+        let command = json["command"].as_str().unwrap_or("");
+        for request in self.get_missing_paths_for_command(command) {
+            self.temp_allowed_paths.push(request.path);
+        }
+        if sandbox::needs_network(command) {
+            self.network_allowed_once = Some(tool_id.to_string());
+        }
+    }
+
+    /// Whether a bash call runs with network access under the sandbox network policy
+    fn network_access(&self, tool_id: &str, args: &str) -> bool {
+        match SandboxConfig::network_policy() {
+            NetworkPolicy::Allow => true,
+            NetworkPolicy::Deny => false,
+            NetworkPolicy::Ask => {
+                let json: Value = serde_json::from_str(args).unwrap_or_default();
+                let command = json["command"].as_str().unwrap_or("");
+                sandbox::needs_network(command)
+                    && (self.network_allowed || self.network_allowed_once.as_deref() == Some(tool_id))
+            }
+        }
+    }
+
+    fn get_missing_paths_for_command(&self, command: &str) -> Vec<sandbox::PathRequest> {
+        let config = SandboxConfig::load_merged();
+        let required = sandbox::detect_required_paths(command);
+
+        required
+            .into_iter()
+            .filter(|req| {
+                let req_path = std::path::Path::new(&req.path);
+                // Check both config and temp allowed paths
+                !config.allowed_paths.iter().chain(&self.temp_allowed_paths).any(|allowed| {
+                    crate::paths::overlaps(req_path, std::path::Path::new(allowed))
+                })
+            })
+            .collect()
+    }
+
+    fn get_all_allowed_paths(&self) -> Vec<String> {
+        let mut paths = sandbox::get_allowed_paths();
+        paths.extend(self.temp_allowed_paths.clone());
+        paths.extend(crate::scratch::current().map(|dir| dir.to_string_lossy().to_string()));
+        paths
+    }
+
+    pub fn modal_up(&mut self) {
+        if let Some(modal) = &mut self.versions_modal {
+            modal.selected = modal.selected.saturating_sub(1);
+            modal.scroll = 0;
+            return;
+        }
+        if let Some(modal) = &mut self.continue_modal {
+            modal.selected = modal.selected.saturating_sub(1);
+            return;
+        }
+        if let Some(modal) = &mut self.match_modal {
+            modal.selected = modal.selected.saturating_sub(1);
+            return;
+        }
+        if let Some(modal) = &mut self.provider_modal {
+            if modal.selected > 0 {
+                modal.selected -= 1;
+            }
+            return;
+        }
+        if let Some(modal) = &mut self.diff_modal {
+            if modal.selected > 0 {
+                modal.selected -= 1;
+            }
+            return;
+        }
+        if let Some(modal) = &mut self.permission_modal {
+            if modal.selected > 0 {
+                modal.selected -= 1;
+            }
+        }
+    }
+
+    pub fn modal_down(&mut self) {
+        if let Some(modal) = &mut self.versions_modal {
+            modal.selected = (modal.selected + 1).min(modal.versions.len() - 1);
+            modal.scroll = 0;
+            return;
+        }
+        if let Some(modal) = &mut self.continue_modal {
+            if modal.selected + 1 < modal.options.len() {
+                modal.selected += 1;
+            }
+            return;
+        }
+        if let Some(modal) = &mut self.match_modal {
+            modal.selected = (modal.selected + 1).min(modal.locations.len());
+            return;
+        }
+        if let Some(modal) = &mut self.provider_modal {
+            if modal.selected + 1 < modal.providers.len() {
+                modal.selected += 1;
+            }
+            return;
+        }
+        if let Some(modal) = &mut self.diff_modal {
+            if modal.selected + 1 < modal.options.len() {
+                modal.selected += 1;
+            }
+            return;
+        }
+        if let Some(modal) = &mut self.permission_modal {
+            if modal.selected + 1 < modal.options.len() {
+                modal.selected += 1;
+            }
+        }
+    }
+
+    pub fn diff_modal_scroll_up(&mut self) {
+        if let Some(modal) = &mut self.diff_modal {
+            modal.scroll_offset = modal.scroll_offset.saturating_add(1);
+        }
+    }
 
-        match response {
-            Ok(resp) => {
-                // Update token usage
-                if let Some(usage) = &resp.usage {
-                    self.token_usage = Some((usage.prompt_tokens, usage.completion_tokens));
-                }
+    pub fn diff_modal_scroll_down(&mut self) {
+        if let Some(modal) = &mut self.diff_modal {
+            modal.scroll_offset = modal.scroll_offset.saturating_sub(1);
+        }
+    }
 
-                if let Some(tool_calls) = resp.tool_calls {
-                    self.handle_tool_calls(tool_calls);
-                    // process_pending_tools will call start_api_call when done
+    pub fn diff_modal_page_up(&mut self) {
+        if let Some(modal) = &mut self.diff_modal {
+            modal.scroll_offset = modal.scroll_offset.saturating_add(10);
+        }
+    }
+
+    pub fn diff_modal_page_down(&mut self) {
+        if let Some(modal) = &mut self.diff_modal {
+            modal.scroll_offset = modal.scroll_offset.saturating_sub(10);
+        }
+    }
+
+    pub fn modal_select(&mut self) {
+        if let Some(modal) = self.versions_modal.take() {
+            self.restore_version(modal);
+            return;
+        }
+        // Handle provider modal
+        if let Some(modal) = self.provider_modal.take() {
+            let name = modal.providers[modal.selected].clone();
+            if let Some(new_provider) = self.config.providers.get(&name).cloned() {
+                let key = new_provider.resolve_api_key();
+                self.config.default_provider = name.clone();
+                self.provider_name = name.clone();
+                self.provider = new_provider.clone();
+                let _ = self.config.save();
+                if let Some(key) = key {
+                    self.api_key = key;
+                    self.messages.push(ChatMessage {
+                        role: MessageRole::Assistant,
+                        content: format!("Switched to **{}** ({})", name, self.provider.model),
+                    });
                 } else {
-                    let content = resp.content.unwrap_or_default();
+                    self.api_key = String::new();
                     self.messages.push(ChatMessage {
                         role: MessageRole::Assistant,
-                        content: content.clone(),
+                        content: format!("Switched to **{}** ({}). No API key set — use `/key <key>` or set ${}", name, self.provider.model, new_provider.api_key_env),
                     });
-                    self.api_messages.push(json!({
-                        "role": "assistant",
-                        "content": content
-                    }));
-                    self.state = AppState::Idle;
-                    self.save_session();
                 }
             }
-            Err(e) => {
-                self.error = Some(format!("API error: {}", e));
-                self.api_messages.pop();
+            return;
+        }
+
+        // Handle tool-limit modal
+        if let Some(modal) = self.continue_modal.take() {
+            if modal.selected == 0 {
+                self.reset_turn_limits();
+                self.state = AppState::Thinking;
+                self.start_api_call();
+            } else {
+                self.messages.push(ChatMessage {
+                    role: MessageRole::Assistant,
+                    content: format!("*Stopped after {} — send a message to continue; {}*", modal.reached, POSTMORTEM_HINT),
+                });
+                self.capture_postmortem(&format!("Stopped after {} in one turn", modal.reached));
                 self.state = AppState::Idle;
+                self.save_session();
             }
+            return;
         }
-    }
 
-    fn handle_tool_calls(&mut self, tool_calls: Vec<Value>) {
-        let calls: Vec<_> = tool_calls
-            .iter()
-            .map(|call| {
-                (
-                    call["id"].as_str().unwrap_or("").to_string(),
-                    call["function"]["name"].as_str().unwrap_or("").to_string(),
-                    call["function"]["arguments"]
-                        .as_str()
-                        .unwrap_or("{}")
-                        .to_string(),
-                )
-            })
-            .collect();
+        // Handle match modal: the pick becomes the call's occurrence, then it runs as usual
+        if let Some(modal) = self.match_modal.take() {
+            let Some((id, name, args)) = self.pending_tool_calls.first_mut() else { return };
+            if modal.selected < modal.locations.len()
+                && let Ok(mut parsed) = serde_json::from_str::<Value>(args)
+            {
+                parsed["occurrence"] = json!(modal.selected + 1);
+                *args = parsed.to_string();
+                self.process_pending_tools();
+                return;
+            }
+            // Rejected: the model gets the ambiguity error with the matching lines
+            let (id, name) = (id.clone(), name.clone());
+            let result = tools::preview_file_change(&name, args).map(|_| String::new());
+            self.pending_tool_calls.remove(0);
+            self.push_tool_result(&id, name, Some(modal.path), result);
+            self.process_pending_tools();
+            return;
+        }
 
-        self.api_messages.push(json!({
-            "role": "assistant",
-            "content": Value::Null,
-            "tool_calls": tool_calls
-        }));
+        // Handle diff modal
+        if let Some(modal) = self.diff_modal.take() {
+            // Let go of the files first: undo::write refuses any file still leased
+            crate::lease::release(&modal.paths(), &self.lease_owner);
+            // Remove from pending
+            if !self.pending_tool_calls.is_empty() {
+                self.pending_tool_calls.remove(0);
+            }
+            let verdict = if modal.selected == 0 { "accepted" } else { "rejected" };
+            crate::debug_log::log(&format!("tool {} {}", modal.tool_name, verdict), &modal.diff_text, &[]);
 
-        // Store pending calls and process them
-        self.pending_tool_calls = calls;
-        self.process_pending_tools();
-    }
+            if modal.selected == 0 {
+                // Accept — apply the writes, reporting any that failed
+                let mut result = modal.diff_text.clone();
+                let mut error = None;
+                for ((path, content), original) in modal.writes.iter().zip(&modal.originals) {
+                    // Another hal session or an editor wrote it while this one waited
+                    if std::fs::read_to_string(path).ok() != *original {
+                        result.push_str(&format!(
+                            "\nError: {} changed on disk since this preview; not written. Read it again before editing.",
+                            path
+                        ));
+                        error.get_or_insert(ErrorKind::Stale);
+                        continue;
+                    }
+                    if let Err(e) = tools::apply_write(path, content) {
+                        result.push_str(&format!("\n{} ({})", e.message, path));
+                        error.get_or_insert(e.kind);
+                    }
+                }
+                // Show diff in chat (same as auto-accept would)
+                self.messages.push(ChatMessage {
+                    role: MessageRole::Tool {
+                        name: modal.tool_name.clone(),
+                        path: Some(modal.path.clone()),
+                        error,
+                    },
+                    content: result.clone(),
+                });
+                self.api_messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": modal.pending_tool_id,
+                    "content": tools::limit_output(
+                        &modal.tool_name,
+                        &tools::result_for_model(&result, error),
+                        self.config.tool_output_limit(&modal.tool_name)
+                    )
+                }));
+            } else {
+                // Reject
+                let result = format!("REJECTED: User rejected changes to {}. Do not attempt to write this file through bash, echo, or any other method. Ask the user what they want instead.", modal.path);
+                self.push_tool_result(&modal.pending_tool_id, modal.tool_name.clone(), None, Err(ToolError::new(ErrorKind::Rejected, result)));
+            }
 
-    fn process_pending_tools(&mut self) {
-        // If already executing a tool, wait for it
-        if self.pending_tool_execution.is_some() {
+            // Continue with remaining tools or next API call
+            self.process_pending_tools();
             return;
         }
 
-        // Get next tool to execute
-        let Some((id, name, args)) = self.pending_tool_calls.first().cloned() else {
-            // No more tools, continue with API call
-            self.state = AppState::Thinking;
-            self.start_api_call();
-            return;
+        // Handle permission modal
+        let modal = match self.permission_modal.take() {
+            Some(m) => m,
+            None => return,
         };
 
-        // Check if bash tool needs permission
-        if name == "bash" {
-            if let Some(modal) = self.check_bash_permission(&args, &id) {
-                self.permission_modal = Some(modal);
-                return; // Wait for user response
+        if modal.kind == PermissionKind::Network {
+            match modal.selected {
+                0 => self.network_allowed_once = Some(modal.pending_tool_id.clone()),
+                1 => self.network_allowed = true,
+                _ => {
+                    let result = "Permission denied: network access was not granted".to_string();
+                    self.deny_pending_bash(&modal.pending_tool_id, result);
+                    return;
+                }
             }
+            self.process_pending_tools();
+            return;
         }
 
-        // Check if write/edit needs manual approval
-        if !self.auto_accept && (name == "write_file" || name == "edit_file") {
-            let preview_result = if name == "write_file" {
-                tools::preview_write_file(&args)
-            } else {
-                tools::preview_edit_file(&args)
-            };
-
-            match preview_result {
-                Ok((diff_text, new_content)) => {
-                    let path = serde_json::from_str::<Value>(&args)
-                        .ok()
-                        .and_then(|v| v["path"].as_str().map(|s| s.to_string()))
-                        .unwrap_or_default();
-                    self.diff_modal = Some(DiffModal::new(
-                        path, diff_text, new_content, name.clone(), id.clone(),
-                    ));
-                    return; // Wait for user response
+        match modal.selected {
+            0 => {
+                // Allow for project
+                if let Err(e) = SandboxConfig::add_path_project(&modal.path) {
+                    self.temp_allowed_paths.push(modal.path.clone());
+                    self.error = Some(format!("{}; allowed for this session only", e.with_hint()));
                 }
-                Err(e) => {
-                    // Preview failed — send error as tool result
-                    self.pending_tool_calls.remove(0);
-                    self.messages.push(ChatMessage {
-                        role: MessageRole::Tool { name: name.clone(), path: None },
-                        content: e.clone(),
-                    });
-                    self.api_messages.push(json!({
-                        "role": "tool",
-                        "tool_call_id": id,
-                        "content": e
-                    }));
-                    self.process_pending_tools();
-                    return;
+            }
+            1 if !Config::writable() => self.temp_allowed_paths.push(modal.path.clone()),
+            1 => {
+                // Allow globally
+                if let Err(e) = SandboxConfig::add_path_global(&modal.path) {
+                    self.temp_allowed_paths.push(modal.path.clone());
+                    self.error = Some(format!("{}; allowed for this session only", e.with_hint()));
                 }
             }
+            2 => {
+                // Allow once (temp)
+                self.temp_allowed_paths.push(modal.path.clone());
+            }
+            3 => {
+                // Deny - return error to the tool
+                let result = format!("Permission denied: access to {} was not granted", modal.path);
+                self.deny_pending_bash(&modal.pending_tool_id, result);
+                return;
+            }
+            _ => return,
         }
 
-        // Remove from pending and start execution
-        self.pending_tool_calls.remove(0);
-        self.state = AppState::ToolCall(format_tool_call(&name, &args));
+        // Permission granted - continue processing (might need more permissions)
+        self.process_pending_tools();
+    }
+
+    /// Answer the pending bash call with a denial and move on
+    fn deny_pending_bash(&mut self, tool_id: &str, result: String) {
+        self.push_tool_result(tool_id, "bash".to_string(), None, Err(ToolError::new(ErrorKind::PermissionDenied, result)));
+        // Remove the denied call from pending
+        if !self.pending_tool_calls.is_empty() {
+            self.pending_tool_calls.remove(0);
+        }
+        // Continue with remaining tools or finish
+        self.process_pending_tools();
+    }
 
-        // Extract path from args for tools that have it
-        let path = serde_json::from_str::<Value>(&args)
-            .ok()
-            .and_then(|v| v["path"].as_str().map(|s| s.to_string()));
+    pub fn modal_cancel(&mut self) {
+        if self.versions_modal.take().is_some() {
+            return;
+        }
+        if let Some(modal) = &mut self.continue_modal {
+            modal.selected = 1; // Stop
+            self.modal_select();
+            return;
+        }
+        if self.provider_modal.is_some() {
+            self.provider_modal = None;
+            return;
+        }
+        if let Some(modal) = &mut self.match_modal {
+            modal.selected = modal.locations.len(); // Reject
+            self.modal_select();
+            return;
+        }
+        // Treat cancel as reject for diff modal
+        if self.diff_modal.is_some() {
+            if let Some(modal) = &mut self.diff_modal {
+                modal.selected = 1; // Reject
+            }
+            self.modal_select();
+            return;
+        }
+        // Treat cancel as deny
+        if self.permission_modal.is_some() {
+            // Set selected to Deny and call modal_select
+            if let Some(modal) = &mut self.permission_modal {
+                modal.selected = modal.options.len() - 1; // Deny
+            }
+            self.modal_select();
+        }
+    }
 
-        // Spawn tool execution in background
-        let (tx, rx) = mpsc::channel();
-        self.pending_tool_execution = Some(rx);
+    /// Tool calls of this turn not yet run, including one waiting on a modal
+    pub fn queued_tool_calls(&self) -> usize {
+        self.pending_tool_calls.len()
+    }
 
-        let allowed_paths = self.get_all_allowed_paths();
-        let name_clone = name.clone();
-        let args_clone = args.clone();
+    pub fn has_modal(&self) -> bool {
+        self.permission_modal.is_some()
+            || self.diff_modal.is_some()
+            || self.provider_modal.is_some()
+            || self.continue_modal.is_some()
+            || self.match_modal.is_some()
+            || self.versions_modal.is_some()
+    }
 
-        thread::spawn(move || {
-            let result = if name_clone == "bash" {
-                tools::execute_bash_with_paths(&args_clone, &allowed_paths)
-            } else {
-                // For non-bash tools, we need to call them directly
-                // since we can't send the function pointer across threads
-                tools::execute_tool_by_name(&name_clone, &args_clone)
-            };
+    pub fn enter_copy_mode(&mut self) {
+        let targets = copy_targets(&self.messages);
+        if targets.is_empty() {
+            return;
+        }
+        let selected = targets.len() - 1;
+        self.copy_mode = Some(CopyMode { targets, selected });
+    }
 
-            let _ = tx.send(ToolExecutionResult {
-                id,
-                name: name_clone,
-                path,
-                result,
-            });
-        });
+    pub fn open_history_search(&mut self) {
+        let mut search = HistorySearch { query: String::new(), results: Vec::new(), selected: 0 };
+        search.results = self.history_matches("");
+        self.history_search = Some(search);
     }
 
-    pub fn poll_tool_result(&mut self) {
-        let rx = match &self.pending_tool_execution {
-            Some(rx) => rx,
-            None => return,
-        };
+    /// Past prompts matching `query`, best first and the most recent among equals
+    fn history_matches(&self, query: &str) -> Vec<(String, Vec<usize>)> {
+        use fuzzy_matcher::FuzzyMatcher;
+        use fuzzy_matcher::skim::SkimMatcherV2;
 
-        match rx.try_recv() {
-            Ok(tool_result) => {
-                self.pending_tool_execution = None;
+        let matcher = SkimMatcherV2::default().ignore_case();
+        let mut seen = std::collections::HashSet::new();
+        let mut matches: Vec<(i64, &String, Vec<usize>)> = self
+            .history
+            .iter()
+            .rev()
+            .filter(|p| seen.insert(*p))
+            .filter_map(|prompt| {
+                if query.is_empty() {
+                    return Some((0, prompt, Vec::new()));
+                }
+                let (score, indices) = matcher.fuzzy_indices(prompt, query)?;
+                Some((score, prompt, indices))
+            })
+            .collect();
+        matches.sort_by_key(|m| std::cmp::Reverse(m.0)); // Stable, so recency breaks ties
+        matches.into_iter().take(MAX_HISTORY_RESULTS).map(|(_, prompt, indices)| (prompt.clone(), indices)).collect()
+    }
 
-                self.messages.push(ChatMessage {
-                    role: MessageRole::Tool {
-                        name: tool_result.name,
-                        path: tool_result.path,
-                    },
-                    content: tool_result.result.clone(),
-                });
+    pub fn history_search_type(&mut self, c: char) {
+        let Some(query) = self.history_search.as_ref().map(|s| format!("{}{}", s.query, c)) else { return };
+        self.set_history_query(query);
+    }
 
-                self.api_messages.push(json!({
-                    "role": "tool",
-                    "tool_call_id": tool_result.id,
-                    "content": tool_result.result
-                }));
+    pub fn history_search_backspace(&mut self) {
+        let Some(mut query) = self.history_search.as_ref().map(|s| s.query.clone()) else { return };
+        query.pop();
+        self.set_history_query(query);
+    }
 
-                // Process next tool or start API call
-                self.process_pending_tools();
-            }
-            Err(mpsc::TryRecvError::Empty) => {
-                // Still executing, keep waiting
-            }
-            Err(mpsc::TryRecvError::Disconnected) => {
-                // Thread crashed
-                self.pending_tool_execution = None;
-                self.error = Some("Tool execution thread crashed".to_string());
-                self.state = AppState::Idle;
-            }
+    fn set_history_query(&mut self, query: String) {
+        let results = self.history_matches(&query);
+        if let Some(search) = &mut self.history_search {
+            search.query = query;
+            search.results = results;
+            search.selected = 0;
         }
     }
 
-    fn check_bash_permission(&self, args: &str, tool_id: &str) -> Option<PermissionModal> {
-        let json: Value = serde_json::from_str(args).unwrap_or_default();
-        let command = json["command"].as_str().unwrap_or("");
+    /// Move to an older match (Ctrl+R again, or ↑)
+    pub fn history_search_older(&mut self) {
+        if let Some(search) = &mut self.history_search
+            && search.selected + 1 < search.results.len()
+        {
+            search.selected += 1;
+        }
+    }
 
-        if command.is_empty() {
-            return None;
+    pub fn history_search_newer(&mut self) {
+        if let Some(search) = &mut self.history_search {
+            search.selected = search.selected.saturating_sub(1);
         }
+    }
 
-        // Get missing paths considering temp allowed paths
-        let missing = self.get_missing_paths_for_command(command);
+    /// Put the selected prompt in the input, replacing what was typed
+    pub fn history_search_accept(&mut self) {
+        let Some(search) = self.history_search.take() else { return };
+        if let Some((prompt, _)) = search.results.get(search.selected) {
+            self.input = prompt.clone();
+            self.input_cursor = self.input.len();
+            self.history_pos = self.history.len();
+        }
+    }
 
-        if let Some(first_missing) = missing.first() {
-            Some(PermissionModal::new(
-                first_missing.path.clone(),
-                first_missing.reason.clone(),
-                tool_id.to_string(),
-            ))
-        } else {
-            None
+    pub fn exit_copy_mode(&mut self) {
+        self.copy_mode = None;
+        self.scroll_offset = 0;
+    }
+
+    pub fn copy_mode_up(&mut self) {
+        if let Some(mode) = &mut self.copy_mode {
+            mode.selected = mode.selected.saturating_sub(1);
         }
     }
 
-    fn get_missing_paths_for_command(&self, command: &str) -> Vec<sandbox::PathRequest> {
-        let config = SandboxConfig::load_merged();
-        let required = sandbox::detect_required_paths(command);
+    pub fn copy_mode_down(&mut self) {
+        if let Some(mode) = &mut self.copy_mode {
+            mode.selected = (mode.selected + 1).min(mode.targets.len() - 1);
+        }
+    }
 
-        required
-            .into_iter()
-            .filter(|req| {
-                let req_path = std::path::Path::new(&req.path);
-                // Check both config and temp allowed paths
-                !config.allowed_paths.iter().any(|allowed| {
-                    let allowed_path = std::path::Path::new(allowed);
-                    req_path.starts_with(allowed_path) || allowed_path.starts_with(req_path)
-                }) && !self.temp_allowed_paths.iter().any(|allowed| {
-                    let allowed_path = std::path::Path::new(allowed);
-                    req_path.starts_with(allowed_path) || allowed_path.starts_with(req_path)
-                })
-            })
-            .collect()
+    pub fn copy_mode_first(&mut self) {
+        if let Some(mode) = &mut self.copy_mode {
+            mode.selected = 0;
+        }
     }
 
-    fn get_all_allowed_paths(&self) -> Vec<String> {
-        let mut paths = sandbox::get_allowed_paths();
-        paths.extend(self.temp_allowed_paths.clone());
-        paths
+    pub fn copy_mode_last(&mut self) {
+        if let Some(mode) = &mut self.copy_mode {
+            mode.selected = mode.targets.len() - 1;
+        }
     }
 
-    pub fn modal_up(&mut self) {
-        if let Some(modal) = &mut self.provider_modal {
-            if modal.selected > 0 {
-                modal.selected -= 1;
-            }
+    /// Expand or collapse the tool output under the copy-mode selection
+    pub fn copy_mode_toggle_expand(&mut self) {
+        let Some(mode) = &self.copy_mode else { return };
+        let index = mode.targets[mode.selected].message;
+        if !matches!(self.messages[index].role, MessageRole::Tool { .. }) {
             return;
         }
-        if let Some(modal) = &mut self.diff_modal {
-            if modal.selected > 0 {
-                modal.selected -= 1;
-            }
-            return;
+        if !self.expanded_tools.remove(&index) {
+            self.expanded_tools.insert(index);
         }
-        if let Some(modal) = &mut self.permission_modal {
-            if modal.selected > 0 {
-                modal.selected -= 1;
+    }
+
+    /// Copy the selected target to the clipboard and leave copy mode
+    pub fn copy_mode_yank(&mut self) {
+        let Some(mode) = self.copy_mode.take() else { return };
+        let target = &mode.targets[mode.selected];
+        match clipboard::copy(&target.text) {
+            Ok(method) => {
+                self.notice = Some(format!("Copied {} ({} chars, via {})", target.label, target.text.chars().count(), method));
+            }
+            Err(e) => {
+                self.error = Some(format!("Copy failed: {}", e));
             }
         }
+        self.scroll_offset = 0;
     }
 
-    pub fn modal_down(&mut self) {
-        if let Some(modal) = &mut self.provider_modal {
-            if modal.selected + 1 < modal.providers.len() {
-                modal.selected += 1;
+    fn switch_mode(&mut self, arg: &str) {
+        let mode = match arg {
+            "" => {
+                self.notice = Some(format!("In {} mode — /mode ask|coding|coach to switch", mode_name(self.config.mode)));
+                return;
+            }
+            "ask" => Mode::Ask,
+            "coding" => Mode::Coding,
+            "coach" => Mode::Coach,
+            _ => {
+                self.error = Some("Usage: /mode ask|coding|coach".to_string());
+                return;
             }
+        };
+        if self.state != AppState::Idle {
+            self.error = Some("Wait for the current turn to finish before switching modes".to_string());
             return;
         }
-        if let Some(modal) = &mut self.diff_modal {
-            if modal.selected + 1 < modal.options.len() {
-                modal.selected += 1;
+        self.config.mode = mode;
+        self.tool_defs = tools::get_tool_definitions(&mode);
+        let prompt = build_system_prompt(&self.config, &self.session.system_prompt);
+        if let Some(system) = self.api_messages.first_mut() {
+            system["content"] = Value::from(prompt);
+        }
+        self.notice = Some(match mode {
+            Mode::Ask => "Ask mode: read-only tools, no bash or file changes".to_string(),
+            _ => format!("Switched to {} mode", mode_name(mode)),
+        });
+    }
+
+    fn seed_command(&mut self, arg: &str) {
+        match arg {
+            "" => {
+                self.notice = Some(match self.session.seed {
+                    Some(seed) if !api::supports_seed(&self.provider) => {
+                        format!("Seed {} set, but {} ignores it", seed, self.provider_name)
+                    }
+                    Some(seed) => format!("Seed {} — /seed off to clear", seed),
+                    None => "No seed set — /seed <n> makes sampling more repeatable".to_string(),
+                });
+                return;
             }
-            return;
+            "off" => {
+                self.session.seed = None;
+                self.notice = Some("Seed cleared".to_string());
+            }
+            _ => match arg.parse::<u64>() {
+                Ok(seed) => {
+                    self.session.seed = Some(seed);
+                    self.notice = Some(format!("Seed {} will be sent with each request", seed));
+                }
+                Err(_) => {
+                    self.error = Some("Usage: /seed [n|off]".to_string());
+                    return;
+                }
+            },
         }
-        if let Some(modal) = &mut self.permission_modal {
-            if modal.selected + 1 < modal.options.len() {
-                modal.selected += 1;
+        self.save_session();
+    }
+
+    /// List, set or unset the preferences added to every system prompt. Saved to the
+    /// global config, so they follow the user across projects and sessions.
+    fn prefs_command(&mut self, arg: &str) {
+        let usage = "Usage: /prefs [set <name> <value> | unset <name>]";
+        let mut parts = arg.splitn(3, char::is_whitespace);
+        let notice = match (parts.next().unwrap_or(""), parts.next(), parts.next().map(str::trim)) {
+            ("", None, None) => {
+                let content = if self.config.preferences.is_empty() {
+                    "No preferences set. `/prefs set verbosity terse` adds one to every system prompt.".to_string()
+                } else {
+                    let lines: Vec<String> = self.config.preferences.iter().map(|(k, v)| format!("- **{}**: {}", k, v)).collect();
+                    format!("**Preferences** (sent with every system prompt):\n\n{}", lines.join("\n"))
+                };
+                self.messages.push(ChatMessage { role: MessageRole::Assistant, content });
+                self.scroll_offset = 0;
+                return;
+            }
+            ("set", Some(name), Some(value)) if !value.is_empty() && !name.is_empty() => {
+                self.config.preferences.insert(name.to_string(), value.to_string());
+                format!("Preference {} set to \"{}\"", name, value)
+            }
+            ("unset", Some(name), None) => match self.config.preferences.remove(name) {
+                Some(_) => format!("Preference {} removed", name),
+                None => {
+                    self.error = Some(format!("No preference named {}", name));
+                    return;
+                }
+            },
+            _ => {
+                self.error = Some(usage.to_string());
+                return;
             }
+        };
+        // With a read-only config dir the change still applies for this session
+        if let Err(e) = self.config.save()
+            && Config::writable()
+        {
+            self.error = Some(format!("Failed to save preferences: {}", e));
+            return;
+        }
+        let prompt = build_system_prompt(&self.config, &self.session.system_prompt);
+        if let Some(system) = self.api_messages.first_mut() {
+            system["content"] = Value::from(prompt);
         }
+        self.notice = Some(notice);
     }
 
-    pub fn diff_modal_scroll_up(&mut self) {
-        if let Some(modal) = &mut self.diff_modal {
-            modal.scroll_offset = modal.scroll_offset.saturating_add(1);
+    /// Token usage and the per-request cost of tool schemas; `compact on|off` switches
+    /// the current provider to shortened schemas
+    fn stats_command(&mut self, arg: &str) {
+        match arg {
+            "" => {}
+            "compact on" | "compact off" => {
+                let compact = arg == "compact on";
+                if let Some(provider) = self.config.providers.get_mut(&self.provider_name) {
+                    provider.compact_tools = compact;
+                    self.provider = provider.clone();
+                }
+                let _ = self.config.save();
+                self.notice = Some(format!(
+                    "{} tool schemas for {}",
+                    if compact { "Compact" } else { "Full" },
+                    self.provider_name
+                ));
+                return;
+            }
+            _ => {
+                self.error = Some("Usage: /stats [compact on|off]".to_string());
+                return;
+            }
         }
+
+        use crate::usage::tokens_for;
+        let size = |defs: &[Value]| defs.iter().map(|d| d.to_string().len()).sum::<usize>();
+        let full = size(&self.tool_defs);
+        let compact = size(&tools::compact_definitions(&self.tool_defs));
+        let sent = if self.provider.compact_tools { compact } else { full };
+        let mut lines = vec![
+            format!("**Stats** — {} ({})", self.provider_name, self.provider.model),
+            String::new(),
+            format!(
+                "- Tool schemas: {} tools, {} bytes (~{} tokens) per request, {}",
+                self.tool_defs.len(),
+                sent,
+                tokens_for(sent),
+                if self.provider.compact_tools { "compact" } else { "full" }
+            ),
+        ];
+        if self.provider.compact_tools {
+            lines.push(format!("- Full schemas would be {} bytes (~{} tokens); `/stats compact off`", full, tokens_for(full)));
+        } else if let Some(ratio) = (compact * 100).checked_div(full) {
+            lines.push(format!(
+                "- Compact schemas would be {} bytes (~{} tokens, {}% smaller); `/stats compact on`",
+                compact,
+                tokens_for(compact),
+                100 - ratio
+            ));
+        }
+        let system = self.api_messages.first().and_then(|m| m["content"].as_str()).map_or(0, str::len);
+        lines.push(format!("- System prompt: {} bytes (~{} tokens)", system, tokens_for(system)));
+        if let Some((prompt, completion)) = self.token_usage {
+            let share = (tokens_for(sent) * 100).checked_div(prompt as usize).unwrap_or(0);
+            lines.push(format!(
+                "- Last request: {} prompt + {} completion tokens; tool schemas ~{}% of the prompt",
+                prompt, completion, share
+            ));
+        }
+        if let Some((read, write)) = self.cache_usage {
+            lines.push(format!("- Prompt cache: {} tokens read, {} written", read, write));
+        }
+        if self.turn_tokens != (0, 0) {
+            lines.push(format!("- This turn: {} prompt + {} completion tokens", self.turn_tokens.0, self.turn_tokens.1));
+        }
+        self.messages.push(ChatMessage {
+            role: MessageRole::Assistant,
+            content: lines.join("\n"),
+        });
+        self.scroll_offset = 0;
     }
 
-    pub fn diff_modal_scroll_down(&mut self) {
-        if let Some(modal) = &mut self.diff_modal {
-            modal.scroll_offset = modal.scroll_offset.saturating_sub(1);
+    /// /usage [tools]: estimated prompt tokens by source, or per tool
+    fn usage_command(&mut self, arg: &str) {
+        if !matches!(arg, "" | "tools") {
+            self.error = Some("Usage: /usage [tools]".to_string());
+            return;
         }
+        let schemas = if self.provider.compact_tools { tools::compact_definitions(&self.tool_defs) } else { self.tool_defs.clone() };
+        let schema_bytes = schemas.iter().map(|d| d.to_string().len()).sum();
+        self.messages.push(ChatMessage {
+            role: MessageRole::Assistant,
+            content: crate::usage::report(&self.api_messages, &self.session.turns, schema_bytes, arg == "tools"),
+        });
+        self.scroll_offset = 0;
     }
 
-    pub fn diff_modal_page_up(&mut self) {
-        if let Some(modal) = &mut self.diff_modal {
-            modal.scroll_offset = modal.scroll_offset.saturating_add(10);
+    fn checkpoint(&mut self, label: &str) {
+        if self.state != AppState::Idle {
+            self.error = Some("Wait for the current turn to finish before checkpointing".to_string());
+            return;
         }
+        self.session.checkpoints.push(Checkpoint {
+            label: label.to_string(),
+            created_at: chrono::Utc::now().timestamp(),
+            messages: self.messages.len(),
+            api_messages: self.api_messages.len(),
+        });
+        self.notice = Some(format!(
+            "Checkpoint {} saved — /rewind {} returns here",
+            self.session.checkpoints.len(),
+            self.session.checkpoints.len()
+        ));
+        self.save_session();
     }
 
-    pub fn diff_modal_page_down(&mut self) {
-        if let Some(modal) = &mut self.diff_modal {
-            modal.scroll_offset = modal.scroll_offset.saturating_sub(10);
+    /// Indices of the last user message in messages and in api_messages
+    fn last_user_turn(&self) -> Option<(usize, usize)> {
+        let message = self.messages.iter().rposition(|m| m.role == MessageRole::User)?;
+        let api_message = self.api_messages.iter().rposition(|m| m["role"] == "user")?;
+        Some((message, api_message))
+    }
+
+    /// Truncate the transcript to `keep` messages and the API history to `api_keep`.
+    /// Returns whether the dropped part changed any files.
+    fn drop_reply(&mut self, keep: usize, api_keep: usize) -> bool {
+        let changed_files = self.messages[keep.min(self.messages.len())..]
+            .iter()
+            .any(|m| matches!(&m.role, MessageRole::Tool { name, .. } if tools::is_file_change(name)));
+        self.messages.truncate(keep);
+        self.api_messages.truncate(api_keep.max(1));
+        self.session.turns.retain(|t| t.api_message < api_keep);
+        // Bookmarks and checkpoints into the dropped reply would point at whatever comes next
+        self.session.bookmarks.retain(|b| b.message < keep);
+        self.session.checkpoints.retain(|c| c.messages <= keep && c.api_messages <= api_keep.max(1));
+        self.expanded_tools.retain(|&i| i < keep);
+        self.copy_mode = None;
+        self.token_usage = None;
+        self.cache_usage = None;
+        changed_files
+    }
+
+    /// /retry: send the last user message again in place of its reply
+    fn retry(&mut self) {
+        if self.state != AppState::Idle {
+            self.error = Some("Wait for the current turn to finish before retrying".to_string());
+            return;
+        }
+        let Some((message, api_message)) = self.last_user_turn() else {
+            self.error = Some("No message to retry".to_string());
+            return;
+        };
+        // Keep the attachment lines shown under the message, when we know where they end
+        let keep = if self.reply_start > message && self.reply_start <= self.messages.len() {
+            self.reply_start
+        } else {
+            message + 1
+        };
+        let changed_files = self.drop_reply(keep, api_message + 1);
+        self.reply_start = self.messages.len();
+        if changed_files {
+            self.notice = Some("Retrying — file changes from the previous reply are kept; /undo reverts them".to_string());
         }
+
+        self.turn_tool_calls = 0;
+        self.turn_tokens = (0, 0);
+        self.history_repaired = false;
+        self.wrapping_up = false;
+        self.turn_started = Instant::now();
+        self.reset_turn_limits();
+        self.state = AppState::Thinking;
+        self.start_api_call();
     }
 
-    pub fn modal_select(&mut self) {
-        // Handle provider modal
-        if let Some(modal) = self.provider_modal.take() {
-            let name = modal.providers[modal.selected].clone();
-            if let Some(new_provider) = self.config.providers.get(&name).cloned() {
-                let key = new_provider
-                    .api_key
-                    .clone()
-                    .or_else(|| std::env::var(&new_provider.api_key_env).ok());
-                self.config.default_provider = name.clone();
-                self.provider = new_provider.clone();
-                let _ = self.config.save();
-                if let Some(key) = key {
-                    self.api_key = key;
-                    self.messages.push(ChatMessage {
-                        role: MessageRole::Assistant,
-                        content: format!("Switched to **{}** ({})", name, self.provider.model),
-                    });
-                } else {
-                    self.api_key = String::new();
-                    self.messages.push(ChatMessage {
-                        role: MessageRole::Assistant,
-                        content: format!("Switched to **{}** ({}). No API key set — use `/key <key>` or set ${}", name, self.provider.model, new_provider.api_key_env),
-                    });
-                }
-            }
+    /// /edit-last: move the last user message back into the input, dropping it and its reply
+    fn edit_last(&mut self) {
+        if self.state != AppState::Idle {
+            self.error = Some("Wait for the current turn to finish before editing".to_string());
+            return;
+        }
+        let Some((message, api_message)) = self.last_user_turn() else {
+            self.error = Some("No message to edit".to_string());
             return;
+        };
+        let text = self.messages[message].content.clone();
+        let changed_files = self.drop_reply(message, api_message);
+        self.save_session();
+        self.input = text;
+        self.input_cursor = self.input.len();
+        if changed_files {
+            self.notice = Some("File changes from the dropped reply are kept; /undo reverts them".to_string());
         }
+    }
 
-        // Handle diff modal
-        if let Some(modal) = self.diff_modal.take() {
-            // Remove from pending
-            if !self.pending_tool_calls.is_empty() {
-                self.pending_tool_calls.remove(0);
+    /// Roll back to checkpoint `n` (default: the latest) in a new branch session.
+    /// The current session is saved first and kept as-is.
+    fn rewind(&mut self, arg: &str) {
+        if self.state != AppState::Idle {
+            self.error = Some("Wait for the current turn to finish before rewinding".to_string());
+            return;
+        }
+        let count = self.session.checkpoints.len();
+        if count == 0 {
+            self.error = Some("No checkpoints in this session — use /checkpoint first".to_string());
+            return;
+        }
+        let n = if arg.is_empty() {
+            count
+        } else {
+            match arg.parse::<usize>() {
+                Ok(n) if (1..=count).contains(&n) => n,
+                _ => {
+                    self.error = Some(format!("Usage: /rewind [1-{}]", count));
+                    return;
+                }
             }
+        };
 
-            if modal.selected == 0 {
-                // Accept — apply the write
-                tools::apply_write(&modal.path, &modal.new_content);
-                // Show diff in chat (same as auto-accept would)
-                self.messages.push(ChatMessage {
-                    role: MessageRole::Tool {
-                        name: modal.tool_name.clone(),
-                        path: Some(modal.path.clone()),
-                    },
-                    content: modal.diff_text.clone(),
-                });
-                self.api_messages.push(json!({
-                    "role": "tool",
-                    "tool_call_id": modal.pending_tool_id,
-                    "content": modal.diff_text
-                }));
-            } else {
-                // Reject
-                let result = format!("REJECTED: User rejected changes to {}. Do not attempt to write this file through bash, echo, or any other method. Ask the user what they want instead.", modal.path);
-                self.messages.push(ChatMessage {
-                    role: MessageRole::Tool {
-                        name: modal.tool_name.clone(),
-                        path: None,
-                    },
-                    content: result.clone(),
-                });
-                self.api_messages.push(json!({
-                    "role": "tool",
-                    "tool_call_id": modal.pending_tool_id,
-                    "content": result
-                }));
+        self.save_session();
+        let checkpoint = self.session.checkpoints[n - 1].clone();
+        let branch = self.session.fork(&checkpoint);
+        let parent = std::mem::replace(&mut self.session, branch);
+
+        self.messages.truncate(checkpoint.messages);
+        self.api_messages.truncate(checkpoint.api_messages.max(1));
+        self.expanded_tools.retain(|&i| i < checkpoint.messages);
+        self.copy_mode = None;
+        self.token_usage = None;
+        self.cache_usage = None;
+        self.notice = Some(format!(
+            "Rewound to checkpoint {}{} in new session {} — original kept as {}",
+            n,
+            if checkpoint.label.is_empty() { String::new() } else { format!(" ({})", checkpoint.label) },
+            self.session.id,
+            parent.id
+        ));
+        self.save_session();
+    }
+
+    /// Open the version viewer for `path`, or the only changed file when no path is given
+    fn open_versions(&mut self, path: &str) {
+        let path = match (path, crate::undo::changes().as_slice()) {
+            ("", []) => {
+                self.error = Some("No files changed yet".to_string());
+                return;
             }
+            ("", [(only, _, _)]) => only.clone(),
+            ("", files) => {
+                let names: Vec<&str> = files.iter().map(|(p, _, _)| p.as_str()).collect();
+                self.error = Some(format!("Usage: /versions <path> — changed: {}", names.join(", ")));
+                return;
+            }
+            (path, _) => path.to_string(),
+        };
+        let versions = crate::undo::versions(&path);
+        if versions.is_empty() {
+            self.error = Some(format!("hal hasn't changed {} this session", path));
+            return;
+        }
+        let selected = versions.len() - 1;
+        self.versions_modal = Some(VersionsModal { path, versions, selected, scroll: 0 });
+    }
 
-            // Continue with remaining tools or next API call
-            self.process_pending_tools();
+    /// Enter in the version viewer: write the selected version back, through the undo journal
+    fn restore_version(&mut self, modal: VersionsModal) {
+        let version = &modal.versions[modal.selected];
+        let Some(content) = &version.content else {
+            self.error = Some(format!("{} didn't exist in that version; /undo back to it instead", modal.path));
+            return;
+        };
+        if std::fs::read_to_string(&modal.path).is_ok_and(|current| current == *content) {
+            self.notice = Some(format!("{} is already at that version", modal.path));
+            return;
+        }
+        match crate::undo::write(&modal.path, content) {
+            Ok(()) => self.notice = Some(format!("Restored {} to version {} — /undo reverts this", modal.path, modal.selected + 1)),
+            Err(e) => self.error = Some(format!("Failed to restore {}: {}", modal.path, e)),
+        }
+    }
+
+    /// Continue the conversation in a new session pinned to another provider (and model),
+    /// to compare how models handle the same context. The original is pinned to the
+    /// current provider so `/load` returns to it unchanged.
+    fn fork_command(&mut self, arg: &str) {
+        if self.state != AppState::Idle {
+            self.error = Some("Wait for the current turn to finish before forking".to_string());
+            return;
+        }
+        let mut parts = arg.split_whitespace();
+        let (Some(name), model, None) = (parts.next(), parts.next(), parts.next()) else {
+            self.error = Some("Usage: /fork <provider> [model]".to_string());
+            return;
+        };
+        let Some(mut provider) = self.config.providers.get(name).cloned() else {
+            let mut names: Vec<&str> = self.config.providers.keys().map(String::as_str).collect();
+            names.sort();
+            self.error = Some(format!("Unknown provider: {} (configured: {})", name, names.join(", ")));
+            return;
+        };
+        if let Some(model) = model {
+            provider.model = model.to_string();
+        }
+        let Some(key) = provider.resolve_api_key() else {
+            self.error = Some(format!("No API key for {} — set ${}", name, provider.api_key_env));
+            return;
+        };
+        if self.messages.is_empty() {
+            self.error = Some("Nothing to fork yet — send a message first".to_string());
             return;
         }
 
-        // Handle permission modal
-        let modal = match self.permission_modal.take() {
-            Some(m) => m,
-            None => return,
+        if self.session.provider.is_none() {
+            self.session.provider = Some(self.provider_name.clone());
+            self.session.model = Some(self.provider.model.clone());
+        }
+        self.save_session();
+        let everything = Checkpoint {
+            label: String::new(),
+            created_at: chrono::Utc::now().timestamp(),
+            messages: self.messages.len(),
+            api_messages: self.api_messages.len(),
         };
+        let mut branch = self.session.fork(&everything);
+        branch.provider = Some(name.to_string());
+        branch.model = Some(provider.model.clone());
+        let parent = std::mem::replace(&mut self.session, branch);
+
+        self.provider_name = name.to_string();
+        self.provider = provider;
+        self.api_key = key;
+        self.token_usage = None;
+        self.cache_usage = None;
+        self.notice = Some(format!(
+            "Forked to {} ({}) in new session {} — /load {} returns to the original",
+            name, self.provider.model, self.session.id, parent.id
+        ));
+        self.save_session();
+    }
 
-        match modal.selected {
-            0 => {
-                // Allow for project
-                if let Err(e) = SandboxConfig::add_path_project(&modal.path) {
-                    self.error = Some(format!("Failed to save: {}", e));
-                }
-            }
-            1 => {
-                // Allow globally
-                if let Err(e) = SandboxConfig::add_path_global(&modal.path) {
-                    self.error = Some(format!("Failed to save: {}", e));
-                }
-            }
-            2 => {
-                // Allow once (temp)
-                self.temp_allowed_paths.push(modal.path.clone());
+    fn draft_command(&mut self, arg: &str) {
+        match arg {
+            "" => {
+                let draft = crate::draft::load();
+                let content = if draft.trim().is_empty() {
+                    "Draft is empty. `/draft <text>` adds a note, `a` in copy mode adds the selected message.".to_string()
+                } else {
+                    format!("**Draft:**\n\n{}\n\n*`/draft insert` includes it in your next message, `/draft clear` empties it.*", draft.trim())
+                };
+                self.messages.push(ChatMessage { role: MessageRole::Assistant, content });
             }
-            3 => {
-                // Deny - return error to the tool
-                let result = format!("Permission denied: access to {} was not granted", modal.path);
-                self.messages.push(ChatMessage {
-                    role: MessageRole::Tool { name: "bash".to_string(), path: None },
-                    content: result.clone(),
-                });
-                self.api_messages.push(json!({
-                    "role": "tool",
-                    "tool_call_id": modal.pending_tool_id,
-                    "content": result
-                }));
-                // Remove the denied call from pending
-                if !self.pending_tool_calls.is_empty() {
-                    self.pending_tool_calls.remove(0);
-                }
-                // Continue with remaining tools or finish
-                if self.pending_tool_calls.is_empty() {
-                    self.state = AppState::Thinking;
-                    self.start_api_call();
+            "insert" => {
+                if crate::draft::load().trim().is_empty() {
+                    self.error = Some("Draft is empty".to_string());
                 } else {
-                    self.process_pending_tools();
+                    self.insert_draft = true;
+                    self.notice = Some("Draft will be included in your next message".to_string());
                 }
-                return;
             }
-            _ => return,
+            "clear" => match crate::draft::clear() {
+                Ok(()) => {
+                    self.insert_draft = false;
+                    self.notice = Some("Draft cleared".to_string());
+                }
+                Err(e) => self.error = Some(e),
+            },
+            text => match crate::draft::append(text) {
+                Ok(()) => self.notice = Some("Added to draft".to_string()),
+                Err(e) => self.error = Some(e),
+            },
         }
-
-        // Permission granted - continue processing (might need more permissions)
-        self.process_pending_tools();
     }
 
-    pub fn modal_cancel(&mut self) {
-        if self.provider_modal.is_some() {
-            self.provider_modal = None;
-            return;
+    /// Append the selected copy-mode target to the draft
+    pub fn copy_mode_append_draft(&mut self) {
+        let Some(mode) = &self.copy_mode else { return };
+        let text = mode.targets[mode.selected].text.clone();
+        match crate::draft::append(&text) {
+            Ok(()) => self.notice = Some("Added to draft — /draft to view".to_string()),
+            Err(e) => self.error = Some(e),
         }
-        // Treat cancel as reject for diff modal
-        if self.diff_modal.is_some() {
-            if let Some(modal) = &mut self.diff_modal {
-                modal.selected = 1; // Reject
-            }
-            self.modal_select();
-            return;
+    }
+
+    /// Bookmark the selected message in copy mode (or remove its bookmark). Text in the
+    /// input box becomes the note.
+    pub fn copy_mode_toggle_bookmark(&mut self) {
+        let Some(mode) = &self.copy_mode else { return };
+        let message = mode.targets[mode.selected].message;
+        if let Some(pos) = self.session.bookmarks.iter().position(|b| b.message == message) {
+            self.session.bookmarks.remove(pos);
+            self.notice = Some("Bookmark removed".to_string());
+        } else {
+            let note = self.input.trim().to_string();
+            self.input.clear();
+            self.input_cursor = 0;
+            self.session.bookmarks.push(Bookmark { message, note });
+            self.session.bookmarks.sort_by_key(|b| b.message);
+            self.notice = Some("Bookmarked — /bookmarks to list, n/N in copy mode to jump".to_string());
         }
-        // Treat cancel as deny
-        if self.permission_modal.is_some() {
-            // Set selected to Deny and call modal_select
-            if let Some(modal) = &mut self.permission_modal {
-                modal.selected = 3; // Deny
-            }
-            self.modal_select();
+        self.save_session();
+    }
+
+    /// Move the copy-mode selection to the next (or previous) bookmarked message
+    pub fn copy_mode_jump_bookmark(&mut self, forward: bool) {
+        let Some(mode) = &mut self.copy_mode else { return };
+        let current = mode.targets[mode.selected].message;
+        let next = if forward {
+            self.session.bookmarks.iter().map(|b| b.message).find(|&m| m > current)
+        } else {
+            self.session.bookmarks.iter().rev().map(|b| b.message).find(|&m| m < current)
+        };
+        if let Some(message) = next
+            && let Some(index) = mode.targets.iter().position(|t| t.message == message)
+        {
+            mode.selected = index;
         }
     }
 
-    pub fn has_modal(&self) -> bool {
-        self.permission_modal.is_some() || self.diff_modal.is_some() || self.provider_modal.is_some()
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        &self.session.bookmarks
     }
 
     pub fn insert_char(&mut self, c: char) {
@@ -1124,6 +3230,118 @@ impl App {
         }
     }
 
+    pub fn move_cursor_home(&mut self) {
+        self.input_cursor = 0;
+    }
+
+    pub fn move_cursor_end(&mut self) {
+        self.input_cursor = self.input.len();
+    }
+
+    pub fn move_word_forward(&mut self) {
+        self.input_cursor = next_word_start(&self.input, self.input_cursor);
+    }
+
+    pub fn move_word_back(&mut self) {
+        self.input_cursor = prev_word_start(&self.input, self.input_cursor);
+    }
+
+    pub fn move_word_end(&mut self) {
+        self.input_cursor = word_end(&self.input, self.input_cursor);
+    }
+
+    /// Delete from the start of the previous word to the cursor (Ctrl+W)
+    pub fn delete_word_back(&mut self) {
+        let start = prev_word_start(&self.input, self.input_cursor);
+        self.input.replace_range(start..self.input_cursor, "");
+        self.input_cursor = start;
+        self.deactivate_picker();
+    }
+
+    /// Delete from the cursor to the end of the input (Ctrl+K)
+    pub fn kill_to_end(&mut self) {
+        self.input.truncate(self.input_cursor);
+        self.deactivate_picker();
+    }
+
+    pub fn is_vim_normal(&self) -> bool {
+        self.config.keybindings == Keybindings::Vim && self.vim_mode == VimMode::Normal
+    }
+
+    pub fn enter_vim_normal(&mut self) {
+        if self.vim_mode == VimMode::Normal {
+            return;
+        }
+        self.vim_mode = VimMode::Normal;
+        self.vim_pending = None;
+        // Like vim, the cursor steps back onto the last inserted char
+        if self.input_cursor == self.input.len() {
+            self.move_cursor_left();
+        }
+    }
+
+    pub fn enter_vim_insert(&mut self) {
+        self.vim_mode = VimMode::Insert;
+        self.vim_pending = None;
+    }
+
+    /// Handle a key press in vim normal mode
+    pub fn vim_normal_key(&mut self, c: char) {
+        if let Some(pending) = self.vim_pending.take() {
+            match (pending, c) {
+                ('d', 'd') => {
+                    self.input.clear();
+                    self.input_cursor = 0;
+                }
+                ('c', 'w') => {
+                    // Change the rest of the run of same-class chars under the cursor
+                    let rest = &self.input[self.input_cursor..];
+                    let class = rest.chars().next().map(char_class);
+                    let len: usize = rest
+                        .chars()
+                        .take_while(|&ch| Some(char_class(ch)) == class)
+                        .map(char::len_utf8)
+                        .sum();
+                    self.input.replace_range(self.input_cursor..self.input_cursor + len, "");
+                    self.enter_vim_insert();
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        match c {
+            'h' => self.move_cursor_left(),
+            'l' => self.move_cursor_right(),
+            '0' => self.move_cursor_home(),
+            '$' => self.move_cursor_end(),
+            'w' => self.move_word_forward(),
+            'b' => self.move_word_back(),
+            'e' => self.move_word_end(),
+            'k' => self.history_up(),
+            'j' => self.history_down(),
+            'x' if self.input_cursor < self.input.len() => {
+                self.input.remove(self.input_cursor);
+            }
+            'D' => self.kill_to_end(),
+            'd' | 'c' => self.vim_pending = Some(c),
+            'i' => self.enter_vim_insert(),
+            'a' => {
+                self.move_cursor_right();
+                self.enter_vim_insert();
+            }
+            'I' => {
+                self.move_cursor_home();
+                self.enter_vim_insert();
+            }
+            'A' => {
+                self.move_cursor_end();
+                self.enter_vim_insert();
+            }
+            _ => {}
+        }
+    }
+
     pub fn history_up(&mut self) {
         if self.picker_mode != PickerMode::None {
             if self.picker_selected > 0 {
@@ -1160,13 +3378,15 @@ impl App {
             return;
         }
 
-        if let Some(item) = self.picker_results.get(self.picker_selected).cloned() {
+        if let Some((item, _)) = self.picker_results.get(self.picker_selected).cloned() {
             match self.picker_mode {
                 PickerMode::Files => {
-                    // Find the @ position and replace from there
+                    // Find the @ position and replace from there, keeping a typed ":start-end"
                     if let Some(at_pos) = self.input[..self.input_cursor].rfind('@') {
-                        self.input.replace_range(at_pos.., &format!("@{}", item));
-                        self.input_cursor = at_pos + 1 + item.len();
+                        let range = self.picker_query.find(':').map_or("", |i| &self.picker_query[i..]);
+                        let reference = format!("@{}{}", item, range);
+                        self.input.replace_range(at_pos.., &reference);
+                        self.input_cursor = at_pos + reference.len();
                     }
                 }
                 PickerMode::Commands => {
@@ -1219,10 +3439,25 @@ impl App {
             PickerMode::Commands => get_commands(),
             PickerMode::None => return,
         };
-        self.picker_results = filter_items(&items, &self.picker_query, MAX_PICKER_ITEMS);
+        // A ":start-end" suffix on a file query is a line range, not part of the name
+        let query = match self.picker_mode {
+            PickerMode::Files => self.picker_query.split(':').next().unwrap_or(""),
+            _ => &self.picker_query,
+        };
+        let recent = if self.picker_mode == PickerMode::Files { &self.recent_files[..] } else { &[] };
+        self.picker_results = filter_items(&items, query, recent, MAX_PICKER_ITEMS);
         self.picker_selected = self.picker_selected.min(self.picker_results.len().saturating_sub(1));
     }
 
+    /// Remember an attached path so the file picker ranks it higher
+    fn note_recent_file(&mut self, reference: &str) {
+        let path = split_line_range(reference).map_or(reference, |(path, _)| path);
+        let path = if Path::new(path).is_dir() && !path.ends_with('/') { format!("{}/", path) } else { path.to_string() };
+        self.recent_files.retain(|p| *p != path);
+        self.recent_files.insert(0, path);
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+
     pub fn picker_active(&self) -> bool {
         self.picker_mode != PickerMode::None
     }
@@ -1240,48 +3475,474 @@ impl App {
             self.deactivate_picker();
         }
         let cleaned = text.replace('\n', " ").replace('\r', "");
+        let start = self.input_cursor;
         self.input.insert_str(self.input_cursor, &cleaned);
         self.input_cursor += cleaned.len();
+
+        // Paths under the working directory become relative; @ references can't hold spaces
+        let cwd = std::env::current_dir().map(|d| crate::paths::canonical(&d)).unwrap_or_default();
+        let references: Option<Vec<String>> = crate::paths::dropped(text).and_then(|paths| {
+            paths
+                .iter()
+                .map(|p| {
+                    let p = crate::paths::canonical(p);
+                    let shown = crate::paths::display(p.strip_prefix(&cwd).unwrap_or(&p));
+                    (!shown.contains(char::is_whitespace)).then(|| format!("@{}", shown))
+                })
+                .collect()
+        });
+        self.pasted_paths = references.map(|references| PastedPaths { input: self.input.clone(), range: start..self.input_cursor, references });
+    }
+
+    /// Estimated size of the request the typed message would send, recomputed when the
+    /// input or the history changes. None for an empty input or a command.
+    pub fn input_estimate(&mut self) -> Option<&crate::usage::Estimate> {
+        if self.input.trim().is_empty() || self.input.starts_with('/') {
+            return None;
+        }
+        let stale = self.input_estimate.as_ref().is_none_or(|(input, len, _)| *input != self.input || *len != self.api_messages.len());
+        if stale {
+            let schema_bytes = self.tool_defs.iter().map(|d| d.to_string().len()).sum();
+            let estimate = self.estimator.estimate(&self.input, &self.api_messages, schema_bytes);
+            self.input_estimate = Some((self.input.clone(), self.api_messages.len(), estimate));
+        }
+        self.input_estimate.as_ref().map(|(_, _, estimate)| estimate)
+    }
+
+    /// @ references offered for a paste of dropped files, while the input is untouched
+    pub fn pasted_paths(&self) -> Option<&[String]> {
+        self.pasted_paths.as_ref().filter(|p| p.input == self.input).map(|p| p.references.as_slice())
+    }
+
+    /// Tab after dropping files: replace the pasted paths with @ references
+    pub fn attach_pasted_paths(&mut self) {
+        let Some(pasted) = self.pasted_paths.take().filter(|p| p.input == self.input) else { return };
+        let references = pasted.references.join(" ") + " ";
+        self.input.replace_range(pasted.range.clone(), &references);
+        self.input_cursor = pasted.range.start + references.len();
+    }
+}
+
+/// The system prompt for the current mode, with a note on the detected build system,
+/// HAL.md context, the project prompt, the user's /prefs and any prompt the session was seeded with
+fn build_system_prompt(config: &Config, session_prompt: &str) -> String {
+    let mut system_prompt = match config.system_prompt_override(config.mode) {
+        Some(custom) => expand_prompt_vars(custom),
+        None => get_system_prompt(&config.mode).to_string(),
+    };
+    if config.mode != Mode::Coach
+        && let Some(note) = crate::project::detect(Path::new("."))
+    {
+        system_prompt.push_str("\n\n");
+        system_prompt.push_str(&note);
+    }
+    if config.mode != Mode::Coach
+        && let Some(context) = load_context_file()
+    {
+        system_prompt.push_str("\n\n## Project Context\n\n");
+        system_prompt.push_str(&context);
+    }
+    if let Some(extra) = config.project_prompt() {
+        system_prompt.push_str("\n\n");
+        system_prompt.push_str(&expand_prompt_vars(extra));
+    }
+    if !config.preferences.is_empty() {
+        system_prompt.push_str("\n\n## User Preferences\n\nThe user set these for every session; follow them unless asked otherwise:");
+        for (key, value) in &config.preferences {
+            system_prompt.push_str(&format!("\n- {}: {}", key, value));
+        }
+    }
+    if !session_prompt.is_empty() {
+        system_prompt.push_str("\n\n");
+        system_prompt.push_str(session_prompt);
+    }
+    system_prompt
+}
+
+/// The provider a session was pinned to by /fork, with its model
+fn pinned_provider(config: &Config, session: &Session) -> Option<(String, Provider)> {
+    let name = session.provider.as_ref()?;
+    let mut provider = config.providers.get(name)?.clone();
+    if let Some(model) = &session.model {
+        provider.model = model.clone();
+    }
+    Some((name.clone(), provider))
+}
+
+/// Banner for a loaded session: when it was last active, relative and absolute
+fn resumed_notice(session: &Session) -> String {
+    let title = session.display_title();
+    format!(
+        "Resumed \"{}\" — last active {} ({})",
+        title,
+        session::relative_time(session.updated_at),
+        session::local_time(session.updated_at)
+    )
+}
+
+/// Whether the mode's tool set has `name`
+fn offers_tool(tool_defs: &[Value], name: &str) -> bool {
+    tool_defs.iter().any(|t| t["function"]["name"] == name)
+}
+
+/// How many pending calls run together: the first, plus the parallel-safe calls right
+/// after it, up to `max`. A call the mode doesn't offer ends the batch, so it's refused
+/// on its own next.
+fn batch_len(pending: &[(String, String, String)], tool_defs: &[Value], max: usize) -> usize {
+    let Some((_, first, _)) = pending.first() else { return 0 };
+    if !tools::is_parallel_safe(first) {
+        return 1;
+    }
+    let batch = pending.iter().take(max.max(1)).take_while(|(_, name, _)| tools::is_parallel_safe(name) && offers_tool(tool_defs, name));
+    batch.count().max(1)
+}
+
+pub fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Coding => "coding",
+        Mode::Ask => "ask",
+        Mode::Coach => "coach",
     }
 }
 
+/// Fill in {{cwd}}, {{os}} and {{date}} in a configured prompt
+fn expand_prompt_vars(prompt: &str) -> String {
+    let cwd = std::env::current_dir().map(|p| p.display().to_string()).unwrap_or_default();
+    prompt
+        .replace("{{cwd}}", &cwd)
+        .replace("{{os}}", std::env::consts::OS)
+        .replace("{{date}}", &chrono::Local::now().format("%Y-%m-%d").to_string())
+}
+
 fn get_system_prompt(mode: &Mode) -> &'static str {
     match mode {
         Mode::Coding => {
             "You are a coding agent with file access. Be concise. Use grep to locate code, then read specific line ranges when needed. When you complete a task using tools, briefly state what you did and stop. The user can see all tool outputs including file diffs, so NEVER repeat code in markdown blocks after using `write_file` or `edit_file`, not even when summarizing what was done after completing a task. For build commands (cargo build, npm run, etc.), use `2>&1 | tail -30` by default. If you need to find specific errors in verbose output, use `2>&1 | grep -i error` instead."
         }
+        Mode::Ask => {
+            "You are a read-only assistant for exploring a codebase. You can read files, list directories and search, but cannot modify files or run commands. Be concise. Use grep to locate code, then read specific line ranges. Answer questions and explain code; if a change is needed, describe it instead of making it."
+        }
         Mode::Coach => {
             "You are a productivity coach. Track projects in projects.md. Give practical advice and encouragement."
         }
     }
 }
 
+/// Build the copy-mode targets: every message, plus each fenced code block in assistant replies
+fn copy_targets(messages: &[ChatMessage]) -> Vec<CopyTarget> {
+    let mut targets = Vec::new();
+
+    for (i, msg) in messages.iter().enumerate() {
+        let label = match &msg.role {
+            MessageRole::User => "prompt".to_string(),
+            MessageRole::Assistant => "reply".to_string(),
+            MessageRole::Tool { name, .. } => format!("{} output", name),
+        };
+        targets.push(CopyTarget { message: i, label, text: msg.content.clone() });
+
+        if msg.role != MessageRole::Assistant {
+            continue;
+        }
+
+        let mut block: Option<(String, Vec<&str>)> = None;
+        for line in msg.content.lines() {
+            if line.starts_with("```") {
+                match block.take() {
+                    Some((lang, lines)) => {
+                        let label = if lang.is_empty() {
+                            "code block".to_string()
+                        } else {
+                            format!("{} code block", lang)
+                        };
+                        targets.push(CopyTarget { message: i, label, text: lines.join("\n") });
+                    }
+                    None => {
+                        block = Some((line.trim_start_matches('`').trim().to_string(), Vec::new()));
+                    }
+                }
+            } else if let Some((_, lines)) = &mut block {
+                lines.push(line);
+            }
+        }
+    }
+
+    targets
+}
+
+/// Character class for word motions: whitespace, word chars, punctuation
+fn char_class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+/// Byte index of the start of the next word after `cursor` (vim `w`)
+fn next_word_start(s: &str, cursor: usize) -> usize {
+    let mut chars = s[cursor..].char_indices().peekable();
+    let Some(&(_, first)) = chars.peek() else { return s.len() };
+    let class = char_class(first);
+    // Skip the rest of the current word, then any whitespace
+    while chars.peek().is_some_and(|&(_, c)| class != 0 && char_class(c) == class) {
+        chars.next();
+    }
+    while chars.peek().is_some_and(|&(_, c)| char_class(c) == 0) {
+        chars.next();
+    }
+    chars.peek().map(|&(i, _)| cursor + i).unwrap_or(s.len())
+}
+
+/// Byte index of the start of the word before `cursor` (vim `b`, Ctrl+W)
+fn prev_word_start(s: &str, cursor: usize) -> usize {
+    let mut chars = s[..cursor].char_indices().rev().peekable();
+    while chars.peek().is_some_and(|&(_, c)| char_class(c) == 0) {
+        chars.next();
+    }
+    let Some(&(mut start, first)) = chars.peek() else { return 0 };
+    let class = char_class(first);
+    while let Some(&(i, c)) = chars.peek() {
+        if char_class(c) != class {
+            break;
+        }
+        start = i;
+        chars.next();
+    }
+    start
+}
+
+/// Byte index of the last char of the current or next word (vim `e`)
+fn word_end(s: &str, cursor: usize) -> usize {
+    let mut chars = s[cursor..].char_indices().skip(1).peekable();
+    while chars.peek().is_some_and(|&(_, c)| char_class(c) == 0) {
+        chars.next();
+    }
+    let Some(&(mut end, first)) = chars.peek() else { return cursor };
+    let class = char_class(first);
+    while let Some(&(i, c)) = chars.peek() {
+        if char_class(c) != class {
+            break;
+        }
+        end = i;
+        chars.next();
+    }
+    cursor + end
+}
+
+/// A message on its way out, from submit_input to send_input
+struct Submission {
+    input: String,          // As typed, for the transcript
+    prompt: Option<String>, // What a command expanded into, sent instead of the input
+    require_approval: bool,
+    resolving: Vec<String>,
+}
+
+impl Submission {
+    /// The text whose references are expanded and sent
+    fn text(&self) -> &str {
+        self.prompt.as_deref().unwrap_or(&self.input)
+    }
+}
+
 struct ExpandedInput {
     text: String,
-    files_read: Vec<(String, usize)>, // (path, line_count)
+    files_read: Vec<(String, usize)>,   // (path, line_count)
+    issues_read: Vec<(String, String)>, // (owner/repo#n, fetched text)
+    images: Vec<crate::image::Image>,
+    warnings: Vec<String>, // Truncated or skipped attachments
+    attached_bytes: usize,  // Counted against AttachmentLimits::total
+}
+
+/// Default for max_file_attachment_bytes
+pub const MAX_FILE_ATTACHMENT_BYTES: usize = 100_000;
+/// Default for max_total_attachment_bytes
+pub const MAX_TOTAL_ATTACHMENT_BYTES: usize = 400_000;
+/// Cap on the files inlined for one @dir/**
+const MAX_DIRECTORY_FILES_BYTES: usize = 150_000;
+
+/// Per-file and per-message caps on inlined attachments, from the config
+#[derive(Debug, Clone, Copy)]
+struct AttachmentLimits {
+    file: usize,
+    total: usize,
+}
+
+/// Read a file to inline into a message, truncating it to fit the per-file and
+/// per-message limits. Binary files and files past the budget are skipped with a warning.
+fn read_attachment(
+    path: &str,
+    range: Option<(usize, usize)>,
+    limits: AttachmentLimits,
+    used: &mut usize,
+    warnings: &mut Vec<String>,
+) -> Option<String> {
+    let bytes = std::fs::read(path).ok()?;
+    let size_kb = bytes.len().div_ceil(1024);
+    let content = match String::from_utf8(bytes) {
+        Ok(content) if !content[..content.len().min(8192)].contains('\0') => content,
+        _ => {
+            warnings.push(format!("Skipped {}: binary file ({} KB)", path, size_kb));
+            return None;
+        }
+    };
+    let content = match range {
+        Some((start, end)) => {
+            let lines: Vec<&str> = content.lines().collect();
+            if start > lines.len() {
+                warnings.push(format!("Skipped {}:{}-{}: the file has {} lines", path, start, end, lines.len()));
+                return None;
+            }
+            lines[start - 1..end.min(lines.len())].join("\n")
+        }
+        None => content,
+    };
+
+    let limit = limits.file.min(limits.total.saturating_sub(*used));
+    if limit < 1024 {
+        warnings.push(format!("Skipped {}: attachment limit for one message reached", path));
+        return None;
+    }
+    let content = if content.len() > limit {
+        let truncated = head_and_tail(&content, limit);
+        warnings.push(format!(
+            "Truncated {} ({} KB) to its first and last lines ({} of {} lines kept)",
+            path,
+            size_kb,
+            truncated.lines().count() - 1,
+            content.lines().count()
+        ));
+        truncated
+    } else {
+        content
+    };
+    *used += content.len();
+    Some(content)
+}
+
+/// The first two thirds and last third of `content`'s lines that fit in `limit` bytes
+fn head_and_tail(content: &str, limit: usize) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let (mut head, mut tail) = (Vec::new(), Vec::new());
+    let (mut head_bytes, mut tail_bytes) = (0, 0);
+    let (mut i, mut j) = (0, lines.len());
+    while i < j {
+        let take_head = head_bytes <= tail_bytes * 2;
+        let line = if take_head { lines[i] } else { lines[j - 1] };
+        if head_bytes + tail_bytes + line.len() + 1 > limit {
+            break;
+        }
+        if take_head {
+            head.push(line);
+            head_bytes += line.len() + 1;
+            i += 1;
+        } else {
+            tail.push(line);
+            tail_bytes += line.len() + 1;
+            j -= 1;
+        }
+    }
+    tail.reverse();
+    format!("{}\n[... {} lines omitted ...]\n{}", head.join("\n"), j - i, tail.join("\n"))
 }
 
-fn expand_file_refs(input: &str) -> ExpandedInput {
+fn expand_file_refs(input: &str, limits: AttachmentLimits, issues: &HashMap<String, String>) -> ExpandedInput {
     let mut result = input.to_string();
     let mut files_content = Vec::new();
     let mut files_read = Vec::new();
+    let mut issues_read = Vec::new();
+    let mut images = Vec::new();
+    let mut warnings = Vec::new();
+    let mut attached_bytes = 0;
 
     for word in input.split_whitespace() {
+        // @gh:owner/repo#123 pulls in an issue or PR, fetched beforehand
+        if let Some(reference) = word.strip_prefix("@gh:")
+            && let Some((repo, number)) = crate::github::parse_ref(reference)
+        {
+            let label = format!("{}#{}", repo, number);
+            let Some(content) = issues.get(&label).cloned() else { continue };
+            result = result.replace(word, &format!("`{}`", label));
+            files_content.push(format!("\n\n<github ref=\"{}\">\n{}\n</github>", label, content.trim()));
+            issues_read.push((label, content));
+            continue;
+        }
         if word.starts_with('@') && word.len() > 1 {
             let path_str = &word[1..];
             let path = Path::new(path_str);
 
+            if path.is_file() && crate::image::is_image(path_str) {
+                // Sent as an image content part; a broken image stays plain text
+                if let Ok(image) = crate::image::load(path_str) {
+                    result = result.replace(word, &format!("`{}`", path_str));
+                    images.push(image);
+                }
+                continue;
+            }
+            // @dir/** also inlines the directory's small files
+            let (dir_str, with_files) = match path_str.strip_suffix("**") {
+                Some(dir) if !dir.is_empty() => (dir, true),
+                _ => (path_str, false),
+            };
+            if Path::new(dir_str).is_dir() {
+                let summary = crate::summary::directory(dir_str);
+                result = result.replace(word, &format!("`{}`", dir_str));
+                attached_bytes += summary.len();
+                files_content.push(format!("\n\n<directory path=\"{}\">\n{}\n</directory>", dir_str, summary));
+                files_read.push((dir_str.to_string(), summary.lines().count()));
+                if with_files {
+                    let budget_end = (attached_bytes + MAX_DIRECTORY_FILES_BYTES).min(limits.total);
+                    let files = crate::summary::small_files(dir_str);
+                    let mut inlined = 0;
+                    for file in &files {
+                        let size = std::fs::metadata(file).map_or(0, |m| m.len() as usize);
+                        if attached_bytes + size > budget_end {
+                            break;
+                        }
+                        let file = crate::paths::display(Path::new(file));
+                        let mut skipped = Vec::new(); // Binary files are expected here, not worth a warning
+                        if let Some(content) = read_attachment(&file, None, limits, &mut attached_bytes, &mut skipped) {
+                            files_content.push(file_block(&file, &content));
+                            files_read.push((file.to_string(), content.lines().count()));
+                        }
+                        inlined += 1;
+                    }
+                    if inlined < files.len() {
+                        warnings.push(format!(
+                            "Attached {} of {} small files in {}: size budget reached",
+                            inlined,
+                            files.len(),
+                            dir_str
+                        ));
+                    }
+                }
+                continue;
+            }
             if path.exists() && path.is_file() {
-                if let Ok(content) = std::fs::read_to_string(path) {
+                if let Some(content) = read_attachment(path_str, None, limits, &mut attached_bytes, &mut warnings) {
                     let line_count = content.lines().count();
                     result = result.replace(word, &format!("`{}`", path_str));
-                    files_content.push(format!(
-                        "\n\n<file path=\"{}\">\n{}\n</file>",
-                        path_str,
-                        content.trim()
-                    ));
+                    files_content.push(file_block(path_str, &content));
                     files_read.push((path_str.to_string(), line_count));
                 }
+                continue;
+            }
+            // @path:start-end inlines just those lines
+            if let Some((file, range)) = split_line_range(path_str)
+                && Path::new(file).is_file()
+                && let Some(content) = read_attachment(file, Some(range), limits, &mut attached_bytes, &mut warnings)
+            {
+                let line_count = content.lines().count();
+                result = result.replace(word, &format!("`{}`", path_str));
+                files_content.push(format!(
+                    "\n\n<file path=\"{}\" lines=\"{}-{}\">\n{}\n</file>",
+                    file,
+                    range.0,
+                    range.0 + line_count.saturating_sub(1),
+                    content.trim_end()
+                ));
+                files_read.push((path_str.to_string(), line_count));
             }
         }
     }
@@ -1293,9 +3954,25 @@ fn expand_file_refs(input: &str) -> ExpandedInput {
     ExpandedInput {
         text: result,
         files_read,
+        issues_read,
+        images,
+        warnings,
+        attached_bytes,
     }
 }
 
+/// Split "path:start-end" (or "path:line") into the path and a 1-indexed inclusive range
+fn split_line_range(reference: &str) -> Option<(&str, (usize, usize))> {
+    let (path, range) = reference.rsplit_once(':')?;
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+    (!path.is_empty() && start >= 1 && end >= start).then_some((path, (start, end)))
+}
+
+fn file_block(path: &str, content: &str) -> String {
+    format!("\n\n<file path=\"{}\">\n{}\n</file>", path, content.trim())
+}
+
 fn format_tool_call(name: &str, args: &str) -> String {
     let json: Value = serde_json::from_str(args).unwrap_or_default();
 
@@ -1308,10 +3985,27 @@ fn format_tool_call(name: &str, args: &str) -> String {
             let path = json["path"].as_str().unwrap_or("?");
             format!("write {}", path)
         }
+        "gh_fetch" => {
+            let reference = json["ref"].as_str().unwrap_or("?");
+            format!("fetch {}", reference)
+        }
+        "replace_in_files" => {
+            let pattern = json["pattern"].as_str().unwrap_or("?");
+            let glob = json["glob"].as_str().unwrap_or("*");
+            let dry_run = if tools::is_dry_run(name, args) { " (dry run)" } else { "" };
+            format!("replace '{}' in {}{}", pattern, glob, dry_run)
+        }
+        "apply_patch" => {
+            let files = json["patch"].as_str().unwrap_or("").lines().filter(|l| l.starts_with("+++ ")).count();
+            format!("patch {} file{}", files, if files == 1 { "" } else { "s" })
+        }
         "list_dir" => {
             let path = json["path"].as_str().unwrap_or(".");
             format!("ls {}", path)
         }
+        "repo_stats" => format!("stats {}", json["path"].as_str().unwrap_or(".")),
+        "list_symbols" => format!("symbols {}", json["path"].as_str().unwrap_or(".")),
+        "find_definition" => format!("definition of {}", json["name"].as_str().unwrap_or("?")),
         "search_files" => {
             let pattern = json["pattern"].as_str().unwrap_or("*");
             let path = json["path"].as_str().unwrap_or(".");
@@ -1326,12 +4020,18 @@ fn format_tool_call(name: &str, args: &str) -> String {
                 format!("$ {}", cmd)
             }
         }
+        "scratch_dir" => "scratch dir".to_string(),
+        "todo_write" => "update plan".to_string(),
+        "todo_read" => "read plan".to_string(),
         "view_projects" => "view projects".to_string(),
         "update_projects" => "update projects".to_string(),
         _ => name.to_string(),
     }
 }
 
+/// Stop indexing for the @ picker here so huge trees (e.g. $HOME) stay responsive
+const MAX_INDEXED_FILES: usize = 50_000;
+
 fn load_files() -> Vec<String> {
     use ignore::WalkBuilder;
 
@@ -1341,48 +4041,99 @@ fn load_files() -> Vec<String> {
         .ignore(true)
         .git_ignore(true)
         .max_depth(Some(6))
-        .add_custom_ignore_filename(".vecoignore");
+        .add_custom_ignore_filename(".vecoignore")
+        .filter_entry(|e| e.file_name() != ".git");
 
     builder
         .build()
         .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+        .filter(|e| e.depth() > 0)
         .map(|e| {
-            let p = e.path().to_string_lossy().to_string();
-            p.strip_prefix("./").unwrap_or(&p).to_string()
+            let p = crate::paths::display(e.path());
+            // Directories are listed with a trailing slash and attach as a summary
+            if e.file_type().is_some_and(|t| t.is_dir()) { format!("{}/", p) } else { p }
         })
+        .take(MAX_INDEXED_FILES)
         .collect()
 }
 
-fn filter_items(items: &[String], query: &str, max: usize) -> Vec<String> {
-    let query_lower = query.to_lowercase();
-    items
+/// Files attached this long ago no longer get a boost
+const MAX_RECENT_FILES: usize = 20;
+
+/// Fuzzy-match `items` against `query`, best first, with matched char indices for
+/// highlighting. Items in `recent` (most recent first) rank higher.
+fn filter_items(items: &[String], query: &str, recent: &[String], max: usize) -> Vec<(String, Vec<usize>)> {
+    use fuzzy_matcher::FuzzyMatcher;
+    use fuzzy_matcher::skim::SkimMatcherV2;
+
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let boost = |item: &str| recent.iter().position(|r| r == item).map_or(0, |rank| (MAX_RECENT_FILES - rank) as i64 * 4);
+    let mut matches: Vec<(i64, usize, &String, Vec<usize>)> = items
         .iter()
-        .filter(|item| {
-            if query_lower.is_empty() {
-                true
-            } else {
-                let item_lower = item.to_lowercase();
-                let mut chars = query_lower.chars().peekable();
-                for c in item_lower.chars() {
-                    if chars.peek() == Some(&c) {
-                        chars.next();
-                    }
-                }
-                chars.peek().is_none()
+        .enumerate()
+        .filter_map(|(order, item)| {
+            if query.is_empty() {
+                return Some((boost(item), order, item, Vec::new()));
             }
+            let (score, indices) = matcher.fuzzy_indices(item, query)?;
+            Some((score + boost(item), order, item, indices))
         })
-        .take(max)
-        .cloned()
-        .collect()
+        .collect();
+    // Ties go to shorter items (only when searching), then the original order
+    let by_length = !query.is_empty();
+    matches.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then(if by_length { a.2.len().cmp(&b.2.len()) } else { std::cmp::Ordering::Equal })
+            .then(a.1.cmp(&b.1))
+    });
+    matches.into_iter().take(max).map(|(_, _, item, indices)| (item.clone(), indices)).collect()
 }
 
+/// Matches shown in the Ctrl+R history search
+const MAX_HISTORY_RESULTS: usize = 10;
+
+/// Built-in commands followed by custom prompt templates
 fn get_commands() -> Vec<String> {
+    let mut commands = builtin_commands();
+    for name in crate::commands::discover().into_keys() {
+        if !commands.contains(&name) {
+            commands.push(name);
+        }
+    }
+    commands
+}
+
+fn builtin_commands() -> Vec<String> {
     vec![
         "autoaccept".to_string(),
+        "auto".to_string(),
         "clear".to_string(),
         "sessions".to_string(),
+        "bookmarks".to_string(),
         "load".to_string(),
+        "export".to_string(),
+        "changelog".to_string(),
+        "digest".to_string(),
+        "handoff".to_string(),
+        "draft".to_string(),
+        "undo".to_string(),
+        "changes".to_string(),
+        "resolve-conflicts".to_string(),
+        "versions".to_string(),
+        "retry".to_string(),
+        "edit-last".to_string(),
+        "checkpoint".to_string(),
+        "rewind".to_string(),
+        "fork".to_string(),
+        "seed".to_string(),
+        "prefs".to_string(),
+        "prompt".to_string(),
+        "stats".to_string(),
+        "usage".to_string(),
+        "debug".to_string(),
+        "log".to_string(),
+        "mode".to_string(),
+        "image".to_string(),
         "model".to_string(),
         "key".to_string(),
         "update".to_string(),
@@ -1391,11 +4142,40 @@ fn get_commands() -> Vec<String> {
     ]
 }
 
+/// How often the config files are checked for edits
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 const HELP_TEXT: &str = r#"**Commands:**
 - `/autoaccept` - Toggle auto-accept file changes (on/off)
+- `/auto` - Toggle permission checks for this session: sandbox path requests and all file changes are approved without asking (also `--dangerously-skip-permissions`)
 - `/clear` - Save and start new session
 - `/sessions` - List saved sessions
+- `/bookmarks` - List bookmarked messages
 - `/load <id>` - Load a saved session
+- `/undo` - Revert the most recent file change
+- `/changes` - List files changed since hal started
+- `!<command>` - Run a shell command yourself, sandboxed; the output stays out of the conversation
+- `!!<command>` - Run it and attach the output to your next message; `!!` alone attaches the last output
+- `/versions [path]` - Step through each version of a file hal changed, with diffs between them; `Enter` restores one
+- `/retry` - Send your last message again, replacing the reply
+- `/edit-last` - Bring your last message back into the input to edit and resend
+- `/checkpoint [label]` - Mark the current point in the conversation
+- `/rewind [n]` - Roll back to checkpoint n (default: latest) in a new branch session
+- `/fork <provider> [model]` - Continue this conversation in a new session on another provider or model; `/load` the original to compare
+- `/mode [ask|coding|coach]` - Switch mode; ask mode only reads and searches (no bash or edits)
+- `/prefs [set <name> <value> | unset <name>]` - Preferences added to every system prompt, e.g. `/prefs set verbosity terse` (saved in the config)
+- `/prompt` - Show the system prompt being sent
+- `/stats [compact on|off]` - Show token usage and tool schema overhead; switch this provider to compact schemas
+- `/debug [on|off]` - Log API requests, responses and tool calls (keys redacted); `/log` shows the file
+- `/usage [tools]` - Estimate where prompt tokens go (attachments, tool results); `tools` ranks tools by cost
+- `/seed [n|off]` - Send a fixed sampling seed with each request (recorded per turn)
+- `/export [md|html] [path]` - Export the transcript to Markdown or HTML
+- `/resolve-conflicts` - Resolve merge or rebase conflicts, each as a diff to approve; files without markers are then `git add`ed
+- `/changelog [range]` - Draft release notes from git log into CHANGELOG.md (default: since last tag)
+- `/digest [ref|date]` - Stand-up summary of commits, changed files and TODO/FIXME changes since a ref or date (default: a week)
+- `/handoff` - Summarize the task, decisions and relevant files into a document for a fresh session or another tool
+- `/draft [text|insert|clear]` - Show the notes draft, add to it, include it in the next message, or clear it
+- `/image <path>` - Attach an image to the next message (vision models)
 - `/model` - Switch model
 - `/model <name>` - Switch to named model
 - `/key <key>` - Set API key for current provider
@@ -1403,11 +4183,118 @@ const HELP_TEXT: &str = r#"**Commands:**
 - `/quit` - Exit (also /exit, /q)
 - `/help` - Show this help
 
+**Custom commands:**
+- Markdown files in `~/.config/hal/commands/` or `.hal/commands/` become `/name` commands
+- `$ARGUMENTS` in the template is replaced with the text after the command
+
 **File references:**
 - `@` - Type @ to open file picker
+- `@gh:owner/repo#123` - Attach a GitHub issue or PR
+- `@src/` - Attach a directory summary (tree, file types, README and manifests)
+- `@src/**` - Attach a directory summary plus its small files, within a size budget
+- `@src/app.rs:100-250` - Attach only those lines (also typed after a picker query)
+- `@shot.png` - Attach an image (png, jpeg, gif, webp)
 - `Tab/Enter` - Select file from picker
 - `Esc` - Cancel picker
 
 **Navigation:**
 - `↑/↓` - History / picker navigation
-- `Ctrl+U/D` - Scroll chat history"#;
+- `Ctrl+R` - Search past prompts; `Enter` inserts the selected one, `Ctrl+R`/`↑` older matches
+- `Ctrl+U/D` - Scroll chat history
+- `Ctrl+B` - After a failed turn, save a diagnostic bundle (redacted) for a hal bug report
+- `Esc` - Abort the request; while a command runs, kill it (also `Ctrl+C`)
+- The bottom line shows keys for the current screen, whether bash runs sandboxed, and pending approvals (`"status_bar": false` hides it)
+
+**Tabs:**
+- `Ctrl+T` - Open a new conversation tab
+- `Ctrl+1..9` (or `Alt+1..9`) - Switch tabs
+- `/quit` closes the current tab, `Ctrl+C` exits hal
+
+**Copy mode:**
+- `Ctrl+Y` - Select a message or code block to copy
+- `↑/↓` or `k/j` - Move selection, `y/Enter` - Copy, `Esc` - Exit
+- `Space/o` - Expand or collapse the selected tool output
+- `a` - Append the selection to the draft
+- `m` - Bookmark the selected message (input text becomes the note), `n/N` - Next/previous bookmark
+
+**Editing:**
+- `Ctrl+A/E` - Jump to start/end of input
+- `Ctrl+W` - Delete previous word
+- `Ctrl+K` - Delete to end of input
+- Pasting nothing but file paths (a drag-and-drop) offers them as attachments: `Tab` swaps in @ references
+- Outside coding mode, a message with obvious typos is held once: `Tab` fixes them, `Enter` sends as is (`"spell_assist": false` turns this off)
+- Set `"keybindings": "vim"` in config for vim normal/insert modes"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_word_start() {
+        let s = "foo.bar  baz";
+        assert_eq!(next_word_start(s, 0), 3);
+        assert_eq!(next_word_start(s, 3), 4);
+        assert_eq!(next_word_start(s, 4), 9);
+        assert_eq!(next_word_start(s, 9), s.len());
+    }
+
+    #[test]
+    fn test_prev_word_start() {
+        let s = "foo bar  baz";
+        assert_eq!(prev_word_start(s, s.len()), 9);
+        assert_eq!(prev_word_start(s, 9), 4);
+        assert_eq!(prev_word_start(s, 2), 0);
+        assert_eq!(prev_word_start(s, 0), 0);
+    }
+
+    #[test]
+    fn test_word_end() {
+        let s = "foo bar";
+        assert_eq!(word_end(s, 0), 2);
+        assert_eq!(word_end(s, 2), 6);
+        assert_eq!(word_end(s, 6), 6);
+    }
+
+    #[test]
+    fn test_filter_items_ranking() {
+        let items: Vec<String> = ["src/api/parse.rs", "src/app.rs", "assets/apple.png", "README.md"].map(String::from).to_vec();
+        let names = |results: Vec<(String, Vec<usize>)>| results.into_iter().map(|(item, _)| item).collect::<Vec<_>>();
+
+        let results = filter_items(&items, "app", &[], 10);
+        assert_eq!(results[0], ("src/app.rs".to_string(), vec![4, 5, 6]));
+        assert!(!names(results).contains(&"README.md".to_string()));
+
+        // Recently attached files come first without a query
+        let recent = ["README.md".to_string()];
+        assert_eq!(names(filter_items(&items, "", &recent, 2)), ["README.md", "src/api/parse.rs"]);
+    }
+
+    #[test]
+    fn test_split_line_range() {
+        assert_eq!(split_line_range("src/app.rs:100-250"), Some(("src/app.rs", (100, 250))));
+        assert_eq!(split_line_range("notes.md:7"), Some(("notes.md", (7, 7))));
+        assert_eq!(split_line_range("src/app.rs:250-100"), None);
+        assert_eq!(split_line_range("src/app.rs"), None);
+    }
+
+    #[test]
+    fn test_batch_stops_at_unavailable_tools() {
+        let calls = |names: &[&str]| names.iter().enumerate().map(|(i, n)| (i.to_string(), n.to_string(), "{}".to_string())).collect::<Vec<_>>();
+        let ask = tools::get_tool_definitions(&Mode::Ask);
+        let coding = tools::get_tool_definitions(&Mode::Coding);
+
+        // scratch_dir and gh_fetch are parallel-safe but not offered in ask mode
+        assert_eq!(batch_len(&calls(&["read_file", "scratch_dir", "grep"]), &ask, 8), 1);
+        assert_eq!(batch_len(&calls(&["read_file", "grep", "gh_fetch"]), &ask, 8), 2);
+        assert!(!offers_tool(&ask, "scratch_dir"));
+        assert_eq!(batch_len(&calls(&["read_file", "grep", "read_file"]), &coding, 2), 2);
+        assert_eq!(batch_len(&calls(&["bash", "read_file"]), &coding, 8), 1);
+    }
+
+    #[test]
+    fn test_head_and_tail() {
+        let content: String = (1..=100).map(|i| format!("line {:03}\n", i)).collect();
+        let cut = head_and_tail(&content, 90);
+        assert_eq!(cut, "line 001\nline 002\nline 003\nline 004\nline 005\nline 006\nline 007\n[... 90 lines omitted ...]\nline 098\nline 099\nline 100");
+    }
+}
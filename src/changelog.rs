@@ -0,0 +1,97 @@
+use std::process::Command;
+
+/// Section names in release-note order, keyed by conventional-commit type
+const GROUPS: &[(&str, &str)] = &[
+    ("feat", "Features"),
+    ("fix", "Fixes"),
+    ("perf", "Performance"),
+    ("refactor", "Refactoring"),
+    ("docs", "Documentation"),
+    ("other", "Other"),
+];
+
+pub fn git(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Group type for a commit subject: conventional prefixes first, then leading verbs
+fn group_of(subject: &str) -> &'static str {
+    let lower = subject.to_lowercase();
+    if let Some((kind, _)) = lower.split_once(':') {
+        let kind = kind.split('(').next().unwrap_or(kind).trim_end_matches('!');
+        if let Some((key, _)) = GROUPS.iter().find(|(key, _)| *key == kind) {
+            return key;
+        }
+    }
+    match lower.split_whitespace().next().unwrap_or("") {
+        "add" | "added" | "adds" | "support" | "implement" | "introduce" => "feat",
+        "fix" | "fixed" | "fixes" | "handle" | "prevent" => "fix",
+        "refactor" | "simplify" | "move" | "rename" | "clean" | "cleanup" => "refactor",
+        "doc" | "docs" | "document" => "docs",
+        "speed" | "optimize" | "perf" => "perf",
+        _ => "other",
+    }
+}
+
+/// Commits in `range` as markdown sections
+fn group_commits(log: &str) -> String {
+    let mut out = String::new();
+    for (key, title) in GROUPS {
+        let lines: Vec<&str> = log
+            .lines()
+            .filter(|line| line.split_once(' ').is_some_and(|(_, subject)| group_of(subject) == *key))
+            .collect();
+        if !lines.is_empty() {
+            out.push_str(&format!("### {}\n{}\n\n", title, lines.iter().map(|l| format!("- {}", l)).collect::<Vec<_>>().join("\n")));
+        }
+    }
+    out
+}
+
+/// Prompt asking the model to draft release notes for `range` and add them to CHANGELOG.md.
+/// Without a range, covers everything since the latest tag.
+pub fn prompt(range: &str) -> Result<String, String> {
+    let range = if range.is_empty() {
+        match git(&["describe", "--tags", "--abbrev=0"]) {
+            Ok(tag) => format!("{}..HEAD", tag.trim()),
+            Err(_) => "HEAD".to_string(),
+        }
+    } else {
+        range.to_string()
+    };
+    let log = git(&["log", "--no-merges", "--pretty=format:%h %s", &range])?;
+    if log.trim().is_empty() {
+        return Err(format!("No commits in {}", range));
+    }
+
+    Ok(format!(
+        "Draft release notes for `{}` from the commits below, grouped as shown. Rewrite subjects for users \
+         (what changed and why it matters), merge related commits and drop purely internal ones. Then add \
+         the notes as a new section at the top of CHANGELOG.md, matching its existing format (create it \
+         if missing).\n\n{} commits:\n\n{}",
+        range,
+        log.lines().count(),
+        group_commits(&log).trim_end()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_commits() {
+        let log = "a1 feat(ui): add dark mode\nb2 Fix crash on empty input\nc3 bump version\nd4 docs: typo";
+        let grouped = group_commits(log);
+        assert!(grouped.starts_with("### Features\n- a1 feat(ui): add dark mode\n\n### Fixes\n- b2 Fix crash"));
+        assert!(grouped.contains("### Documentation\n- d4 docs: typo"));
+        assert!(grouped.contains("### Other\n- c3 bump version"));
+    }
+}
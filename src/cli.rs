@@ -0,0 +1,222 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Chat with LLMs from your terminal
+#[derive(Parser)]
+#[command(name = "hal", version)]
+pub struct Cli {
+    #[command(flatten)]
+    pub args: RunArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Args, Default)]
+pub struct RunArgs {
+    /// Run in coach mode
+    #[arg(short, long, conflicts_with = "ask")]
+    pub coach: bool,
+    /// Run in ask mode (read-only tools, no bash)
+    #[arg(long)]
+    pub ask: bool,
+    /// Provider name from config
+    #[arg(short, long, value_name = "NAME")]
+    pub model: Option<String>,
+    /// Resume the last session
+    #[arg(short, long, conflicts_with_all = ["session", "seed"])]
+    pub resume: bool,
+    /// Load a specific session by ID
+    #[arg(short, long, value_name = "ID", conflicts_with = "seed")]
+    pub session: Option<String>,
+    /// Start a new session from a prepared conversation (.md or .json)
+    #[arg(long, value_name = "FILE")]
+    pub seed: Option<PathBuf>,
+    /// Run non-interactively and print the final answer
+    #[arg(short, long, value_name = "TEXT")]
+    pub prompt: Option<String>,
+    /// With -p, save changed files, the answer and a manifest to DIR
+    #[arg(long, value_name = "DIR", requires = "prompt")]
+    pub save_output: Option<PathBuf>,
+    /// With -p, exit non-zero when a condition is met (comma-separated)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "CONDITION", requires = "prompt")]
+    pub fail_on: Vec<FailOn>,
+    /// With -p, have the model end with a JSON result ({files_changed, summary}) and print
+    /// only that to stdout; the narrative goes to stderr
+    #[arg(long, requires = "prompt")]
+    pub json_result: bool,
+    /// Wrap up with a summary and stop after this long, e.g. 90s, 10m or 1h30m
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub max_duration: Option<Duration>,
+    /// Log API requests, responses and tool calls to ~/.config/hal/logs/
+    #[arg(long)]
+    pub debug: bool,
+    /// Allow coding tools when started in the home directory or a filesystem root
+    #[arg(long)]
+    pub allow_broad_dir: bool,
+    /// Approve sandbox path requests and file changes without asking (for disposable containers)
+    #[arg(long)]
+    pub dangerously_skip_permissions: bool,
+    /// Attach a file to the first message (repeatable)
+    #[arg(short, long = "file", value_name = "PATH")]
+    pub files: Vec<String>,
+    /// Attach all files matching a glob (repeatable)
+    #[arg(long = "context", value_name = "GLOB")]
+    pub contexts: Vec<String>,
+}
+
+/// Conditions that fail a -p run, each with its own exit code
+#[derive(Clone, Copy, ValueEnum)]
+pub enum FailOn {
+    /// A tool call returned an error (exit 3)
+    ToolError,
+    /// The run changed no files (exit 4)
+    NoEdits,
+    /// The run stopped at the tool-call or time limit (exit 5)
+    BudgetExceeded,
+}
+
+impl FailOn {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            FailOn::ToolError => 3,
+            FailOn::NoEdits => 4,
+            FailOn::BudgetExceeded => 5,
+        }
+    }
+}
+
+/// Parse a duration like "90s", "10m" or "1h30m"; a bare number is seconds
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{}' (expected e.g. 90s, 10m or 1h30m)", text);
+    if let Ok(secs) = text.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+    let mut secs: u64 = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            _ => return Err(invalid()),
+        };
+        let n: u64 = number.parse().map_err(|_| invalid())?;
+        secs = n.checked_mul(unit).and_then(|n| secs.checked_add(n)).ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || secs == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Start hal (the default when no command is given)
+    Run(RunArgs),
+    /// Explain and review a diff, read-only and without tools
+    Explain {
+        /// Explain staged changes instead of unstaged ones
+        #[arg(long, conflicts_with = "range")]
+        staged: bool,
+        /// Explain the changes in a commit range
+        #[arg(long, value_name = "A..B")]
+        range: Option<String>,
+    },
+    /// Manage saved sessions
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+    /// Sync sessions, encrypted, with the remote store in the config's "sync" section
+    Sync {
+        /// Show what would be uploaded and downloaded without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show or edit the configuration
+    Config {
+        #[command(subcommand)]
+        command: Option<ConfigCommand>,
+    },
+    /// Update hal to the latest version
+    Update,
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SessionsCommand {
+    /// List saved sessions, most recent first
+    List,
+    /// Print a session's details and transcript
+    Show { id: String },
+    /// Delete a saved session
+    Delete { id: String },
+    /// Export a session to stdout or a file
+    Export {
+        id: String,
+        #[arg(long, value_enum, default_value = "md")]
+        format: ExportFormat,
+        /// Write to PATH instead of stdout
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Md,
+    Json,
+    /// JSON list of messages, with tool calls summarized as in the chat view
+    Transcript,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Print the effective configuration, with API keys hidden (default)
+    Show,
+    /// Print the path of the config file
+    Path,
+    /// Open the config file in $EDITOR
+    Edit,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["hal", "-m", "openai", "-f", "a.rs", "-f", "b.rs", "-p", "hi"]).unwrap();
+        assert_eq!(cli.args.model.as_deref(), Some("openai"));
+        assert_eq!(cli.args.files, ["a.rs", "b.rs"]);
+
+        let cli = Cli::try_parse_from(["hal", "-p", "hi", "--fail-on", "tool-error,no-edits"]).unwrap();
+        assert_eq!(cli.args.fail_on.iter().map(|f| f.exit_code()).collect::<Vec<_>>(), [3, 4]);
+        assert!(Cli::try_parse_from(["hal", "--json-result"]).is_err());
+
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert!(parse_duration("10 minutes").is_err());
+        assert!(parse_duration("5m3").is_err());
+        assert!(parse_duration("5124095576030432h").is_err());
+        assert!(parse_duration("5124095576030431h1h").is_err());
+
+        // Malformed input is rejected instead of ignored
+        assert!(Cli::try_parse_from(["hal", "--save-output", "out"]).is_err());
+        assert!(Cli::try_parse_from(["hal", "-m"]).is_err());
+        assert!(Cli::try_parse_from(["hal", "--bogus"]).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use base64::Engine;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Copy text to the system clipboard. Tries the platform's clipboard command first,
+/// then falls back to an OSC 52 escape sequence (works over SSH in most terminals).
+/// Returns the method that was used.
+pub fn copy(text: &str) -> Result<&'static str, String> {
+    let over_ssh = std::env::var("SSH_TTY").is_ok() || std::env::var("SSH_CONNECTION").is_ok();
+
+    if !over_ssh {
+        for (program, args) in clipboard_commands() {
+            if pipe_to(program, args, text) {
+                return Ok(program);
+            }
+        }
+    }
+
+    copy_osc52(text)?;
+    Ok("OSC 52")
+}
+
+fn clipboard_commands() -> Vec<(&'static str, &'static [&'static str])> {
+    #[cfg(target_os = "macos")]
+    return vec![("pbcopy", &[])];
+
+    #[cfg(target_os = "windows")]
+    return vec![("clip", &[])];
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    {
+        let mut commands: Vec<(&'static str, &'static [&'static str])> = Vec::new();
+        if std::env::var("WAYLAND_DISPLAY").is_ok() {
+            commands.push(("wl-copy", &[]));
+        }
+        if std::env::var("DISPLAY").is_ok() {
+            commands.push(("xclip", &["-selection", "clipboard"]));
+            commands.push(("xsel", &["--clipboard", "--input"]));
+        }
+        commands
+    }
+}
+
+fn pipe_to(program: &str, args: &[&str], text: &str) -> bool {
+    let Ok(mut child) = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+
+    let written = child
+        .stdin
+        .take()
+        .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+
+    child.wait().is_ok_and(|status| status.success()) && written
+}
+
+fn copy_osc52(text: &str) -> Result<(), String> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", encoded).map_err(|e| e.to_string())?;
+    stdout.flush().map_err(|e| e.to_string())
+}
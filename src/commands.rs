@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Directories holding markdown prompt templates, lowest precedence first
+fn command_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(config) = dirs::config_dir() {
+        dirs.push(config.join("hal").join("commands"));
+    }
+    dirs.push(PathBuf::from(".hal").join("commands"));
+    dirs
+}
+
+/// Custom commands by name (file stem); project templates shadow global ones
+pub fn discover() -> BTreeMap<String, PathBuf> {
+    let mut commands = BTreeMap::new();
+    for dir in command_dirs() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "md")
+                && let Some(name) = path.file_stem().and_then(|s| s.to_str())
+            {
+                commands.insert(name.to_string(), path);
+            }
+        }
+    }
+    commands
+}
+
+/// Expand `/name args` into the template's prompt, or None if no such template exists
+pub fn expand(input: &str) -> Option<String> {
+    let rest = input.strip_prefix('/')?;
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let template = fs::read_to_string(discover().get(name)?).ok()?;
+    Some(substitute(&template, args.trim()))
+}
+
+fn substitute(template: &str, args: &str) -> String {
+    let template = template.trim();
+    if template.contains("$ARGUMENTS") {
+        template.replace("$ARGUMENTS", args)
+    } else if args.is_empty() {
+        template.to_string()
+    } else {
+        format!("{}\n\n{}", template, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute_arguments() {
+        assert_eq!(substitute("Review $ARGUMENTS for bugs.\n", "src/app.rs"), "Review src/app.rs for bugs.");
+        assert_eq!(substitute("Review the diff.", "focus on errors"), "Review the diff.\n\nfocus on errors");
+        assert_eq!(substitute("Review the diff.", ""), "Review the diff.");
+    }
+}
@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::PathBuf;
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -9,20 +10,154 @@ pub struct Config {
     pub mode: Mode,
     #[serde(default = "default_true")]
     pub auto_accept: bool,
+    #[serde(default = "default_max_parallel_tools")]
+    pub max_parallel_tools: usize,
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+    #[serde(default = "default_max_tool_calls_per_turn")]
+    pub max_tool_calls_per_turn: usize, // 0 = never pause
+    #[serde(default)]
+    pub max_tokens_per_turn: u64, // Pause once a turn's prompt + completion tokens reach this; 0 = never
+    #[serde(default)]
+    pub max_turn_minutes: u64, // Pause once a turn has run this long; 0 = never
+    #[serde(default = "default_max_tool_output")]
+    pub max_tool_output: usize, // Characters of one tool result sent to the model; 0 = no limit
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tool_output_limits: HashMap<String, usize>, // Per-tool overrides of max_tool_output
+    #[serde(default = "default_attachment_warn_tokens")]
+    pub attachment_warn_tokens: usize, // Warn while typing when one @ attachment is larger; 0 = off
+    #[serde(default = "default_max_file_attachment_bytes")]
+    pub max_file_attachment_bytes: usize, // Largest file inlined whole; bigger ones keep their head and tail
+    #[serde(default = "default_max_total_attachment_bytes")]
+    pub max_total_attachment_bytes: usize, // Cap on everything inlined into one message
+    #[serde(default)]
+    pub bash_quiet: bool, // Successful bash commands report only their exit code unless the call sets quiet false
+    #[serde(default)]
+    pub print_transcript_on_exit: bool,
+    #[serde(default = "default_true")]
+    pub generate_titles: bool, // Ask the model for a session title after its first reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_model: Option<String>, // Cheaper model on the active provider for titles; default the chat model
+    #[serde(default)]
+    pub keep_backups: bool, // Keep <file>.hal-backup pre-images until hal exits
+    #[serde(default)]
+    pub keybindings: Keybindings,
+    #[serde(default = "default_true")]
+    pub spell_assist: bool, // Flag obvious typos before sending, outside coding mode
+    #[serde(default)]
+    pub notify: Notify, // When a long turn ends while the terminal is in the background
+    #[serde(default = "default_notify_after_secs")]
+    pub notify_after_secs: u64, // Shorter turns don't notify
+    #[serde(default)]
+    pub glyphs: GlyphStyle, // "ascii" for terminals or fonts that show boxes for symbols
+    #[serde(default)]
+    pub reduced_motion: bool, // A static busy indicator with elapsed seconds instead of a spinner, redrawn less often
+    #[serde(default = "default_true")]
+    pub status_bar: bool, // Bottom line with key hints, sandbox state and pending approvals
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_token: Option<String>, // Falls back to $GITHUB_TOKEN / $GH_TOKEN
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub system_prompts: HashMap<Mode, String>, // Replace the built-in prompt per mode
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub preferences: BTreeMap<String, String>, // Set with /prefs; added to every system prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync: Option<crate::sync::SyncConfig>, // Remote store for `hal sync`
     pub providers: HashMap<String, Provider>,
+    /// Overrides applied from .hal/config.json; never written back to the global file
+    #[serde(skip)]
+    pub project: Option<ProjectConfig>,
+    /// --dangerously-skip-permissions or /auto: sandbox path requests and file changes are
+    /// approved without asking. Session-only, never saved.
+    #[serde(skip)]
+    pub skip_permissions: bool,
+}
+
+/// Project-level settings in .hal/config.json, merged over the global config at startup.
+/// Sandbox policy lives in the same file under "sandbox" (see SandboxConfig).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProjectConfig {
+    pub default_provider: Option<String>,
+    pub mode: Option<Mode>,
+    pub model: Option<String>,         // Model for the active provider
+    pub system_prompt: Option<String>, // Appended to the system prompt
+    #[serde(default)]
+    pub system_prompts: HashMap<Mode, String>, // Replace the system prompt per mode
+    #[serde(skip)]
+    global: Option<(String, Mode, Option<String>)>, // Global (provider, mode, model) before overrides
 }
 
 fn default_true() -> bool {
     true
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+fn default_max_parallel_tools() -> usize {
+    4
+}
+
+fn default_max_concurrent_requests() -> usize {
+    2
+}
+
+fn default_max_tool_calls_per_turn() -> usize {
+    50
+}
+
+fn default_max_tool_output() -> usize {
+    30_000
+}
+
+fn default_attachment_warn_tokens() -> usize {
+    20_000
+}
+
+fn default_max_file_attachment_bytes() -> usize {
+    crate::app::MAX_FILE_ATTACHMENT_BYTES
+}
+
+fn default_max_total_attachment_bytes() -> usize {
+    crate::app::MAX_TOTAL_ATTACHMENT_BYTES
+}
+
+fn default_notify_after_secs() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Mode {
     Coding,
+    Ask, // Read-only tools, no bash
     Coach,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Keybindings {
+    #[default]
+    Default,
+    Vim,
+}
+
+/// How to get the user's attention when hal is waiting on them
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Notify {
+    Off,
+    #[default]
+    Bell,
+    Desktop, // OSC 9 notification (iTerm2, WezTerm, kitty, Windows Terminal, ...)
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GlyphStyle {
+    #[default]
+    Auto,
+    Unicode,
+    Ascii,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Provider {
     pub base_url: String,
@@ -30,16 +165,200 @@ pub struct Provider {
     pub api_key_env: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub api_key: Option<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub auth: Auth,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_header: Option<String>, // Header name for auth "header", e.g. "x-portkey-api-key"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_prefix: Option<String>, // Prepended to the key in auth_header, e.g. "Bearer "
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>, // Sent as OpenAI-Organization
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>, // Sent as OpenAI-Project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_caching: Option<bool>, // cache_control hints; auto-detected for Anthropic when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<Gateway>, // Set for LiteLLM/Portkey-style gateways
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compat: Option<MessageCompat>, // Message shape quirks; detected from base_url when unset
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub compact_tools: bool, // Send tool schemas with shortened descriptions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_price: Option<f64>, // USD per million input tokens, for the estimate shown while typing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_price: Option<f64>, // USD per million output tokens; with input_price, prices a paused turn
+}
+
+/// How strictly a backend validates tool and assistant message shapes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageCompat {
+    Openai, // Accepts null assistant content and tool results without names
+    Gemini, // Wants the function name on tool results
+    Strict, // Names on tool results, no null or empty content, "{}" for empty arguments
+}
+
+/// How the API key is sent
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Auth {
+    None, // Local endpoints (Ollama, llama.cpp) that need no key
+    #[default]
+    Bearer, // Authorization: Bearer <key>
+    Header, // Key in auth_header (default "api-key"), after auth_prefix if set
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+impl Provider {
+    /// USD for `tokens` (prompt, completion), if both prices are set
+    pub fn cost(&self, tokens: (u32, u32)) -> Option<f64> {
+        let (input, output) = (self.input_price?, self.output_price?);
+        Some((tokens.0 as f64 * input + tokens.1 as f64 * output) / 1_000_000.0)
+    }
+
+    /// The key from the config or environment; empty for auth "none"
+    pub fn resolve_api_key(&self) -> Option<String> {
+        if self.auth == Auth::None {
+            return Some(self.api_key.clone().unwrap_or_default());
+        }
+        self.api_key.clone().or_else(|| std::env::var(&self.api_key_env).ok())
+    }
+
+    /// Move a gateway's auth_header/auth_prefix, from configs written before auth
+    /// settings moved to the provider, into auth "header". True if there were any.
+    fn migrate_gateway_auth(&mut self) -> bool {
+        let Some(gateway) = &mut self.gateway else { return false };
+        if gateway.auth_header.is_none() && gateway.auth_prefix.is_none() {
+            return false;
+        }
+        let header = gateway.auth_header.take().unwrap_or_else(|| "Authorization".to_string());
+        let prefix = gateway.auth_prefix.take().unwrap_or_else(|| "Bearer ".to_string());
+        if !(header.eq_ignore_ascii_case("Authorization") && prefix == "Bearer ") {
+            self.auth = Auth::Header;
+            self.auth_header = Some(header);
+            self.auth_prefix = (!prefix.is_empty()).then_some(prefix);
+        }
+        true
+    }
+}
+
+/// Settings for a provider that is an LLM gateway in front of other providers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gateway {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub model_prefix: String, // Prepended to the model for routing, e.g. "anthropic/"
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>, // Static headers sent with every request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_header: Option<String>, // Header carrying the user id, for chargeback
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_header: Option<String>, // Header carrying the hal session id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>, // User id to send; defaults to $USER
+    // Older configs' auth settings, read only to be migrated to the provider
+    #[serde(default, skip_serializing)]
+    auth_header: Option<String>,
+    #[serde(default, skip_serializing)]
+    auth_prefix: Option<String>,
+}
+
+impl Gateway {
+    /// Model name as the gateway expects it
+    pub fn route(&self, model: &str) -> String {
+        if model.starts_with(&self.model_prefix) {
+            model.to_string()
+        } else {
+            format!("{}{}", self.model_prefix, model)
+        }
+    }
+
+    pub fn user_id(&self) -> Option<String> {
+        self.user
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+    }
 }
 
 impl Config {
+    /// Global config with .hal/config.json overrides applied
     pub fn load() -> Self {
+        let mut config = Self::load_global();
+        if let Ok(content) = fs::read_to_string(Self::project_config_path()) {
+            match serde_json::from_str::<ProjectConfig>(&content) {
+                Ok(project) => config.apply_project(project),
+                Err(e) => eprintln!("Warning: Failed to parse {}: {}", Self::project_config_path().display(), e),
+            }
+        }
+        config
+    }
+
+    fn apply_project(&mut self, mut project: ProjectConfig) {
+        let provider = project.default_provider.clone().unwrap_or_else(|| self.default_provider.clone());
+        let model = self.providers.get(&provider).map(|p| p.model.clone());
+        project.global = Some((self.default_provider.clone(), self.mode, model));
+
+        if let Some(name) = &project.default_provider {
+            self.default_provider = name.clone();
+        }
+        if let Some(mode) = project.mode {
+            self.mode = mode;
+        }
+        if let Some(model) = &project.model
+            && let Some(p) = self.providers.get_mut(&provider)
+        {
+            p.model = model.clone();
+        }
+        self.project = Some(project);
+    }
+
+    /// This config with project overrides swapped back for the global values, unless
+    /// they were changed since (e.g. with /model), so saving doesn't leak them
+    fn without_project(&self) -> Config {
+        let mut config = self.clone();
+        let Some(project) = &self.project else { return config };
+        let Some((provider, mode, model)) = &project.global else { return config };
+
+        if project.mode == Some(self.mode) {
+            config.mode = *mode;
+        }
+        if let Some(project_model) = &project.model {
+            let target = project.default_provider.as_ref().unwrap_or(provider);
+            if let Some(p) = config.providers.get_mut(target)
+                && &p.model == project_model
+                && let Some(model) = model
+            {
+                p.model = model.clone();
+            }
+        }
+        if project.default_provider.as_ref() == Some(&self.default_provider) {
+            config.default_provider = provider.clone();
+        }
+        config
+    }
+
+    fn load_global() -> Self {
         let config_path = Self::config_path();
 
         if config_path.exists() {
             match fs::read_to_string(&config_path) {
-                Ok(content) => match serde_json::from_str(&content) {
-                    Ok(config) => return config,
+                Ok(content) => match serde_json::from_str::<Config>(&content) {
+                    Ok(mut config) => {
+                        // Preset added after older configs were written
+                        config.providers.entry("ollama".to_string()).or_insert_with(ollama_preset);
+                        for (name, provider) in &mut config.providers {
+                            if provider.migrate_gateway_auth() {
+                                eprintln!(
+                                    "Warning: providers.{0}.gateway.auth_header and auth_prefix are now providers.{0}.auth_header and auth_prefix with \"auth\": \"header\"; converted, and saved that way the next time the config is written.",
+                                    name
+                                );
+                            }
+                        }
+                        return config;
+                    }
                     Err(e) => {
                         eprintln!(
                             "Warning: Failed to parse config at {}: {}",
@@ -61,32 +380,131 @@ impl Config {
         }
 
         let default = Self::default();
-        let _ = default.save();
+        if Self::writable() {
+            let _ = default.save();
+        }
         default
     }
 
     pub fn save(&self) -> Result<(), String> {
+        if !Self::writable() {
+            return Err(format!("{} is read-only, so this lasts until hal exits", Self::dir().display()));
+        }
         let config_path = Self::config_path();
 
         if let Some(parent) = config_path.parent() {
             fs::create_dir_all(parent).map_err(|e| e.to_string())?;
         }
 
-        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let content = serde_json::to_string_pretty(&self.without_project()).map_err(|e| e.to_string())?;
         fs::write(&config_path, content).map_err(|e| e.to_string())?;
 
         Ok(())
     }
 
+    /// Re-read the global and project config for a live reload. Unlike `load`, a file
+    /// that doesn't parse is an error instead of a fallback to defaults.
+    pub fn reload() -> Result<Self, String> {
+        let path = Self::config_path();
+        let content = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut config: Config = serde_json::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        config.providers.entry("ollama".to_string()).or_insert_with(ollama_preset);
+
+        let project_path = Self::project_config_path();
+        if let Ok(content) = fs::read_to_string(&project_path) {
+            let project = serde_json::from_str::<ProjectConfig>(&content)
+                .map_err(|e| format!("{}: {}", project_path.display(), e))?;
+            config.apply_project(project);
+        }
+        Ok(config)
+    }
+
+    /// Contents of the global and project config files, compared to notice outside edits
+    pub fn files_snapshot() -> [Option<String>; 2] {
+        [Self::config_path(), Self::project_config_path()].map(|path| fs::read_to_string(path).ok())
+    }
+
+    /// Whether `content` is what `save` writes for this config, i.e. hal's own write
+    pub fn is_saved_form(&self, content: &str) -> bool {
+        serde_json::to_string_pretty(&self.without_project()).is_ok_and(|saved| saved == content)
+    }
+
+    /// Replacement for the built-in system prompt of `mode`; the project's wins
+    pub fn system_prompt_override(&self, mode: Mode) -> Option<&str> {
+        self.project
+            .as_ref()
+            .and_then(|p| p.system_prompts.get(&mode))
+            .or_else(|| self.system_prompts.get(&mode))
+            .map(|s| s.as_str())
+    }
+
+    /// Extra system prompt text from the project config
+    pub fn project_prompt(&self) -> Option<&str> {
+        self.project.as_ref()?.system_prompt.as_deref()
+    }
+
+    fn project_config_path() -> PathBuf {
+        PathBuf::from(".hal").join("config.json")
+    }
+
+    /// Characters of `tool`'s results sent to the model; 0 = no limit
+    pub fn tool_output_limit(&self, tool: &str) -> usize {
+        self.tool_output_limits.get(tool).copied().unwrap_or(self.max_tool_output)
+    }
+
     pub fn get_provider(&self) -> Option<&Provider> {
         self.providers.get(&self.default_provider)
     }
 
-    fn config_path() -> PathBuf {
-        dirs::config_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join("hal")
-            .join("config.json")
+    pub fn config_path() -> PathBuf {
+        Self::dir().join("config.json")
+    }
+
+    /// hal's config and data dir: config, sessions, history, grants
+    pub fn dir() -> PathBuf {
+        dirs::config_dir().unwrap_or_else(|| PathBuf::from(".")).join("hal")
+    }
+
+    /// Whether hal can write its dir, checked once. On read-only homes and locked-down
+    /// sandboxes it can't, and settings, grants and sessions stay in memory instead.
+    pub fn writable() -> bool {
+        static WRITABLE: OnceLock<bool> = OnceLock::new();
+        *WRITABLE.get_or_init(|| {
+            let dir = Self::dir();
+            let probe = dir.join(format!(".write-test-{}", std::process::id()));
+            let writable = fs::create_dir_all(&dir).is_ok() && fs::write(&probe, "").is_ok();
+            let _ = fs::remove_file(&probe);
+            writable
+        })
+    }
+
+    /// Startup notice when `writable` is false
+    pub fn read_only_notice() -> String {
+        format!(
+            "{} is read-only: settings, permission grants and this session last until hal exits. API keys come from environment variables.",
+            crate::paths::display(&Self::dir())
+        )
+    }
+}
+
+/// Local Ollama server; needs no API key
+fn ollama_preset() -> Provider {
+    Provider {
+        base_url: "http://localhost:11434/v1".to_string(),
+        model: "qwen2.5-coder".to_string(),
+        api_key_env: "OLLAMA_API_KEY".to_string(),
+        api_key: None,
+        auth: Auth::None,
+        auth_header: None,
+        auth_prefix: None,
+        organization: None,
+        project: None,
+        prompt_caching: None,
+        gateway: None,
+        compat: None,
+        compact_tools: false,
+        input_price: None,
+        output_price: None,
     }
 }
 
@@ -101,6 +519,17 @@ impl Default for Config {
                 model: "gemini-3-flash-preview".to_string(),
                 api_key_env: "HAL_API_KEY_GEMINI".to_string(),
                 api_key: None,
+                auth: Auth::Bearer,
+                auth_header: None,
+                auth_prefix: None,
+                organization: None,
+                project: None,
+                prompt_caching: None,
+                gateway: None,
+                compat: None,
+                compact_tools: false,
+                input_price: None,
+                output_price: None,
             },
         );
 
@@ -111,6 +540,17 @@ impl Default for Config {
                 model: "gpt-5.2-codex".to_string(),
                 api_key_env: "HAL_API_KEY_OPENAI".to_string(),
                 api_key: None,
+                auth: Auth::Bearer,
+                auth_header: None,
+                auth_prefix: None,
+                organization: None,
+                project: None,
+                prompt_caching: None,
+                gateway: None,
+                compat: None,
+                compact_tools: false,
+                input_price: None,
+                output_price: None,
             },
         );
 
@@ -121,6 +561,17 @@ impl Default for Config {
                 model: "claude-opus-4.5".to_string(),
                 api_key_env: "HAL_API_KEY_ANTHROPIC".to_string(),
                 api_key: None,
+                auth: Auth::Bearer,
+                auth_header: None,
+                auth_prefix: None,
+                organization: None,
+                project: None,
+                prompt_caching: None,
+                gateway: None,
+                compat: None,
+                compact_tools: false,
+                input_price: None,
+                output_price: None,
             },
         );
 
@@ -131,6 +582,17 @@ impl Default for Config {
                 model: "anthropic/claude-sonnet-4".to_string(),
                 api_key_env: "HAL_API_KEY_OPENROUTER".to_string(),
                 api_key: None,
+                auth: Auth::Bearer,
+                auth_header: None,
+                auth_prefix: None,
+                organization: None,
+                project: None,
+                prompt_caching: None,
+                gateway: None,
+                compat: None,
+                compact_tools: false,
+                input_price: None,
+                output_price: None,
             },
         );
 
@@ -141,6 +603,17 @@ impl Default for Config {
                 model: "glm-4.7".to_string(),
                 api_key_env: "HAL_API_KEY_GLM".to_string(),
                 api_key: None,
+                auth: Auth::Bearer,
+                auth_header: None,
+                auth_prefix: None,
+                organization: None,
+                project: None,
+                prompt_caching: None,
+                gateway: None,
+                compat: None,
+                compact_tools: false,
+                input_price: None,
+                output_price: None,
             },
         );
 
@@ -151,14 +624,113 @@ impl Default for Config {
                 model: "kimi-k2.5".to_string(),
                 api_key_env: "HAL_API_KEY_KIMI".to_string(),
                 api_key: None,
+                auth: Auth::Bearer,
+                auth_header: None,
+                auth_prefix: None,
+                organization: None,
+                project: None,
+                prompt_caching: None,
+                gateway: None,
+                compat: None,
+                compact_tools: false,
+                input_price: None,
+                output_price: None,
             },
         );
 
+        providers.insert("ollama".to_string(), ollama_preset());
+
         Config {
             default_provider: "gemini".to_string(),
             mode: Mode::Coding,
             auto_accept: true,
+            max_parallel_tools: default_max_parallel_tools(),
+            max_concurrent_requests: default_max_concurrent_requests(),
+            max_tool_calls_per_turn: default_max_tool_calls_per_turn(),
+            max_tokens_per_turn: 0,
+            max_turn_minutes: 0,
+            max_tool_output: default_max_tool_output(),
+            tool_output_limits: HashMap::new(),
+            attachment_warn_tokens: default_attachment_warn_tokens(),
+            max_file_attachment_bytes: default_max_file_attachment_bytes(),
+            max_total_attachment_bytes: default_max_total_attachment_bytes(),
+            bash_quiet: false,
+            print_transcript_on_exit: false,
+            generate_titles: true,
+            title_model: None,
+            keep_backups: false,
+            keybindings: Keybindings::Default,
+            spell_assist: true,
+            notify: Notify::Bell,
+            notify_after_secs: default_notify_after_secs(),
+            glyphs: GlyphStyle::Auto,
+            reduced_motion: false,
+            status_bar: true,
+            github_token: None,
+            system_prompts: HashMap::new(),
+            preferences: BTreeMap::new(),
+            sync: None,
             providers,
+            project: None,
+            skip_permissions: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_overrides_are_not_saved_globally() {
+        let mut config = Config::default();
+        config.apply_project(ProjectConfig {
+            default_provider: Some("openai".to_string()),
+            mode: Some(Mode::Coach),
+            model: Some("project-model".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(config.default_provider, "openai");
+        assert_eq!(config.get_provider().unwrap().model, "project-model");
+
+        let saved = config.without_project();
+        assert_eq!(saved.default_provider, "gemini");
+        assert_eq!(saved.mode, Mode::Coding);
+        assert_eq!(saved.providers["openai"].model, Config::default().providers["openai"].model);
+
+        // Changes made during the session still persist
+        config.default_provider = "kimi".to_string();
+        assert_eq!(config.without_project().default_provider, "kimi");
+    }
+
+    #[test]
+    fn test_gateway_auth_migrates_to_provider() {
+        let provider = |gateway: &str| -> Provider {
+            serde_json::from_str(&format!(
+                r#"{{"base_url": "https://gw", "model": "m", "api_key_env": "KEY", "gateway": {}}}"#,
+                gateway
+            ))
+            .unwrap()
+        };
+
+        let mut portkey = provider(r#"{"auth_header": "x-portkey-api-key", "auth_prefix": ""}"#);
+        assert!(portkey.migrate_gateway_auth());
+        assert_eq!(portkey.auth, Auth::Header);
+        assert_eq!(portkey.auth_header.as_deref(), Some("x-portkey-api-key"));
+        assert_eq!(portkey.auth_prefix, None);
+        let saved = serde_json::to_value(&portkey).unwrap();
+        assert!(saved["gateway"].get("auth_header").is_none());
+        assert_eq!(saved["auth_header"], "x-portkey-api-key");
+
+        // A custom header keeps its prefix
+        let mut prefixed = provider(r#"{"auth_header": "x-api-key", "auth_prefix": "Token "}"#);
+        assert!(prefixed.migrate_gateway_auth());
+        assert_eq!(prefixed.auth_prefix.as_deref(), Some("Token "));
+
+        // The old defaults were plain bearer auth
+        let mut bearer = provider(r#"{"auth_header": "Authorization"}"#);
+        assert!(bearer.migrate_gateway_auth());
+        assert_eq!(bearer.auth, Auth::Bearer);
+        assert!(!provider(r#"{"model_prefix": "openai/"}"#).migrate_gateway_auth());
+    }
+}
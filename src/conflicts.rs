@@ -0,0 +1,161 @@
+use crate::changelog::git;
+use std::path::Path;
+
+/// Conflicts longer than this are listed by line range instead of quoted in the prompt
+const MAX_QUOTED_LINES: usize = 200;
+
+/// One `<<<<<<< ... >>>>>>>` block, 1-indexed inclusive lines including the markers
+#[derive(Debug, PartialEq)]
+struct Hunk {
+    start: usize,
+    end: usize,
+}
+
+/// Conflict blocks in a file's text
+fn hunks(content: &str) -> Vec<Hunk> {
+    let mut hunks = Vec::new();
+    let mut start = None;
+    for (i, line) in content.lines().enumerate() {
+        if line.starts_with("<<<<<<<") {
+            start = Some(i + 1);
+        } else if line.starts_with(">>>>>>>")
+            && let Some(start) = start.take()
+        {
+            hunks.push(Hunk { start, end: i + 1 });
+        }
+    }
+    hunks
+}
+
+/// Whether any line is a conflict marker, even one left over from a half-resolved block
+fn has_markers(content: &str) -> bool {
+    content.lines().any(|line| {
+        ["<<<<<<<", "|||||||", "=======", ">>>>>>>"]
+            .iter()
+            .any(|marker| line.strip_prefix(marker).is_some_and(|rest| rest.is_empty() || rest.starts_with(' ')))
+    })
+}
+
+/// Files git reports as unmerged, relative to the current directory
+fn conflicted_files() -> Result<Vec<String>, String> {
+    let output = git(&["diff", "--name-only", "--diff-filter=U"])?;
+    let to_root = git(&["rev-parse", "--show-cdup"])?;
+    Ok(output.lines().map(|file| format!("{}{}", to_root.trim(), file)).collect())
+}
+
+/// The operation that stopped on conflicts, for the prompt and the continue hint
+fn operation() -> Option<&'static str> {
+    [("rebase-merge", "rebase"), ("rebase-apply", "rebase"), ("MERGE_HEAD", "merge"), ("CHERRY_PICK_HEAD", "cherry-pick"), ("REVERT_HEAD", "revert")]
+        .into_iter()
+        .find(|(file, _)| git(&["rev-parse", "--git-path", file]).is_ok_and(|path| Path::new(path.trim()).exists()))
+        .map(|(_, operation)| operation)
+}
+
+/// Prompt asking the model to resolve every conflict in the working tree, and the files
+/// it covers (those with conflict markers; deleted-on-one-side and binary conflicts are
+/// left to the user)
+pub fn prompt() -> Result<(String, Vec<String>), String> {
+    let files = conflicted_files()?;
+    if files.is_empty() {
+        return Err("No conflicted files".to_string());
+    }
+
+    let mut sections = Vec::new();
+    let mut covered = Vec::new();
+    let mut skipped = Vec::new();
+    for file in files {
+        let content = std::fs::read_to_string(&file).unwrap_or_default();
+        let hunks = hunks(&content);
+        if hunks.is_empty() {
+            skipped.push(file);
+            continue;
+        }
+        let lines: Vec<&str> = content.lines().collect();
+        let mut section = format!("### {} ({} conflict{})", file, hunks.len(), if hunks.len() == 1 { "" } else { "s" });
+        for hunk in &hunks {
+            if hunk.end - hunk.start < MAX_QUOTED_LINES {
+                section.push_str(&format!("\n\nLines {}-{}:\n```\n{}\n```", hunk.start, hunk.end, lines[hunk.start - 1..hunk.end].join("\n")));
+            } else {
+                section.push_str(&format!("\n\nLines {}-{} ({} lines; read them with read_file)", hunk.start, hunk.end, hunk.end - hunk.start + 1));
+            }
+        }
+        sections.push(section);
+        covered.push(file);
+    }
+    if covered.is_empty() {
+        return Err(format!("No conflict markers to resolve; decide these yourself: {}", skipped.join(", ")));
+    }
+
+    let operation = operation();
+    let mut prompt = format!(
+        "Resolve the git {}conflicts below. For each one, work out what both sides meant to change (read the \
+         surrounding code, and `git log` for the commits involved if it helps) and combine them; where they \
+         truly contradict, pick one and say why. Replace each whole conflict block, markers included, with \
+         edit_lines or edit_file; the user approves every edit as a diff. Don't run `git add` or `--continue`: \
+         hal marks files resolved once no markers are left. Leave a conflict alone and explain it if only the \
+         user can decide it.",
+        operation.map(|op| format!("{} ", op)).unwrap_or_default()
+    );
+    if operation == Some("rebase") {
+        prompt.push_str("\n\nThis is a rebase: HEAD (\"ours\") is the branch being rebased onto, and the other side is the commit being replayed.");
+    }
+    if !skipped.is_empty() {
+        prompt.push_str(&format!("\n\nAlso conflicted, but without markers (deleted on one side or binary), so leave them to the user: {}", skipped.join(", ")));
+    }
+    prompt.push_str(&format!("\n\n{}", sections.join("\n\n")));
+    Ok((prompt, covered))
+}
+
+/// `git add` the files that no longer have conflict markers. Returns a note for the
+/// transcript: what was marked resolved, what is left, and how to continue.
+pub fn mark_resolved(files: &[String]) -> String {
+    let (resolved, remaining): (Vec<&String>, Vec<&String>) =
+        files.iter().partition(|file| std::fs::read_to_string(file).is_ok_and(|content| !has_markers(&content)));
+    let mut notes = Vec::new();
+    if !resolved.is_empty() {
+        let mut args = vec!["add", "--"];
+        args.extend(resolved.iter().map(|f| f.as_str()));
+        match git(&args) {
+            Ok(_) => notes.push(format!("Marked resolved: {}", join(&resolved))),
+            Err(e) => notes.push(format!("Failed to mark {} resolved: {}", join(&resolved), e)),
+        }
+    }
+    if !remaining.is_empty() {
+        notes.push(format!("Still conflicted: {}", join(&remaining)));
+    }
+    // Conflicts hal wasn't asked about, such as a file deleted on one side
+    let others: Vec<String> = conflicted_files().unwrap_or_default().into_iter().filter(|f| !remaining.contains(&f)).collect();
+    if !others.is_empty() {
+        notes.push(format!("Also unmerged: {}", others.join(", ")));
+    } else if remaining.is_empty()
+        && let Some(op) = operation()
+    {
+        notes.push(format!("Review the result, then run `git {} --continue`", op));
+    }
+    format!("*{}*", notes.join(". "))
+}
+
+fn join(files: &[&String]) -> String {
+    files.iter().map(|f| f.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hunks() {
+        let content = "a\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\nb\n<<<<<<< HEAD\nx\n||||||| base\nw\n=======\ny\n>>>>>>> feature\n";
+        assert_eq!(hunks(content), vec![Hunk { start: 2, end: 6 }, Hunk { start: 8, end: 14 }]);
+        assert!(hunks("no conflicts\n=======\n").is_empty());
+    }
+
+    #[test]
+    fn test_has_markers() {
+        assert!(!has_markers("resolved\n== not a marker\n"));
+        // A block with one end removed still counts
+        assert!(has_markers("ours\n=======\ntheirs\n>>>>>>> feature\n"));
+        assert!(has_markers("x\n||||||| base\n"));
+        assert!(!has_markers("<<<<<<<<< longer run\n"));
+    }
+}
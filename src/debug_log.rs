@@ -0,0 +1,98 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Older log files are deleted when a new one is started
+const MAX_LOG_FILES: usize = 20;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LOG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+fn logs_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("hal")
+        .join("logs")
+}
+
+/// This process's log file, named when first asked for
+pub fn path() -> &'static PathBuf {
+    LOG_PATH.get_or_init(|| {
+        let name = format!("hal-{}-{}.log", chrono::Local::now().format("%Y%m%d-%H%M%S"), std::process::id());
+        logs_dir().join(name)
+    })
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Append a timestamped entry when debug logging is on. Each `secrets` value is
+/// replaced with [redacted], as are bearer tokens and sk-style keys.
+pub fn log(title: &str, body: &str, secrets: &[&str]) {
+    if !enabled() {
+        return;
+    }
+    let path = path();
+    let fresh = !path.exists();
+    if fresh && let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+        prune(dir);
+    }
+    let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) else { return };
+    let entry = format!(
+        "=== {} {}\n{}\n\n",
+        chrono::Local::now().format("%H:%M:%S%.3f"),
+        title,
+        redact(body.trim_end(), secrets)
+    );
+    let _ = file.write_all(entry.as_bytes());
+}
+
+pub fn redact(text: &str, secrets: &[&str]) -> String {
+    let mut text = text.to_string();
+    for secret in secrets.iter().filter(|s| s.len() >= 8) {
+        text = text.replace(secret, "[redacted]");
+    }
+    let keys = regex::Regex::new(r"(?i)(bearer\s+)[A-Za-z0-9._~+/=-]{8,}|\bsk-[A-Za-z0-9_-]{8,}").expect("valid regex");
+    keys.replace_all(&text, |caps: &regex::Captures| {
+        format!("{}[redacted]", caps.get(1).map_or("", |m| m.as_str()))
+    })
+    .into_owned()
+}
+
+/// Keep the newest MAX_LOG_FILES - 1 logs, leaving room for the one being started
+fn prune(dir: &std::path::Path) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut logs: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "log"))
+        .collect();
+    logs.sort(); // Names start with the timestamp
+    let excess = logs.len().saturating_sub(MAX_LOG_FILES - 1);
+    for old in &logs[..excess] {
+        let _ = fs::remove_file(old);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let text = r#"{"key": "abc123secretvalue", "auth": "Bearer eyJhbGciOiJIUzI1NiJ9.x", "other": "sk-proj-AAAAbbbbCCCC"}"#;
+        assert_eq!(
+            redact(text, &["abc123secretvalue", ""]),
+            r#"{"key": "[redacted]", "auth": "Bearer [redacted]", "other": "[redacted]"}"#
+        );
+        assert_eq!(redact("task-list and skip", &[]), "task-list and skip");
+    }
+}
@@ -0,0 +1,128 @@
+use crate::changelog::git;
+
+/// Default period when /digest is given no ref or date
+const DEFAULT_SINCE: &str = "1 week ago";
+/// Caps on what goes into the prompt
+const MAX_COMMITS: usize = 200;
+const MAX_STAT_LINES: usize = 60;
+const MAX_TODOS: usize = 40;
+
+/// Git's hash of the empty tree, to diff against when the period starts before the first commit
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+const MARKERS: &[&str] = &["TODO", "FIXME", "XXX", "HACK"];
+
+/// TODO/FIXME lines added and removed in a `git diff -U0`, as "path: text"
+fn todo_deltas(diff: &str) -> (Vec<String>, Vec<String>) {
+    let (mut added, mut removed) = (Vec::new(), Vec::new());
+    let mut file = "";
+    for line in diff.lines() {
+        if let Some(path) = line.strip_prefix("+++ ") {
+            file = path.strip_prefix("b/").unwrap_or(path);
+            continue;
+        }
+        if line.starts_with("--- ") {
+            continue;
+        }
+        let (list, text) = match line.split_at_checked(1) {
+            Some(("+", text)) => (&mut added, text),
+            Some(("-", text)) => (&mut removed, text),
+            _ => continue,
+        };
+        if MARKERS.iter().any(|m| text.contains(m)) {
+            list.push(format!("{}: {}", file, text.trim()));
+        }
+    }
+    // A marker that only moved or was reworded in place isn't news
+    let moved: Vec<String> = added.iter().filter(|a| removed.contains(a)).cloned().collect();
+    added.retain(|a| !moved.contains(a));
+    removed.retain(|r| !moved.contains(r));
+    (added, removed)
+}
+
+fn capped(lines: Vec<String>, max: usize) -> String {
+    let total = lines.len();
+    let mut out: Vec<String> = lines.into_iter().take(max).collect();
+    if total > max {
+        out.push(format!("... and {} more", total - max));
+    }
+    out.join("\n")
+}
+
+/// Prompt asking for a stand-up style summary of activity since `since`: a ref
+/// (tag, branch, commit) or anything `git log --since` accepts. Defaults to a week.
+pub fn prompt(since: &str) -> Result<String, String> {
+    let since = if since.is_empty() { DEFAULT_SINCE } else { since };
+    let is_ref = git(&["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", since)]).is_ok();
+
+    let (base, log) = if is_ref {
+        let range = format!("{}..HEAD", since);
+        (since.to_string(), git(&["log", "--no-merges", "--date=short", "--pretty=format:%h %ad %an: %s", &range])?)
+    } else {
+        let since_arg = format!("--since={}", since);
+        let before_arg = format!("--before={}", since);
+        let log = git(&["log", "--no-merges", "--date=short", "--pretty=format:%h %ad %an: %s", &since_arg])?;
+        let base = git(&["rev-list", "-1", &before_arg, "HEAD"])?.trim().to_string();
+        (if base.is_empty() { EMPTY_TREE.to_string() } else { base }, log)
+    };
+    let status = git(&["status", "--short"]).unwrap_or_default();
+    if log.trim().is_empty() && status.trim().is_empty() {
+        return Err(format!("No commits or uncommitted changes since {}", since));
+    }
+
+    let commits: Vec<String> = log.lines().map(str::to_string).collect();
+    let stat: Vec<String> = git(&["diff", "--stat=100", &base, "HEAD"])?.lines().map(str::to_string).collect();
+    let (added, removed) = todo_deltas(&git(&["diff", "-U0", &base, "HEAD"])?);
+
+    let mut sections = vec![format!("{} commits:\n\n{}", commits.len(), capped(commits, MAX_COMMITS))];
+    if !stat.is_empty() {
+        // The stat's last line is the totals; keep it when the list is cut
+        let totals = stat.last().cloned().unwrap_or_default();
+        let files = stat[..stat.len() - 1].to_vec();
+        sections.push(format!("Files changed:\n\n{}\n{}", capped(files, MAX_STAT_LINES), totals));
+    }
+    if !added.is_empty() {
+        sections.push(format!("TODO/FIXME added:\n\n{}", capped(added, MAX_TODOS)));
+    }
+    if !removed.is_empty() {
+        sections.push(format!("TODO/FIXME resolved:\n\n{}", capped(removed, MAX_TODOS)));
+    }
+    if !status.trim().is_empty() {
+        sections.push(format!("Uncommitted:\n\n{}", status.trim_end()));
+    }
+
+    Ok(format!(
+        "Write a stand-up style digest of the repository activity since `{}` from the git data below: \
+         what got done (grouped by theme, not commit by commit), what's in progress, TODOs/FIXMEs worth \
+         mentioning, and anything that looks risky or unfinished. Keep it to short bullet points a \
+         teammate can skim. Don't change any files.\n\n{}",
+        since,
+        sections.join("\n\n")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_todo_deltas() {
+        let diff = "\
+diff --git a/src/a.rs b/src/a.rs
+--- a/src/a.rs
++++ b/src/a.rs
+@@ -1 +1,2 @@
+-// TODO: handle errors
++// FIXME: this leaks
++let x = 1;
+--- a/src/b.rs
++++ b/src/b.rs
+@@ -3 +3 @@
+-    // TODO: cache this
++    // TODO: cache this
+";
+        let (added, removed) = todo_deltas(diff);
+        assert_eq!(added, ["src/a.rs: // FIXME: this leaks"]);
+        assert_eq!(removed, ["src/a.rs: // TODO: handle errors"]);
+    }
+}
@@ -0,0 +1,37 @@
+use std::fs;
+use std::path::PathBuf;
+
+/// Notes collected across prompts and restarts, kept until cleared
+fn draft_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("hal")
+        .join("draft.md")
+}
+
+pub fn load() -> String {
+    fs::read_to_string(draft_path()).unwrap_or_default()
+}
+
+/// Append a note, separated from the previous one by a blank line
+pub fn append(text: &str) -> Result<(), String> {
+    let mut draft = load();
+    if !draft.trim().is_empty() {
+        draft = format!("{}\n\n", draft.trim_end());
+    }
+    draft.push_str(text.trim());
+    draft.push('\n');
+    save(&draft)
+}
+
+pub fn clear() -> Result<(), String> {
+    save("")
+}
+
+fn save(draft: &str) -> Result<(), String> {
+    let path = draft_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(&path, draft).map_err(|e| format!("Failed to save draft: {}", e))
+}
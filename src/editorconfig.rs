@@ -0,0 +1,240 @@
+use similar::{ChangeTag, TextDiff};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The .editorconfig properties applied to writes; None when unset
+#[derive(Debug, Default, PartialEq)]
+struct Properties {
+    indent_style: Option<IndentStyle>,
+    indent_size: Option<usize>,
+    tab_width: Option<usize>,
+    insert_final_newline: Option<bool>,
+    trim_trailing_whitespace: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IndentStyle {
+    Tab,
+    Space,
+}
+
+/// `new` with its changed lines brought in line with the .editorconfig for `path`.
+/// Lines unchanged from `old` are left alone so an edit doesn't reformat the whole file.
+pub fn conform(path: &str, old: &str, new: String) -> String {
+    let props = properties(Path::new(path));
+    if props == Properties::default() {
+        return new;
+    }
+
+    let mut out = String::with_capacity(new.len());
+    let mut last_changed = false;
+    for change in TextDiff::from_lines(old, &new).iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Delete => {}
+            ChangeTag::Equal => {
+                out.push_str(change.value());
+                last_changed = false;
+            }
+            ChangeTag::Insert => {
+                out.push_str(&props.line(change.value()));
+                last_changed = true;
+            }
+        }
+    }
+
+    if last_changed && !out.is_empty() {
+        match props.insert_final_newline {
+            Some(true) if !out.ends_with('\n') => out.push_str(if out.contains("\r\n") { "\r\n" } else { "\n" }),
+            Some(false) => {
+                let trimmed = out.trim_end_matches(['\r', '\n']).len();
+                out.truncate(trimmed);
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+impl Properties {
+    /// Columns a tab stands for, if the config says
+    fn tab_columns(&self) -> Option<usize> {
+        self.tab_width.or(self.indent_size).filter(|&n| n > 0)
+    }
+
+    fn line(&self, line: &str) -> String {
+        let body = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[body.len()..];
+        let body = if self.trim_trailing_whitespace == Some(true) { body.trim_end_matches([' ', '\t']) } else { body };
+
+        let text = body.trim_start_matches([' ', '\t']);
+        let indent = &body[..body.len() - text.len()];
+        let reindented = match (self.indent_style, self.tab_columns()) {
+            (Some(IndentStyle::Tab), Some(tab)) if indent.contains(' ') => {
+                let width = indent_width(indent, tab);
+                format!("{}{}", "\t".repeat(width / tab), " ".repeat(width % tab))
+            }
+            (Some(IndentStyle::Space), Some(tab)) if indent.contains('\t') => " ".repeat(indent_width(indent, tab)),
+            _ => indent.to_string(),
+        };
+        format!("{}{}{}", reindented, text, ending)
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        let unset = value == "unset";
+        let flag = |v: &str| match v {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+        match key {
+            "indent_style" => {
+                self.indent_style = match value {
+                    "tab" => Some(IndentStyle::Tab),
+                    "space" => Some(IndentStyle::Space),
+                    _ => None,
+                }
+            }
+            // "indent_size = tab" means the tab width
+            "indent_size" => self.indent_size = if unset { None } else { value.parse().ok().or(self.tab_width) },
+            "tab_width" => self.tab_width = value.parse().ok(),
+            "insert_final_newline" => self.insert_final_newline = flag(value),
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace = flag(value),
+            _ => {}
+        }
+    }
+}
+
+/// Visual width of leading whitespace with tab stops every `tab` columns
+fn indent_width(indent: &str, tab: usize) -> usize {
+    indent.chars().fold(0, |width, c| if c == '\t' { (width / tab + 1) * tab } else { width + 1 })
+}
+
+/// Properties for `path` from each .editorconfig between it and the nearest `root = true`,
+/// closer files taking precedence
+fn properties(path: &Path) -> Properties {
+    let mut props = Properties::default();
+    let Ok(path) = std::path::absolute(path) else {
+        return props;
+    };
+
+    let mut configs: Vec<(PathBuf, String)> = Vec::new();
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if let Ok(text) = fs::read_to_string(d.join(".editorconfig")) {
+            let root = text.lines().take_while(|l| !l.trim_start().starts_with('[')).any(|l| {
+                l.split_once('=').is_some_and(|(k, v)| {
+                    k.trim().eq_ignore_ascii_case("root") && v.trim().eq_ignore_ascii_case("true")
+                })
+            });
+            configs.push((d.to_path_buf(), text));
+            if root {
+                break;
+            }
+        }
+        dir = d.parent();
+    }
+
+    for (dir, text) in configs.iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else { continue };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        let mut matched = false;
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                matched = section_matches(section, &relative);
+            } else if matched && let Some((key, value)) = line.split_once('=') {
+                props.set(&key.trim().to_lowercase(), &value.trim().to_lowercase());
+            }
+        }
+    }
+    props
+}
+
+/// Whether a section glob matches `relative` (a /-separated path from the .editorconfig's directory).
+/// Globs without a slash match the file name at any depth.
+fn section_matches(section: &str, relative: &str) -> bool {
+    let glob = match section.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if section.contains('/') => section.to_string(),
+        None => format!("**/{}", section),
+    };
+    globset::GlobBuilder::new(&to_globset(&glob))
+        .literal_separator(true)
+        .backslash_escape(true)
+        .empty_alternates(true)
+        .build()
+        .is_ok_and(|glob| glob.compile_matcher().is_match(relative))
+}
+
+/// Numeric ranges expanded into alternatives; larger ones never match
+const MAX_RANGE: i64 = 1000;
+
+/// Rewrite the EditorConfig glob syntax globset lacks: `**` inside a path component,
+/// which crosses directories there too, and numeric ranges like {1..9}
+fn to_globset(glob: &str) -> String {
+    let range = regex::Regex::new(r"\{(-?[0-9]+)\.\.(-?[0-9]+)\}").expect("valid regex");
+    let glob = range.replace_all(glob, |caps: &regex::Captures| {
+        let (a, b): (i64, i64) = (caps[1].parse().unwrap_or(0), caps[2].parse().unwrap_or(0));
+        let (low, high) = (a.min(b), a.max(b));
+        if high - low > MAX_RANGE {
+            return "{}".to_string();
+        }
+        format!("{{{}}}", (low..=high).map(|n| n.to_string()).collect::<Vec<_>>().join(","))
+    });
+
+    let mut out = String::new();
+    let mut rest: &str = &glob;
+    while let Some(i) = rest.find("**") {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 2..];
+        let starts = out.is_empty() || out.ends_with('/');
+        let ends = rest.is_empty() || rest.starts_with('/');
+        out.push_str(match (starts, ends) {
+            (true, true) => "**",
+            (true, false) => "**/*",
+            (false, true) => "*{,/**}",
+            (false, false) => "*{,/**/}*",
+        });
+    }
+    out + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conform_changed_lines() {
+        assert!(section_matches("*.{js,ts}", "src/app.ts"));
+        assert!(section_matches("/Makefile", "Makefile"));
+        assert!(!section_matches("/Makefile", "sub/Makefile"));
+        assert!(section_matches("lib/**.go", "lib/a/b.go"));
+        assert!(!section_matches("*.go", "main.rs"));
+        assert!(section_matches("lib/**.go", "lib/b.go"));
+        assert!(section_matches("build**", "build-out/x/y.txt"));
+        assert!(section_matches("/a**z.txt", "ab/c/dz.txt"));
+        assert!(section_matches("file{1..3}.txt", "docs/file2.txt"));
+        assert!(!section_matches("file{1..3}.txt", "file4.txt"));
+        assert!(section_matches("*.{js,{md,txt}}", "README.md"));
+        assert!(section_matches("[!a]*.rs", "b.rs"));
+        assert!(!section_matches("[!a]*.rs", "a.rs"));
+
+        let dir = std::env::temp_dir().join(format!("hal-editorconfig-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join(".editorconfig"),
+            "root = true\n\n[*]\ntrim_trailing_whitespace = true\ninsert_final_newline = true\n\n[*.go]\nindent_style = tab\nindent_size = 4\n",
+        )
+        .unwrap();
+        let path = dir.join("src/main.go");
+        let path = path.to_str().unwrap();
+
+        // The untouched line keeps its trailing space; new lines get tabs and lose theirs
+        let old = "func a() {  \n\treturn\n}\n";
+        let new = "func a() {  \n        x := 1   \n\treturn\n}".to_string();
+        assert_eq!(conform(path, old, new), "func a() {  \n\t\tx := 1\n\treturn\n}\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
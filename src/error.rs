@@ -0,0 +1,104 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Failures shown to the user, by kind so each can say what to do next. Covers provider
+/// requests and sandbox policy saves, where the kind decides the hint; commands and
+/// helpers whose errors are shown as-is keep returning `Result<_, String>`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HalError {
+    /// The provider rejected the credentials (401/403)
+    Auth { status: u16, message: String, key_env: String },
+    /// Still rate limited (429) after retrying
+    RateLimit { message: String, retry_after: Duration },
+    /// The conversation no longer fits the model's context window
+    ContextOverflow { status: u16, message: String },
+    /// Any other error status from the provider
+    Api { status: u16, message: String },
+    /// The request never got a response
+    Network(String),
+    /// The response couldn't be read
+    Parse(String),
+    /// Sandbox policy couldn't be saved
+    Sandbox(String),
+}
+
+/// Phrases providers use when a request exceeds the context window
+const CONTEXT_OVERFLOW: &[&str] = &[
+    "context_length_exceeded",
+    "context length",
+    "context window",
+    "maximum context",
+    "prompt is too long",
+    "too many tokens",
+    "reduce the length",
+];
+
+impl HalError {
+    /// Classify an error status and message from the provider
+    pub fn from_status(status: u16, message: String, key_env: &str, retry_after: Duration) -> Self {
+        let lower = message.to_lowercase();
+        match status {
+            401 | 403 => HalError::Auth { status, message, key_env: key_env.to_string() },
+            429 => HalError::RateLimit { message, retry_after },
+            400 | 413 if CONTEXT_OVERFLOW.iter().any(|p| lower.contains(p)) => HalError::ContextOverflow { status, message },
+            _ => HalError::Api { status, message },
+        }
+    }
+
+    /// What the user can do about it, if there's anything specific
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            HalError::Auth { key_env, .. } if key_env.is_empty() => Some("run /key <key> to set the API key".to_string()),
+            HalError::Auth { key_env, .. } => Some(format!("run /key <key> or set ${}", key_env)),
+            HalError::RateLimit { retry_after, .. } => Some(format!("rate limited; send again in {}s", retry_after.as_secs())),
+            HalError::ContextOverflow { .. } => {
+                Some("the conversation is too long for this model; /handoff then /clear to continue fresh, or /model to switch".to_string())
+            }
+            HalError::Api { status, .. } if *status >= 500 => Some("the provider is having trouble; /retry in a moment".to_string()),
+            HalError::Api { .. } => Some("/debug logs the full request to see what was rejected".to_string()),
+            HalError::Network(_) => Some("check your connection and the provider's base_url".to_string()),
+            HalError::Parse(_) => Some("/debug logs the raw response".to_string()),
+            HalError::Sandbox(_) => Some("check that the config directory is writable".to_string()),
+        }
+    }
+
+    /// The error followed by its hint, on one line
+    pub fn with_hint(&self) -> String {
+        match self.hint() {
+            Some(hint) => format!("{} — {}", self, hint),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for HalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HalError::Auth { status, message, .. }
+            | HalError::ContextOverflow { status, message }
+            | HalError::Api { status, message } => write!(f, "{}: {}", status, message),
+            HalError::RateLimit { message, .. } => write!(f, "429: {}", message),
+            HalError::Network(message) | HalError::Parse(message) | HalError::Sandbox(message) => f.write_str(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status_hints() {
+        let wait = Duration::from_secs(20);
+        let error = |status, message: &str| HalError::from_status(status, message.to_string(), "OPENAI_API_KEY", wait);
+
+        let auth = error(401, "Incorrect API key provided");
+        assert_eq!(auth.with_hint(), "401: Incorrect API key provided — run /key <key> or set $OPENAI_API_KEY");
+        assert_eq!(error(429, "Slow down").hint().unwrap(), "rate limited; send again in 20s");
+        assert!(matches!(
+            error(400, "This model's maximum context length is 128000 tokens"),
+            HalError::ContextOverflow { .. }
+        ));
+        assert!(matches!(error(400, "Invalid 'messages[2].role'"), HalError::Api { status: 400, .. }));
+    }
+}
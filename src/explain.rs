@@ -0,0 +1,52 @@
+use crate::changelog::git;
+
+/// Largest diff sent as-is; anything longer is cut with a note
+const MAX_DIFF_BYTES: usize = 200_000;
+
+/// Which changes `hal explain` looks at
+pub enum Target {
+    Worktree,
+    Staged,
+    Range(String),
+}
+
+impl Target {
+    fn describe(&self) -> String {
+        match self {
+            Target::Worktree => "unstaged changes".to_string(),
+            Target::Staged => "staged changes".to_string(),
+            Target::Range(range) => format!("changes in `{}`", range),
+        }
+    }
+}
+
+/// Prompt asking the model to explain and review the diff for `target`
+pub fn prompt(target: &Target) -> Result<String, String> {
+    let mut diff = match target {
+        Target::Worktree => git(&["diff"])?,
+        Target::Staged => git(&["diff", "--staged"])?,
+        Target::Range(range) => git(&["diff", range])?,
+    };
+    if diff.trim().is_empty() {
+        return Err(format!("No {} to explain", target.describe()));
+    }
+    let stat = match target {
+        Target::Worktree => git(&["diff", "--stat"])?,
+        Target::Staged => git(&["diff", "--staged", "--stat"])?,
+        Target::Range(range) => git(&["diff", "--stat", range])?,
+    };
+    if diff.len() > MAX_DIFF_BYTES {
+        let cut = (0..=MAX_DIFF_BYTES).rev().find(|&i| diff.is_char_boundary(i)).unwrap_or(0);
+        diff.truncate(cut);
+        diff.push_str("\n[diff truncated]\n");
+    }
+
+    Ok(format!(
+        "Explain the {} below for a reviewer: summarize what changed and why it likely changed, then \
+         list anything that looks wrong or risky (bugs, missed cases, breaking changes) with file and \
+         line. You have no tools; work only from the diff.\n\n{}\n```diff\n{}```",
+        target.describe(),
+        stat.trim_end(),
+        diff
+    ))
+}
@@ -0,0 +1,107 @@
+use serde_json::Value;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Longest a GitHub request may take, connecting included
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+static CONFIG_TOKEN: Mutex<Option<String>> = Mutex::new(None);
+
+/// Use github_token from the loaded config when neither environment variable is set
+pub fn set_token(token: Option<String>) {
+    *CONFIG_TOKEN.lock().unwrap_or_else(|e| e.into_inner()) = token;
+}
+
+/// Parse "owner/repo#123", "gh:owner/repo#123" or a github.com issue/PR URL
+pub fn parse_ref(reference: &str) -> Option<(String, u64)> {
+    let reference = reference.trim().trim_start_matches("gh:");
+    if let Some(rest) = reference
+        .strip_prefix("https://github.com/")
+        .or_else(|| reference.strip_prefix("github.com/"))
+    {
+        let parts: Vec<&str> = rest.split('/').collect();
+        if parts.len() >= 4 && matches!(parts[2], "issues" | "pull") {
+            let number = parts[3].split(['#', '?']).next()?.parse().ok()?;
+            return Some((format!("{}/{}", parts[0], parts[1]), number));
+        }
+        return None;
+    }
+
+    let (repo, number) = reference.split_once('#')?;
+    if repo.split('/').count() != 2 || repo.starts_with('/') || repo.ends_with('/') {
+        return None;
+    }
+    Some((repo.to_string(), number.parse().ok()?))
+}
+
+/// Token from $GITHUB_TOKEN, $GH_TOKEN or github_token in the config
+fn token() -> Option<String> {
+    std::env::var("GITHUB_TOKEN")
+        .or_else(|_| std::env::var("GH_TOKEN"))
+        .ok()
+        .filter(|t| !t.is_empty())
+        .or_else(|| CONFIG_TOKEN.lock().unwrap_or_else(|e| e.into_inner()).clone())
+}
+
+fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| ureq::Agent::config_builder().timeout_global(Some(TIMEOUT)).build().new_agent())
+}
+
+fn get(url: &str) -> Result<Value, String> {
+    let mut request = agent()
+        .get(url)
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "hal");
+    if let Some(token) = token() {
+        request = request.header("Authorization", &format!("Bearer {}", token));
+    }
+    request
+        .call()
+        .map_err(|e| match e {
+            ureq::Error::StatusCode(404) => "not found (private repos need GITHUB_TOKEN)".to_string(),
+            ureq::Error::StatusCode(403) => "forbidden or rate limited (set GITHUB_TOKEN)".to_string(),
+            e => e.to_string(),
+        })?
+        .body_mut()
+        .read_json()
+        .map_err(|e| format!("Failed to parse GitHub response: {}", e))
+}
+
+/// Fetch an issue or PR with its comments, formatted as markdown
+pub fn fetch_issue(repo: &str, number: u64) -> Result<String, String> {
+    let base = format!("https://api.github.com/repos/{}/issues/{}", repo, number);
+    let issue = get(&base).map_err(|e| format!("Error fetching {}#{}: {}", repo, number, e))?;
+    let comments = get(&format!("{}/comments?per_page=100", base))
+        .map_err(|e| format!("Error fetching comments for {}#{}: {}", repo, number, e))?;
+
+    let kind = if issue.get("pull_request").is_some() { "PR" } else { "Issue" };
+    let labels: Vec<&str> = issue["labels"]
+        .as_array()
+        .map(|l| l.iter().filter_map(|l| l["name"].as_str()).collect())
+        .unwrap_or_default();
+
+    let mut out = format!(
+        "# {} {}#{}: {}\n\nState: {} · Author: @{}",
+        kind,
+        repo,
+        number,
+        issue["title"].as_str().unwrap_or(""),
+        issue["state"].as_str().unwrap_or("?"),
+        issue["user"]["login"].as_str().unwrap_or("?"),
+    );
+    if !labels.is_empty() {
+        out.push_str(&format!(" · Labels: {}", labels.join(", ")));
+    }
+    out.push_str(&format!("\n\n{}\n", issue["body"].as_str().unwrap_or("(no description)").trim()));
+
+    for comment in comments.as_array().into_iter().flatten() {
+        out.push_str(&format!(
+            "\n---\n@{} ({}):\n\n{}\n",
+            comment["user"]["login"].as_str().unwrap_or("?"),
+            comment["created_at"].as_str().unwrap_or(""),
+            comment["body"].as_str().unwrap_or("").trim()
+        ));
+    }
+    Ok(out)
+}
@@ -0,0 +1,133 @@
+use crate::config::GlyphStyle;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Decorative characters drawn by the UI and the terminal transcript
+pub struct Glyphs {
+    pub spinner: &'static [&'static str],
+    pub prompt: &'static str,    // Before user messages, the input and selected list items
+    pub tool: &'static str,      // Tool call succeeded
+    pub failed: &'static str,    // Tool call or action failed
+    pub declined: &'static str,  // Tool call declined or interrupted
+    pub ok: &'static str,        // Notices
+    pub bullet: &'static str,    // List items, busy tabs
+    pub separator: &'static str, // Between header and hint items
+    pub ellipsis: &'static str,
+    pub collapsed: &'static str, // Hidden tool output
+    pub bookmark: &'static str,
+    pub selection: &'static str, // Copy-mode gutter bar
+    pub newline: char,           // A line break shown inside one line (history search)
+    pub cursor: &'static str,
+    pub rule: &'static str,   // Horizontal separator, repeated
+    pub gutter: char,         // Between line numbers and code in diffs
+    pub hunks: &'static str,  // Between diff hunks
+    pub minus: &'static str,  // Removed-lines count in a diff stat
+    pub warning: &'static str,
+    pub arrow: &'static str,
+    pub branch: &'static str, // A session forked from another
+    pub pending: &'static str, // Plan items
+    pub in_progress: &'static str,
+    pub done: &'static str,
+    pub border: ratatui::symbols::border::Set<'static>,
+}
+
+const UNICODE: Glyphs = Glyphs {
+    spinner: &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"],
+    prompt: "›",
+    tool: "◇",
+    failed: "✗",
+    declined: "⊘",
+    ok: "✓",
+    bullet: "•",
+    separator: "·",
+    ellipsis: "…",
+    collapsed: "▸",
+    bookmark: "★",
+    selection: "▌",
+    newline: '⏎',
+    cursor: "▏",
+    rule: "─",
+    gutter: '│',
+    hunks: "···",
+    minus: "−",
+    warning: "⚠",
+    arrow: "→",
+    branch: "↳",
+    pending: "○",
+    in_progress: "◐",
+    done: "●",
+    border: ratatui::symbols::border::PLAIN,
+};
+
+const ASCII: Glyphs = Glyphs {
+    spinner: &["|", "/", "-", "\\"],
+    prompt: ">",
+    tool: "*",
+    failed: "x",
+    declined: "-",
+    ok: "+",
+    bullet: "*",
+    separator: "|",
+    ellipsis: "...",
+    collapsed: ">",
+    bookmark: "*",
+    selection: "|",
+    newline: '~',
+    cursor: "_",
+    rule: "-",
+    gutter: '|',
+    hunks: "...",
+    minus: "-",
+    warning: "!",
+    arrow: "->",
+    branch: "<-",
+    pending: "[ ]",
+    in_progress: "[~]",
+    done: "[x]",
+    border: ratatui::symbols::border::Set {
+        top_left: "+",
+        top_right: "+",
+        bottom_left: "+",
+        bottom_right: "+",
+        vertical_left: "|",
+        vertical_right: "|",
+        horizontal_top: "-",
+        horizontal_bottom: "-",
+    },
+};
+
+static USE_ASCII: AtomicBool = AtomicBool::new(false);
+
+/// Pick the glyph set for `style`; `auto` checks the terminal and locale
+pub fn set(style: GlyphStyle) {
+    let ascii = match style {
+        GlyphStyle::Ascii => true,
+        GlyphStyle::Unicode => false,
+        GlyphStyle::Auto => !unicode_supported(),
+    };
+    USE_ASCII.store(ascii, Ordering::SeqCst);
+}
+
+pub fn get() -> &'static Glyphs {
+    if USE_ASCII.load(Ordering::Relaxed) { &ASCII } else { &UNICODE }
+}
+
+/// Whether the terminal can be trusted with non-ASCII symbols: not a terminal type
+/// known to lack them, and a UTF-8 locale (the first of LC_ALL, LC_CTYPE, LANG that is set)
+fn unicode_supported() -> bool {
+    let term = std::env::var("TERM").unwrap_or_default();
+    if matches!(term.as_str(), "dumb" | "linux" | "vt100" | "vt102" | "vt220" | "ansi" | "cons25") {
+        return false;
+    }
+    // Windows has no locale variables; Windows Terminal and modern consoles render Unicode
+    if cfg!(windows) {
+        return true;
+    }
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty());
+    locale.is_some_and(|l| {
+        let l = l.to_lowercase();
+        l.contains("utf-8") || l.contains("utf8")
+    })
+}
@@ -0,0 +1,40 @@
+use crate::app::{ChatMessage, MessageRole};
+use crate::tools;
+
+/// Prompt asking the model to condense the conversation into a self-contained handoff
+/// document, listing the files this session read and changed so it can cite them
+pub fn prompt(messages: &[ChatMessage]) -> Result<String, String> {
+    if !messages.iter().any(|m| m.role == MessageRole::User) {
+        return Err("Nothing to hand off yet".to_string());
+    }
+
+    let mut changed: Vec<&str> = Vec::new();
+    let mut read: Vec<&str> = Vec::new();
+    for message in messages {
+        let MessageRole::Tool { name, path: Some(path), error: None } = &message.role else { continue };
+        let list = if tools::is_file_change(name) { &mut changed } else if name == "read_file" { &mut read } else { continue };
+        if !list.contains(&path.as_str()) {
+            list.push(path);
+        }
+    }
+    read.retain(|path| !changed.contains(path));
+
+    let mut prompt = "Write a handoff document so this work can continue in a fresh session or another tool \
+         that has none of this conversation. Reply with only the document, in Markdown, with these sections:\n\n\
+         ## Task\nWhat the user wants, in their terms, and where it stands.\n\n\
+         ## Decisions\nChoices made so far and why, including approaches ruled out.\n\n\
+         ## Relevant files\nEach path with one line on its role; leave out files that no longer matter.\n\n\
+         ## Current state\nWhat is done, what is verified, what is broken.\n\n\
+         ## Next steps\nA numbered list of concrete actions.\n\n\
+         ## Open questions\nAnything still waiting on the user.\n\n\
+         Make it self-contained: no references to \"above\" or \"earlier\", and quote exact names, commands \
+         and errors. Be brief and skip history that no longer matters. Don't call any tools."
+        .to_string();
+    if !changed.is_empty() {
+        prompt.push_str(&format!("\n\nFiles changed this session: {}", changed.join(", ")));
+    }
+    if !read.is_empty() {
+        prompt.push_str(&format!("\nFiles read this session: {}", read.join(", ")));
+    }
+    Ok(prompt)
+}
@@ -0,0 +1,283 @@
+use crate::app::{App, AppState, MessageRole};
+use crate::config::Config;
+use crate::session::Session;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Options for a non-interactive `-p` run
+pub struct HeadlessOptions {
+    pub prompt: String,
+    pub attachments: Vec<String>,
+    pub save_output: Option<PathBuf>,
+    pub session: Option<Session>,
+    pub tools: bool,
+    pub max_duration: Option<Duration>,
+    pub json_result: bool, // Print only the validated result block to stdout
+}
+
+/// Fence tag of the structured result block asked for by --json-result
+const RESULT_TAG: &str = "hal-result";
+/// Follow-up turns asking the model to fix a missing or invalid result block
+const RESULT_RETRIES: usize = 2;
+const RESULT_INSTRUCTIONS: &str = "## Final result
+
+This run is automated. End your final answer with a fenced block tagged `hal-result` holding one JSON object and nothing after it:
+
+```hal-result
+{\"files_changed\": [\"src/example.rs\"], \"summary\": \"What was done, in a few sentences\"}
+```
+
+`files_changed` lists every file you created, modified or deleted, relative to the working directory (empty if none). No other keys.";
+
+/// What happened during a run, for --fail-on
+pub struct Outcome {
+    pub tool_errors: usize,
+    pub files_changed: usize,
+    pub budget_exceeded: bool, // Stopped at the tool-call or time limit
+}
+
+/// Run one prompt to completion without a terminal UI and print the final answer.
+/// Nobody is there to answer modals: file changes are applied, sandbox permission
+/// requests are denied, and the tool-call and time limits stop the run.
+pub fn run(mut config: Config, options: HeadlessOptions) -> Result<Outcome, String> {
+    // The run exits right after the answer, before a title would come back
+    config.generate_titles = false;
+    let mut app = App::new(config, options.session)?;
+    if crate::debug_log::enabled() {
+        eprintln!("Debug log: {}", crate::debug_log::path().display());
+    }
+    let first_message = app.messages.len();
+    let mut budget_exceeded = false;
+    app.attachments = options.attachments;
+    if !options.tools {
+        app.disable_tools();
+    }
+    if let Some(limit) = options.max_duration {
+        app.set_max_duration(limit);
+    }
+    if options.json_result {
+        app.append_system_prompt(RESULT_INSTRUCTIONS);
+    }
+
+    let before = options.save_output.as_ref().map(|_| snapshot());
+
+    app.input = options.prompt.clone();
+    app.submit_input();
+    finish_turn(&mut app, &mut budget_exceeded)?;
+
+    let mut answer = last_answer(&app);
+    let mut result = None;
+    if options.json_result {
+        let mut retries = 0;
+        let (value, narrative) = loop {
+            match extract_result(&answer).and_then(|extracted| check_files_changed(&extracted.0).map(|_| extracted)) {
+                Ok(extracted) => break extracted,
+                Err(e) if retries < RESULT_RETRIES => {
+                    retries += 1;
+                    app.input = format!(
+                        "Your final answer needs a valid `{}` block: {}. Reply with your summary again, ending with the corrected block.",
+                        RESULT_TAG, e
+                    );
+                    app.submit_input();
+                    finish_turn(&mut app, &mut budget_exceeded)?;
+                    answer = last_answer(&app);
+                }
+                Err(e) => return Err(format!("No valid {} block in the final answer: {}", RESULT_TAG, e)),
+            }
+        };
+        eprintln!("{}", narrative);
+        println!("{}", value);
+        answer = narrative;
+        result = Some(value);
+    } else {
+        println!("{}", answer);
+    }
+
+    if let (Some(dir), Some(before)) = (options.save_output, before) {
+        save_output(&dir, &app, &options.prompt, &answer, result.as_ref(), &before)?;
+    }
+
+    let tool_errors = app.messages[first_message..]
+        .iter()
+        .filter(|m| matches!(m.role, MessageRole::Tool { error: Some(kind), .. } if !kind.declined()))
+        .count();
+    Ok(Outcome {
+        tool_errors,
+        files_changed: crate::undo::changes().len(),
+        budget_exceeded: budget_exceeded || app.timed_out,
+    })
+}
+
+/// Drive the submitted turn until the app is idle, answering modals as a headless run does
+fn finish_turn(app: &mut App, budget_exceeded: &mut bool) -> Result<(), String> {
+    loop {
+        app.poll_api_response();
+        app.poll_tool_result();
+
+        if let Some(modal) = &mut app.diff_modal {
+            modal.selected = 0; // Accept
+            app.modal_select();
+        } else if app.permission_modal.is_some() || app.continue_modal.is_some() || app.match_modal.is_some() {
+            *budget_exceeded |= app.continue_modal.is_some();
+            app.modal_cancel();
+        }
+
+        if let Some(e) = app.error.take() {
+            return Err(e);
+        }
+        if app.state == AppState::Idle && !app.has_modal() {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn last_answer(app: &App) -> String {
+    app.messages
+        .iter()
+        .rev()
+        .find(|m| m.role == MessageRole::Assistant)
+        .map(|m| m.content.clone())
+        .unwrap_or_default()
+}
+
+/// The last `hal-result` block in `answer`, checked against the result schema, and the
+/// answer without it
+fn extract_result(answer: &str) -> Result<(Value, String), String> {
+    let open = format!("```{}", RESULT_TAG);
+    let start = answer.rfind(&open).ok_or("the block is missing")?;
+    let body_start = start + open.len();
+    let body_end = answer[body_start..].find("```").map(|i| body_start + i).ok_or("the block is not closed")?;
+    let value: Value =
+        serde_json::from_str(answer[body_start..body_end].trim()).map_err(|e| format!("the block is not valid JSON ({})", e))?;
+
+    let object = value.as_object().ok_or("the block must hold a JSON object")?;
+    if let Some(key) = object.keys().find(|k| !matches!(k.as_str(), "files_changed" | "summary")) {
+        return Err(format!("unexpected key \"{}\"", key));
+    }
+    let files = object.get("files_changed").and_then(Value::as_array);
+    if !files.is_some_and(|files| files.iter().all(Value::is_string)) {
+        return Err("\"files_changed\" must be an array of paths".to_string());
+    }
+    if !object.get("summary").is_some_and(Value::is_string) {
+        return Err("\"summary\" must be a string".to_string());
+    }
+
+    let narrative = format!("{}{}", &answer[..start], &answer[body_end + 3..]);
+    Ok((value, narrative.trim().to_string()))
+}
+
+/// Fails if `files_changed` leaves out a file hal wrote this run. Extra entries are fine:
+/// changes made through bash aren't in the undo journal.
+fn check_files_changed(value: &Value) -> Result<(), String> {
+    let listed: Vec<PathBuf> = value["files_changed"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(|path| crate::paths::canonical(Path::new(path)))
+        .collect();
+    let missing: Vec<String> = crate::undo::changes()
+        .into_iter()
+        .map(|(path, _, _)| path)
+        .filter(|path| !listed.contains(&crate::paths::canonical(Path::new(path))))
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("\"files_changed\" leaves out {}, which this run changed", missing.join(", ")))
+    }
+}
+
+/// Modification time and size of every non-ignored file under the cwd
+fn snapshot() -> HashMap<String, (Option<SystemTime>, u64)> {
+    ignore::WalkBuilder::new(".")
+        .hidden(false)
+        .filter_entry(|e| e.file_name() != ".git")
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            let path = e.path().strip_prefix(".").unwrap_or(e.path()).to_string_lossy().to_string();
+            Some((path, (meta.modified().ok(), meta.len())))
+        })
+        .collect()
+}
+
+/// Write changed files, the final answer and a manifest.json into `dir`
+fn save_output(
+    dir: &Path,
+    app: &App,
+    prompt: &str,
+    answer: &str,
+    result: Option<&Value>,
+    before: &HashMap<String, (Option<SystemTime>, u64)>,
+) -> Result<(), String> {
+    let after = snapshot();
+    let mut changes: Vec<(String, &str)> = Vec::new();
+    for (path, stat) in &after {
+        match before.get(path) {
+            None => changes.push((path.clone(), "created")),
+            Some(old) if old != stat => changes.push((path.clone(), "modified")),
+            _ => {}
+        }
+    }
+    for path in before.keys().filter(|p| !after.contains_key(*p)) {
+        changes.push((path.clone(), "deleted"));
+    }
+    changes.sort();
+
+    let files_dir = dir.join("files");
+    for (path, status) in &changes {
+        if *status == "deleted" {
+            continue;
+        }
+        let dest = files_dir.join(path);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::copy(path, &dest).map_err(|e| format!("Failed to save {}: {}", path, e))?;
+    }
+
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    fs::write(dir.join("answer.md"), answer).map_err(|e| format!("Failed to write answer: {}", e))?;
+
+    let (tool_calls, (prompt_tokens, completion_tokens)) = app.turn_usage();
+    let mut manifest = json!({
+        "prompt": prompt,
+        "provider": app.provider_name,
+        "answer": "answer.md",
+        "files": changes
+            .iter()
+            .map(|(path, status)| json!({ "path": path, "status": status }))
+            .collect::<Vec<_>>(),
+        "tool_calls": tool_calls,
+        "tokens": { "prompt": prompt_tokens, "completion": completion_tokens },
+    });
+    if let Some(result) = result {
+        manifest["result"] = result.clone();
+    }
+    let manifest = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    fs::write(dir.join("manifest.json"), manifest).map_err(|e| format!("Failed to write manifest: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_result() {
+        let answer = "Fixed the parser.\n\n```hal-result\n{\"files_changed\": [\"src/a.rs\"], \"summary\": \"Fixed it\"}\n```\n";
+        let (value, narrative) = extract_result(answer).unwrap();
+        assert_eq!(value, json!({"files_changed": ["src/a.rs"], "summary": "Fixed it"}));
+        assert_eq!(narrative, "Fixed the parser.");
+
+        assert!(extract_result("Done.").unwrap_err().contains("missing"));
+        assert!(extract_result("```hal-result\n{\"summary\": \"x\"}\n```").unwrap_err().contains("files_changed"));
+        assert!(extract_result("```hal-result\n{\"files_changed\": [], \"summary\": \"x\", \"extra\": 1}\n```").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Prompts kept across restarts; older ones are dropped when the file is rewritten
+const MAX_HISTORY: usize = 1000;
+
+/// Past prompts, one JSON string per line so multi-line prompts survive
+fn history_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("hal")
+        .join("history.jsonl")
+}
+
+/// Saved prompts, oldest first
+pub fn load() -> Vec<String> {
+    let content = fs::read_to_string(history_path()).unwrap_or_default();
+    let mut history: Vec<String> = content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+    let excess = history.len().saturating_sub(MAX_HISTORY);
+    history.drain(..excess);
+    history
+}
+
+/// Record a prompt, compacting the file once it holds twice MAX_HISTORY entries
+pub fn append(prompt: &str) -> Result<(), String> {
+    if !crate::config::Config::writable() {
+        return Ok(());
+    }
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let line = serde_json::to_string(prompt).map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to save history: {}", e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Failed to save history: {}", e))?;
+
+    let content = fs::read_to_string(&path).unwrap_or_default();
+    if content.lines().count() > MAX_HISTORY * 2 {
+        let kept: Vec<String> = load().iter().filter_map(|p| serde_json::to_string(p).ok()).collect();
+        fs::write(&path, format!("{}\n", kept.join("\n"))).map_err(|e| format!("Failed to save history: {}", e))?;
+    }
+    Ok(())
+}
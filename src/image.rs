@@ -0,0 +1,131 @@
+use base64::Engine;
+use serde_json::{Value, json};
+use std::path::Path;
+
+const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// An image read from disk, ready to send as a content part
+pub struct Image {
+    pub path: String,
+    pub mime: &'static str,
+    pub width: u32,
+    pub height: u32,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Transcript placeholder, e.g. "screenshot.png (1280×720)"
+    pub fn label(&self) -> String {
+        let name = Path::new(&self.path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.clone());
+        format!("{} ({}×{})", name, self.width, self.height)
+    }
+
+    /// OpenAI-style image_url content part with a base64 data URL
+    pub fn content_part(&self) -> Value {
+        let data = base64::engine::general_purpose::STANDARD.encode(&self.data);
+        json!({
+            "type": "image_url",
+            "image_url": { "url": format!("data:{};base64,{}", self.mime, data) }
+        })
+    }
+}
+
+fn mime_for(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+pub fn is_image(path: &str) -> bool {
+    mime_for(Path::new(path)).is_some()
+}
+
+pub fn load(path: &str) -> Result<Image, String> {
+    let mime = mime_for(Path::new(path)).ok_or_else(|| format!("{}: not a png, jpeg, gif or webp image", path))?;
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    if data.len() > MAX_IMAGE_BYTES {
+        return Err(format!("{}: image is larger than 20 MB", path));
+    }
+    let (width, height) = dimensions(&data).ok_or_else(|| format!("{}: unrecognized image data", path))?;
+    Ok(Image {
+        path: path.to_string(),
+        mime,
+        width,
+        height,
+        data,
+    })
+}
+
+/// Read width and height from a PNG, GIF, JPEG or WebP header
+fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |i: usize| Some(u16::from_be_bytes([*data.get(i)?, *data.get(i + 1)?]) as u32);
+    let le16 = |i: usize| Some(u16::from_le_bytes([*data.get(i)?, *data.get(i + 1)?]) as u32);
+    let le24 = |i: usize| Some(u32::from_le_bytes([*data.get(i)?, *data.get(i + 1)?, *data.get(i + 2)?, 0]));
+
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+        return Some((width, height));
+    }
+    if data.starts_with(b"GIF8") {
+        return Some((le16(6)?, le16(8)?));
+    }
+    if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        return match data.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3fff, le16(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        };
+    }
+    if data.starts_with(&[0xff, 0xd8]) {
+        // Walk the segments until a start-of-frame marker
+        let mut i = 2;
+        while i + 9 < data.len() {
+            if data[i] != 0xff {
+                return None;
+            }
+            let marker = data[i + 1];
+            if (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+                return Some((be16(i + 7)?, be16(i + 5)?));
+            }
+            i += 2 + be16(i + 2)? as usize;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend_from_slice(&1280u32.to_be_bytes());
+        png.extend_from_slice(&720u32.to_be_bytes());
+        assert_eq!(dimensions(&png), Some((1280, 720)));
+
+        let gif = b"GIF89a\x40\x01\xf0\x00";
+        assert_eq!(dimensions(gif), Some((320, 240)));
+
+        let jpeg = [
+            0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, // APP0
+            0xff, 0xc0, 0x00, 0x11, 0x08, 0x02, 0x58, 0x03, 0x20, 0x03, // SOF0 800x600
+        ];
+        assert_eq!(dimensions(&jpeg), Some((800, 600)));
+
+        assert_eq!(dimensions(b"not an image"), None);
+    }
+}
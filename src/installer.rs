@@ -0,0 +1,162 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A package manager that owns the hal binary, so `hal update` must not replace it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Installer {
+    Homebrew,
+    Cargo,
+    Scoop,
+    Winget,
+    Nix,
+    Snap,
+    System, // A distro package (/usr/bin and friends)
+}
+
+impl Installer {
+    /// Guess from where the (symlink-resolved) binary lives. None means a plain
+    /// install.sh or manual install that self-update can replace.
+    pub fn detect(exe: &Path, cargo_home: Option<&Path>) -> Option<Self> {
+        let path = exe.to_string_lossy().replace('\\', "/");
+        let lower = path.to_lowercase();
+        if path.contains("/Cellar/") || path.starts_with("/opt/homebrew/") || path.starts_with("/home/linuxbrew/") {
+            Some(Self::Homebrew)
+        } else if cargo_home.is_some_and(|home| exe.starts_with(home.join("bin"))) {
+            Some(Self::Cargo)
+        } else if lower.contains("/scoop/apps/") || lower.contains("/scoop/shims/") {
+            Some(Self::Scoop)
+        } else if lower.contains("/winget/packages/") || lower.contains("/winget/links/") {
+            Some(Self::Winget)
+        } else if path.starts_with("/nix/store/") {
+            Some(Self::Nix)
+        } else if path.starts_with("/snap/") {
+            Some(Self::Snap)
+        } else if ["/usr/bin/", "/usr/sbin/", "/bin/", "/sbin/", "/usr/lib/", "/usr/libexec/"].iter().any(|p| path.starts_with(p)) {
+            Some(Self::System)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Homebrew => "Homebrew",
+            Self::Cargo => "cargo install",
+            Self::Scoop => "Scoop",
+            Self::Winget => "winget",
+            Self::Nix => "Nix",
+            Self::Snap => "snap",
+            Self::System => "a system package",
+        }
+    }
+
+    /// The upgrade command, and whether hal may run it itself (no sudo, no system config).
+    /// hal is only published as GitHub releases and a git repo, so the package is named
+    /// after what's actually installed at `exe`. None if it can't be worked out.
+    pub fn upgrade_command(self, exe: &Path) -> Option<(String, bool)> {
+        let path = exe.to_string_lossy().replace('\\', "/");
+        match self {
+            Self::Homebrew => Some((format!("brew upgrade {}", segment_after(&path, "/Cellar/")?), true)),
+            Self::Cargo => cargo_command(),
+            Self::Scoop => Some((format!("scoop update {}", segment_after(&path.to_lowercase(), "/scoop/apps/")?), true)),
+            // Package folders are named <id>_<source>
+            Self::Winget => {
+                let folder = segment_after(&path, "/WinGet/Packages/").or_else(|| segment_after(&path, "/winget/packages/"))?;
+                Some((format!("winget upgrade --id {} --exact", folder.split('_').next()?), true))
+            }
+            Self::Nix => None,
+            Self::Snap => Some((format!("sudo snap refresh {}", segment_after(&path, "/snap/")?), false)),
+            Self::System => {
+                let (tool, package) = owning_package(exe)?;
+                let command = match tool {
+                    "dpkg" => format!("sudo apt-get install --only-upgrade {}", package),
+                    "rpm" if which("dnf") => format!("sudo dnf upgrade {}", package),
+                    "rpm" => format!("sudo zypper update {}", package),
+                    _ => format!("sudo pacman -S {}", package),
+                };
+                Some((command, false))
+            }
+        }
+    }
+}
+
+/// The path component after `marker`: the formula in `/Cellar/<formula>/<version>/`
+fn segment_after(path: &str, marker: &str) -> Option<String> {
+    let rest = &path[path.find(marker)? + marker.len()..];
+    rest.split('/').next().filter(|s| !s.is_empty()).map(str::to_string)
+}
+
+/// The distro package that owns `exe`, and the tool that said so
+fn owning_package(exe: &Path) -> Option<(&'static str, String)> {
+    let queries: [(&str, &[&str]); 3] = [("dpkg", &["-S"]), ("rpm", &["-qf", "--queryformat", "%{NAME}"]), ("pacman", &["-Qqo"])];
+    queries.into_iter().filter(|(tool, _)| which(tool)).find_map(|(tool, args)| {
+        let output = Command::new(tool).args(args).arg(exe).output().ok().filter(|o| o.status.success())?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // dpkg prints "package: /usr/bin/hal"
+        let package = stdout.lines().next()?.split(':').next()?.trim().to_string();
+        (!package.is_empty()).then_some((tool, package))
+    })
+}
+
+pub fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME").map(PathBuf::from).or_else(|| dirs::home_dir().map(|home| home.join(".cargo")))
+}
+
+/// `cargo install` from the git repo; "hal" on crates.io is a different crate. A build
+/// from a local checkout is left to the user, who has to update the checkout first.
+fn cargo_command() -> Option<(String, bool)> {
+    let manifest = cargo_home().and_then(|home| std::fs::read_to_string(home.join(".crates.toml")).ok()).unwrap_or_default();
+    Some(cargo_command_for(&manifest))
+}
+
+fn cargo_command_for(manifest: &str) -> (String, bool) {
+    let entry = manifest.lines().find(|l| l.starts_with("\"hal ")).unwrap_or("");
+    if let Some(source) = entry.split("(path+file://").nth(1).and_then(|rest| rest.split(')').next()) {
+        return (format!("cargo install --locked --force --path {}", source), false);
+    }
+    ("cargo install --locked --force --git https://github.com/fairhill1/hal".to_string(), true)
+}
+
+fn which(tool: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(tool).is_file()))
+}
+
+/// Run an upgrade command with the terminal attached
+pub fn run(command: &str) -> Result<(), String> {
+    let mut parts = command.split_whitespace();
+    let program = parts.next().ok_or("Empty upgrade command")?;
+    let status = Command::new(program).args(parts).status().map_err(|e| format!("Failed to run `{}`: {}", command, e))?;
+    if status.success() { Ok(()) } else { Err(format!("`{}` failed ({})", command, status)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let cargo = Path::new("/home/me/.cargo");
+        let detect = |path: &str| Installer::detect(Path::new(path), Some(cargo));
+        assert_eq!(detect("/opt/homebrew/Cellar/hal/0.4.0/bin/hal"), Some(Installer::Homebrew));
+        assert_eq!(detect("/home/me/.cargo/bin/hal"), Some(Installer::Cargo));
+        assert_eq!(detect("C:\\Users\\me\\scoop\\apps\\hal\\current\\hal.exe"), Some(Installer::Scoop));
+        assert_eq!(detect("/nix/store/abc-hal-0.4.0/bin/hal"), Some(Installer::Nix));
+        assert_eq!(detect("/usr/bin/hal"), Some(Installer::System));
+        assert_eq!(detect("/home/me/.local/bin/hal"), None);
+        assert_eq!(detect("/usr/local/bin/hal"), None);
+
+        let upgrade = |installer: Installer, path: &str| installer.upgrade_command(Path::new(path)).map(|(command, _)| command);
+        assert_eq!(upgrade(Installer::Homebrew, "/opt/homebrew/Cellar/hal-ai/0.4.0/bin/hal").as_deref(), Some("brew upgrade hal-ai"));
+        assert_eq!(upgrade(Installer::Scoop, "C:/Users/me/scoop/apps/hal/current/hal.exe").as_deref(), Some("scoop update hal"));
+        assert_eq!(
+            upgrade(Installer::Winget, "C:/Users/me/AppData/Local/Microsoft/WinGet/Packages/Fairhill.Hal_Microsoft.Winget.Source_8wekyb3d8bbwe/hal.exe").as_deref(),
+            Some("winget upgrade --id Fairhill.Hal --exact")
+        );
+        assert_eq!(upgrade(Installer::Nix, "/nix/store/abc-hal-0.4.0/bin/hal"), None);
+
+        let (command, run) = cargo_command_for("[v1]\n\"hal 0.4.0 (path+file:///home/me/hal)\" = [\"hal\"]\n");
+        assert_eq!((command.as_str(), run), ("cargo install --locked --force --path /home/me/hal", false));
+        let (command, run) = cargo_command_for("[v1]\n\"hal 0.4.0 (registry+https://github.com/rust-lang/crates.io-index)\" = [\"hal\"]\n");
+        assert!(command.ends_with("--git https://github.com/fairhill1/hal") && run);
+    }
+}
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A lease not released by then is ignored, even if its process is still running
+const LEASE_SECS: i64 = 600;
+
+/// Marks a file one hal tab is about to write, while the change waits for approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lease {
+    pub path: String,
+    pub pid: u32,
+    #[serde(default)]
+    pub owner: String, // The tab, since one process can have several
+    pub expires: i64,
+}
+
+impl Lease {
+    /// Who holds it, for warnings
+    pub fn describe(&self) -> String {
+        if self.pid == std::process::id() {
+            format!("another hal tab is waiting to write {}", self.path)
+        } else {
+            format!("another hal session (pid {}) is waiting to write {}", self.pid, self.path)
+        }
+    }
+
+    fn held_by(&self, owner: &str) -> bool {
+        self.pid == std::process::id() && self.owner == owner
+    }
+}
+
+/// A new id for one tab's leases
+pub fn owner_id() -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    format!("tab-{}", NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Shared by every hal process of this user, so sessions in different directories see each other
+fn leases_dir() -> PathBuf {
+    if cfg!(test) {
+        return std::env::temp_dir().join(format!("hal-test-leases-{}", std::process::id()));
+    }
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("hal")
+        .join("leases")
+}
+
+/// The absolute path, resolved through the parent so files not created yet get the same key
+fn absolute(path: &str) -> String {
+    let path = Path::new(path);
+    let resolved = match (path.parent().filter(|p| !p.as_os_str().is_empty()), path.file_name()) {
+        (Some(parent), Some(name)) => crate::paths::canonical(parent).join(name),
+        (None, Some(name)) => crate::paths::canonical(Path::new(".")).join(name),
+        _ => crate::paths::canonical(path),
+    };
+    resolved.to_string_lossy().to_string()
+}
+
+/// FNV-1a of the absolute path, as the lease file name
+fn lease_path(absolute: &str) -> PathBuf {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in absolute.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    leases_dir().join(format!("{:016x}.json", hash))
+}
+
+/// Whether a process is still running; a hal that crashed leaves its leases behind
+fn alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    if cfg!(target_os = "linux") {
+        return Path::new(&format!("/proc/{}", pid)).exists();
+    }
+    if cfg!(windows) {
+        return std::process::Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+            .output()
+            .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).contains(&pid.to_string()));
+    }
+    std::process::Command::new("kill").args(["-0", &pid.to_string()]).output().is_ok_and(|o| o.status.success())
+}
+
+fn read(absolute: &str) -> Option<Lease> {
+    let content = fs::read_to_string(lease_path(absolute)).ok()?;
+    let lease: Lease = serde_json::from_str(&content).ok()?;
+    (lease.path == absolute && lease.expires > chrono::Utc::now().timestamp() && alive(lease.pid)).then_some(lease)
+}
+
+/// A live lease on `path`. Writes are refused while one exists; the tab holding it
+/// releases it before writing the approved change.
+pub fn held(path: &str) -> Option<Lease> {
+    read(&absolute(path))
+}
+
+/// Take leases on `paths` for `owner`. Returns the leases other tabs and sessions already
+/// hold on them; those are left in place, and the caller should warn.
+pub fn acquire(paths: &[&str], owner: &str) -> Vec<Lease> {
+    let mut others = Vec::new();
+    for path in paths {
+        let absolute = absolute(path);
+        if let Some(lease) = read(&absolute).filter(|l| !l.held_by(owner)) {
+            others.push(lease);
+            continue;
+        }
+        let lease = Lease {
+            path: absolute.clone(),
+            pid: std::process::id(),
+            owner: owner.to_string(),
+            expires: chrono::Utc::now().timestamp() + LEASE_SECS,
+        };
+        if fs::create_dir_all(leases_dir()).is_ok()
+            && let Ok(json) = serde_json::to_string(&lease)
+        {
+            let _ = fs::write(lease_path(&absolute), json);
+        }
+    }
+    others
+}
+
+/// Drop `owner`'s leases on `paths`
+pub fn release(paths: &[&str], owner: &str) {
+    for path in paths {
+        let absolute = absolute(path);
+        if read(&absolute).is_some_and(|lease| lease.held_by(owner)) {
+            let _ = fs::remove_file(lease_path(&absolute));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire_and_release() {
+        let path = std::env::temp_dir().join(format!("hal-lease-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(acquire(&[path], "tab-a").is_empty());
+        assert!(acquire(&[path], "tab-a").is_empty()); // Our own lease
+
+        // Another tab of this process sees it, and can't release it
+        assert_eq!(acquire(&[path], "tab-b")[0].owner, "tab-a");
+        release(&[path], "tab-b");
+        assert!(held(path).is_some());
+        release(&[path], "tab-a");
+        assert!(held(path).is_none());
+
+        // So does another process, while it's running
+        let mut process = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let other = Lease { path: absolute(path), pid: process.id(), owner: "tab-0".to_string(), expires: chrono::Utc::now().timestamp() + 60 };
+        fs::write(lease_path(&other.path), serde_json::to_string(&other).unwrap()).unwrap();
+        assert_eq!(acquire(&[path], "tab-a")[0].pid, process.id());
+        process.kill().unwrap();
+        process.wait().unwrap();
+        assert!(held(path).is_none());
+        let expired = Lease { expires: 0, ..other };
+        fs::write(lease_path(&expired.path), serde_json::to_string(&expired).unwrap()).unwrap();
+        assert!(held(path).is_none());
+        let _ = fs::remove_dir_all(leases_dir());
+    }
+}
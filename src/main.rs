@@ -1,24 +1,78 @@
 mod api;
 mod app;
+mod changelog;
+mod cli;
+mod clipboard;
+mod commands;
 mod config;
+mod conflicts;
+mod debug_log;
+mod digest;
+mod draft;
+mod editorconfig;
+mod error;
+mod explain;
+mod github;
+mod glyphs;
+mod handoff;
+mod headless;
+mod history;
+mod image;
+mod installer;
+mod lease;
+mod notify;
+mod patch;
+mod postmortem;
+mod paths;
+mod project;
+mod recovery;
+mod render;
 mod sandbox;
+mod scratch;
 mod session;
+mod spell;
+mod summary;
+mod symbols;
+mod sync;
+mod todo;
 mod tools;
 mod ui;
+mod undo;
+mod usage;
 
 use app::{App, AppState};
-use config::{Config, Mode};
+use clap::{CommandFactory, Parser};
+use cli::{Command, ConfigCommand, ExportFormat, FailOn, RunArgs, SessionsCommand};
+use config::{Auth, Config, Keybindings, Mode};
 use crossterm::{
-    event::{self, DisableBracketedPaste, EnableBracketedPaste, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseEventKind},
+    event::{self, DisableBracketedPaste, EnableBracketedPaste, DisableFocusChange, EnableFocusChange, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::prelude::*;
-use std::io::{self, stdout, BufRead, Write};
+use std::io::{self, stdout, BufRead, IsTerminal, Write};
 use std::time::Duration;
 
-pub fn self_update() -> Result<String, String> {
+/// Replace the running binary with the latest release. Installs owned by a package manager
+/// are upgraded through it instead: run directly when `run_package_manager` is set and the
+/// command needs no sudo, otherwise refused with the command to run.
+pub fn self_update(run_package_manager: bool) -> Result<String, String> {
     let current_version = env!("CARGO_PKG_VERSION");
+    let current_exe = std::env::current_exe().map_err(|e| format!("Failed to get current exe path: {}", e))?;
+
+    // Resolved so a Homebrew symlink in bin/ points at its Cellar
+    if let Some(installer) = installer::Installer::detect(&paths::canonical(&current_exe), installer::cargo_home().as_deref()) {
+        let refused = format!("hal was installed with {}, so it won't replace its own binary.", installer.name());
+        return match installer.upgrade_command(&paths::canonical(&current_exe)) {
+            Some((command, true)) if run_package_manager => {
+                eprintln!("hal was installed with {}. Running `{}`", installer.name(), command);
+                installer::run(&command)?;
+                Ok(format!("Updated with {}. Restart hal to use the new version.", installer.name()))
+            }
+            Some((command, _)) => Err(format!("{} Run `{}` instead.", refused, command)),
+            None => Err(format!("{} Update it the same way you installed it.", refused)),
+        };
+    }
 
     // Check latest version from GitHub API
     let api_url = "https://api.github.com/repos/fairhill1/hal/releases/latest";
@@ -55,8 +109,6 @@ pub fn self_update() -> Result<String, String> {
         os, arch
     );
 
-    let current_exe = std::env::current_exe().map_err(|e| format!("Failed to get current exe path: {}", e))?;
-
     let response = ureq::get(&url).call().map_err(|e| format!("Download failed: {}", e))?;
 
     let body = response
@@ -82,68 +134,171 @@ pub fn self_update() -> Result<String, String> {
 
     std::fs::rename(&temp_path, &current_exe).map_err(|e| format!("Failed to replace binary: {}", e))?;
 
-    Ok(format!("Updated v{} → v{}. Restart hal to use the new version.", current_version, latest_tag))
+    Ok(format!("Updated v{} {} v{}. Restart hal to use the new version.", current_version, glyphs::get().arrow, latest_tag))
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let mut config = Config::load();
-    let mut session_to_load: Option<session::Session> = None;
-
-    let mut i = 1;
-    while i < args.len() {
-        match args[i].as_str() {
-            "-c" | "--coach" => {
-                config.mode = Mode::Coach;
-            }
-            "--model" | "-m" => {
-                if i + 1 < args.len() {
-                    config.default_provider = args[i + 1].clone();
-                    i += 1;
-                }
+    let cli = cli::Cli::parse();
+    let config = Config::load();
+    glyphs::set(config.glyphs);
+
+    match cli.command {
+        None => start(config, cli.args),
+        Some(Command::Run(args)) => start(config, args),
+        Some(Command::Explain { staged, range }) => {
+            let target = match (staged, range) {
+                (_, Some(range)) => explain::Target::Range(range),
+                (true, None) => explain::Target::Staged,
+                (false, None) => explain::Target::Worktree,
+            };
+            let config = apply_flags(config, &cli.args);
+            let result = explain::prompt(&target).and_then(|prompt| {
+                let options = headless::HeadlessOptions { prompt, attachments: Vec::new(), save_output: None, session: None, tools: false, max_duration: None, json_result: false };
+                headless::run(config, options)
+            });
+            exit_on_error(result.map(|_| ()));
+        }
+        Some(Command::Sessions { command }) => exit_on_error(sessions_command(command)),
+        Some(Command::Sync { dry_run }) => exit_on_error(sync_command(config, dry_run)),
+        Some(Command::Config { command }) => exit_on_error(config_command(config, command.unwrap_or(ConfigCommand::Show))),
+        Some(Command::Update) => match self_update(true) {
+            Ok(msg) => println!("{}", msg),
+            Err(e) => {
+                eprintln!("Update failed: {}", e);
+                std::process::exit(1);
             }
-            "--resume" | "-r" => {
-                session_to_load = session::get_latest_session();
+        },
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut cli::Cli::command(), "hal", &mut stdout());
+        }
+    }
+}
+
+fn exit_on_error(result: Result<(), String>) {
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Mode and provider flags shared by every way of starting a conversation
+fn apply_flags(mut config: Config, args: &RunArgs) -> Config {
+    if args.coach {
+        config.mode = Mode::Coach;
+    }
+    if args.ask {
+        config.mode = Mode::Ask;
+    }
+    if let Some(model) = &args.model {
+        config.default_provider = model.clone();
+    }
+    if args.debug {
+        debug_log::set_enabled(true);
+    }
+    config.skip_permissions = args.dangerously_skip_permissions;
+    config
+}
+
+/// Start the TUI, or a headless run with -p
+fn start(config: Config, args: RunArgs) {
+    let mut config = apply_flags(config, &args);
+
+    let session_to_load = if args.resume {
+        session::get_latest_session()
+    } else if let Some(id) = &args.session {
+        match session::Session::load(id) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                eprintln!("Failed to load session: {}", e);
+                std::process::exit(1);
             }
-            "--session" | "-s" => {
-                if i + 1 < args.len() {
-                    match session::Session::load(&args[i + 1]) {
-                        Ok(s) => session_to_load = Some(s),
-                        Err(e) => {
-                            eprintln!("Failed to load session: {}", e);
-                            std::process::exit(1);
-                        }
-                    }
-                    i += 1;
-                }
+        }
+    } else if let Some(path) = &args.seed {
+        match session::Session::from_seed(path) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
             }
-            "--help" | "-h" => {
-                print_help();
-                return;
+        }
+    } else {
+        None
+    };
+
+    let mut attachments = Vec::new();
+    for path in &args.files {
+        if !std::path::Path::new(path).is_file() {
+            eprintln!("No such file: {}", path);
+            std::process::exit(1);
+        }
+        attachments.push(path.clone());
+    }
+    for pattern in &args.contexts {
+        match expand_context_glob(pattern) {
+            Ok(paths) => attachments.extend(paths),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
             }
-            "update" => {
-                match self_update() {
-                    Ok(msg) => { println!("{}", msg); return; }
-                    Err(e) => { eprintln!("Update failed: {}", e); std::process::exit(1); }
+        }
+    }
+    let mut seen = std::collections::HashSet::new();
+    attachments.retain(|p| seen.insert(p.clone()));
+
+    let cwd = std::env::current_dir().unwrap_or_default();
+    if config.mode == Mode::Coding
+        && !args.allow_broad_dir
+        && let Some(what) = sandbox::broad_directory(&cwd)
+    {
+        if args.prompt.is_some() {
+            eprintln!(
+                "hal is running in {} ({}), where coding tools could change anything. \
+                 cd into a project or pass --allow-broad-dir.",
+                what,
+                cwd.display()
+            );
+            std::process::exit(1);
+        }
+        if let Err(e) = confirm_broad_dir(&mut config, what, &cwd) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(prompt) = args.prompt {
+        let options = headless::HeadlessOptions {
+            prompt,
+            attachments,
+            save_output: args.save_output,
+            session: session_to_load,
+            tools: true,
+            max_duration: args.max_duration,
+            json_result: args.json_result,
+        };
+        let result = headless::run(config, options);
+        undo::remove_backups();
+        scratch::remove();
+        match result {
+            Ok(outcome) => {
+                let failed = args.fail_on.iter().find(|condition| match condition {
+                    FailOn::ToolError => outcome.tool_errors > 0,
+                    FailOn::NoEdits => outcome.files_changed == 0,
+                    FailOn::BudgetExceeded => outcome.budget_exceeded,
+                });
+                if let Some(condition) = failed {
+                    std::process::exit(condition.exit_code());
                 }
             }
-            _ => {
-                eprintln!("Unknown argument: {}", args[i]);
-                std::process::exit(1);
-            }
+            Err(e) => exit_on_error(Err(e)),
         }
-        i += 1;
+        return;
     }
 
     // Check if the current provider has an API key configured
-    let needs_setup = {
-        let provider = config.get_provider();
-        match provider {
-            Some(p) => p.api_key.is_none() && std::env::var(&p.api_key_env).is_err(),
-            None => true,
-        }
+    let needs_setup = match config.get_provider() {
+        Some(p) => p.resolve_api_key().is_none(),
+        None => true,
     };
-
     if needs_setup {
         config = match setup(config) {
             Ok(c) => c,
@@ -154,9 +309,194 @@ fn main() {
         };
     }
 
-    if let Err(e) = run(config, session_to_load) {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    let session_to_load = session_to_load.or_else(offer_recovery);
+    let result = run(config, session_to_load, attachments, args.max_duration);
+    undo::remove_backups();
+    scratch::remove();
+    exit_on_error(result);
+}
+
+fn sync_command(config: Config, dry_run: bool) -> Result<(), String> {
+    let report = sync::run(config.sync, dry_run)?;
+    let verb = |done: &'static str, planned: &'static str| if dry_run { planned } else { done };
+    for id in &report.uploaded {
+        println!("{} {}", verb("Uploaded", "Would upload"), id);
+    }
+    for id in &report.downloaded {
+        println!("{} {}", verb("Downloaded", "Would download"), id);
+    }
+    for (id, copy) in &report.conflicts {
+        println!("Conflict on {}: kept the newer version, older one {} {}", id, verb("saved as", "would be saved as"), copy);
+    }
+    if report.uploaded.is_empty() && report.downloaded.is_empty() && report.conflicts.is_empty() {
+        println!("Sessions are in sync");
+    }
+    Ok(())
+}
+
+fn sessions_command(command: SessionsCommand) -> Result<(), String> {
+    match command {
+        SessionsCommand::List => {
+            for s in session::list_sessions() {
+                println!("{:<14} {:<16} {}", s.id, session::relative_time(s.updated_at), s.display_title());
+            }
+        }
+        SessionsCommand::Show { id } => {
+            let s = session::Session::load(&id).map_err(|e| format!("Failed to load session {}: {}", id, e))?;
+            println!("{}", s.display_title());
+            println!("  id:       {}", s.id);
+            println!("  created:  {} ({})", session::local_time(s.created_at), session::relative_time(s.created_at));
+            println!("  updated:  {} ({})", session::local_time(s.updated_at), session::relative_time(s.updated_at));
+            println!("  messages: {}", s.messages.len());
+            if let Some(parent) = &s.parent_id {
+                println!("  branch of {}", parent);
+            }
+            if !s.checkpoints.is_empty() {
+                println!("  checkpoints: {}", s.checkpoints.len());
+            }
+            if let Some(seed) = s.seed {
+                println!("  seed:     {}", seed);
+            }
+            let mut renderer = render::PlainTextRenderer::new(false);
+            render::render_transcript(&s.messages, &mut renderer);
+            print!("{}", renderer.finish());
+        }
+        SessionsCommand::Delete { id } => {
+            session::Session::delete(&id)?;
+            println!("Deleted session {}", id);
+        }
+        SessionsCommand::Export { id, format, output } => {
+            let s = session::Session::load(&id).map_err(|e| format!("Failed to load session {}: {}", id, e))?;
+            let content = match format {
+                ExportFormat::Md => render::markdown_document(&s.display_title(), &s.messages),
+                ExportFormat::Json => serde_json::to_string_pretty(&s).map_err(|e| e.to_string())? + "\n",
+                ExportFormat::Transcript => {
+                    let mut renderer = render::JsonRenderer::default();
+                    render::render_transcript(&s.messages, &mut renderer);
+                    serde_json::to_string_pretty(&renderer.entries).map_err(|e| e.to_string())? + "\n"
+                }
+            };
+            match output {
+                Some(path) => std::fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?,
+                None => print!("{}", content),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn config_command(config: Config, command: ConfigCommand) -> Result<(), String> {
+    let path = Config::config_path();
+    match command {
+        ConfigCommand::Path => println!("{}", path.display()),
+        ConfigCommand::Show => {
+            let mut value = serde_json::to_value(&config).map_err(|e| e.to_string())?;
+            if let Some(providers) = value.get_mut("providers").and_then(|p| p.as_object_mut()) {
+                for provider in providers.values_mut() {
+                    if let Some(key) = provider.get_mut("api_key").filter(|k| !k.is_null()) {
+                        *key = serde_json::Value::from("(hidden)");
+                    }
+                }
+            }
+            if value.get("github_token").is_some() {
+                value["github_token"] = serde_json::Value::from("(hidden)");
+            }
+            println!("{}", serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?);
+        }
+        ConfigCommand::Edit => {
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+            let status = std::process::Command::new(&editor)
+                .arg(&path)
+                .status()
+                .map_err(|e| format!("Failed to run {}: {}", editor, e))?;
+            if !status.success() {
+                return Err(format!("{} exited with {}", editor, status));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Files matching a --context glob, sorted
+fn expand_context_glob(pattern: &str) -> Result<Vec<String>, String> {
+    let entries = glob::glob(pattern).map_err(|e| format!("Invalid --context pattern '{}': {}", pattern, e))?;
+    let mut paths: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|p| p.is_file())
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    if paths.is_empty() {
+        return Err(format!("No files match --context '{}'", pattern));
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// After a crash or kill, ask whether to pick up the session that was open
+fn offer_recovery() -> Option<session::Session> {
+    let mut sessions = recovery::unclean_sessions();
+    if sessions.is_empty() || !io::stdin().is_terminal() {
+        return None;
+    }
+    let session = sessions.remove(0);
+    println!();
+    println!("  {}  hal didn't shut down cleanly last time.", glyphs::get().warning);
+    println!(
+        "     Resume \"{}\" ({} messages, {})? [Y/n]",
+        session.display_title(),
+        session.messages.len(),
+        session::relative_time(session.updated_at)
+    );
+    for other in &sessions {
+        println!("     Also open then: \"{}\" (hal --session {})", other.display_title(), other.id);
+    }
+    print!("  > ");
+    io::stdout().flush().ok()?;
+    let line = io::stdin().lock().lines().next()?.ok()?;
+    matches!(line.trim().to_lowercase().as_str(), "" | "y" | "yes").then_some(session)
+}
+
+/// Ask before granting coding tools the whole home directory or filesystem: continue,
+/// move to a narrower directory, or fall back to ask mode
+fn confirm_broad_dir(config: &mut Config, what: &str, cwd: &std::path::Path) -> Result<(), String> {
+    println!();
+    println!("  {}  hal was started in {} ({}).", glyphs::get().warning, what, cwd.display());
+    println!("     Coding tools (bash, file edits) could read and change everything under it.");
+    println!();
+    println!("  Type 'yes' to continue here, a directory to work in instead,");
+    println!("  or press Enter for ask mode (read-only):");
+
+    let stdin = io::stdin();
+    loop {
+        print!("  > ");
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        let line = stdin.lock().lines().next()
+            .ok_or_else(|| "No input".to_string())?
+            .map_err(|e| e.to_string())?;
+
+        match line.trim() {
+            "" => {
+                config.mode = Mode::Ask;
+                return Ok(());
+            }
+            "yes" | "y" => return Ok(()),
+            dir => {
+                let dir = std::path::Path::new(dir);
+                if !dir.is_dir() {
+                    println!("  Not a directory: {}", dir.display());
+                    continue;
+                }
+                std::env::set_current_dir(dir).map_err(|e| e.to_string())?;
+                let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+                if let Some(what) = sandbox::broad_directory(&cwd) {
+                    println!("  {} is {} too; pick a project directory.", cwd.display(), what);
+                    continue;
+                }
+                return Ok(());
+            }
+        }
     }
 }
 
@@ -201,6 +541,15 @@ fn setup(mut config: Config) -> Result<Config, String> {
         .map(|p| p.api_key_env.clone())
         .unwrap_or_default();
 
+    // Local endpoints need no key
+    if config.providers.get(selected_name).is_some_and(|p| p.auth == Auth::None) {
+        config.save().map_err(|e| format!("Failed to save config: {}", e))?;
+        println!();
+        println!("  Saved! Starting hal...");
+        println!();
+        return Ok(config);
+    }
+
     println!();
     print!("  Enter your {} API key (or set ${}): ", selected_name, env_var);
     io::stdout().flush().map_err(|e| e.to_string())?;
@@ -217,6 +566,12 @@ fn setup(mut config: Config) -> Result<Config, String> {
     // Store the key in the provider config
     if let Some(provider) = config.providers.get_mut(selected_name) {
         provider.api_key = Some(key);
+
+        // Org/project scoping only applies to OpenAI itself
+        if provider.base_url.contains("api.openai.com") {
+            provider.organization = prompt_optional_id(&stdin, "Organization ID", "org-")?;
+            provider.project = prompt_optional_id(&stdin, "Project ID", "proj_")?;
+        }
     }
 
     config.save().map_err(|e| format!("Failed to save config: {}", e))?;
@@ -228,33 +583,67 @@ fn setup(mut config: Config) -> Result<Config, String> {
     Ok(config)
 }
 
-fn print_help() {
-    println!("hal - Chat with LLMs from your terminal");
-    println!("\nUSAGE:");
-    println!("    hal [OPTIONS]");
-    println!("    hal update");
-    println!("\nOPTIONS:");
-    println!("    -c, --coach              Run in coach mode");
-    println!("    -m, --model <NAME>       Model name from config (default: gemini)");
-    println!("    -r, --resume             Resume the last session");
-    println!("    -s, --session <ID>       Load a specific session by ID");
-    println!("    -h, --help               Print help");
-    println!("\nCOMMANDS:");
-    println!("    update                   Update hal to the latest version");
+/// Ask for an optional ID that must start with `prefix`; empty input skips it
+fn prompt_optional_id(stdin: &io::Stdin, label: &str, prefix: &str) -> Result<Option<String>, String> {
+    loop {
+        print!("  {} (optional, {}...): ", label, prefix);
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        let line = stdin.lock().lines().next()
+            .ok_or_else(|| "No input".to_string())?
+            .map_err(|e| e.to_string())?;
+        let value = line.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        if value.starts_with(prefix) && !value.contains(char::is_whitespace) {
+            return Ok(Some(value.to_string()));
+        }
+        println!("  {} should start with '{}'", label, prefix);
+    }
 }
 
-fn run(config: Config, session: Option<session::Session>) -> Result<(), String> {
+fn run(
+    config: Config,
+    session: Option<session::Session>,
+    attachments: Vec<String>,
+    max_duration: Option<Duration>,
+) -> Result<(), String> {
     let mut app = App::new(config, session)?;
+    if let Some(limit) = max_duration {
+        app.set_max_duration(limit);
+    }
+    if !attachments.is_empty() {
+        app.notice = Some(format!("{} file(s) will be attached to your first message", attachments.len()));
+    }
+    app.attachments = attachments;
+    app.track_recovery();
+    let mut tabs = vec![app];
+
+    // A panic on the main thread would leave the terminal raw and in the alternate screen
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            recovery::mark_crashed();
+            disable_raw_mode().ok();
+            execute!(stdout(), LeaveAlternateScreen, DisableBracketedPaste, DisableMouseCapture, DisableFocusChange).ok();
+        }
+        default_hook(info);
+    }));
 
     // Setup terminal
     enable_raw_mode().map_err(|e| e.to_string())?;
     let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen, EnableBracketedPaste, EnableMouseCapture).map_err(|e| e.to_string())?;
+    execute!(stdout, EnterAlternateScreen, EnableBracketedPaste, EnableMouseCapture, EnableFocusChange).map_err(|e| e.to_string())?;
 
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend).map_err(|e| e.to_string())?;
 
-    let result = run_app(&mut terminal, &mut app);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| run_app(&mut terminal, &mut tabs))).unwrap_or_else(|_| {
+        for app in tabs.iter_mut() {
+            app.save_session();
+        }
+        Err("hal crashed. The conversation was saved; hal offers to resume it next time it starts.".to_string())
+    });
 
     // Restore terminal
     disable_raw_mode().ok();
@@ -262,48 +651,142 @@ fn run(config: Config, session: Option<session::Session>) -> Result<(), String>
         terminal.backend_mut(),
         LeaveAlternateScreen,
         DisableBracketedPaste,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableFocusChange
     )
     .ok();
     terminal.show_cursor().ok();
 
+    // Leave the session's outcome visible in the terminal's scrollback
+    for app in &tabs {
+        if app.config.print_transcript_on_exit && !app.messages.is_empty() {
+            print!("{}", app.condensed_transcript());
+        }
+    }
+
     result
 }
 
-fn run_app<B: Backend + Write>(terminal: &mut Terminal<B>, app: &mut App) -> Result<(), String> {
+fn run_app<B: Backend + Write>(terminal: &mut Terminal<B>, tabs: &mut Vec<App>) -> Result<(), String> {
+    let mut active = 0;
+
     loop {
-        terminal.draw(|f| ui::draw(f, app)).map_err(|e| e.to_string())?;
+        let busy: Vec<bool> = tabs.iter().map(|app| app.state != AppState::Idle).collect();
+        terminal
+            .draw(|f| ui::draw(f, &mut tabs[active], &busy, active))
+            .map_err(|e| e.to_string())?;
+
+        let mut quit_all = false;
 
-        // If we're processing, poll for API response
-        if app.state != AppState::Idle {
-            // Poll for events with short timeout to keep spinner animated
-            if event::poll(Duration::from_millis(50)).unwrap_or(false) {
+        // If any tab is processing, poll for API responses
+        if busy.contains(&true) {
+            // Poll for events with short timeout to keep spinner animated; with reduced
+            // motion there's only an elapsed-seconds count to refresh
+            let timeout = if tabs[active].config.reduced_motion { 500 } else { 50 };
+            if event::poll(Duration::from_millis(timeout)).unwrap_or(false) {
                 if let Ok(ev) = event::read() {
-                    handle_event(app, ev);
+                    quit_all = handle_tab_event(tabs, &mut active, ev);
                 }
             }
-            // Check if API response or tool result is ready (non-blocking)
-            app.poll_api_response();
-            app.poll_tool_result();
+            // Check if API responses or tool results are ready (non-blocking),
+            // background tabs included
+            for app in tabs.iter_mut() {
+                app.poll_api_response();
+                app.poll_tool_result();
+                app.poll_shell();
+            }
         } else {
-            // Wait for events when idle
-            if let Ok(ev) = event::read() {
-                handle_event(app, ev);
+            // Wait for events when idle, waking now and then to pick up config edits
+            if event::poll(Duration::from_millis(500)).unwrap_or(false)
+                && let Ok(ev) = event::read()
+            {
+                quit_all = handle_tab_event(tabs, &mut active, ev);
             }
         }
+        for app in tabs.iter_mut() {
+            app.poll_config_reload();
+            app.check_attention();
+        }
 
-        if app.should_quit {
+        if quit_all || tabs.iter().all(|app| app.should_quit) {
             break;
         }
+
+        // /quit closes just that tab while others remain
+        if tabs.iter().any(|app| app.should_quit) {
+            let mut index = 0;
+            tabs.retain(|app| {
+                let keep = !app.should_quit;
+                if !keep {
+                    app.release_leases();
+                }
+                if !keep && index < active {
+                    active -= 1;
+                }
+                index += 1;
+                keep
+            });
+            active = active.min(tabs.len() - 1);
+        }
     }
 
+    // Quitting with a change still under review gives up its leases
+    for app in tabs.iter() {
+        app.release_leases();
+    }
     Ok(())
 }
 
+/// Handle tab-level keys (new tab, switch tab, quit everything) before passing
+/// the event to the active tab. Returns true when hal should exit.
+fn handle_tab_event(tabs: &mut Vec<App>, active: &mut usize, event: Event) -> bool {
+    match event {
+        Event::FocusGained => notify::set_focused(true),
+        Event::FocusLost => notify::set_focused(false),
+        _ => {}
+    }
+    if let Event::Key(key) = event {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            // Ctrl+C first interrupts a running tool; otherwise always allow quit
+            KeyCode::Char('c') if ctrl && tabs[*active].tool_running() => {
+                return !tabs[*active].interrupt_tools();
+            }
+            KeyCode::Char('c') | KeyCode::Char('d') if ctrl => return true,
+            KeyCode::Char('t') if ctrl => {
+                let config = tabs[*active].config.clone();
+                match App::new(config, None) {
+                    Ok(mut app) => {
+                        app.track_recovery();
+                        tabs.push(app);
+                        *active = tabs.len() - 1;
+                    }
+                    Err(e) => tabs[*active].error = Some(format!("Failed to open tab: {}", e)),
+                }
+                return false;
+            }
+            // Ctrl+1..9 (Alt+1..9 for terminals that don't report Ctrl+digit)
+            KeyCode::Char(c @ '1'..='9') if ctrl || alt => {
+                let index = c as usize - '1' as usize;
+                if index < tabs.len() {
+                    *active = index;
+                }
+                return false;
+            }
+            _ => {}
+        }
+    }
+
+    handle_event(&mut tabs[*active], event);
+    false
+}
+
 fn handle_event(app: &mut App, event: Event) {
     // Clear error on any input
     if matches!(event, Event::Key(_)) {
         app.error = None;
+        app.notice = None;
     }
 
     match event {
@@ -333,26 +816,6 @@ fn handle_event(app: &mut App, event: Event) {
 }
 
 fn handle_key(app: &mut App, key: KeyEvent) {
-    // Always allow quit
-    if matches!(
-        key,
-        KeyEvent {
-            code: KeyCode::Char('c'),
-            modifiers: KeyModifiers::CONTROL,
-            ..
-        }
-    ) | matches!(
-        key,
-        KeyEvent {
-            code: KeyCode::Char('d'),
-            modifiers: KeyModifiers::CONTROL,
-            ..
-        }
-    ) {
-        app.should_quit = true;
-        return;
-    }
-
     // Handle modals (permission, diff, provider)
     if app.diff_modal.is_some() {
         // Diff modal: Up/Down scroll, Left/Right switch options
@@ -368,6 +831,21 @@ fn handle_key(app: &mut App, key: KeyEvent) {
             _ => {}
         }
         return;
+    } else if app.versions_modal.is_some() {
+        // Version viewer: Left/Right step through versions, Up/Down scroll the diff
+        let scroll = &mut app.versions_modal.as_mut().unwrap().scroll;
+        match key.code {
+            KeyCode::Up => *scroll = scroll.saturating_sub(1),
+            KeyCode::Down => *scroll = scroll.saturating_add(1),
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+            KeyCode::PageDown => *scroll = scroll.saturating_add(10),
+            KeyCode::Left => app.modal_up(),
+            KeyCode::Right => app.modal_down(),
+            KeyCode::Enter => app.modal_select(),
+            KeyCode::Esc => app.modal_cancel(),
+            _ => {}
+        }
+        return;
     } else if app.has_modal() {
         match key.code {
             KeyCode::Left => app.modal_up(),
@@ -381,8 +859,67 @@ fn handle_key(app: &mut App, key: KeyEvent) {
         return;
     }
 
+    // Copy mode: navigate the transcript, expand tool output, and yank to the clipboard
+    if app.copy_mode.is_some() {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => app.copy_mode_up(),
+            KeyCode::Down | KeyCode::Char('j') => app.copy_mode_down(),
+            KeyCode::Home | KeyCode::Char('g') => app.copy_mode_first(),
+            KeyCode::End | KeyCode::Char('G') => app.copy_mode_last(),
+            KeyCode::Char(' ') | KeyCode::Char('o') => app.copy_mode_toggle_expand(),
+            KeyCode::Enter | KeyCode::Char('y') => app.copy_mode_yank(),
+            KeyCode::Char('m') => app.copy_mode_toggle_bookmark(),
+            KeyCode::Char('a') => app.copy_mode_append_draft(),
+            KeyCode::Char('n') => app.copy_mode_jump_bookmark(true),
+            KeyCode::Char('N') => app.copy_mode_jump_bookmark(false),
+            KeyCode::Esc | KeyCode::Char('q') => app.exit_copy_mode(),
+            _ => {}
+        }
+        return;
+    }
+
+    // History search: typing narrows the matches, Enter puts the selection in the input
+    if app.history_search.is_some() {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('r') | KeyCode::Char('p') if ctrl => app.history_search_older(),
+            KeyCode::Char('n') if ctrl => app.history_search_newer(),
+            KeyCode::Char('g') if ctrl => app.history_search = None,
+            KeyCode::Char(c) if !ctrl => app.history_search_type(c),
+            KeyCode::Up => app.history_search_older(),
+            KeyCode::Down => app.history_search_newer(),
+            KeyCode::Backspace => app.history_search_backspace(),
+            KeyCode::Enter | KeyCode::Tab => app.history_search_accept(),
+            KeyCode::Esc => app.history_search = None,
+            _ => {}
+        }
+        return;
+    }
+
+    if key.code == KeyCode::Char('y') && key.modifiers.contains(KeyModifiers::CONTROL) {
+        app.enter_copy_mode();
+        return;
+    }
+    if key.code == KeyCode::Char('r') && key.modifiers.contains(KeyModifiers::CONTROL) {
+        app.open_history_search();
+        return;
+    }
+    if key.code == KeyCode::Char('b') && key.modifiers.contains(KeyModifiers::CONTROL) && app.postmortem.is_some() {
+        app.save_postmortem();
+        return;
+    }
+
     let is_processing = app.state != AppState::Idle;
 
+    // Vim normal mode: plain keys are motions and commands, not text
+    if let KeyCode::Char(c) = key.code
+        && app.is_vim_normal()
+        && !key.modifiers.contains(KeyModifiers::CONTROL)
+    {
+        app.vim_normal_key(c);
+        return;
+    }
+
     match key {
         // Submit - blocked while processing
         KeyEvent {
@@ -396,6 +933,7 @@ fn handle_key(app: &mut App, key: KeyEvent) {
                 app.select_picker_item();
             } else {
                 app.submit_input();
+                app.enter_vim_insert();
             }
         }
 
@@ -405,17 +943,23 @@ fn handle_key(app: &mut App, key: KeyEvent) {
         } => {
             if app.picker_active() {
                 app.select_picker_item();
+            } else if app.pasted_paths().is_some() {
+                app.attach_pasted_paths();
+            } else if app.spell_fixes().is_some() {
+                app.apply_spell_fixes();
             }
         }
 
-        // Escape - abort if processing, otherwise cancel picker
+        // Escape - abort if processing, otherwise cancel picker (or leave vim insert mode)
         KeyEvent {
             code: KeyCode::Esc, ..
         } => {
             if is_processing {
                 app.abort_request();
-            } else {
+            } else if app.picker_active() || app.config.keybindings != Keybindings::Vim {
                 app.cancel_picker();
+            } else {
+                app.enter_vim_normal();
             }
         }
 
@@ -452,6 +996,36 @@ fn handle_key(app: &mut App, key: KeyEvent) {
             app.move_cursor_right();
         }
 
+        // Emacs-style line editing
+        KeyEvent {
+            code: KeyCode::Char('a'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        } => {
+            app.move_cursor_home();
+        }
+        KeyEvent {
+            code: KeyCode::Char('e'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        } => {
+            app.move_cursor_end();
+        }
+        KeyEvent {
+            code: KeyCode::Char('w'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        } => {
+            app.delete_word_back();
+        }
+        KeyEvent {
+            code: KeyCode::Char('k'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        } => {
+            app.kill_to_end();
+        }
+
         // Scroll
         KeyEvent {
             code: KeyCode::PageUp, ..
@@ -0,0 +1,28 @@
+use crate::config::Notify;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Tracked from focus events; terminals that don't report focus always count as focused
+static FOCUSED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_focused(focused: bool) {
+    FOCUSED.store(focused, Ordering::SeqCst);
+}
+
+pub fn focused() -> bool {
+    FOCUSED.load(Ordering::Relaxed)
+}
+
+/// Ring the bell and/or post a desktop notification through the terminal (OSC 9)
+pub fn send(style: Notify, message: &str) {
+    let message: String = message.chars().filter(|c| !c.is_control()).collect();
+    let sequence = match style {
+        Notify::Off => return,
+        Notify::Bell => "\x07".to_string(),
+        Notify::Desktop => format!("\x1b]9;{}\x07", message),
+        Notify::Both => format!("\x07\x1b]9;{}\x07", message),
+    };
+    let mut stdout = std::io::stdout();
+    let _ = stdout.write_all(sequence.as_bytes());
+    let _ = stdout.flush();
+}
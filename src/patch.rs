@@ -0,0 +1,210 @@
+/// One file's section of a unified diff
+#[derive(Debug)]
+pub struct FilePatch {
+    pub old_path: Option<String>, // None for /dev/null (new file)
+    pub new_path: Option<String>, // None for /dev/null (deleted file)
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Debug)]
+pub struct Hunk {
+    old_start: usize, // 1-indexed; 0 if the header had no line numbers
+    lines: Vec<(char, String)>, // ' ' context, '-' removed, '+' added
+}
+
+impl FilePatch {
+    /// Path the result is written to
+    pub fn path(&self) -> &str {
+        self.new_path.as_deref().or(self.old_path.as_deref()).unwrap_or("")
+    }
+}
+
+fn header_path(rest: &str) -> Option<String> {
+    // Drop a trailing timestamp ("path\t2024-01-01 ...")
+    let path = rest.split('\t').next().unwrap_or(rest).trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path.strip_prefix("a/").or_else(|| path.strip_prefix("b/")).unwrap_or(path);
+    Some(path.to_string())
+}
+
+fn hunk_start(header: &str) -> usize {
+    header
+        .trim_start_matches('@')
+        .trim()
+        .strip_prefix('-')
+        .and_then(|r| r.split([',', ' ']).next())
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Parse a (possibly multi-file) unified diff. Hunk line counts are ignored since
+/// models often get them wrong; a hunk runs until the next header.
+pub fn parse(patch: &str) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = patch.lines().collect();
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some(old) = line.strip_prefix("--- ")
+            && let Some(new) = lines.get(i + 1).and_then(|l| l.strip_prefix("+++ "))
+        {
+            files.push(FilePatch { old_path: header_path(old), new_path: header_path(new), hunks: Vec::new() });
+            i += 2;
+            continue;
+        }
+        if line.starts_with("@@") {
+            let file = files.last_mut().ok_or("Error: hunk before any ---/+++ file header")?;
+            file.hunks.push(Hunk { old_start: hunk_start(line), lines: Vec::new() });
+        } else if let Some(hunk) = files.last_mut().and_then(|f| f.hunks.last_mut()) {
+            match line.chars().next() {
+                Some(tag @ (' ' | '-' | '+')) => hunk.lines.push((tag, line[1..].to_string())),
+                None => hunk.lines.push((' ', String::new())), // Context line with its space stripped
+                _ => {} // "\ No newline at end of file", "diff --git", "index ..."
+            }
+        }
+        i += 1;
+    }
+    if files.is_empty() {
+        return Err("Error: no ---/+++ file headers found in patch".to_string());
+    }
+    Ok(files)
+}
+
+/// Line comparisons from strictest to loosest
+const MATCHERS: [fn(&str, &str) -> bool; 3] = [
+    |a, b| a == b,
+    |a, b| a.trim_end() == b.trim_end(),
+    |a, b| a.split_whitespace().eq(b.split_whitespace()),
+];
+
+/// Where `old` occurs in `lines` at or after `min`, preferring positions close to `expected`
+fn find(lines: &[String], old: &[&str], expected: usize, min: usize) -> Option<usize> {
+    if old.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - old.len();
+    if min > last {
+        return None;
+    }
+    let expected = expected.clamp(min, last);
+    for matches in MATCHERS {
+        let at = |pos: usize| old.iter().enumerate().all(|(j, o)| matches(&lines[pos + j], o));
+        for distance in 0..=last - min {
+            let candidates = [expected.checked_sub(distance), Some(expected + distance)];
+            for pos in candidates.into_iter().flatten() {
+                if pos >= min && pos <= last && at(pos) {
+                    return Some(pos);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Apply hunks to `content` in order. Returns the new content and one status line per hunk;
+/// hunks that can't be placed are skipped and reported.
+pub fn apply(content: &str, hunks: &[Hunk]) -> (String, Vec<Result<String, String>>) {
+    let eol = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<String> = content.lines().map(|l| l.to_string()).collect();
+    let trailing_newline = content.is_empty() || content.ends_with('\n');
+
+    let mut results = Vec::new();
+    let mut offset: isize = 0;
+    let mut min = 0;
+    for hunk in hunks {
+        // Trim up to two context lines from each end if the full hunk doesn't match
+        let mut placed = None;
+        for fuzz in 0..=2 {
+            let lead = hunk.lines.iter().take(fuzz).take_while(|(t, _)| *t == ' ').count();
+            let trail = hunk.lines.iter().rev().take(fuzz).take_while(|(t, _)| *t == ' ').count();
+            if fuzz > 0 && lead + trail == 0 {
+                break;
+            }
+            let body = &hunk.lines[lead..hunk.lines.len() - trail.min(hunk.lines.len() - lead)];
+            let old: Vec<&str> = body.iter().filter(|(t, _)| *t != '+').map(|(_, l)| l.as_str()).collect();
+            if old.is_empty() {
+                // "@@ -5,0 ..." inserts after line 5
+                let declared = hunk.old_start + lead;
+                let at = (declared as isize + offset).max(0) as usize;
+                placed = Some((at.clamp(min, lines.len()), body, declared, fuzz));
+                break;
+            }
+            let declared = hunk.old_start.max(1) - 1 + lead;
+            let expected = (declared as isize + offset).max(0) as usize;
+            if let Some(pos) = find(&lines, &old, expected, min) {
+                placed = Some((pos, body, declared, fuzz));
+                break;
+            }
+        }
+
+        let Some((pos, body, declared, fuzz)) = placed else {
+            results.push(Err("context not found".to_string()));
+            continue;
+        };
+
+        // Context keeps the file's own text; only -/+ lines change it
+        let mut replacement = Vec::new();
+        let mut cursor = pos;
+        for (tag, text) in body {
+            match tag {
+                ' ' => {
+                    replacement.push(lines[cursor].clone());
+                    cursor += 1;
+                }
+                '-' => cursor += 1,
+                _ => replacement.push(text.clone()),
+            }
+        }
+        let removed = cursor - pos;
+        let added = replacement.len();
+        lines.splice(pos..cursor, replacement);
+
+        let mut note = format!("applied at line {}", pos + 1);
+        if hunk.old_start > 0 && pos != declared {
+            note.push_str(&format!(" (offset {:+})", pos as isize - declared as isize));
+        }
+        if fuzz > 0 {
+            note.push_str(&format!(" (fuzz {})", fuzz));
+        }
+        results.push(Ok(note));
+        offset = (pos + added) as isize - (declared + removed) as isize;
+        min = pos + added;
+    }
+
+    let mut output = lines.join(eol);
+    if trailing_newline && !lines.is_empty() {
+        output.push_str(eol);
+    }
+    (output, results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_with_offset_and_whitespace_drift() {
+        let patch = "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,3 +1,3 @@\n fn a() {\n-    1\n+    2\n }\n@@ -20,2 +20,2 @@\n fn missing() {\n-    x\n+    y\n";
+        let files = parse(patch).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path(), "src/lib.rs");
+
+        // Shifted down two lines, trailing whitespace on the context line
+        let content = "// header\n\nfn a() {  \n    1\n}\n";
+        let (updated, results) = apply(content, &files[0].hunks);
+        assert_eq!(updated, "// header\n\nfn a() {  \n    2\n}\n");
+        assert_eq!(results[0].as_deref(), Ok("applied at line 3 (offset +2)"));
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn test_parse_new_file() {
+        let files = parse("--- /dev/null\n+++ b/notes.md\n@@ -0,0 +1,2 @@\n+one\n+two\n").unwrap();
+        assert!(files[0].old_path.is_none());
+        let (updated, results) = apply("", &files[0].hunks);
+        assert_eq!(updated, "one\ntwo\n");
+        assert!(results[0].is_ok());
+    }
+}
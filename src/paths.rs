@@ -0,0 +1,150 @@
+use std::path::{Component, Path, PathBuf};
+
+/// A path as shown to the user and the model: no leading "./" and forward slashes,
+/// so Windows paths look the same as everywhere else and match typed @ references
+pub fn display(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    let text = if cfg!(windows) { text.replace('\\', "/") } else { text.into_owned() };
+    strip_dot(&text).to_string()
+}
+
+/// Drop a leading "./" (or ".\" on Windows)
+pub fn strip_dot(path: &str) -> &str {
+    path.strip_prefix("./")
+        .or_else(|| if cfg!(windows) { path.strip_prefix(".\\") } else { None })
+        .unwrap_or(path)
+}
+
+/// Expand a leading "~" to the home directory
+pub fn expand_home(path: &str) -> String {
+    let rest = path.strip_prefix("~/").or_else(|| if cfg!(windows) { path.strip_prefix("~\\") } else { None });
+    match (rest, dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+        _ if path == "~" => dirs::home_dir().map_or(path.to_string(), |h| h.to_string_lossy().to_string()),
+        _ => path.to_string(),
+    }
+}
+
+/// Resolve symlinks and "..", without the \\?\ prefix Windows adds; the path as
+/// given if it doesn't exist
+pub fn canonical(path: &Path) -> PathBuf {
+    dunce::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Whether one path contains the other. Compares components, so separators and
+/// trailing slashes don't matter, and ignores case on Windows like the filesystem.
+pub fn overlaps(a: &Path, b: &Path) -> bool {
+    let (a, b) = (components(a), components(b));
+    a.starts_with(&b) || b.starts_with(&a)
+}
+
+/// The paths in pasted text that is nothing but existing absolute files and directories, as
+/// terminals paste a drag-and-drop: space-separated, shell-quoted or backslash-escaped,
+/// or file:// URLs. None if any part isn't an existing path.
+pub fn dropped(text: &str) -> Option<Vec<PathBuf>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote = None;
+    let mut in_word = false;
+    let mut chars = text.trim().chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '\\') if !cfg!(windows) => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                }
+                in_word = false;
+            }
+            (_, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return None;
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    let paths: Vec<PathBuf> = words
+        .iter()
+        .map(|w| match w.strip_prefix("file://") {
+            Some(url) => PathBuf::from(percent_decode(url)),
+            None => PathBuf::from(w),
+        })
+        .collect();
+    (!paths.is_empty() && paths.iter().all(|p| p.is_absolute() && p.exists())).then_some(paths)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], text.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| {
+            let part = c.as_os_str().to_string_lossy();
+            if cfg!(windows) { part.to_lowercase() } else { part.into_owned() }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlaps_and_display() {
+        assert!(overlaps(Path::new("/home/u/.cargo/bin"), Path::new("/home/u/.cargo/")));
+        assert!(overlaps(Path::new("/home/u"), Path::new("/home/u/.cargo")));
+        assert!(!overlaps(Path::new("/home/u/.cargo2"), Path::new("/home/u/.cargo")));
+        assert_eq!(display(Path::new("./src/main.rs")), "src/main.rs");
+        assert_eq!(strip_dot("./a.rs"), "a.rs");
+        assert_eq!(expand_home("/tmp/x"), "/tmp/x");
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(expand_home("~/x"), home.join("x").to_string_lossy());
+        }
+    }
+
+    #[test]
+    fn test_dropped() {
+        let dir = std::env::temp_dir().join(format!("hal-dropped-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("my dir")).unwrap();
+        std::fs::write(dir.join("a.rs"), "").unwrap();
+        let d = dir.display();
+        let expected = vec![dir.join("a.rs"), dir.join("my dir")];
+        assert_eq!(dropped(&format!("{}/a.rs {}/my\\ dir ", d, d)), Some(expected.clone()));
+        assert_eq!(dropped(&format!("'{}/a.rs' \"{}/my dir\"", d, d)), Some(expected.clone()));
+        assert_eq!(dropped(&format!("file://{}/a.rs file://{}/my%20dir", d, d)), Some(expected));
+        assert_eq!(dropped(&format!("{}/a.rs and more", d)), None);
+        assert_eq!(dropped("src/main.rs"), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
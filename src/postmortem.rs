@@ -0,0 +1,136 @@
+use crate::config::{Mode, Provider};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// History messages described (roles and sizes only) in the bundle
+const RECENT_MESSAGES: usize = 12;
+/// Tool results quoted, newest last, each cut to MAX_RESULT_CHARS
+const RECENT_RESULTS: usize = 5;
+const MAX_RESULT_CHARS: usize = 1500;
+/// Tail of this session's debug log, when /debug was on
+const LOG_LINES: usize = 80;
+
+fn postmortems_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("hal")
+        .join("postmortems")
+}
+
+fn fence(text: &str) -> String {
+    format!("```\n{}\n```", text.trim_end())
+}
+
+/// A markdown report of a failed turn for a hal bug report: versions, provider settings,
+/// the shape of the request (no message text), recent tool results and the debug log
+/// tail. Every `secrets` value, bearer token and sk- key is redacted.
+pub fn bundle(failure: &str, provider: &Provider, mode: Mode, api_messages: &[Value], secrets: &[&str]) -> String {
+    let mut tool_names: HashMap<&str, &str> = HashMap::new();
+    let mut roles: Vec<(String, usize)> = Vec::new();
+    let mut shape = Vec::new();
+    let mut results = Vec::new();
+    for message in api_messages {
+        let role = message["role"].as_str().unwrap_or("?");
+        match roles.iter_mut().find(|(r, _)| r == role) {
+            Some((_, count)) => *count += 1,
+            None => roles.push((role.to_string(), 1)),
+        }
+        let chars = message["content"].as_str().map_or(0, |c| c.chars().count());
+        let line = match role {
+            "assistant" if message["tool_calls"].is_array() => {
+                let calls = message["tool_calls"].as_array().into_iter().flatten();
+                let names: Vec<&str> = calls
+                    .filter_map(|call| {
+                        let name = call["function"]["name"].as_str()?;
+                        tool_names.insert(call["id"].as_str()?, name);
+                        Some(name)
+                    })
+                    .collect();
+                format!("assistant, {} chars, tool calls: {}", chars, names.join(", "))
+            }
+            "tool" => {
+                let name = message["tool_call_id"].as_str().and_then(|id| tool_names.get(id)).unwrap_or(&"unknown");
+                let content = message["content"].as_str().unwrap_or("");
+                let quoted: String = content.chars().take(MAX_RESULT_CHARS).collect();
+                let cut = if content.chars().count() > MAX_RESULT_CHARS { "\n[...]" } else { "" };
+                results.push(format!("### {}\n\n{}", name, fence(&format!("{}{}", quoted, cut))));
+                format!("tool ({}), {} chars", name, chars)
+            }
+            _ => format!("{}, {} chars", role, chars),
+        };
+        shape.push(line);
+    }
+
+    let bytes: usize = api_messages.iter().map(|m| m.to_string().len()).sum();
+    let counts: Vec<String> = roles.iter().map(|(role, count)| format!("{} {}", role, count)).collect();
+    let mut sections = vec![
+        "# hal post-mortem".to_string(),
+        [
+            format!("- Failure: {}", failure),
+            format!("- When: {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S %z")),
+            format!("- hal {} ({} {})", env!("CARGO_PKG_VERSION"), std::env::consts::OS, std::env::consts::ARCH),
+            format!("- TERM: {}", std::env::var("TERM").unwrap_or_default()),
+            format!("- Provider: {} model {}", provider.base_url, provider.model),
+            format!("- Auth: {:?}, compat: {:?}, compact tools: {}", provider.auth, provider.compat, provider.compact_tools),
+            format!("- Mode: {:?}", mode),
+        ]
+        .join("\n"),
+        format!(
+            "## Request\n\n{} messages (~{} bytes): {}\n\nLast {} (text omitted):\n\n{}",
+            api_messages.len(),
+            bytes,
+            counts.join(", "),
+            RECENT_MESSAGES.min(shape.len()),
+            shape[shape.len().saturating_sub(RECENT_MESSAGES)..]
+                .iter()
+                .map(|l| format!("- {}", l))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    ];
+    if !results.is_empty() {
+        sections.push(format!("## Recent tool results\n\n{}", results[results.len().saturating_sub(RECENT_RESULTS)..].join("\n\n")));
+    }
+    if crate::debug_log::path().exists() {
+        let log = fs::read_to_string(crate::debug_log::path()).unwrap_or_default();
+        let lines: Vec<&str> = log.lines().collect();
+        let tail = lines[lines.len().saturating_sub(LOG_LINES)..].join("\n");
+        sections.push(format!("## Debug log (last {} lines)\n\n{}", LOG_LINES, fence(&tail)));
+    } else {
+        sections.push("## Debug log\n\nOff. Run `/debug on` and reproduce the failure to include API traffic.".to_string());
+    }
+    crate::debug_log::redact(&sections.join("\n\n"), secrets) + "\n"
+}
+
+/// Write a bundle to the config dir, returning its path
+pub fn save(bundle: &str) -> Result<PathBuf, String> {
+    let dir = postmortems_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to save post-mortem: {}", e))?;
+    let path = dir.join(format!("hal-postmortem-{}.md", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    fs::write(&path, bundle).map_err(|e| format!("Failed to save post-mortem: {}", e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bundle_omits_message_text() {
+        let provider = crate::config::Config::default().providers["openai"].clone();
+        let messages = vec![
+            json!({"role": "system", "content": "system prompt"}),
+            json!({"role": "user", "content": "my private question"}),
+            json!({"role": "assistant", "content": null, "tool_calls": [{"id": "1", "function": {"name": "bash", "arguments": "{}"}}]}),
+            json!({"role": "tool", "tool_call_id": "1", "content": "token=abcdefgh12345678 done"}),
+        ];
+        let bundle = bundle("API error: 500: boom", &provider, Mode::Coding, &messages, &["abcdefgh12345678"]);
+        assert!(bundle.contains("- Failure: API error: 500: boom"));
+        assert!(bundle.contains("assistant, 0 chars, tool calls: bash"));
+        assert!(bundle.contains("### bash") && bundle.contains("token=[redacted] done"));
+        assert!(!bundle.contains("my private question"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::Path;
+
+/// A short note on the project's language and build commands for the system prompt,
+/// or None if nothing recognizable is in `dir`
+pub fn detect(dir: &Path) -> Option<String> {
+    let has = |name: &str| dir.join(name).exists();
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap_or_default();
+
+    if has("Cargo.toml") {
+        let kind = if read("Cargo.toml").contains("[workspace]") { "a Rust workspace" } else { "a Rust crate" };
+        let test = if on_path("cargo-nextest") { "`cargo nextest run`" } else { "`cargo test`" };
+        return Some(format!("This is {}; build with `cargo build`, test with {}, lint with `cargo clippy`.", kind, test));
+    }
+    if has("package.json") {
+        let manager = if has("pnpm-lock.yaml") {
+            "pnpm"
+        } else if has("yarn.lock") {
+            "yarn"
+        } else if has("bun.lockb") || has("bun.lock") {
+            "bun"
+        } else {
+            "npm"
+        };
+        let language = if has("tsconfig.json") { "TypeScript" } else { "JavaScript" };
+        let package: serde_json::Value = serde_json::from_str(&read("package.json")).unwrap_or_default();
+        let scripts: Vec<String> = ["build", "test", "lint", "typecheck"]
+            .iter()
+            .filter(|name| package["scripts"].get(**name).is_some())
+            .map(|name| format!("`{} run {}`", manager, name))
+            .collect();
+        let mut note = format!("This is a {} project using {}.", language, manager);
+        if !scripts.is_empty() {
+            note.push_str(&format!(" Scripts: {}.", scripts.join(", ")));
+        }
+        return Some(note);
+    }
+    if has("pyproject.toml") || has("setup.py") || has("requirements.txt") {
+        let runner = if has("uv.lock") {
+            "uv run "
+        } else if has("poetry.lock") {
+            "poetry run "
+        } else {
+            ""
+        };
+        return Some(format!("This is a Python project; test with `{}pytest`.", runner));
+    }
+    if has("go.mod") {
+        return Some("This is a Go module; build with `go build ./...`, test with `go test ./...`.".to_string());
+    }
+    if has("pom.xml") {
+        return Some("This is a Maven project; build with `mvn -q compile`, test with `mvn -q test`.".to_string());
+    }
+    if has("build.gradle") || has("build.gradle.kts") {
+        let gradle = if has("gradlew") { "./gradlew" } else { "gradle" };
+        return Some(format!("This is a Gradle project; build with `{} build`, test with `{} test`.", gradle, gradle));
+    }
+    if has("Gemfile") {
+        return Some("This is a Ruby project; run commands through `bundle exec`.".to_string());
+    }
+    if has("CMakeLists.txt") {
+        return Some("This is a CMake project; configure with `cmake -B build`, build with `cmake --build build`.".to_string());
+    }
+    if has("Makefile") {
+        return Some("This project builds with `make`; check the Makefile for targets.".to_string());
+    }
+    None
+}
+
+fn on_path(binary: &str) -> bool {
+    let Some(paths) = std::env::var_os("PATH") else {
+        return false;
+    };
+    let name = format!("{}{}", binary, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&paths).any(|dir| dir.join(&name).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_node_project() {
+        let dir = std::env::temp_dir().join(format!("hal-project-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(detect(&dir), None);
+
+        std::fs::write(dir.join("package.json"), r#"{"scripts": {"test": "vitest", "build": "tsc"}}"#).unwrap();
+        std::fs::write(dir.join("pnpm-lock.yaml"), "").unwrap();
+        std::fs::write(dir.join("tsconfig.json"), "{}").unwrap();
+        assert_eq!(
+            detect(&dir).unwrap(),
+            "This is a TypeScript project using pnpm. Scripts: `pnpm run build`, `pnpm run test`."
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
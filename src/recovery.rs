@@ -0,0 +1,115 @@
+use crate::config::Config;
+use crate::session::Session;
+use std::fs::{self, File};
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Set when hal panics, so markers outlive the unwind and the next start offers recovery
+static CRASHED: AtomicBool = AtomicBool::new(false);
+static NEXT_MARKER: AtomicUsize = AtomicUsize::new(0);
+
+fn markers_dir() -> PathBuf {
+    if cfg!(test) {
+        return std::env::temp_dir().join(format!("hal-test-running-{}", std::process::id()));
+    }
+    Config::dir().join("running")
+}
+
+/// Names the session one hal tab is working in, locked for as long as the process lives.
+/// A marker that can be locked at startup was left by a hal that crashed or was killed.
+pub struct Marker {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl Marker {
+    pub fn create() -> Option<Self> {
+        if !cfg!(test) && !Config::writable() {
+            return None;
+        }
+        let dir = markers_dir();
+        fs::create_dir_all(&dir).ok()?;
+        let path = dir.join(format!("{}-{}", std::process::id(), NEXT_MARKER.fetch_add(1, Ordering::SeqCst)));
+        let file = File::create(&path).ok()?;
+        file.try_lock().ok()?;
+        Some(Self { path, file: Some(file) })
+    }
+
+    /// Record the session this tab is on
+    pub fn set(&mut self, session_id: &str) {
+        if let Some(file) = &mut self.file {
+            let _ = file.set_len(0);
+            let _ = file.rewind();
+            let _ = file.write_all(session_id.as_bytes());
+        }
+    }
+}
+
+impl Drop for Marker {
+    /// A clean exit removes the marker
+    fn drop(&mut self) {
+        if CRASHED.load(Ordering::SeqCst) {
+            return;
+        }
+        drop(self.file.take());
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub fn mark_crashed() {
+    CRASHED.store(true, Ordering::SeqCst);
+}
+
+/// Session ids from markers no running hal holds. The markers are removed.
+fn unclean_ids() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(markers_dir()) else { return Vec::new() };
+    let mut ids = Vec::new();
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let Ok(mut file) = File::open(&path) else { continue };
+        // Still locked: that hal is running
+        if file.try_lock().is_err() {
+            continue;
+        }
+        let mut id = String::new();
+        let _ = file.read_to_string(&mut id);
+        drop(file);
+        let _ = fs::remove_file(&path);
+        if !id.is_empty() && !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// Sessions that were open when hal last crashed or was killed, most recent first
+pub fn unclean_sessions() -> Vec<Session> {
+    let mut sessions: Vec<Session> = unclean_ids()
+        .iter()
+        .filter_map(|id| Session::load(id).ok())
+        .filter(|s| !s.messages.is_empty())
+        .collect();
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.updated_at));
+    sessions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markers() {
+        let mut marker = Marker::create().unwrap();
+        marker.set("live");
+        // A running hal's marker is left alone
+        assert!(unclean_ids().is_empty());
+        drop(marker);
+        assert_eq!(fs::read_dir(markers_dir()).unwrap().count(), 0);
+
+        // One whose process died is unlocked
+        fs::write(markers_dir().join("1-0"), "crashed").unwrap();
+        assert_eq!(unclean_ids(), vec!["crashed".to_string()]);
+        assert_eq!(fs::read_dir(markers_dir()).unwrap().count(), 0);
+        let _ = fs::remove_dir_all(markers_dir());
+    }
+}
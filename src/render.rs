@@ -0,0 +1,590 @@
+use crate::app::{ChatMessage, MessageRole};
+use crate::tools::{self, ErrorKind};
+use serde_json::{json, Value};
+use std::ops::Range;
+
+/// Output target for a transcript. The TUI chat view and the exporters all walk
+/// messages through `render_transcript`, so tool calls and diffs are summarized
+/// the same way everywhere.
+pub trait TranscriptRenderer<'a> {
+    fn user(&mut self, content: &'a str);
+    fn assistant(&mut self, content: &'a str);
+    /// Condensed summary of a tool result (see `format_tool_result`) along with the raw output,
+    /// and why the call failed if it did
+    fn tool_summary(&mut self, name: &'a str, summary: String, output: &'a str, error: Option<ErrorKind>);
+    /// A file change: header line ("Wrote path") followed by non-empty diff lines
+    fn tool_diff(&mut self, name: &'a str, path: Option<&'a str>, header: &'a str, diff: Vec<&'a str>, error: Option<ErrorKind>);
+}
+
+pub fn render_transcript<'a, R: TranscriptRenderer<'a>>(messages: &'a [ChatMessage], renderer: &mut R) {
+    for msg in messages {
+        match &msg.role {
+            MessageRole::User => renderer.user(&msg.content),
+            MessageRole::Assistant => renderer.assistant(&msg.content),
+            MessageRole::Tool { name, path, error } => {
+                if tools::is_file_change(name) {
+                    let mut lines = msg.content.lines();
+                    if let Some(header) = lines.next() {
+                        let diff = lines.filter(|l| !l.is_empty()).collect();
+                        renderer.tool_diff(name, path.as_deref(), header, diff, *error);
+                    }
+                } else {
+                    // Failures are shown as they are, not summarized
+                    let summary = match error {
+                        Some(_) => msg.content.clone(),
+                        None => format_tool_result(name, path.as_deref(), &msg.content),
+                    };
+                    renderer.tool_summary(name, summary, &msg.content, *error);
+                }
+            }
+        }
+    }
+}
+
+/// Diffs longer than this collapse to a stat line
+const LARGE_DIFF_LINES: usize = 300;
+
+const LOCKFILES: &[&str] = &[
+    "Cargo.lock", "package-lock.json", "npm-shrinkwrap.json", "yarn.lock", "pnpm-lock.yaml", "bun.lock",
+    "poetry.lock", "Pipfile.lock", "uv.lock", "Gemfile.lock", "composer.lock", "go.sum", "flake.lock",
+    "mix.lock", "pubspec.lock", "Podfile.lock",
+];
+
+const GENERATED_MARKERS: &[&str] = &["@generated", "DO NOT EDIT", "Code generated", "auto-generated", "autogenerated"];
+
+/// Lockfiles, snapshots, minified or generated files and very large diffs, which are
+/// shown as a stat line unless expanded
+pub fn is_low_signal_diff(path: Option<&str>, diff: &[&str]) -> bool {
+    if diff.len() > LARGE_DIFF_LINES {
+        return true;
+    }
+    if let Some(path) = path {
+        let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+        if LOCKFILES.contains(&name)
+            || [".snap", ".min.js", ".min.css", ".map", ".pb.go", "_pb2.py"].iter().any(|ext| name.ends_with(ext))
+            || name.contains(".generated.")
+            || path.contains("__snapshots__")
+        {
+            return true;
+        }
+    }
+    diff.iter().take(20).any(|line| GENERATED_MARKERS.iter().any(|m| line.contains(m)))
+}
+
+/// "+12 −3" for a preview diff
+pub fn diff_stat(diff: &[&str]) -> String {
+    let added = diff.iter().filter(|l| l.starts_with('+')).count();
+    let removed = diff.iter().filter(|l| l.starts_with('-')).count();
+    format!("+{} {}{}", added, crate::glyphs::get().minus, removed)
+}
+
+/// Split a preview diff line into marker, line number and code ("+  12│code")
+pub fn split_diff_line(line: &str) -> Option<(char, &str, &str)> {
+    let marker = line.chars().next().filter(|c| matches!(c, '+' | '-' | ' '))?;
+    let (number, code) = line[1..].split_once('│')?;
+    Some((marker, number, code))
+}
+
+/// Paired lines sharing less than this are shown as whole-line changes
+const MIN_WORD_DIFF_SIMILARITY: f32 = 0.4;
+
+/// Changed byte ranges within one line
+type Emphasis = Vec<Range<usize>>;
+
+/// Byte ranges of `old` and `new` that a word diff marks as changed, or None when
+/// the lines have too little in common for word emphasis to help
+fn changed_words(old: &str, new: &str) -> Option<(Emphasis, Emphasis)> {
+    let diff = similar::TextDiff::from_words(old, new);
+    if diff.ratio() < MIN_WORD_DIFF_SIMILARITY {
+        return None;
+    }
+    let (mut old_ranges, mut new_ranges): (Emphasis, Emphasis) = (Vec::new(), Vec::new());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    let push = |ranges: &mut Vec<Range<usize>>, range: Range<usize>| match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
+    };
+    for change in diff.iter_all_changes() {
+        let len = change.value().len();
+        match change.tag() {
+            similar::ChangeTag::Equal => {
+                old_pos += len;
+                new_pos += len;
+            }
+            similar::ChangeTag::Delete => {
+                push(&mut old_ranges, old_pos..old_pos + len);
+                old_pos += len;
+            }
+            similar::ChangeTag::Insert => {
+                push(&mut new_ranges, new_pos..new_pos + len);
+                new_pos += len;
+            }
+        }
+    }
+    Some((old_ranges, new_ranges))
+}
+
+/// Word-level changes for each line of a preview diff, as byte ranges in the line's
+/// code. Each run of removed lines is paired in order with the added lines after it.
+pub fn word_emphasis(diff: &[&str]) -> Vec<Emphasis> {
+    let parsed: Vec<Option<(char, &str)>> = diff.iter().map(|l| split_diff_line(l).map(|(m, _, code)| (m, code))).collect();
+    let marker = |i: usize| parsed.get(i).copied().flatten().map(|(m, _)| m);
+    let mut emphasis = vec![Vec::new(); diff.len()];
+    let mut i = 0;
+    while i < diff.len() {
+        if marker(i) != Some('-') {
+            i += 1;
+            continue;
+        }
+        let removed_end = (i..diff.len()).find(|&j| marker(j) != Some('-')).unwrap_or(diff.len());
+        let added_end = (removed_end..diff.len()).find(|&j| marker(j) != Some('+')).unwrap_or(diff.len());
+        for (old, new) in (i..removed_end).zip(removed_end..added_end) {
+            let (Some((_, old_code)), Some((_, new_code))) = (parsed[old], parsed[new]) else { continue };
+            if let Some((old_ranges, new_ranges)) = changed_words(old_code, new_code) {
+                emphasis[old] = old_ranges;
+                emphasis[new] = new_ranges;
+            }
+        }
+        i = added_end.max(i + 1);
+    }
+    emphasis
+}
+
+/// Drop the line-number gutter from a preview diff line ("+  12│code" -> "+code")
+pub fn strip_diff_gutter(line: &str) -> String {
+    match line.find('│') {
+        Some(sep) if matches!(line.as_bytes()[0], b'+' | b'-' | b' ') => {
+            format!("{}{}", &line[..1], &line[sep + '│'.len_utf8()..])
+        }
+        _ => line.to_string(),
+    }
+}
+
+pub fn format_tool_result(name: &str, path: Option<&str>, result: &str) -> String {
+    match name {
+        "read_file" => {
+            let lines = result.lines().count();
+            match path {
+                Some(p) => format!("Read {} ({} lines)", p, lines),
+                None => format!("Read file ({} lines)", lines),
+            }
+        }
+        "list_dir" => {
+            let items = result.lines().count();
+            let dir = path.unwrap_or(".");
+            if items <= 8 {
+                format!("Listed {} ({})", dir, result.lines().collect::<Vec<_>>().join("  "))
+            } else {
+                format!("Listed {} ({} items)", dir, items)
+            }
+        }
+        "repo_stats" => format!("Stats: {}", result.lines().next().unwrap_or("")),
+        "list_symbols" => {
+            let symbols = result.lines().filter(|l| l.starts_with("  ")).count();
+            format!("Listed {} symbol{}", symbols, if symbols == 1 { "" } else { "s" })
+        }
+        "find_definition" => {
+            let found: Vec<_> = result.lines().filter(|l| !l.starts_with("    ")).collect();
+            match found.as_slice() {
+                _ if result.starts_with("No definition") || result.starts_with("Error:") => result.to_string(),
+                [only] => format!("Found {}", only),
+                _ => format!("Found {} definitions", found.len()),
+            }
+        }
+        "todo_write" | "todo_read" => {
+            let summary = result.lines().next().unwrap_or("").trim_start_matches("Plan ");
+            let verb = if name == "todo_write" { "Updated" } else { "Read" };
+            format!("{} plan {}", verb, summary)
+        }
+        "search_files" => {
+            let files: Vec<_> = result.lines().collect();
+            if files.len() <= 6 {
+                format!("Found {}", files.join(", "))
+            } else {
+                format!("Found {} files", files.len())
+            }
+        }
+        "gh_fetch" => {
+            // First line is "# Issue owner/repo#n: title"
+            let title = result.lines().next().unwrap_or("").trim_start_matches("# ");
+            let comments = result.matches("\n---\n").count();
+            format!("Fetched {} ({} comments)", title, comments)
+        }
+        "image" => result.to_string(),
+        "write_file" => {
+            // Extract just the first line (the "Wrote path" part)
+            result.lines().next().unwrap_or(result).to_string()
+        }
+        "bash" => {
+            let mut lines = result.lines();
+            let cmd = lines.next().unwrap_or("$ ?");
+            let output: Vec<_> = lines.collect();
+            if output.is_empty() {
+                cmd.to_string()
+            } else if output.len() <= 10 {
+                format!("{}\n{}", cmd, output.join("\n"))
+            } else {
+                format!("{}\n{}\n... ({} more lines)", cmd, output[..8].join("\n"), output.len() - 8)
+            }
+        }
+        "grep" => {
+            // First line is "grep 'pattern':" - extract pattern
+            let mut lines = result.lines();
+            let header = lines.next().unwrap_or("");
+            let pattern = header.strip_prefix("grep '")
+                .and_then(|s| s.strip_suffix("':"))
+                .unwrap_or("?");
+            let matches: Vec<_> = lines.filter(|l| !l.is_empty() && *l != "--").collect();
+            if matches.is_empty() || result.contains("no matches") {
+                format!("Grep '{}': no matches", pattern)
+            } else if matches.len() <= 4 {
+                format!("Grep '{}':\n{}", pattern, matches.iter().map(|l| {
+                    if l.len() > 60 { format!("{}...", &l[..60]) } else { l.to_string() }
+                }).collect::<Vec<_>>().join("\n"))
+            } else {
+                format!("Grep '{}': {} matches\n{}\n...", pattern, matches.len(), matches[..3].iter().map(|l| {
+                    if l.len() > 60 { format!("{}...", &l[..60]) } else { l.to_string() }
+                }).collect::<Vec<_>>().join("\n"))
+            }
+        }
+        _ => {
+            if result.len() > 60 {
+                format!("{}...", &result[..60])
+            } else {
+                result.to_string()
+            }
+        }
+    }
+}
+
+/// A transcript as a standalone Markdown document
+pub fn markdown_document(title: &str, messages: &[ChatMessage]) -> String {
+    let mut renderer = MarkdownRenderer::default();
+    render_transcript(messages, &mut renderer);
+    format!("# {}\n\n{}", title, renderer.out)
+}
+
+/// Plain text, as printed to the terminal. In condensed mode tool output is
+/// dropped and only the list of changed files is kept.
+pub struct PlainTextRenderer<'a> {
+    out: String,
+    condensed: bool,
+    files_changed: Vec<&'a str>,
+}
+
+impl<'a> PlainTextRenderer<'a> {
+    pub fn new(condensed: bool) -> Self {
+        Self { out: String::new(), condensed, files_changed: Vec::new() }
+    }
+
+    pub fn finish(mut self) -> String {
+        if !self.files_changed.is_empty() {
+            self.out.push_str(&format!("\nFiles changed: {}\n", self.files_changed.join(", ")));
+        }
+        self.out
+    }
+}
+
+impl<'a> TranscriptRenderer<'a> for PlainTextRenderer<'a> {
+    fn user(&mut self, content: &'a str) {
+        self.out.push_str(&format!("\n{} {}\n", crate::glyphs::get().prompt, content));
+    }
+
+    fn assistant(&mut self, content: &'a str) {
+        self.out.push_str(&format!("\n{}\n", content.trim_end()));
+    }
+
+    fn tool_summary(&mut self, _name: &'a str, summary: String, _output: &'a str, _error: Option<ErrorKind>) {
+        if self.condensed {
+            return;
+        }
+        for (i, line) in summary.lines().enumerate() {
+            let prefix = if i == 0 { format!("  {} ", crate::glyphs::get().tool) } else { "    ".to_string() };
+            self.out.push_str(&format!("{}{}\n", prefix, line));
+        }
+    }
+
+    fn tool_diff(&mut self, _name: &'a str, path: Option<&'a str>, header: &'a str, diff: Vec<&'a str>, error: Option<ErrorKind>) {
+        if let Some(path) = path
+            && error.is_none()
+            && !self.files_changed.contains(&path)
+        {
+            self.files_changed.push(path);
+        }
+        if self.condensed {
+            return;
+        }
+        self.out.push_str(&format!("  {} {}\n", crate::glyphs::get().tool, header));
+        for line in diff {
+            self.out.push_str(&format!("    {}\n", strip_diff_gutter(line)));
+        }
+    }
+}
+
+/// Markdown with tool summaries as quotes and file changes as ```diff blocks
+#[derive(Default)]
+pub struct MarkdownRenderer {
+    pub out: String,
+}
+
+impl<'a> TranscriptRenderer<'a> for MarkdownRenderer {
+    fn user(&mut self, content: &'a str) {
+        self.out.push_str(&format!("## › User\n\n{}\n\n", content));
+    }
+
+    fn assistant(&mut self, content: &'a str) {
+        self.out.push_str(&format!("## Assistant\n\n{}\n\n", content.trim_end()));
+    }
+
+    fn tool_summary(&mut self, _name: &'a str, summary: String, _output: &'a str, _error: Option<ErrorKind>) {
+        for (i, line) in summary.lines().enumerate() {
+            let prefix = if i == 0 { "> ◇ " } else { "> " };
+            self.out.push_str(&format!("{}{}  \n", prefix, line));
+        }
+        self.out.push('\n');
+    }
+
+    fn tool_diff(&mut self, _name: &'a str, path: Option<&'a str>, header: &'a str, diff: Vec<&'a str>, _error: Option<ErrorKind>) {
+        if is_low_signal_diff(path, &diff) {
+            self.out.push_str(&format!("> ◇ {} ({}, diff omitted)\n\n", header, diff_stat(&diff)));
+            return;
+        }
+        self.out.push_str(&format!("> ◇ {}\n\n", header));
+        if diff.is_empty() {
+            return;
+        }
+        self.out.push_str("```diff\n");
+        for line in diff {
+            self.out.push_str(&strip_diff_gutter(line));
+            self.out.push('\n');
+        }
+        self.out.push_str("```\n\n");
+    }
+}
+
+/// Standalone HTML page: messages rendered from markdown, code blocks and diffs
+/// highlighted with syntect, full tool output behind a <details> toggle
+#[derive(Default)]
+pub struct HtmlRenderer {
+    body: String,
+}
+
+impl HtmlRenderer {
+    pub fn finish(self, title: &str) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n{}</body>\n</html>\n",
+            escape_html(title),
+            HTML_STYLE,
+            escape_html(title),
+            self.body
+        )
+    }
+}
+
+const HTML_STYLE: &str = "
+body { font-family: -apple-system, 'Segoe UI', sans-serif; max-width: 860px; margin: 2em auto; padding: 0 1em; color: #222; }
+.user { border-left: 3px solid #a35ddb; padding: 0.2em 1em; margin: 1.5em 0; background: #faf6fd; }
+.assistant { margin: 1em 0; }
+.tool { color: #666; font-size: 0.9em; margin: 0.4em 0; }
+.tool summary { cursor: pointer; }
+pre { padding: 0.8em; overflow-x: auto; border-radius: 4px; font-size: 0.85em; }
+code { font-family: ui-monospace, Menlo, Consolas, monospace; }
+.role { font-weight: bold; color: #a35ddb; font-size: 0.85em; }
+";
+
+impl<'a> TranscriptRenderer<'a> for HtmlRenderer {
+    fn user(&mut self, content: &'a str) {
+        self.body.push_str(&format!(
+            "<div class=\"user\"><div class=\"role\">› You</div>{}</div>\n",
+            markdown_to_html(content)
+        ));
+    }
+
+    fn assistant(&mut self, content: &'a str) {
+        self.body.push_str(&format!("<div class=\"assistant\">{}</div>\n", markdown_to_html(content)));
+    }
+
+    fn tool_summary(&mut self, _name: &'a str, summary: String, output: &'a str, _error: Option<ErrorKind>) {
+        let first = summary.lines().next().unwrap_or("");
+        self.body.push_str(&format!(
+            "<details class=\"tool\"><summary>◇ {}</summary><pre>{}</pre></details>\n",
+            escape_html(first),
+            escape_html(output.trim_end())
+        ));
+    }
+
+    fn tool_diff(&mut self, _name: &'a str, _path: Option<&'a str>, header: &'a str, diff: Vec<&'a str>, _error: Option<ErrorKind>) {
+        self.body.push_str(&format!("<div class=\"tool\">◇ {}</div>\n", escape_html(header)));
+        if !diff.is_empty() {
+            let diff: Vec<String> = diff.into_iter().map(strip_diff_gutter).collect();
+            self.body.push_str(&highlight_html(&diff.join("\n"), "diff"));
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Highlight code as a standalone <pre> block, falling back to plain text for unknown languages
+fn highlight_html(code: &str, lang: &str) -> String {
+    let syntax_set = crate::ui::get_syntax_set();
+    let syntax = syntax_set
+        .find_syntax_by_token(lang)
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+    let theme = &crate::ui::get_theme_set().themes["InspiredGitHub"];
+    syntect::html::highlighted_html_for_string(code, syntax_set, syntax, theme)
+        .unwrap_or_else(|_| format!("<pre><code>{}</code></pre>", escape_html(code)))
+}
+
+/// Markdown to HTML with fenced code blocks run through syntect
+fn markdown_to_html(markdown: &str) -> String {
+    use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
+
+    let mut events = Vec::new();
+    let mut code_block: Option<(String, String)> = None; // (lang, code)
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let lang = match kind {
+                    CodeBlockKind::Fenced(lang) => lang.split_whitespace().next().unwrap_or("").to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code_block = Some((lang, String::new()));
+            }
+            Event::Text(text) if code_block.is_some() => {
+                if let Some((_, code)) = &mut code_block {
+                    code.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some((lang, code)) = code_block.take() {
+                    events.push(Event::Html(highlight_html(&code, &lang).into()));
+                }
+            }
+            // Raw HTML in messages is shown, not interpreted
+            Event::Html(html) | Event::InlineHtml(html) => events.push(Event::Text(html)),
+            event => events.push(event),
+        }
+    }
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+    html
+}
+
+/// JSON array of transcript entries, for machine consumption (`sessions export --format transcript`)
+#[derive(Default)]
+pub struct JsonRenderer {
+    pub entries: Vec<Value>,
+}
+
+impl<'a> TranscriptRenderer<'a> for JsonRenderer {
+    fn user(&mut self, content: &'a str) {
+        self.entries.push(json!({ "role": "user", "content": content }));
+    }
+
+    fn assistant(&mut self, content: &'a str) {
+        self.entries.push(json!({ "role": "assistant", "content": content }));
+    }
+
+    fn tool_summary(&mut self, name: &'a str, summary: String, _output: &'a str, error: Option<ErrorKind>) {
+        self.entries.push(json!({ "role": "tool", "name": name, "summary": summary, "error": error }));
+    }
+
+    fn tool_diff(&mut self, name: &'a str, path: Option<&'a str>, header: &'a str, diff: Vec<&'a str>, error: Option<ErrorKind>) {
+        let diff: Vec<String> = diff.into_iter().map(strip_diff_gutter).collect();
+        self.entries.push(json!({
+            "role": "tool",
+            "name": name,
+            "path": path,
+            "summary": header,
+            "diff": diff,
+            "error": error,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_low_signal_diff() {
+        let small = vec!["+  1│fn main() {}"];
+        assert!(!is_low_signal_diff(Some("src/main.rs"), &small));
+        assert!(is_low_signal_diff(Some("Cargo.lock"), &small));
+        assert!(is_low_signal_diff(Some("web/package-lock.json"), &small));
+        assert!(is_low_signal_diff(Some("tests/__snapshots__/app.test.ts.snap"), &small));
+        assert!(is_low_signal_diff(Some("src/api.rs"), &["+  1│// @generated by protoc"]));
+        assert!(is_low_signal_diff(Some("src/big.rs"), &vec!["+  1│x"; LARGE_DIFF_LINES + 1]));
+        assert_eq!(diff_stat(&["+  1│a", "+  2│b", "-  3│c", "   4│d"]), "+2 −1");
+    }
+
+    #[test]
+    fn test_word_emphasis() {
+        let diff = ["   1│fn main() {", "-  2│    let total = 1;", "+  2│    let count = 1;", "+  3│    run();", "   4│}"];
+        let emphasis = word_emphasis(&diff);
+        assert_eq!(&diff[1][diff[1].find('│').unwrap() + '│'.len_utf8()..][emphasis[1][0].clone()], "total");
+        assert_eq!(emphasis[2], vec![8..13]);
+        assert!(emphasis[0].is_empty() && emphasis[3].is_empty());
+        // Unrelated lines stay whole-line changes
+        assert!(word_emphasis(&["-  1│alpha beta", "+  1│gamma delta"]).iter().all(|e| e.is_empty()));
+    }
+
+    #[test]
+    fn test_strip_diff_gutter() {
+        assert_eq!(strip_diff_gutter("+  12│let x = 1;"), "+let x = 1;");
+        assert_eq!(strip_diff_gutter("   3│fn main() {"), " fn main() {");
+        assert_eq!(strip_diff_gutter("-old line"), "-old line");
+    }
+
+    #[test]
+    fn test_find_definition_summary() {
+        let summary = |result| format_tool_result("find_definition", None, result);
+        assert_eq!(summary("src/a.rs:3 fn parse\n    fn parse() {}"), "Found src/a.rs:3 fn parse");
+        assert_eq!(summary("No definition of parse found"), "No definition of parse found");
+        assert_eq!(summary("Error: symbol is required"), "Error: symbol is required");
+    }
+
+    #[test]
+    fn test_markdown_to_html_highlights_code_and_escapes_html() {
+        let html = markdown_to_html("Try <b>this</b>:\n\n```rust\nfn main() {}\n```\n");
+        assert!(html.contains("&lt;b&gt;"));
+        assert!(html.contains("<pre style="));
+        assert!(!html.contains("```"));
+    }
+
+    #[test]
+    fn test_condensed_plain_text_lists_changed_files() {
+        let messages = vec![
+            ChatMessage { role: MessageRole::User, content: "fix it".to_string() },
+            ChatMessage {
+                role: MessageRole::Tool { name: "edit_file".to_string(), path: Some("src/a.rs".to_string()), error: None },
+                content: "Edited src/a.rs\n-old\n+new\n".to_string(),
+            },
+            ChatMessage { role: MessageRole::Assistant, content: "Done.".to_string() },
+        ];
+        let mut renderer = PlainTextRenderer::new(true);
+        render_transcript(&messages, &mut renderer);
+        assert_eq!(renderer.finish(), "\n› fix it\n\nDone.\n\nFiles changed: src/a.rs\n");
+    }
+
+    #[test]
+    fn test_json_transcript() {
+        let messages = vec![
+            ChatMessage {
+                role: MessageRole::Tool { name: "edit_file".to_string(), path: Some("src/a.rs".to_string()), error: None },
+                content: "Edited src/a.rs\n-  1│old\n+  1│new\n".to_string(),
+            },
+            ChatMessage {
+                role: MessageRole::Tool { name: "read_file".to_string(), path: None, error: Some(ErrorKind::NotFound) },
+                content: "Error: no such file".to_string(),
+            },
+        ];
+        let mut renderer = JsonRenderer::default();
+        render_transcript(&messages, &mut renderer);
+        assert_eq!(renderer.entries[0]["diff"], json!(["-old", "+new"]));
+        assert_eq!(renderer.entries[1], json!({ "role": "tool", "name": "read_file", "summary": "Error: no such file", "error": "not_found" }));
+    }
+}
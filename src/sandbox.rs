@@ -1,12 +1,31 @@
+use crate::error::HalError;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once a bash command had to run without isolation
+static FELL_BACK: AtomicBool = AtomicBool::new(false);
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SandboxConfig {
     #[serde(default)]
     pub allowed_paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkPolicy>, // Project setting wins over global; unset means allow
+}
+
+/// Whether sandboxed bash commands get network access
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkPolicy {
+    #[default]
+    Allow,
+    Deny,
+    /// Prompt for commands that look like they need the network; others run offline
+    Ask,
 }
 
 impl SandboxConfig {
@@ -20,17 +39,36 @@ impl SandboxConfig {
 
         SandboxConfig {
             allowed_paths: paths.into_iter().collect(),
+            network: project.network.or(global.network),
         }
     }
 
+    pub fn network_policy() -> NetworkPolicy {
+        Self::load_merged().network.unwrap_or_default()
+    }
+
     pub fn load_global() -> Self {
         let path = global_config_path();
         Self::load_from(&path).unwrap_or_default()
     }
 
+    /// .hal/sandbox.json, plus the "sandbox" section of .hal/config.json
     pub fn load_project() -> Self {
         let path = project_config_path();
-        Self::load_from(&path).unwrap_or_default()
+        let mut config = Self::load_from(&path).unwrap_or_default();
+        let from_settings = fs::read_to_string(PathBuf::from(".hal").join("config.json"))
+            .ok()
+            .and_then(|c| serde_json::from_str::<serde_json::Value>(&c).ok())
+            .and_then(|v| serde_json::from_value::<SandboxConfig>(v.get("sandbox")?.clone()).ok());
+        if let Some(extra) = from_settings {
+            for path in extra.allowed_paths.iter().map(|p| expand_path(p)) {
+                if !config.allowed_paths.contains(&path) {
+                    config.allowed_paths.push(path);
+                }
+            }
+            config.network = config.network.or(extra.network);
+        }
+        config
     }
 
     fn load_from(path: &Path) -> Option<Self> {
@@ -38,25 +76,26 @@ impl SandboxConfig {
         serde_json::from_str(&content).ok()
     }
 
-    pub fn save_global(&self) -> Result<(), String> {
+    pub fn save_global(&self) -> Result<(), HalError> {
         let path = global_config_path();
         self.save_to(&path)
     }
 
-    pub fn save_project(&self) -> Result<(), String> {
+    pub fn save_project(&self) -> Result<(), HalError> {
         let path = project_config_path();
         self.save_to(&path)
     }
 
-    fn save_to(&self, path: &Path) -> Result<(), String> {
+    fn save_to(&self, path: &Path) -> Result<(), HalError> {
+        let failed = |e: &dyn std::fmt::Display| HalError::Sandbox(format!("Failed to save {}: {}", path.display(), e));
         if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            fs::create_dir_all(parent).map_err(|e| failed(&e))?;
         }
-        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
-        fs::write(path, content).map_err(|e| e.to_string())
+        let content = serde_json::to_string_pretty(self).map_err(|e| failed(&e))?;
+        fs::write(path, content).map_err(|e| failed(&e))
     }
 
-    pub fn add_path_global(path: &str) -> Result<(), String> {
+    pub fn add_path_global(path: &str) -> Result<(), HalError> {
         let mut config = Self::load_global();
         let expanded = expand_path(path);
         if !config.allowed_paths.contains(&expanded) {
@@ -65,8 +104,9 @@ impl SandboxConfig {
         config.save_global()
     }
 
-    pub fn add_path_project(path: &str) -> Result<(), String> {
-        let mut config = Self::load_project();
+    pub fn add_path_project(path: &str) -> Result<(), HalError> {
+        // Only sandbox.json is written; the config.json section is left to the user
+        let mut config = Self::load_from(&project_config_path()).unwrap_or_default();
         let expanded = expand_path(path);
         if !config.allowed_paths.contains(&expanded) {
             config.allowed_paths.push(expanded);
@@ -87,12 +127,7 @@ fn project_config_path() -> PathBuf {
 }
 
 fn expand_path(path: &str) -> String {
-    if path.starts_with("~/") {
-        if let Some(home) = dirs::home_dir() {
-            return home.join(&path[2..]).to_string_lossy().to_string();
-        }
-    }
-    path.to_string()
+    crate::paths::expand_home(path)
 }
 
 /// Detect paths that a command might need based on common tools
@@ -205,6 +240,25 @@ pub fn detect_required_paths(command: &str) -> Vec<PathRequest> {
     requests
 }
 
+/// Guess whether a command needs the network (downloads, package installs, remote git)
+pub fn needs_network(command: &str) -> bool {
+    const PATTERNS: &[&str] = &[
+        "curl ", "wget ", "ssh ", "scp ", "rsync ",
+        "git clone", "git fetch", "git pull", "git push", "git submodule",
+        "pip install", "pip3 install", "pip download", "uv pip", "uv add", "uv sync", "poetry install", "poetry add",
+        "npm install", "npm i ", "npm ci", "npx ", "yarn add", "yarn install", "pnpm install", "pnpm add",
+        "cargo fetch", "cargo install", "cargo update", "cargo add", "cargo search", "cargo publish",
+        "go get", "go mod download", "go install",
+        "brew install", "brew update", "apt install", "apt-get install", "apt update", "apt-get update",
+        "docker pull", "docker push",
+    ];
+    // Pad so patterns ending in a space also match a bare trailing command
+    let padded = format!(" {} ", command.split_whitespace().collect::<Vec<_>>().join(" "));
+    PATTERNS.iter().any(|p| padded.contains(&format!(" {}", p)))
+        || command.contains("http://")
+        || command.contains("https://")
+}
+
 #[derive(Debug, Clone)]
 pub struct PathRequest {
     pub path: String,
@@ -221,15 +275,534 @@ pub fn get_missing_paths(command: &str) -> Vec<PathRequest> {
         .into_iter()
         .filter(|req| {
             let req_path = Path::new(&req.path);
-            !config.allowed_paths.iter().any(|allowed| {
-                let allowed_path = Path::new(allowed);
-                req_path.starts_with(allowed_path) || allowed_path.starts_with(req_path)
-            })
+            !config.allowed_paths.iter().any(|allowed| crate::paths::overlaps(req_path, Path::new(allowed)))
         })
         .collect()
 }
 
+/// Why `dir` is too broad to grant coding tools by default: the home directory or a
+/// filesystem root, where the cwd grant covers effectively everything
+pub fn broad_directory(dir: &Path) -> Option<&'static str> {
+    let dir = crate::paths::canonical(dir);
+    if dir.parent().is_none() {
+        return Some("a filesystem root");
+    }
+    let home = dirs::home_dir()?;
+    (crate::paths::canonical(&home) == dir).then_some("your home directory")
+}
+
+/// Record that a command ran unsandboxed because isolation wasn't available
+pub fn note_fallback() {
+    FELL_BACK.store(true, Ordering::SeqCst);
+}
+
+/// Whether bash commands run isolated: false on Linux without bwrap, and after any
+/// command fell back to running unsandboxed
+pub fn isolated() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    let available = AVAILABLE.get_or_init(|| {
+        !cfg!(target_os = "linux")
+            || std::process::Command::new("which").arg("bwrap").output().is_ok_and(|o| o.status.success())
+    });
+    *available && !FELL_BACK.load(Ordering::Relaxed)
+}
+
 /// Build sandbox profile paths from config
 pub fn get_allowed_paths() -> Vec<String> {
     SandboxConfig::load_merged().allowed_paths
 }
+
+/// Windows isolation: commands run under a restricted, low-integrity copy of our token
+/// inside a job object. Low integrity keeps reads working but blocks writes to anything
+/// not labelled low, so the cwd, allowed paths and a private temp dir are relabelled first.
+#[cfg(target_os = "windows")]
+pub mod windows {
+    use std::collections::HashMap;
+    use std::ffi::{OsStr, c_void};
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::mem::{size_of, zeroed};
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle, RawHandle};
+    use std::os::windows::process::ExitStatusExt;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, ExitStatus, Output};
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Mutex, OnceLock};
+
+    use windows_sys::Win32::Foundation::{
+        GENERIC_READ, GetLastError, HANDLE, HANDLE_FLAG_INHERIT, INVALID_HANDLE_VALUE, LocalFree,
+        SetHandleInformation, WAIT_TIMEOUT,
+    };
+    use windows_sys::Win32::Security::Authorization::{
+        ConvertSecurityDescriptorToStringSecurityDescriptorW, ConvertStringSecurityDescriptorToSecurityDescriptorW,
+        ConvertStringSidToSidW, GetNamedSecurityInfoW, SDDL_REVISION_1, SE_FILE_OBJECT, SetNamedSecurityInfoW,
+    };
+    use windows_sys::Win32::Security::{
+        ACL, CreateRestrictedToken, DISABLE_MAX_PRIVILEGE, GetLengthSid, GetSecurityDescriptorSacl,
+        LABEL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, SID_AND_ATTRIBUTES,
+        SetTokenInformation, TOKEN_ADJUST_DEFAULT, TOKEN_ASSIGN_PRIMARY, TOKEN_DUPLICATE,
+        TOKEN_MANDATORY_LABEL, TOKEN_QUERY, TokenIntegrityLevel,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JOB_OBJECT_LIMIT_DIE_ON_UNHANDLED_EXCEPTION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE, JOB_OBJECT_UILIMIT_DESKTOP,
+        JOB_OBJECT_UILIMIT_DISPLAYSETTINGS, JOB_OBJECT_UILIMIT_EXITWINDOWS,
+        JOB_OBJECT_UILIMIT_GLOBALATOMS, JOB_OBJECT_UILIMIT_HANDLES,
+        JOB_OBJECT_UILIMIT_READCLIPBOARD, JOB_OBJECT_UILIMIT_SYSTEMPARAMETERS,
+        JOB_OBJECT_UILIMIT_WRITECLIPBOARD, JOBOBJECT_BASIC_UI_RESTRICTIONS,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JobObjectBasicUIRestrictions,
+        JobObjectExtendedLimitInformation, SetInformationJobObject, TerminateJobObject,
+    };
+    use windows_sys::Win32::System::Pipes::CreatePipe;
+    use windows_sys::Win32::System::Threading::{
+        CREATE_NO_WINDOW, CREATE_SUSPENDED, CREATE_UNICODE_ENVIRONMENT, CreateProcessAsUserW,
+        GetCurrentProcess, GetExitCodeProcess, OpenProcessToken, PROCESS_INFORMATION,
+        ResumeThread, STARTF_USESTDHANDLES, STARTUPINFOW, TerminateProcess, WaitForSingleObject,
+    };
+
+    /// Low mandatory integrity level
+    const LOW_INTEGRITY_SID: &str = "S-1-16-4096";
+
+    /// Run a command isolated, or unisolated with a warning banner if isolation can't be set up
+    pub fn run(
+        command: &str,
+        cwd: &Path,
+        allowed_paths: &[String],
+        allow_network: bool,
+        cancel: &AtomicBool,
+    ) -> io::Result<Output> {
+        match spawn(command, cwd, allowed_paths) {
+            Ok(child) => {
+                let mut output = child.wait_with_output(cancel)?;
+                // A low-integrity token doesn't restrict sockets
+                if !allow_network {
+                    let banner = "[WARNING: network access cannot be blocked on Windows; command ran with network]\n";
+                    output.stderr.splice(0..0, banner.bytes());
+                }
+                Ok(output)
+            }
+            Err(e) => {
+                super::note_fallback();
+                let mut cmd = Command::new("cmd");
+                cmd.args(["/C", command]).current_dir(cwd);
+                let mut output = crate::tools::output_cancellable(cmd, cancel)?;
+                let banner = format!(
+                    "[WARNING: sandbox isolation could not be established ({}); command ran UNSANDBOXED]\n",
+                    e
+                );
+                output.stderr.splice(0..0, banner.into_bytes());
+                Ok(output)
+            }
+        }
+    }
+
+    struct Sandboxed {
+        process: OwnedHandle,
+        // Dropping the job kills anything the command left running
+        job: OwnedHandle,
+        stdout: File,
+        stderr: File,
+        // Dropped after the job, once nothing sandboxed is left to write
+        _labels: Labels,
+    }
+
+    impl Sandboxed {
+        /// Wait for exit; setting `cancel` terminates everything in the job
+        fn wait_with_output(self, cancel: &AtomicBool) -> io::Result<Output> {
+            let read = |mut file: File| {
+                std::thread::spawn(move || {
+                    let mut buf = Vec::new();
+                    let _ = file.read_to_end(&mut buf);
+                    buf
+                })
+            };
+            let stdout_thread = read(self.stdout);
+            let stderr_thread = read(self.stderr);
+
+            let process = self.process.as_raw_handle() as HANDLE;
+            let mut killed = false;
+            unsafe {
+                while WaitForSingleObject(process, 20) == WAIT_TIMEOUT {
+                    if !killed && cancel.load(Ordering::SeqCst) {
+                        TerminateJobObject(self.job.as_raw_handle() as HANDLE, 1);
+                        killed = true;
+                    }
+                }
+            }
+            let stdout = stdout_thread.join().unwrap_or_default();
+            let stderr = stderr_thread.join().unwrap_or_default();
+
+            let mut code = 0u32;
+            if unsafe { GetExitCodeProcess(process, &mut code) } == 0 {
+                return Err(last_error("GetExitCodeProcess"));
+            }
+            Ok(Output { status: ExitStatus::from_raw(code), stdout, stderr })
+        }
+    }
+
+    fn spawn(command: &str, cwd: &Path, allowed_paths: &[String]) -> io::Result<Sandboxed> {
+        let temp = std::env::temp_dir().join("hal-sandbox");
+        std::fs::create_dir_all(&temp)?;
+
+        // cwd and temp must be writable; missing allowed paths are skipped
+        let mut writable = vec![cwd.to_path_buf(), temp.clone()];
+        writable.extend(allowed_paths.iter().map(PathBuf::from).filter(|p| p.exists()));
+        let labels = Labels::apply(writable)?;
+
+        let token = low_integrity_token()?;
+        let job = job_object()?;
+
+        let inherit = SECURITY_ATTRIBUTES {
+            nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: ptr::null_mut(),
+            bInheritHandle: 1,
+        };
+        let (stdout_read, stdout_write) = pipe(&inherit)?;
+        let (stderr_read, stderr_write) = pipe(&inherit)?;
+        let nul = unsafe {
+            CreateFileW(
+                wide(OsStr::new("NUL")).as_ptr(),
+                GENERIC_READ,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                &inherit,
+                OPEN_EXISTING,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if nul == INVALID_HANDLE_VALUE {
+            return Err(last_error("CreateFileW(NUL)"));
+        }
+        let nul = unsafe { OwnedHandle::from_raw_handle(nul as RawHandle) };
+
+        let mut startup: STARTUPINFOW = unsafe { zeroed() };
+        startup.cb = size_of::<STARTUPINFOW>() as u32;
+        startup.dwFlags = STARTF_USESTDHANDLES;
+        startup.hStdInput = nul.as_raw_handle() as HANDLE;
+        startup.hStdOutput = stdout_write.as_raw_handle() as HANDLE;
+        startup.hStdError = stderr_write.as_raw_handle() as HANDLE;
+
+        let mut command_line = wide(OsStr::new(&format!("cmd /C {}", command)));
+        let cwd_wide = wide(cwd.as_os_str());
+        let environment = environment_block(&temp);
+        let mut info: PROCESS_INFORMATION = unsafe { zeroed() };
+
+        let created = unsafe {
+            CreateProcessAsUserW(
+                token.as_raw_handle() as HANDLE,
+                ptr::null(),
+                command_line.as_mut_ptr(),
+                ptr::null(),
+                ptr::null(),
+                1,
+                CREATE_SUSPENDED | CREATE_UNICODE_ENVIRONMENT | CREATE_NO_WINDOW,
+                environment.as_ptr() as *const c_void,
+                cwd_wide.as_ptr(),
+                &startup,
+                &mut info,
+            )
+        };
+        if created == 0 {
+            return Err(last_error("CreateProcessAsUserW"));
+        }
+        let process = unsafe { OwnedHandle::from_raw_handle(info.hProcess as RawHandle) };
+        let thread = unsafe { OwnedHandle::from_raw_handle(info.hThread as RawHandle) };
+
+        // Assign before the first instruction runs so no child can escape the job
+        unsafe {
+            if AssignProcessToJobObject(job.as_raw_handle() as HANDLE, info.hProcess) == 0 {
+                let err = last_error("AssignProcessToJobObject");
+                TerminateProcess(info.hProcess, 1);
+                return Err(err);
+            }
+            ResumeThread(thread.as_raw_handle() as HANDLE);
+        }
+
+        // Our copies of the child's ends must close or reads never see EOF
+        drop((stdout_write, stderr_write, nul));
+
+        Ok(Sandboxed {
+            process,
+            job,
+            stdout: File::from(stdout_read),
+            stderr: File::from(stderr_read),
+            _labels: labels,
+        })
+    }
+
+    /// Directories currently labelled low: the label each had before, and how many
+    /// running commands need it. Tabs can run commands at the same time.
+    static LABELLED: OnceLock<Mutex<HashMap<PathBuf, (String, usize)>>> = OnceLock::new();
+
+    /// The low integrity label on the directories one command may write, for as long as it
+    /// runs. Dropping it puts back the labels the directories had.
+    struct Labels(Vec<PathBuf>);
+
+    impl Labels {
+        fn apply(paths: Vec<PathBuf>) -> io::Result<Self> {
+            let mut labels = Labels(Vec::new());
+            for path in paths {
+                // On failure, dropping `labels` restores the ones already set
+                label_low(&path)?;
+                labels.0.push(path);
+            }
+            Ok(labels)
+        }
+    }
+
+    impl Drop for Labels {
+        fn drop(&mut self) {
+            let mut labelled = LABELLED.get_or_init(Default::default).lock().unwrap();
+            for path in &self.0 {
+                let Some((_, users)) = labelled.get_mut(path) else { continue };
+                *users -= 1;
+                if *users == 0
+                    && let Some((original, _)) = labelled.remove(path)
+                    && let Err(e) = write_label(path, &original)
+                {
+                    crate::debug_log::log(&format!("sandbox: restoring the label of {}", path.display()), &e.to_string(), &[]);
+                }
+            }
+        }
+    }
+
+    /// Give a directory (and everything created under it) the low integrity label,
+    /// remembering the label it had
+    fn label_low(path: &Path) -> io::Result<()> {
+        let mut labelled = LABELLED.get_or_init(Default::default).lock().unwrap();
+        if let Some((_, users)) = labelled.get_mut(path) {
+            *users += 1;
+            return Ok(());
+        }
+
+        let original = read_label(path)?;
+        let output = Command::new("icacls")
+            .arg(path)
+            .args(["/setintegritylevel", "(OI)(CI)low", "/Q"])
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "cannot make {} writable to the sandbox: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        labelled.insert(path.to_path_buf(), (original, 1));
+        Ok(())
+    }
+
+    /// The mandatory label part of a path's security descriptor, as SDDL ("S:" with no
+    /// entries when it has none)
+    fn read_label(path: &Path) -> io::Result<String> {
+        unsafe {
+            let mut sacl: *mut ACL = ptr::null_mut();
+            let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+            let err = GetNamedSecurityInfoW(
+                wide(path.as_os_str()).as_ptr(),
+                SE_FILE_OBJECT,
+                LABEL_SECURITY_INFORMATION,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut sacl,
+                &mut descriptor,
+            );
+            if err != 0 {
+                return Err(io::Error::from_raw_os_error(err as i32));
+            }
+            let mut sddl = ptr::null_mut();
+            let converted = ConvertSecurityDescriptorToStringSecurityDescriptorW(
+                descriptor,
+                SDDL_REVISION_1,
+                LABEL_SECURITY_INFORMATION,
+                &mut sddl,
+                ptr::null_mut(),
+            );
+            LocalFree(descriptor);
+            if converted == 0 {
+                return Err(last_error("ConvertSecurityDescriptorToStringSecurityDescriptorW"));
+            }
+            let len = (0..).take_while(|&i| *sddl.add(i) != 0).count();
+            let label = String::from_utf16_lossy(std::slice::from_raw_parts(sddl, len));
+            LocalFree(sddl as *mut c_void);
+            Ok(if label.is_empty() { "S:".to_string() } else { label })
+        }
+    }
+
+    /// Put back a label saved by `read_label`. Children that inherited the low label lose it.
+    fn write_label(path: &Path, label: &str) -> io::Result<()> {
+        unsafe {
+            let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+            if ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                wide(OsStr::new(label)).as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                ptr::null_mut(),
+            ) == 0
+            {
+                return Err(last_error("ConvertStringSecurityDescriptorToSecurityDescriptorW"));
+            }
+            let (mut present, mut defaulted) = (0, 0);
+            let mut sacl: *mut ACL = ptr::null_mut();
+            GetSecurityDescriptorSacl(descriptor, &mut present, &mut sacl, &mut defaulted);
+            let err = SetNamedSecurityInfoW(
+                wide(path.as_os_str()).as_ptr(),
+                SE_FILE_OBJECT,
+                LABEL_SECURITY_INFORMATION,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null(),
+                sacl,
+            );
+            LocalFree(descriptor);
+            if err != 0 {
+                return Err(io::Error::from_raw_os_error(err as i32));
+            }
+            Ok(())
+        }
+    }
+
+    /// Our token with all privileges stripped and integrity lowered to Low
+    fn low_integrity_token() -> io::Result<OwnedHandle> {
+        unsafe {
+            let mut own: HANDLE = ptr::null_mut();
+            let access = TOKEN_DUPLICATE | TOKEN_QUERY | TOKEN_ASSIGN_PRIMARY | TOKEN_ADJUST_DEFAULT;
+            if OpenProcessToken(GetCurrentProcess(), access, &mut own) == 0 {
+                return Err(last_error("OpenProcessToken"));
+            }
+            let own = OwnedHandle::from_raw_handle(own as RawHandle);
+
+            let mut restricted: HANDLE = ptr::null_mut();
+            if CreateRestrictedToken(
+                own.as_raw_handle() as HANDLE,
+                DISABLE_MAX_PRIVILEGE,
+                0,
+                ptr::null(),
+                0,
+                ptr::null(),
+                0,
+                ptr::null(),
+                &mut restricted,
+            ) == 0
+            {
+                return Err(last_error("CreateRestrictedToken"));
+            }
+            let restricted = OwnedHandle::from_raw_handle(restricted as RawHandle);
+
+            let mut sid = ptr::null_mut();
+            if ConvertStringSidToSidW(wide(OsStr::new(LOW_INTEGRITY_SID)).as_ptr(), &mut sid) == 0 {
+                return Err(last_error("ConvertStringSidToSidW"));
+            }
+            let label = TOKEN_MANDATORY_LABEL {
+                Label: SID_AND_ATTRIBUTES { Sid: sid, Attributes: 0x20 }, // SE_GROUP_INTEGRITY
+            };
+            let set = SetTokenInformation(
+                restricted.as_raw_handle() as HANDLE,
+                TokenIntegrityLevel,
+                &label as *const _ as *const c_void,
+                size_of::<TOKEN_MANDATORY_LABEL>() as u32 + GetLengthSid(sid),
+            );
+            LocalFree(sid);
+            if set == 0 {
+                return Err(last_error("SetTokenInformation"));
+            }
+            Ok(restricted)
+        }
+    }
+
+    /// Job that kills the whole process tree on close and blocks clipboard/desktop access
+    fn job_object() -> io::Result<OwnedHandle> {
+        unsafe {
+            let job = CreateJobObjectW(ptr::null(), ptr::null());
+            if job.is_null() {
+                return Err(last_error("CreateJobObjectW"));
+            }
+            let job = OwnedHandle::from_raw_handle(job as RawHandle);
+            let handle = job.as_raw_handle() as HANDLE;
+
+            let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = zeroed();
+            limits.BasicLimitInformation.LimitFlags =
+                JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE | JOB_OBJECT_LIMIT_DIE_ON_UNHANDLED_EXCEPTION;
+            if SetInformationJobObject(
+                handle,
+                JobObjectExtendedLimitInformation,
+                &limits as *const _ as *const c_void,
+                size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            ) == 0
+            {
+                return Err(last_error("SetInformationJobObject"));
+            }
+
+            let ui = JOBOBJECT_BASIC_UI_RESTRICTIONS {
+                UIRestrictionsClass: JOB_OBJECT_UILIMIT_DESKTOP
+                    | JOB_OBJECT_UILIMIT_DISPLAYSETTINGS
+                    | JOB_OBJECT_UILIMIT_EXITWINDOWS
+                    | JOB_OBJECT_UILIMIT_GLOBALATOMS
+                    | JOB_OBJECT_UILIMIT_HANDLES
+                    | JOB_OBJECT_UILIMIT_READCLIPBOARD
+                    | JOB_OBJECT_UILIMIT_SYSTEMPARAMETERS
+                    | JOB_OBJECT_UILIMIT_WRITECLIPBOARD,
+            };
+            if SetInformationJobObject(
+                handle,
+                JobObjectBasicUIRestrictions,
+                &ui as *const _ as *const c_void,
+                size_of::<JOBOBJECT_BASIC_UI_RESTRICTIONS>() as u32,
+            ) == 0
+            {
+                return Err(last_error("SetInformationJobObject"));
+            }
+            Ok(job)
+        }
+    }
+
+    /// Anonymous pipe whose write end is inherited by the child and read end is not
+    fn pipe(inherit: &SECURITY_ATTRIBUTES) -> io::Result<(OwnedHandle, OwnedHandle)> {
+        unsafe {
+            let mut read: HANDLE = ptr::null_mut();
+            let mut write: HANDLE = ptr::null_mut();
+            if CreatePipe(&mut read, &mut write, inherit, 0) == 0 {
+                return Err(last_error("CreatePipe"));
+            }
+            let read = OwnedHandle::from_raw_handle(read as RawHandle);
+            let write = OwnedHandle::from_raw_handle(write as RawHandle);
+            if SetHandleInformation(read.as_raw_handle() as HANDLE, HANDLE_FLAG_INHERIT, 0) == 0 {
+                return Err(last_error("SetHandleInformation"));
+            }
+            Ok((read, write))
+        }
+    }
+
+    /// Current environment with TEMP/TMP pointed at the sandbox's writable temp dir
+    fn environment_block(temp: &Path) -> Vec<u16> {
+        let mut vars: Vec<(String, String)> = std::env::vars()
+            .filter(|(k, _)| !k.eq_ignore_ascii_case("TEMP") && !k.eq_ignore_ascii_case("TMP"))
+            .collect();
+        let temp = temp.to_string_lossy().to_string();
+        vars.push(("TEMP".to_string(), temp.clone()));
+        vars.push(("TMP".to_string(), temp));
+        vars.sort_by_key(|(k, _)| k.to_uppercase());
+
+        let mut block: Vec<u16> = Vec::new();
+        for (key, value) in vars {
+            block.extend(OsStr::new(&format!("{}={}", key, value)).encode_wide());
+            block.push(0);
+        }
+        block.push(0);
+        block
+    }
+
+    fn wide(s: &OsStr) -> Vec<u16> {
+        s.encode_wide().chain(std::iter::once(0)).collect()
+    }
+
+    fn last_error(call: &str) -> io::Error {
+        let code = unsafe { GetLastError() };
+        io::Error::other(format!("{} failed: {}", call, io::Error::from_raw_os_error(code as i32)))
+    }
+}
@@ -0,0 +1,59 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// This process's scratch directory, created on first use by the scratch_dir tool
+static SCRATCH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// The scratch directory, created if needed
+pub fn get_or_create() -> Result<PathBuf, String> {
+    let mut scratch = SCRATCH.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(dir) = scratch.as_ref().filter(|d| d.is_dir()) {
+        return Ok(dir.clone());
+    }
+    let name = format!("hal-scratch-{}-{}", std::process::id(), chrono::Utc::now().timestamp());
+    let dir = std::env::temp_dir().join(name);
+    fs::create_dir_all(&dir).map_err(|e| format!("Error creating scratch directory: {}", e))?;
+    // Resolved so sandbox rules and path checks see the same path (/tmp is a symlink on macOS)
+    let dir = crate::paths::canonical(&dir);
+    *scratch = Some(dir.clone());
+    Ok(dir)
+}
+
+/// The scratch directory, if one has been created
+pub fn current() -> Option<PathBuf> {
+    SCRATCH.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Whether `path` is inside the scratch directory
+pub fn contains(path: &str) -> bool {
+    let Some(dir) = current() else { return false };
+    // Paths not created yet resolve through their nearest existing ancestor
+    let path = Path::new(path);
+    let Some(existing) = path.ancestors().find(|a| a.exists()) else { return false };
+    let rest = path.strip_prefix(existing).unwrap_or(Path::new(""));
+    crate::paths::canonical(existing).join(rest).starts_with(&dir)
+}
+
+/// Delete the scratch directory; called when hal exits
+pub fn remove() {
+    if let Some(dir) = SCRATCH.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scratch_lifecycle() {
+        let dir = get_or_create().unwrap();
+        assert_eq!(get_or_create().unwrap(), dir);
+        assert!(contains(&dir.join("exp/main.py").to_string_lossy()));
+        assert!(!contains("src/main.rs"));
+        assert!(!contains(&format!("{}-other/x", dir.display())));
+        remove();
+        assert!(!dir.exists() && current().is_none());
+    }
+}
@@ -1,17 +1,85 @@
-use crate::app::ChatMessage;
+use crate::app::{ChatMessage, MessageRole};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Value, json};
+use chrono::Datelike;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub id: String,
     pub created_at: i64,
     pub updated_at: i64,
-    pub title: String,
+    pub title: String, // First user message, truncated
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub generated_title: String, // Short title written by the model after the first reply
     pub messages: Vec<ChatMessage>,
     pub api_messages: Vec<Value>,
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub system_prompt: String, // Appended to the system prompt; set by --seed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>, // Session this one was rewound from
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>, // Sampling seed sent with each request; set by /seed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>, // Provider this session is pinned to; set by /fork
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>, // Model override for the pinned provider
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub turns: Vec<Turn>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub todos: Vec<crate::todo::Todo>, // The model's plan, from todo_write
+    #[serde(skip)]
+    pub dry_runs: crate::tools::DryRuns, // replace_in_files dry runs waiting for a confirmed call
+}
+
+/// What produced one model response, for reproducing a session from a bug report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub api_message: usize, // Index of the response in api_messages
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>, // None if unset or the provider doesn't take one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>, // Backend build, if the provider reports one
+    pub time: i64,
+}
+
+/// A point in the conversation that /rewind can return to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub label: String,
+    pub created_at: i64,
+    pub messages: usize,     // Length of messages at the checkpoint
+    pub api_messages: usize, // Length of api_messages at the checkpoint
+}
+
+/// A prepared conversation loaded with --seed
+#[derive(Debug, Default, Deserialize)]
+struct Seed {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    system: String,
+    #[serde(default)]
+    messages: Vec<SeedTurn>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SeedTurn {
+    role: String,
+    content: String,
+}
+
+/// A marked message, with an optional note about why it matters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub message: usize, // Index into messages
+    pub note: String,
 }
 
 impl Session {
@@ -22,12 +90,89 @@ impl Session {
             created_at: now,
             updated_at: now,
             title: String::new(),
+            generated_title: String::new(),
             messages: Vec::new(),
             api_messages: Vec::new(),
+            bookmarks: Vec::new(),
+            system_prompt: String::new(),
+            parent_id: None,
+            checkpoints: Vec::new(),
+            seed: None,
+            provider: None,
+            model: None,
+            turns: Vec::new(),
+            todos: Vec::new(),
+            dry_runs: Default::default(),
+        }
+    }
+
+    /// A new session branched from this one at `checkpoint`, which leaves this one untouched
+    pub fn fork(&self, checkpoint: &Checkpoint) -> Session {
+        let mut branch = Session::new();
+        // Ids are timestamps; don't collide with a session saved in the same second
+        let base = branch.id.clone();
+        let mut n = 1;
+        while branch.id == self.id || sessions_dir().join(format!("{}.json", branch.id)).exists() {
+            n += 1;
+            branch.id = format!("{}-{}", base, n);
         }
+        branch.parent_id = Some(self.id.clone());
+        branch.title = self.title.clone();
+        branch.generated_title = self.generated_title.clone();
+        branch.system_prompt = self.system_prompt.clone();
+        branch.seed = self.seed;
+        branch.provider = self.provider.clone();
+        branch.model = self.model.clone();
+        branch.todos = self.todos.clone();
+        branch.turns = self.turns.iter().filter(|t| t.api_message < checkpoint.api_messages).cloned().collect();
+        branch.messages = self.messages[..checkpoint.messages.min(self.messages.len())].to_vec();
+        branch.api_messages = self.api_messages[..checkpoint.api_messages.min(self.api_messages.len())].to_vec();
+        branch.bookmarks = self.bookmarks.iter().filter(|b| b.message < checkpoint.messages).cloned().collect();
+        branch.checkpoints = self
+            .checkpoints
+            .iter()
+            .filter(|c| c.messages <= checkpoint.messages)
+            .cloned()
+            .collect();
+        branch
+    }
+
+    /// Start a new session from a seed file: JSON `{title, system, messages: [{role, content}]}`
+    /// or markdown with `## System`, `## User` and `## Assistant` sections
+    pub fn from_seed(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let seed = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::from_str(&content).map_err(|e| format!("Invalid seed {}: {}", path.display(), e))?
+        } else {
+            parse_seed_markdown(&content)
+        };
+
+        let mut session = Session::new();
+        session.title = seed.title;
+        session.system_prompt = seed.system.trim().to_string();
+        // Placeholder for the system prompt, which App::new rebuilds
+        session.api_messages.push(json!({ "role": "system", "content": "" }));
+        for turn in seed.messages {
+            let role = match turn.role.to_lowercase().as_str() {
+                "user" => MessageRole::User,
+                "assistant" => MessageRole::Assistant,
+                other => return Err(format!("Invalid seed {}: unknown role '{}'", path.display(), other)),
+            };
+            let content = turn.content.trim().to_string();
+            session.api_messages.push(json!({
+                "role": if role == MessageRole::User { "user" } else { "assistant" },
+                "content": content
+            }));
+            session.messages.push(ChatMessage { role, content });
+        }
+        Ok(session)
     }
 
     pub fn save(&self) -> Result<(), String> {
+        // Session-only when the config dir is read-only; the startup notice says so
+        if !crate::config::Config::writable() {
+            return Ok(());
+        }
         let path = sessions_dir().join(format!("{}.json", self.id));
 
         if let Some(parent) = path.parent() {
@@ -40,10 +185,34 @@ impl Session {
     }
 
     pub fn load(id: &str) -> Result<Self, String> {
-        let path = sessions_dir().join(format!("{}.json", id));
+        let path = session_path(id)?;
         let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
         serde_json::from_str(&content).map_err(|e| e.to_string())
     }
+
+    pub fn delete(id: &str) -> Result<(), String> {
+        let path = session_path(id)?;
+        fs::remove_file(&path).map_err(|e| format!("Failed to delete session {}: {}", id, e))
+    }
+
+    /// Title for exports and listings: the generated one if there is one
+    pub fn display_title(&self) -> String {
+        if !self.generated_title.is_empty() {
+            self.generated_title.clone()
+        } else if !self.title.is_empty() {
+            self.title.clone()
+        } else {
+            format!("hal session {}", self.id)
+        }
+    }
+}
+
+/// The file for session `id`; ids never name anything outside the sessions directory
+pub fn session_path(id: &str) -> Result<PathBuf, String> {
+    if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+        return Err(format!("Invalid session id: {}", id));
+    }
+    Ok(sessions_dir().join(format!("{}.json", id)))
 }
 
 pub fn sessions_dir() -> PathBuf {
@@ -80,3 +249,101 @@ pub fn list_sessions() -> Vec<Session> {
 pub fn get_latest_session() -> Option<Session> {
     list_sessions().into_iter().next()
 }
+
+/// Timestamp in local time, e.g. "2026-03-14 09:30"
+pub fn local_time(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|d| d.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// Timestamp relative to now, e.g. "5 minutes ago", "yesterday", "Mar 14"
+pub fn relative_time(timestamp: i64) -> String {
+    relative_to(timestamp, chrono::Local::now())
+}
+
+fn relative_to<Tz: chrono::TimeZone>(timestamp: i64, now: chrono::DateTime<Tz>) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let Some(then) = chrono::DateTime::from_timestamp(timestamp, 0).map(|d| d.with_timezone(&now.timezone())) else {
+        return String::new();
+    };
+    let plural = |n: i64, unit: &str| format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" });
+    let seconds = now.timestamp() - timestamp;
+    let days = (now.date_naive() - then.date_naive()).num_days();
+    match seconds {
+        ..60 => "just now".to_string(),
+        60..3600 => plural(seconds / 60, "minute"),
+        _ if days == 0 => plural(seconds / 3600, "hour"),
+        _ if days == 1 => "yesterday".to_string(),
+        _ if days < 7 => plural(days, "day"),
+        _ if then.year() == now.year() => then.format("%b %-d").to_string(),
+        _ => then.format("%Y-%m-%d").to_string(),
+    }
+}
+
+/// Split a markdown seed into `# Title` and `## System|User|Assistant` sections
+fn parse_seed_markdown(content: &str) -> Seed {
+    let mut seed = Seed::default();
+    let mut current: Option<String> = None;
+    let mut body = String::new();
+
+    let flush = |role: Option<String>, body: &mut String, seed: &mut Seed| {
+        let text = std::mem::take(body);
+        match role.as_deref() {
+            Some("system") => seed.system.push_str(text.trim()),
+            Some(role) => seed.messages.push(SeedTurn { role: role.to_string(), content: text }),
+            None => {}
+        }
+    };
+
+    for line in content.lines() {
+        if let Some(heading) = line.strip_prefix("## ") {
+            let role = heading.trim().to_lowercase();
+            if matches!(role.as_str(), "system" | "user" | "assistant") {
+                flush(current.take(), &mut body, &mut seed);
+                current = Some(role);
+                continue;
+            }
+        }
+        if current.is_none() && seed.title.is_empty() && let Some(title) = line.strip_prefix("# ") {
+            seed.title = title.trim().to_string();
+            continue;
+        }
+        body.push_str(line);
+        body.push('\n');
+    }
+    flush(current, &mut body, &mut seed);
+    seed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_time() {
+        let now = chrono::DateTime::parse_from_rfc3339("2026-03-14T09:30:00+00:00").unwrap();
+        let ago = |secs: i64| relative_to(now.timestamp() - secs, now);
+        assert_eq!(ago(10), "just now");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(2 * 3600), "2 hours ago");
+        assert_eq!(ago(12 * 3600), "yesterday");
+        assert_eq!(ago(3 * 86400), "3 days ago");
+        assert_eq!(ago(30 * 86400), "Feb 12");
+        assert_eq!(ago(400 * 86400), "2025-02-07");
+    }
+
+    #[test]
+    fn test_parse_seed_markdown() {
+        let seed = parse_seed_markdown(
+            "# Demo\n\n## System\nAnswer in French.\n\n## User\nHi\n\n## Assistant\nBonjour !\n### Notes\nkept\n",
+        );
+        assert_eq!(seed.title, "Demo");
+        assert_eq!(seed.system, "Answer in French.");
+        assert_eq!(seed.messages.len(), 2);
+        assert_eq!(seed.messages[0].role, "user");
+        assert_eq!(seed.messages[1].content.trim(), "Bonjour !\n### Notes\nkept");
+    }
+}
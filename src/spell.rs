@@ -0,0 +1,188 @@
+/// Frequent misspellings and their corrections, lowercase. Deliberately short: only
+/// words that are never right as typed, so a flag is almost always a real typo.
+const MISSPELLINGS: &[(&str, &str)] = &[
+    ("teh", "the"),
+    ("hte", "the"),
+    ("taht", "that"),
+    ("thier", "their"),
+    ("recieve", "receive"),
+    ("recieved", "received"),
+    ("beleive", "believe"),
+    ("belive", "believe"),
+    ("acheive", "achieve"),
+    ("wierd", "weird"),
+    ("freind", "friend"),
+    ("freinds", "friends"),
+    ("untill", "until"),
+    ("occured", "occurred"),
+    ("occurence", "occurrence"),
+    ("seperate", "separate"),
+    ("seperately", "separately"),
+    ("definately", "definitely"),
+    ("definitly", "definitely"),
+    ("accomodate", "accommodate"),
+    ("adress", "address"),
+    ("alot", "a lot"),
+    ("arguement", "argument"),
+    ("begining", "beginning"),
+    ("calender", "calendar"),
+    ("commited", "committed"),
+    ("comming", "coming"),
+    ("concious", "conscious"),
+    ("enviroment", "environment"),
+    ("existance", "existence"),
+    ("goverment", "government"),
+    ("happend", "happened"),
+    ("immediatly", "immediately"),
+    ("independant", "independent"),
+    ("knowlege", "knowledge"),
+    ("neccessary", "necessary"),
+    ("necessery", "necessary"),
+    ("noticable", "noticeable"),
+    ("occassion", "occasion"),
+    ("persistant", "persistent"),
+    ("posession", "possession"),
+    ("prefered", "preferred"),
+    ("probaly", "probably"),
+    ("publically", "publicly"),
+    ("realy", "really"),
+    ("reccomend", "recommend"),
+    ("recomend", "recommend"),
+    ("refered", "referred"),
+    ("relevent", "relevant"),
+    ("responsability", "responsibility"),
+    ("succesful", "successful"),
+    ("sucessful", "successful"),
+    ("supercede", "supersede"),
+    ("suprise", "surprise"),
+    ("tommorow", "tomorrow"),
+    ("tomorow", "tomorrow"),
+    ("truely", "truly"),
+    ("wich", "which"),
+    ("whith", "with"),
+    ("writting", "writing"),
+    ("yesterdya", "yesterday"),
+    ("becuase", "because"),
+    ("becasue", "because"),
+    ("beacuse", "because"),
+    ("dont", "don't"),
+    ("doesnt", "doesn't"),
+    ("didnt", "didn't"),
+    ("isnt", "isn't"),
+    ("wasnt", "wasn't"),
+    ("havent", "haven't"),
+    ("shouldnt", "shouldn't"),
+    ("couldnt", "couldn't"),
+    ("wouldnt", "wouldn't"),
+    ("thats", "that's"),
+    ("ive", "I've"),
+    ("im", "I'm"),
+    ("youre", "you're"),
+    ("theyre", "they're"),
+];
+
+/// A suggested replacement for `text[start..end]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub start: usize,
+    pub end: usize,
+    pub word: String,
+    pub suggestion: String,
+}
+
+/// Keep the typed word's leading capital on the suggestion
+fn match_case(word: &str, suggestion: &str) -> String {
+    if word.chars().next().is_some_and(char::is_uppercase) {
+        let mut chars = suggestion.chars();
+        chars.next().map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+    } else {
+        suggestion.to_string()
+    }
+}
+
+/// Obvious typos in prose: common misspellings, a lone lowercase "i", and a word typed
+/// twice in a row. Code spans, paths, @references and URLs are left alone.
+pub fn check(text: &str) -> Vec<Fix> {
+    let word = regex::Regex::new(r"[A-Za-z']+").expect("valid regex");
+    let mut fixes = Vec::new();
+    let mut previous: Option<(usize, &str)> = None; // End and text of the last prose word
+
+    for m in word.find_iter(text) {
+        let in_code = text[..m.start()].matches('`').count() % 2 == 1;
+        let before = text[..m.start()].chars().next_back();
+        let after = text[m.end()..].chars().next();
+        let token_start = text[..m.start()].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let token_end = text[m.end()..].find(char::is_whitespace).map_or(text.len(), |i| m.end() + i);
+        let token = &text[token_start..token_end];
+        if in_code
+            || token.contains(['@', '/', '\\', '_', '#', '='])
+            || token.contains("://")
+            || matches!(before, Some(c) if c.is_ascii_digit())
+            || matches!(after, Some(c) if c.is_ascii_digit() || c == '.' && text[m.end() + 1..].starts_with(|c: char| c.is_alphanumeric()))
+        {
+            previous = None;
+            continue;
+        }
+
+        let typed = m.as_str().trim_matches('\'');
+        let start = m.start() + (m.as_str().len() - m.as_str().trim_start_matches('\'').len());
+        let end = start + typed.len();
+        let lower = typed.to_lowercase();
+        let suggestion = if typed == "i" {
+            Some("I".to_string())
+        } else if let Some(rest) = typed.strip_prefix("i'") {
+            Some(format!("I'{}", rest))
+        } else {
+            MISSPELLINGS.iter().find(|(wrong, _)| *wrong == lower).map(|(_, right)| match_case(typed, right))
+        };
+
+        if let Some(suggestion) = suggestion {
+            fixes.push(Fix { start, end, word: typed.to_string(), suggestion });
+        } else if let Some((previous_end, previous_word)) = previous
+            && previous_word.eq_ignore_ascii_case(typed)
+            && text[previous_end..start].chars().all(|c| c == ' ')
+            && typed.len() > 1
+            && !matches!(lower.as_str(), "that" | "had") // "that that", "had had" are fine
+        {
+            // Drop the repeat along with the space before it
+            fixes.push(Fix { start: previous_end, end, word: format!("{} {}", previous_word, typed), suggestion: String::new() });
+        }
+        previous = Some((end, typed));
+    }
+    fixes
+}
+
+/// `text` with every fix applied
+pub fn apply(text: &str, fixes: &[Fix]) -> String {
+    let mut out = text.to_string();
+    for fix in fixes.iter().rev() {
+        out.replace_range(fix.start..fix.end, &fix.suggestion);
+    }
+    out
+}
+
+/// "teh → the, the the → the" for the review line
+pub fn describe(fixes: &[Fix]) -> String {
+    fixes
+        .iter()
+        .map(|fix| {
+            let suggestion = if fix.suggestion.is_empty() { fix.word.split(' ').next().unwrap_or("") } else { &fix.suggestion };
+            format!("{} {} {}", fix.word, crate::glyphs::get().arrow, suggestion)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_and_apply() {
+        let text = "Teh plan is is good, i think. Dont touch `teh_var` or src/teh.rs";
+        let fixes = check(text);
+        assert_eq!(fixes.iter().map(|f| f.word.as_str()).collect::<Vec<_>>(), ["Teh", "is is", "i", "Dont"]);
+        assert_eq!(apply(text, &fixes), "The plan is good, I think. Don't touch `teh_var` or src/teh.rs");
+        assert!(check("Setting up the env, then I'm done").is_empty());
+    }
+}
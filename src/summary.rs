@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Rough size limit for a directory summary (~8k tokens at 4 chars per token)
+const BUDGET_BYTES: usize = 32_000;
+const MAX_TREE_ENTRIES: usize = 200;
+const MAX_DEPTH: usize = 3;
+const MAX_KEY_FILE_BYTES: usize = 8_000;
+
+/// Top-level files worth including verbatim: what the project is and how it builds
+const KEY_FILES: &[&str] = &[
+    "README.md",
+    "README",
+    "README.txt",
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "requirements.txt",
+    "go.mod",
+    "Gemfile",
+    "composer.json",
+    "pom.xml",
+    "build.gradle",
+    "CMakeLists.txt",
+    "Makefile",
+];
+
+/// Summary of a directory for an @-reference: its tree (gitignore-aware), file counts
+/// by extension, and small key files, kept under BUDGET_BYTES
+pub fn directory(path: &str) -> String {
+    let root = Path::new(path);
+    let mut tree = Vec::new();
+    let mut hidden = 0;
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    let mut total = 0;
+
+    let walker = ignore::WalkBuilder::new(root)
+        .hidden(true)
+        .git_ignore(true)
+        .add_custom_ignore_filename(".vecoignore")
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    for entry in walker.filter_map(|e| e.ok()).filter(|e| e.depth() > 0) {
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        if !is_dir {
+            total += 1;
+            let ext = entry
+                .path()
+                .extension()
+                .map(|e| format!(".{}", e.to_string_lossy()))
+                .unwrap_or_else(|| "(no extension)".to_string());
+            *counts.entry(ext).or_default() += 1;
+        }
+        if entry.depth() > MAX_DEPTH || tree.len() >= MAX_TREE_ENTRIES {
+            hidden += 1;
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy();
+        let indent = "  ".repeat(entry.depth() - 1);
+        tree.push(format!("{}{}{}", indent, name, if is_dir { "/" } else { "" }));
+    }
+    if hidden > 0 {
+        tree.push(format!("… {} more entries", hidden));
+    }
+
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let counts = counts.iter().map(|(ext, n)| format!("{} {}", n, ext)).collect::<Vec<_>>().join(", ");
+
+    let mut out = format!(
+        "Tree:\n{}\n\n{} files: {}",
+        tree.join("\n"),
+        total,
+        if counts.is_empty() { "none".to_string() } else { counts }
+    );
+    for name in KEY_FILES {
+        let file = root.join(name);
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        if content.len() > MAX_KEY_FILE_BYTES || out.len() + content.len() > BUDGET_BYTES {
+            out.push_str(&format!("\n\n({} omitted: too large)", file.display()));
+            continue;
+        }
+        out.push_str(&format!("\n\n<file path=\"{}\">\n{}\n</file>", file.display(), content.trim()));
+    }
+    out
+}
+
+/// Largest file `small_files` lists
+const MAX_SMALL_FILE_BYTES: u64 = 16_000;
+
+/// Text-sized files under `path` (gitignore-aware, sorted, within MAX_DEPTH) for
+/// inlining alongside a directory summary
+pub fn small_files(path: &str) -> Vec<String> {
+    ignore::WalkBuilder::new(path)
+        .hidden(true)
+        .git_ignore(true)
+        .add_custom_ignore_filename(".vecoignore")
+        .max_depth(Some(MAX_DEPTH))
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+        .filter(|e| e.metadata().is_ok_and(|m| m.len() > 0 && m.len() <= MAX_SMALL_FILE_BYTES))
+        .map(|e| e.path().to_string_lossy().to_string())
+        .collect()
+}
+
+/// Files past this count are left out of `repo_stats`
+const MAX_STATS_FILES: usize = 20_000;
+/// Larger files count toward sizes but aren't read for line counts
+const MAX_STATS_FILE_BYTES: u64 = 2_000_000;
+
+/// (extension, language, is source code)
+const LANGUAGES: &[(&str, &str, bool)] = &[
+    ("rs", "Rust", true), ("py", "Python", true), ("js", "JavaScript", true), ("jsx", "JavaScript", true),
+    ("mjs", "JavaScript", true), ("cjs", "JavaScript", true), ("ts", "TypeScript", true),
+    ("tsx", "TypeScript", true), ("go", "Go", true), ("java", "Java", true), ("kt", "Kotlin", true),
+    ("c", "C", true), ("h", "C", true), ("cc", "C++", true), ("cpp", "C++", true), ("hpp", "C++", true),
+    ("cs", "C#", true), ("rb", "Ruby", true), ("php", "PHP", true), ("swift", "Swift", true),
+    ("scala", "Scala", true), ("ex", "Elixir", true), ("exs", "Elixir", true), ("lua", "Lua", true),
+    ("dart", "Dart", true), ("zig", "Zig", true), ("vue", "Vue", true), ("svelte", "Svelte", true),
+    ("sh", "Shell", true), ("bash", "Shell", true), ("sql", "SQL", true), ("html", "HTML", false),
+    ("css", "CSS", false), ("scss", "CSS", false), ("md", "Markdown", false), ("json", "JSON", false),
+    ("toml", "TOML", false), ("yaml", "YAML", false), ("yml", "YAML", false), ("xml", "XML", false),
+];
+
+/// Whether a path looks like a test file by name or directory
+fn is_test_path(path: &str) -> bool {
+    let path = path.replace('\\', "/");
+    let name = path.rsplit('/').next().unwrap_or(&path);
+    let stem = name.split('.').next().unwrap_or(name);
+    path.split('/').any(|dir| matches!(dir, "test" | "tests" | "__tests__" | "spec"))
+        || stem.starts_with("test_")
+        || stem.ends_with("_test")
+        || stem.ends_with("_spec")
+        || name.contains(".test.")
+        || name.contains(".spec.")
+}
+
+/// Cheap aggregates for a codebase overview: files and lines by language, the largest
+/// files and how much of the code is tests
+pub fn repo_stats(path: &str) -> String {
+    struct Lang {
+        files: usize,
+        lines: usize,
+        code: bool,
+    }
+    let mut langs: BTreeMap<&str, Lang> = BTreeMap::new();
+    let mut largest: Vec<(usize, u64, String)> = Vec::new(); // (lines, bytes, path)
+    let (mut files, mut lines, mut bytes) = (0, 0, 0);
+    let (mut source_files, mut test_files, mut inline_tests) = (0, 0, 0);
+    let mut truncated = false;
+
+    let walker = ignore::WalkBuilder::new(path)
+        .hidden(true)
+        .git_ignore(true)
+        .add_custom_ignore_filename(".vecoignore")
+        .build();
+    for entry in walker.filter_map(|e| e.ok()).filter(|e| e.file_type().is_some_and(|t| t.is_file())) {
+        if files == MAX_STATS_FILES {
+            truncated = true;
+            break;
+        }
+        let size = entry.metadata().map_or(0, |m| m.len());
+        let display = crate::paths::display(entry.path());
+        let content = if size <= MAX_STATS_FILE_BYTES { fs::read(entry.path()).ok() } else { None };
+        let text = content.as_deref().filter(|c| !c[..c.len().min(8192)].contains(&0));
+        let line_count = text.map_or(0, |t| t.iter().filter(|&&b| b == b'\n').count());
+
+        files += 1;
+        lines += line_count;
+        bytes += size;
+
+        let ext = entry.path().extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        let (name, code) = LANGUAGES
+            .iter()
+            .find(|(e, _, _)| *e == ext)
+            .map_or(("Other", false), |(_, name, code)| (*name, *code));
+        let lang = langs.entry(name).or_insert(Lang { files: 0, lines: 0, code });
+        lang.files += 1;
+        lang.lines += line_count;
+
+        if code {
+            if is_test_path(&display) {
+                test_files += 1;
+            } else {
+                source_files += 1;
+                if text.is_some_and(|t| String::from_utf8_lossy(t).contains("#[cfg(test)]")) {
+                    inline_tests += 1;
+                }
+            }
+        }
+        largest.push((line_count, size, display));
+    }
+
+    let mut out = format!("{} files, {} lines, {} KB", files, lines, bytes.div_ceil(1024));
+    if truncated {
+        out.push_str(&format!(" (stopped after {} files)", MAX_STATS_FILES));
+    }
+
+    let mut by_lines: Vec<(&str, Lang)> = langs.into_iter().collect();
+    by_lines.sort_by(|a, b| b.1.lines.cmp(&a.1.lines).then(b.1.files.cmp(&a.1.files)));
+    out.push_str("\n\nBy language:");
+    for (name, lang) in &by_lines {
+        let kind = if lang.code || *name == "Other" { "" } else { " (non-code)" };
+        out.push_str(&format!("\n  {}: {} files, {} lines{}", name, lang.files, lang.lines, kind));
+    }
+
+    largest.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+    out.push_str("\n\nLargest files:");
+    for (line_count, size, path) in largest.iter().take(10) {
+        out.push_str(&format!("\n  {}: {} lines, {} KB", path, line_count, size.div_ceil(1024)));
+    }
+
+    out.push_str(&format!("\n\nTests: {} test files for {} source files", test_files, source_files));
+    if source_files > 0 {
+        out.push_str(&format!(" ({:.2} per source file)", test_files as f64 / source_files as f64));
+    }
+    if inline_tests > 0 {
+        out.push_str(&format!("; {} source files also have inline #[cfg(test)] modules", inline_tests));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_summary() {
+        let dir = std::env::temp_dir().join(format!("hal-summary-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("README.md"), "# Demo\n").unwrap();
+        fs::write(dir.join("src/a.rs"), "").unwrap();
+        fs::write(dir.join("src/b.rs"), "").unwrap();
+
+        let summary = directory(dir.to_str().unwrap());
+        assert!(summary.contains("Tree:\nREADME.md\nsrc/\n  a.rs\n  b.rs"));
+        assert!(summary.contains("3 files: 2 .rs, 1 .md"));
+        assert!(summary.contains("# Demo\n</file>"));
+        // Empty files aren't worth inlining
+        assert_eq!(small_files(dir.to_str().unwrap()), [dir.join("README.md").to_string_lossy()]);
+
+        fs::write(dir.join("src/a.rs"), "fn a() {}\n#[cfg(test)]\nmod tests {}\n").unwrap();
+        fs::create_dir_all(dir.join("tests")).unwrap();
+        fs::write(dir.join("tests/it.rs"), "#[test]\nfn it() {}\n").unwrap();
+        let stats = repo_stats(dir.to_str().unwrap());
+        assert!(stats.starts_with("4 files, 6 lines"), "{}", stats);
+        assert!(stats.contains("Rust: 3 files, 5 lines"));
+        assert!(stats.contains("Markdown: 1 files, 1 lines (non-code)"));
+        assert!(stats.contains("Tests: 1 test files for 2 source files (0.50 per source file); 1 source files also"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
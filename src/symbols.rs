@@ -0,0 +1,298 @@
+use crate::tools::{ErrorKind, ToolError, ToolResult};
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser};
+
+/// Directories past this many parsed files are cut off in `list_symbols`
+const MAX_FILES: usize = 200;
+/// Files larger than this aren't parsed
+const MAX_FILE_BYTES: u64 = 1_000_000;
+
+/// A definition found in a syntax tree, with 1-indexed inclusive lines
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub kind: &'static str,
+    pub name: String,
+    pub parent: Option<String>, // The enclosing impl, class, module, ...
+    pub depth: usize,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub signature: String, // First line of the definition
+}
+
+/// Language name, grammar and (node kind, symbol kind) table
+type Grammar = (&'static str, Language, &'static [(&'static str, &'static str)]);
+
+fn language(ext: &str) -> Option<Grammar> {
+    const RUST: &[(&str, &str)] = &[
+        ("function_item", "fn"), ("function_signature_item", "fn"), ("struct_item", "struct"), ("enum_item", "enum"),
+        ("union_item", "union"), ("trait_item", "trait"), ("impl_item", "impl"), ("mod_item", "mod"),
+        ("const_item", "const"), ("static_item", "static"), ("type_item", "type"), ("macro_definition", "macro"),
+    ];
+    const PYTHON: &[(&str, &str)] = &[("function_definition", "def"), ("class_definition", "class")];
+    const JAVASCRIPT: &[(&str, &str)] = &[
+        ("function_declaration", "function"), ("generator_function_declaration", "function"), ("class_declaration", "class"),
+        ("method_definition", "method"), ("variable_declarator", "function"),
+    ];
+    const TYPESCRIPT: &[(&str, &str)] = &[
+        ("function_declaration", "function"), ("generator_function_declaration", "function"), ("class_declaration", "class"),
+        ("abstract_class_declaration", "class"), ("method_definition", "method"), ("variable_declarator", "function"),
+        ("interface_declaration", "interface"), ("type_alias_declaration", "type"), ("enum_declaration", "enum"),
+        ("internal_module", "namespace"),
+    ];
+    const GO: &[(&str, &str)] = &[("function_declaration", "func"), ("method_declaration", "method"), ("type_spec", "type")];
+    const C: &[(&str, &str)] = &[
+        ("function_definition", "function"), ("struct_specifier", "struct"), ("enum_specifier", "enum"),
+        ("union_specifier", "union"), ("type_definition", "typedef"),
+    ];
+    const CPP: &[(&str, &str)] = &[
+        ("function_definition", "function"), ("struct_specifier", "struct"), ("enum_specifier", "enum"),
+        ("union_specifier", "union"), ("type_definition", "typedef"), ("class_specifier", "class"),
+        ("namespace_definition", "namespace"),
+    ];
+    const JAVA: &[(&str, &str)] = &[
+        ("class_declaration", "class"), ("interface_declaration", "interface"), ("enum_declaration", "enum"),
+        ("record_declaration", "record"), ("method_declaration", "method"), ("constructor_declaration", "constructor"),
+    ];
+    Some(match ext {
+        "rs" => ("Rust", tree_sitter_rust::LANGUAGE.into(), RUST),
+        "py" | "pyi" => ("Python", tree_sitter_python::LANGUAGE.into(), PYTHON),
+        "js" | "jsx" | "mjs" | "cjs" => ("JavaScript", tree_sitter_javascript::LANGUAGE.into(), JAVASCRIPT),
+        "ts" | "mts" | "cts" => ("TypeScript", tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(), TYPESCRIPT),
+        "tsx" => ("TypeScript", tree_sitter_typescript::LANGUAGE_TSX.into(), TYPESCRIPT),
+        "go" => ("Go", tree_sitter_go::LANGUAGE.into(), GO),
+        "c" | "h" => ("C", tree_sitter_c::LANGUAGE.into(), C),
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => ("C++", tree_sitter_cpp::LANGUAGE.into(), CPP),
+        "java" => ("Java", tree_sitter_java::LANGUAGE.into(), JAVA),
+        _ => return None,
+    })
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+pub fn is_supported(path: &Path) -> bool {
+    language(&extension(path)).is_some()
+}
+
+/// Definitions in `source`, outermost first. None if the language isn't supported.
+pub fn parse(path: &Path, source: &str) -> Option<Vec<Symbol>> {
+    let (_, grammar, kinds) = language(&extension(path))?;
+    let mut parser = Parser::new();
+    parser.set_language(&grammar).ok()?;
+    let tree = parser.parse(source, None)?;
+    let mut symbols = Vec::new();
+    collect(tree.root_node(), source, kinds, None, 0, &mut symbols);
+    Some(symbols)
+}
+
+fn collect(node: Node, source: &str, kinds: &[(&str, &'static str)], parent: Option<&str>, depth: usize, out: &mut Vec<Symbol>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let symbol = kinds.iter().find(|(k, _)| *k == child.kind()).and_then(|(_, kind)| symbol(child, source, kind));
+        match symbol {
+            Some(mut symbol) => {
+                symbol.parent = parent.map(str::to_string);
+                symbol.depth = depth;
+                let name = symbol.name.clone();
+                out.push(symbol);
+                collect(child, source, kinds, Some(&name), depth + 1, out);
+            }
+            None => collect(child, source, kinds, parent, depth, out),
+        }
+    }
+}
+
+fn symbol(node: Node, source: &str, kind: &'static str) -> Option<Symbol> {
+    let text = |n: Node| n.utf8_text(source.as_bytes()).ok().map(str::to_string);
+    let name = match node.kind() {
+        // `impl Trait for Type` has no name field
+        "impl_item" => {
+            let ty = text(node.child_by_field_name("type")?)?;
+            match node.child_by_field_name("trait").and_then(text) {
+                Some(tr) => format!("{} for {}", tr, ty),
+                None => ty,
+            }
+        }
+        // Only `const f = () => ...` and `const f = function ...` are definitions
+        "variable_declarator" => {
+            let value = node.child_by_field_name("value")?;
+            if !matches!(value.kind(), "arrow_function" | "function_expression" | "function" | "generator_function") {
+                return None;
+            }
+            text(node.child_by_field_name("name")?)?
+        }
+        // C declarators nest (pointer, function, ...) around the name
+        "function_definition" | "type_definition" if node.child_by_field_name("name").is_none() => {
+            let mut declarator = node.child_by_field_name("declarator")?;
+            while let Some(inner) = declarator.child_by_field_name("declarator") {
+                declarator = inner;
+            }
+            text(declarator)?
+        }
+        // Forward declarations and anonymous structs aren't definitions
+        "struct_specifier" | "enum_specifier" | "union_specifier" | "class_specifier" if node.child_by_field_name("body").is_none() => return None,
+        _ => text(node.child_by_field_name("name")?)?,
+    };
+    let signature = source[node.start_byte()..].lines().next().unwrap_or("").trim().to_string();
+    Some(Symbol {
+        kind,
+        name,
+        parent: None,
+        depth: 0,
+        start_line: node.start_position().row + 1,
+        end_line: node.end_position().row + 1,
+        signature,
+    })
+}
+
+/// Supported source files under `path` (or `path` itself), gitignore-aware and sorted
+fn source_files(path: &str) -> Vec<std::path::PathBuf> {
+    ignore::WalkBuilder::new(path)
+        .hidden(true)
+        .git_ignore(true)
+        .add_custom_ignore_filename(".vecoignore")
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_some_and(|t| t.is_file()) && is_supported(e.path()))
+        .filter(|e| e.metadata().is_ok_and(|m| m.len() <= MAX_FILE_BYTES))
+        .map(|e| e.into_path())
+        .collect()
+}
+
+fn outline(symbols: &[Symbol]) -> String {
+    let width = symbols.iter().map(|s| format!("{}-{}", s.start_line, s.end_line).len()).max().unwrap_or(0);
+    symbols
+        .iter()
+        .map(|s| format!("  {:<width$}  {}{} {}", format!("{}-{}", s.start_line, s.end_line), "  ".repeat(s.depth), s.kind, s.name))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Outline of a file, or of every supported file in a directory
+pub fn list_symbols(path: &str) -> ToolResult {
+    let target = Path::new(path);
+    if target.is_file() {
+        let Some((language, _, _)) = language(&extension(target)) else {
+            return Err(ToolError::new(
+                ErrorKind::Unavailable,
+                format!("Error: no parser for {} (supported: Rust, Python, JavaScript, TypeScript, Go, C, C++, Java)", path),
+            ));
+        };
+        let source = std::fs::read_to_string(target).map_err(|e| ToolError::new(ErrorKind::Io, format!("Error: failed to read {}: {}", path, e)))?;
+        let symbols = parse(target, &source).unwrap_or_default();
+        if symbols.is_empty() {
+            return Ok(format!("{} ({}): no definitions", path, language));
+        }
+        return Ok(format!("{} ({}, {} lines)\n{}", path, language, source.lines().count(), outline(&symbols)));
+    }
+    if !target.is_dir() {
+        return Err(ToolError::new(ErrorKind::NotFound, format!("Error: not found: {}", path)));
+    }
+
+    let files = source_files(path);
+    if files.is_empty() {
+        return Ok(format!("{}: no Rust, Python, JavaScript, TypeScript, Go, C, C++ or Java files", path));
+    }
+    let mut sections = Vec::new();
+    for file in files.iter().take(MAX_FILES) {
+        let Ok(source) = std::fs::read_to_string(file) else { continue };
+        let symbols = parse(file, &source).unwrap_or_default();
+        if !symbols.is_empty() {
+            sections.push(format!("{}\n{}", crate::paths::display(file), outline(&symbols)));
+        }
+    }
+    if files.len() > MAX_FILES {
+        sections.push(format!("[{} more files not listed; call list_symbols on a subdirectory]", files.len() - MAX_FILES));
+    }
+    Ok(sections.join("\n\n"))
+}
+
+/// Where `name` is defined. `Type::method` or `Class.method` narrows to one parent.
+pub fn find_definition(name: &str, path: &str) -> ToolResult {
+    let (parent, name) = match name.rsplit_once("::").or_else(|| name.rsplit_once('.')) {
+        Some((parent, name)) => (Some(parent), name),
+        None => (None, name),
+    };
+    if name.is_empty() {
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: name is required"));
+    }
+    if !Path::new(path).exists() {
+        return Err(ToolError::new(ErrorKind::NotFound, format!("Error: not found: {}", path)));
+    }
+
+    let mut found = Vec::new();
+    for file in source_files(path) {
+        let Ok(source) = std::fs::read_to_string(&file) else { continue };
+        // Skip parsing files that can't contain the name
+        if !source.contains(name) {
+            continue;
+        }
+        for symbol in parse(&file, &source).unwrap_or_default() {
+            // An impl block's name is its type; `impl Trait for Type` matches either
+            let names_match = symbol.name == name || symbol.name.split(" for ").any(|part| part == name);
+            let parent_matches = parent.is_none_or(|p| symbol.parent.as_deref().is_some_and(|sp| sp.split(" for ").any(|part| part == p)));
+            if names_match && parent_matches {
+                let within = symbol.parent.as_ref().map(|p| format!(" (in {})", p)).unwrap_or_default();
+                found.push(format!(
+                    "{}:{}-{} {} {}{}\n    {}",
+                    crate::paths::display(&file),
+                    symbol.start_line,
+                    symbol.end_line,
+                    symbol.kind,
+                    symbol.name,
+                    within,
+                    symbol.signature
+                ));
+            }
+        }
+    }
+    if found.is_empty() {
+        Ok(format!("No definition of {} found (grep can find uses or unsupported languages)", name))
+    } else {
+        Ok(found.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_find() {
+        let rust = "struct App;\n\nimpl App {\n    fn new() -> Self {\n        App\n    }\n}\n\nimpl Default for App {\n    fn default() -> Self { App::new() }\n}\n";
+        let symbols = parse(Path::new("a.rs"), rust).unwrap();
+        let summary: Vec<_> = symbols.iter().map(|s| (s.kind, s.name.as_str(), s.parent.as_deref(), s.start_line, s.end_line)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("struct", "App", None, 1, 1),
+                ("impl", "App", None, 3, 7),
+                ("fn", "new", Some("App"), 4, 6),
+                ("impl", "Default for App", None, 9, 11),
+                ("fn", "default", Some("Default for App"), 10, 10),
+            ]
+        );
+
+        let python = "class Greeter:\n    def hello(self):\n        pass\n\ndef main():\n    Greeter().hello()\n";
+        let names: Vec<_> = parse(Path::new("g.py"), python).unwrap().into_iter().map(|s| (s.kind, s.name, s.depth)).collect();
+        assert_eq!(names, vec![("class", "Greeter".to_string(), 0), ("def", "hello".to_string(), 1), ("def", "main".to_string(), 0)]);
+
+        let js = "const add = (a, b) => a + b;\nconst limit = 3;\n";
+        let names: Vec<_> = parse(Path::new("m.js"), js).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["add"]);
+        assert!(parse(Path::new("notes.txt"), "").is_none());
+
+        let dir = std::env::temp_dir().join(format!("hal-symbols-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.rs"), rust).unwrap();
+        let dir_str = dir.to_str().unwrap();
+        let found = find_definition("App::new", dir_str).unwrap();
+        assert!(found.contains("a.rs:4-6 fn new (in App)"), "{}", found);
+        assert!(found.contains("fn new() -> Self {"));
+        assert!(find_definition("Default::default", dir_str).unwrap().contains("a.rs:10-10"));
+        assert!(find_definition("missing", dir_str).unwrap().starts_with("No definition"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,453 @@
+use crate::session::{self, Session};
+use base64::Engine;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, hmac, pbkdf2};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+
+/// Plaintext object at the remote root holding the key derivation salt
+const META: &str = "hal-sync.json";
+/// Encrypted map of session id to updated_at, so a sync only moves changed sessions
+const INDEX: &str = "index.enc";
+const KDF_ITERATIONS: u32 = 200_000;
+/// Remotes created from this version on bind each object to its key; older ones also
+/// accept objects sealed without it
+const META_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    S3,
+    Webdav,
+}
+
+/// Remote store for `hal sync`. Credentials and the passphrase are read from the named
+/// environment variables, never from the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConfig {
+    pub backend: Backend,
+    pub url: String, // S3: https://host/bucket[/prefix] (path-style); WebDAV: an existing collection
+    #[serde(default = "default_region")]
+    pub region: String, // S3 only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_env: Option<String>, // Default AWS_ACCESS_KEY_ID / HAL_SYNC_USER
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_env: Option<String>, // Default AWS_SECRET_ACCESS_KEY / HAL_SYNC_PASSWORD
+    #[serde(default = "default_passphrase_env")]
+    pub passphrase_env: String,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_passphrase_env() -> String {
+    "HAL_SYNC_PASSPHRASE".to_string()
+}
+
+#[derive(Serialize, Deserialize)]
+struct Meta {
+    version: u32,
+    salt: String,
+    iterations: u32,
+}
+
+/// What to do with one session id
+#[derive(Debug, PartialEq)]
+enum Action {
+    Upload,
+    Download,
+    /// Changed on both sides since the last sync; the newer copy wins
+    Conflict { local_newer: bool },
+}
+
+/// Compare local and remote updated_at against the values seen at the last sync
+fn plan(local: &HashMap<String, i64>, remote: &BTreeMap<String, i64>, synced: &BTreeMap<String, i64>) -> Vec<(String, Action)> {
+    let mut ids: Vec<&String> = local.keys().chain(remote.keys()).collect();
+    ids.sort();
+    ids.dedup();
+    ids.into_iter()
+        .filter_map(|id| {
+            let last = synced.get(id).copied();
+            let action = match (local.get(id).copied(), remote.get(id).copied()) {
+                (Some(_), None) => Action::Upload,
+                (None, Some(_)) => Action::Download,
+                (Some(l), Some(r)) if l == r => return None,
+                (Some(l), Some(r)) if l > r && last.is_some_and(|s| r <= s) => Action::Upload,
+                (Some(l), Some(r)) if r > l && last.is_some_and(|s| l <= s) => Action::Download,
+                (Some(l), Some(r)) => Action::Conflict { local_newer: l > r },
+                (None, None) => return None,
+            };
+            Some((id.clone(), action))
+        })
+        .collect()
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> LessSafeKey {
+    let mut key = [0u8; 32];
+    let iterations = NonZeroU32::new(iterations.max(1)).expect("nonzero");
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).expect("32-byte key"))
+}
+
+/// nonce || ciphertext || tag, authenticated with the object key so the remote can't
+/// swap one object's contents for another's
+fn encrypt(key: &LessSafeKey, object: &str, plain: &[u8]) -> Result<Vec<u8>, String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).map_err(|_| "Failed to generate a nonce".to_string())?;
+    let mut data = plain.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(object.as_bytes()), &mut data)
+        .map_err(|_| "Encryption failed".to_string())?;
+    Ok([nonce.as_slice(), &data].concat())
+}
+
+/// Open what `encrypt` sealed for `object`. With `legacy`, data sealed before objects
+/// were bound to their keys is accepted too.
+fn decrypt(key: &LessSafeKey, object: &str, data: &[u8], legacy: bool) -> Result<Vec<u8>, String> {
+    let failed = || "Failed to decrypt remote data (wrong passphrase?)".to_string();
+    if data.len() < NONCE_LEN {
+        return Err(failed());
+    }
+    let (nonce, sealed) = data.split_at(NONCE_LEN);
+    let open = |aad: Aad<&[u8]>| {
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut sealed = sealed.to_vec();
+        key.open_in_place(nonce, aad, &mut sealed).ok().map(|plain| plain.to_vec())
+    };
+    open(Aad::from(object.as_bytes()))
+        .or_else(|| legacy.then(|| open(Aad::from(&[][..]))).flatten())
+        .ok_or_else(failed)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters (and '/' in paths)
+fn uri_encode(s: &str, keep_slash: bool) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            b'/' if keep_slash => "/".to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// AWS Signature Version 4 signing key for `date` (YYYYMMDD)
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let sign = |key: &[u8], data: &str| hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes());
+    let k = sign(format!("AWS4{}", secret).as_bytes(), date);
+    let k = sign(k.as_ref(), region);
+    let k = sign(k.as_ref(), service);
+    sign(k.as_ref(), "aws4_request").as_ref().to_vec()
+}
+
+/// What a conditional PUT expects to find at the key
+enum Current {
+    Absent,
+    Etag(String),
+    Unknown, // Exists, but the server gave no ETag to check against
+}
+
+struct Remote {
+    config: SyncConfig,
+    user: String,
+    secret: String,
+    agent: ureq::Agent,
+}
+
+impl Remote {
+    fn new(config: SyncConfig) -> Result<Self, String> {
+        let (user_env, secret_env) = match config.backend {
+            Backend::S3 => ("AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY"),
+            Backend::Webdav => ("HAL_SYNC_USER", "HAL_SYNC_PASSWORD"),
+        };
+        let user_env = config.user_env.clone().unwrap_or(user_env.to_string());
+        let secret_env = config.secret_env.clone().unwrap_or(secret_env.to_string());
+        let var = |name: &str| std::env::var(name).map_err(|_| format!("Set ${} for sync", name));
+        let agent = ureq::Agent::config_builder().http_status_as_error(false).build().new_agent();
+        Ok(Self { user: var(&user_env)?, secret: var(&secret_env)?, config, agent })
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", self.config.url.trim_end_matches('/'), key)
+    }
+
+    /// Authorization headers for an S3 request
+    fn s3_headers(&self, method: &str, url: &str, body: &[u8]) -> Result<Vec<(String, String)>, String> {
+        let uri: ureq::http::Uri = url.parse().map_err(|e| format!("Invalid sync url {}: {}", url, e))?;
+        let host = uri.authority().map(|a| a.as_str().rsplit('@').next().unwrap_or("").to_string()).unwrap_or_default();
+        let now = chrono::Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload = hex(digest::digest(&digest::SHA256, body).as_ref());
+        let canonical = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method,
+            uri_encode(uri.path(), true),
+            host,
+            payload,
+            timestamp,
+            payload
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.config.region);
+        let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", timestamp, scope, hex(digest::digest(&digest::SHA256, canonical.as_bytes()).as_ref()));
+        let signature = hex(hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &signing_key(&self.secret, &date, &self.config.region, "s3")), to_sign.as_bytes()).as_ref());
+        Ok(vec![
+            ("x-amz-date".to_string(), timestamp),
+            ("x-amz-content-sha256".to_string(), payload),
+            (
+                "Authorization".to_string(),
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+                    self.user, scope, signature
+                ),
+            ),
+        ])
+    }
+
+    /// Status, ETag and body of a request
+    fn request(&self, method: &str, key: &str, body: &[u8], headers: &[(&str, String)]) -> Result<(u16, Option<String>, Vec<u8>), String> {
+        let url = self.url(key);
+        let mut builder = ureq::http::Request::builder().method(method).uri(&url);
+        for (name, value) in headers {
+            builder = builder.header(*name, value);
+        }
+        match self.config.backend {
+            Backend::S3 => {
+                for (name, value) in self.s3_headers(method, &url, body)? {
+                    builder = builder.header(name, value);
+                }
+            }
+            Backend::Webdav => {
+                let credentials = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", self.user, self.secret));
+                builder = builder.header("Authorization", format!("Basic {}", credentials));
+            }
+        }
+        let request = builder.body(body.to_vec()).map_err(|e| e.to_string())?;
+        let response = self.agent.run(request).map_err(|e| format!("Sync request to {} failed: {}", url, e))?;
+        let status = response.status().as_u16();
+        let etag = response.headers().get("etag").and_then(|v| v.to_str().ok()).map(str::to_string);
+        let body = response.into_body().read_to_vec().map_err(|e| format!("Sync request to {} failed: {}", url, e))?;
+        Ok((status, etag, body))
+    }
+
+    /// An object's contents, or None if it doesn't exist
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        Ok(self.get_current(key)?.map(|(body, _)| body))
+    }
+
+    /// An object's contents and what a later `put_if` should expect to replace
+    fn get_current(&self, key: &str) -> Result<Option<(Vec<u8>, Current)>, String> {
+        match self.request("GET", key, &[], &[])? {
+            (200, etag, body) => Ok(Some((body, etag.map_or(Current::Unknown, Current::Etag)))),
+            (404, _, _) => Ok(None),
+            (status, _, _) => Err(format!("GET {} failed with HTTP {}", self.url(key), status)),
+        }
+    }
+
+    fn put(&self, key: &str, body: &[u8]) -> Result<(), String> {
+        let (mut status, _, _) = self.request("PUT", key, body, &[])?;
+        // WebDAV needs parent collections to exist
+        if status == 409
+            && self.config.backend == Backend::Webdav
+            && let Some((dir, _)) = key.rsplit_once('/')
+        {
+            self.request("MKCOL", &format!("{}/", dir), &[], &[])?;
+            status = self.request("PUT", key, body, &[])?.0;
+        }
+        match status {
+            200..=299 => Ok(()),
+            _ => Err(format!("PUT {} failed with HTTP {}", self.url(key), status)),
+        }
+    }
+
+    /// PUT only if the object is still `current`, so two syncs running at once can't
+    /// overwrite each other's index or salt
+    fn put_if(&self, key: &str, body: &[u8], current: &Current) -> Result<(), String> {
+        let condition = match (current, self.config.backend) {
+            (Current::Absent, _) => Some(("If-None-Match", "*".to_string())),
+            (Current::Etag(etag), Backend::S3) => Some(("If-Match", etag.clone())),
+            (Current::Etag(etag), Backend::Webdav) => Some(("If", format!("([{}])", etag))),
+            (Current::Unknown, _) => None,
+        };
+        match self.request("PUT", key, body, condition.as_slice())?.0 {
+            200..=299 => Ok(()),
+            // S3 answers 409 when a concurrent conditional write wins
+            412 | 409 => Err(format!("{} changed on the remote during this sync; run hal sync again", key)),
+            status => Err(format!("PUT {} failed with HTTP {}", self.url(key), status)),
+        }
+    }
+}
+
+fn state_path() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(|| PathBuf::from(".")).join("hal").join("sync-state.json")
+}
+
+/// updated_at of each session as of the last successful sync
+fn load_state() -> BTreeMap<String, i64> {
+    fs::read_to_string(state_path()).ok().and_then(|c| serde_json::from_str(&c).ok()).unwrap_or_default()
+}
+
+fn save_state(state: &BTreeMap<String, i64>) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(state).map_err(|e| e.to_string())?;
+    fs::write(state_path(), content).map_err(|e| format!("Failed to save sync state: {}", e))
+}
+
+/// Summary of one `hal sync`
+#[derive(Default)]
+pub struct Report {
+    pub uploaded: Vec<String>,
+    pub downloaded: Vec<String>,
+    /// (session id, id of the local copy kept for the losing side)
+    pub conflicts: Vec<(String, String)>,
+}
+
+/// Sync sessions with the configured remote. Only sessions that changed on one side since
+/// the last sync are transferred; deletions are not synced. With `dry_run`, nothing is
+/// written and the report lists what would happen.
+pub fn run(config: Option<SyncConfig>, dry_run: bool) -> Result<Report, String> {
+    let config = config.ok_or(
+        "No sync backend configured. Add a \"sync\" section to the config, e.g. \
+         {\"backend\": \"s3\", \"url\": \"https://s3.us-east-1.amazonaws.com/my-bucket/hal\"}",
+    )?;
+    if !dry_run && !crate::config::Config::writable() {
+        return Err(format!("{} is read-only, so downloaded sessions couldn't be saved", crate::config::Config::dir().display()));
+    }
+    let passphrase = std::env::var(&config.passphrase_env)
+        .ok()
+        .filter(|p| !p.is_empty())
+        .ok_or(format!("Set ${} to the passphrase used to encrypt synced sessions", config.passphrase_env))?;
+    let remote = Remote::new(config)?;
+
+    let meta = match remote.get(META)? {
+        Some(body) => serde_json::from_slice::<Meta>(&body).map_err(|e| format!("Invalid {} on the remote: {}", META, e))?,
+        None => {
+            let mut salt = [0u8; 16];
+            SystemRandom::new().fill(&mut salt).map_err(|_| "Failed to generate a salt".to_string())?;
+            let meta = Meta { version: META_VERSION, salt: base64::engine::general_purpose::STANDARD.encode(salt), iterations: KDF_ITERATIONS };
+            if !dry_run {
+                remote.put_if(META, &serde_json::to_vec_pretty(&meta).map_err(|e| e.to_string())?, &Current::Absent)?;
+            }
+            meta
+        }
+    };
+    let salt = base64::engine::general_purpose::STANDARD.decode(&meta.salt).map_err(|e| format!("Invalid salt in {}: {}", META, e))?;
+    let key = derive_key(&passphrase, &salt, meta.iterations);
+    let legacy = meta.version < META_VERSION;
+
+    let (mut index, index_current): (BTreeMap<String, i64>, Current) = match remote.get_current(INDEX)? {
+        Some((body, current)) => {
+            let index = serde_json::from_slice(&decrypt(&key, INDEX, &body, legacy)?).map_err(|e| format!("Invalid remote index: {}", e))?;
+            (index, current)
+        }
+        None => (BTreeMap::new(), Current::Absent),
+    };
+    let local: HashMap<String, i64> = session::list_sessions().into_iter().map(|s| (s.id, s.updated_at)).collect();
+    let state = load_state();
+
+    let mut report = Report::default();
+    let object = |id: &str| format!("sessions/{}.json.enc", id);
+    let upload = |id: &str| -> Result<(), String> {
+        let content = fs::read(session::session_path(id)?).map_err(|e| format!("Failed to read session {}: {}", id, e))?;
+        remote.put(&object(id), &encrypt(&key, &object(id), &content)?)
+    };
+    let download = |id: &str| -> Result<Session, String> {
+        session::session_path(id)?;
+        let body = remote.get(&object(id))?.ok_or(format!("Session {} is in the remote index but missing", id))?;
+        let session: Session = serde_json::from_slice(&decrypt(&key, &object(id), &body, legacy)?).map_err(|e| format!("Invalid remote session {}: {}", id, e))?;
+        if session.id != id {
+            return Err(format!("Remote session {} has mismatched id {}", id, session.id));
+        }
+        Ok(session)
+    };
+
+    for (id, action) in plan(&local, &index, &state) {
+        match action {
+            Action::Upload => {
+                if !dry_run {
+                    upload(&id)?;
+                    index.insert(id.clone(), local[&id]);
+                }
+                report.uploaded.push(id);
+            }
+            Action::Download => {
+                if !dry_run {
+                    let session = download(&id)?;
+                    index.insert(id.clone(), session.updated_at);
+                    session.save()?;
+                }
+                report.downloaded.push(id);
+            }
+            Action::Conflict { local_newer } => {
+                // The older side is kept as a separate local session so nothing is lost
+                let loser_at = if local_newer { index[&id] } else { local[&id] };
+                let copy_id = format!("{}-conflict-{}", id, loser_at);
+                if !dry_run {
+                    let mut copy = if local_newer { download(&id)? } else { Session::load(&id)? };
+                    copy.id = copy_id.clone();
+                    copy.save()?;
+                    if local_newer {
+                        upload(&id)?;
+                        index.insert(id.clone(), local[&id]);
+                    } else {
+                        let session = download(&id)?;
+                        index.insert(id.clone(), session.updated_at);
+                        session.save()?;
+                    }
+                    upload(&copy_id)?;
+                    index.insert(copy_id.clone(), copy.updated_at);
+                }
+                report.conflicts.push((id, copy_id));
+            }
+        }
+    }
+
+    if !dry_run {
+        if !report.uploaded.is_empty() || !report.downloaded.is_empty() || !report.conflicts.is_empty() {
+            let body = encrypt(&key, INDEX, &serde_json::to_vec(&index).map_err(|e| e.to_string())?)?;
+            remote.put_if(INDEX, &body, &index_current)?;
+        }
+        // Every indexed session now exists on both sides at the indexed version
+        save_state(&index)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_and_crypto() {
+        let local = HashMap::from([("a".to_string(), 5), ("b".to_string(), 9), ("c".to_string(), 3), ("d".to_string(), 7)]);
+        let remote = BTreeMap::from([("b".to_string(), 4), ("c".to_string(), 6), ("d".to_string(), 8), ("e".to_string(), 1)]);
+        let synced = BTreeMap::from([("b".to_string(), 4), ("c".to_string(), 3), ("d".to_string(), 2)]);
+        assert_eq!(
+            plan(&local, &remote, &synced),
+            [
+                ("a".to_string(), Action::Upload),
+                ("b".to_string(), Action::Upload),
+                ("c".to_string(), Action::Download),
+                ("d".to_string(), Action::Conflict { local_newer: false }),
+                ("e".to_string(), Action::Download),
+            ]
+        );
+
+        let key = derive_key("passphrase", b"salt", 10);
+        let sealed = encrypt(&key, "sessions/a.json.enc", b"session").unwrap();
+        assert_eq!(decrypt(&key, "sessions/a.json.enc", &sealed, false).unwrap(), b"session");
+        assert!(decrypt(&derive_key("other", b"salt", 10), "sessions/a.json.enc", &sealed, false).is_err());
+        // An object moved to another key doesn't open, even on a legacy remote
+        assert!(decrypt(&key, "sessions/b.json.enc", &sealed, true).is_err());
+
+        // Example signing key from the AWS Signature Version 4 documentation
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+        assert_eq!(hex(&key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+    }
+}
@@ -0,0 +1,82 @@
+use crate::tools::{ErrorKind, ToolError, ToolResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One step of the model's plan for a multi-step task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Todo {
+    pub content: String,
+    pub status: Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Pending,
+    InProgress,
+    #[serde(alias = "completed")]
+    Done,
+}
+
+/// Replace the plan with the `todos` list in a todo_write call. Returns the new checklist.
+pub fn write(plan: &mut Vec<Todo>, args: &str) -> ToolResult {
+    let invalid = |message: String| ToolError::new(ErrorKind::InvalidArguments, format!("Error: {}", message));
+    let args: Value = serde_json::from_str(args).map_err(|e| invalid(format!("invalid arguments: {}", e)))?;
+    let todos: Vec<Todo> = serde_json::from_value(args["todos"].clone()).map_err(|e| {
+        invalid(format!("todos must be a list of {{content, status}} with status pending, in_progress or done: {}", e))
+    })?;
+    if todos.iter().any(|t| t.content.trim().is_empty()) {
+        return Err(invalid("every todo needs content".to_string()));
+    }
+    *plan = todos;
+    Ok(checklist(plan))
+}
+
+/// How many items are done, out of all of them
+pub fn progress(plan: &[Todo]) -> (usize, usize) {
+    (plan.iter().filter(|t| t.status == Status::Done).count(), plan.len())
+}
+
+/// Whether the panel should stay up: something is left to do
+pub fn is_open(plan: &[Todo]) -> bool {
+    plan.iter().any(|t| t.status != Status::Done)
+}
+
+/// The plan as a text checklist, for the model
+pub fn checklist(plan: &[Todo]) -> String {
+    if plan.is_empty() {
+        return "No plan yet".to_string();
+    }
+    let (done, total) = progress(plan);
+    let items = plan.iter().map(|t| {
+        let mark = match t.status {
+            Status::Pending => "[ ]",
+            Status::InProgress => "[~]",
+            Status::Done => "[x]",
+        };
+        format!("{} {}", mark, t.content)
+    });
+    format!("Plan ({}/{} done)\n{}", done, total, items.collect::<Vec<_>>().join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let mut plan = Vec::new();
+        assert_eq!(checklist(&plan), "No plan yet");
+        let args = r#"{"todos": [{"content": "Read the parser", "status": "completed"}, {"content": "Fix the bug", "status": "in_progress"}, {"content": "Add a test", "status": "pending"}]}"#;
+        assert_eq!(write(&mut plan, args).unwrap(), "Plan (1/3 done)\n[x] Read the parser\n[~] Fix the bug\n[ ] Add a test");
+        assert!(is_open(&plan));
+
+        // A bad call leaves the plan alone
+        assert!(write(&mut plan, r#"{"todos": [{"content": "x", "status": "later"}]}"#).is_err());
+        assert!(write(&mut plan, r#"{"todos": [{"content": " ", "status": "done"}]}"#).is_err());
+        assert_eq!(progress(&plan), (1, 3));
+
+        write(&mut plan, r#"{"todos": [{"content": "Fix the bug", "status": "done"}]}"#).unwrap();
+        assert!(!is_open(&plan));
+    }
+}
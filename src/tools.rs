@@ -1,9 +1,16 @@
 use crate::config::Mode;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use similar::{ChangeTag, TextDiff};
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// The read-only subset offered in ask mode
+const ASK_TOOLS: &[&str] = &["read_file", "grep", "list_dir", "search_files", "repo_stats", "list_symbols", "find_definition"];
 
 pub fn get_tool_definitions(mode: &Mode) -> Vec<Value> {
     match mode {
@@ -12,7 +19,7 @@ pub fn get_tool_definitions(mode: &Mode) -> Vec<Value> {
                 "type": "function",
                 "function": {
                     "name": "read_file",
-                    "description": "Read the contents of a file. Use start_line/end_line to read specific sections (line numbers from grep output).",
+                    "description": "Read the contents of a file. Use start_line/end_line to read specific sections (line numbers from grep output). Ranged reads end with a hash of the lines for use with edit_lines.",
                     "parameters": {
                         "type": "object",
                         "properties": {
@@ -49,12 +56,91 @@ pub fn get_tool_definitions(mode: &Mode) -> Vec<Value> {
                         "properties": {
                             "path": { "type": "string", "description": "File path to edit" },
                             "old": { "type": "string", "description": "Exact text to find (must match exactly)" },
-                            "new": { "type": "string", "description": "Text to replace it with" }
+                            "new": { "type": "string", "description": "Text to replace it with" },
+                            "occurrence": { "type": "integer", "description": "Which match to replace, counting from 1 at the top, when old appears more than once" }
                         },
                         "required": ["path", "old", "new"]
                     }
                 }
             }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "multi_edit",
+                    "description": "Apply several exact-text replacements to one file in a single step. Edits apply in order; if any old text is missing or ambiguous, nothing is written.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": { "type": "string", "description": "File path to edit" },
+                            "edits": {
+                                "type": "array",
+                                "description": "Replacements, applied in order",
+                                "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "old": { "type": "string", "description": "Exact text to find (must match exactly once)" },
+                                        "new": { "type": "string", "description": "Text to replace it with" }
+                                    },
+                                    "required": ["old", "new"]
+                                }
+                            }
+                        },
+                        "required": ["path", "edits"]
+                    }
+                }
+            }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "apply_patch",
+                    "description": "Apply a unified diff (---/+++ headers, @@ hunks), possibly spanning several files. Context is matched fuzzily (line offsets, whitespace). Reports each hunk as applied or failed; failed hunks are skipped, so resend just those.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "patch": { "type": "string", "description": "Unified diff text" }
+                        },
+                        "required": ["patch"]
+                    }
+                }
+            }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "edit_lines",
+                    "description": "Edit a file by line range. Use when edit_file fails on repeated or whitespace-sensitive text. replace/delete need the hash printed by a read_file of exactly that range.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": { "type": "string", "description": "File path to edit" },
+                            "operation": { "type": "string", "enum": ["replace", "insert", "delete"], "description": "replace (default), insert before start_line, or delete" },
+                            "start_line": { "type": "integer", "description": "First line, 1-indexed" },
+                            "end_line": { "type": "integer", "description": "Last line, inclusive (replace/delete)" },
+                            "content": { "type": "string", "description": "New lines (replace/insert)" },
+                            "hash": { "type": "string", "description": "Hash of the current start_line..end_line from read_file (replace/delete)" }
+                        },
+                        "required": ["path", "start_line"]
+                    }
+                }
+            }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "replace_in_files",
+                    "description": "Regex find/replace across all files matching a glob. Without confirm it is a dry run that returns the diff and writes nothing; check it, then call again with the same arguments and confirm: true, which the user approves before anything is written. Use for mechanical refactors instead of many edit_file calls.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "pattern": { "type": "string", "description": "Regex to find (plain text if literal is true)" },
+                            "replacement": { "type": "string", "description": "Replacement text ($1, $name refer to regex groups)" },
+                            "glob": { "type": "string", "description": "Files to search, relative to path (e.g., '**/*.rs')" },
+                            "path": { "type": "string", "description": "Starting directory (default: current dir)" },
+                            "literal": { "type": "boolean", "description": "Match pattern as plain text (default: false)" },
+                            "confirm": { "type": "boolean", "description": "Apply the changes reviewed in an earlier dry run (default: false)" }
+                        },
+                        "required": ["pattern", "replacement", "glob"]
+                    }
+                }
+            }),
             json!({
                 "type": "function",
                 "function": {
@@ -69,6 +155,49 @@ pub fn get_tool_definitions(mode: &Mode) -> Vec<Value> {
                     }
                 }
             }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "repo_stats",
+                    "description": "Overview of a codebase in one call: file and line counts by language, the largest files and the test-to-source ratio. Use this before exploring an unfamiliar repo instead of many list_dir calls.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": { "type": "string", "description": "Directory to summarize (default: current dir)" }
+                        },
+                        "required": []
+                    }
+                }
+            }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "list_symbols",
+                    "description": "Outline the functions, types, classes and methods in a file or directory with their line ranges, parsed with tree-sitter (Rust, Python, JavaScript, TypeScript, Go, C, C++, Java). Use this to learn a file's structure before reading it, then read_file just the range you need.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": { "type": "string", "description": "File or directory to outline (default: current dir)" }
+                        },
+                        "required": []
+                    }
+                }
+            }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "find_definition",
+                    "description": "Find where a function, type, class or method is defined, with its line range and signature. Faster and more precise than grepping for a definition. Same languages as list_symbols.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string", "description": "Symbol name, optionally qualified by its parent: 'parse', 'App::new' or 'Parser.parse'" },
+                            "path": { "type": "string", "description": "File or directory to search (default: current dir)" }
+                        },
+                        "required": ["name"]
+                    }
+                }
+            }),
             json!({
                 "type": "function",
                 "function": {
@@ -77,7 +206,7 @@ pub fn get_tool_definitions(mode: &Mode) -> Vec<Value> {
                     "parameters": {
                         "type": "object",
                         "properties": {
-                            "pattern": { "type": "string", "description": "Glob pattern (e.g., '*.rs', '**/*.json')" },
+                            "pattern": { "type": "string", "description": "Glob over paths relative to `path`, e.g. '*.rs', 'src/**/*.rs', 'src/{a,b}/*.rs', '**/test_[0-9]*.py'. Without a slash it matches file names at any depth" },
                             "path": { "type": "string", "description": "Starting directory (default: current dir)" }
                         },
                         "required": ["pattern"]
@@ -100,6 +229,20 @@ pub fn get_tool_definitions(mode: &Mode) -> Vec<Value> {
                     }
                 }
             }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "gh_fetch",
+                    "description": "Fetch a GitHub issue or pull request (title, body, comments). Use when the user references an issue or PR.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "ref": { "type": "string", "description": "owner/repo#123 or an issue/PR URL" }
+                        },
+                        "required": ["ref"]
+                    }
+                }
+            }),
             json!({
                 "type": "function",
                 "function": {
@@ -108,13 +251,67 @@ pub fn get_tool_definitions(mode: &Mode) -> Vec<Value> {
                     "parameters": {
                         "type": "object",
                         "properties": {
-                            "command": { "type": "string", "description": "The bash command to execute" }
+                            "command": { "type": "string", "description": "The bash command to execute" },
+                            "quiet": { "type": "boolean", "description": "If the command succeeds, return only its exit code and output size instead of the output; failures still return everything. Use for builds, installs and test runs where only failures matter. Defaults to the user's setting" }
                         },
                         "required": ["command"]
                     }
                 }
             }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "scratch_dir",
+                    "description": "Get a temporary directory for throwaway experiments (trying an API, a minimal repro, a test project) instead of writing them into the project. bash can write there and file changes there skip review. It is deleted when hal exits, so don't leave anything the user needs in it.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {},
+                        "required": []
+                    }
+                }
+            }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "todo_write",
+                    "description": "Write the plan for a task with several steps as a checklist the user sees while you work. Send the whole list every time: mark a step in_progress when you start it and done as soon as it's finished, keeping one step in progress at a time. Add steps you discover along the way. Skip this for simple requests.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "todos": {
+                                "type": "array",
+                                "description": "Every step of the plan, in order",
+                                "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "content": { "type": "string", "description": "What the step does, in a few words" },
+                                        "status": { "type": "string", "enum": ["pending", "in_progress", "done"] }
+                                    },
+                                    "required": ["content", "status"]
+                                }
+                            }
+                        },
+                        "required": ["todos"]
+                    }
+                }
+            }),
+            json!({
+                "type": "function",
+                "function": {
+                    "name": "todo_read",
+                    "description": "Read the current plan written with todo_write",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {},
+                        "required": []
+                    }
+                }
+            }),
         ],
+        Mode::Ask => get_tool_definitions(&Mode::Coding)
+            .into_iter()
+            .filter(|t| t["function"]["name"].as_str().is_some_and(|n| ASK_TOOLS.contains(&n)))
+            .collect(),
         Mode::Coach => vec![
             json!({
                 "type": "function",
@@ -146,37 +343,298 @@ pub fn get_tool_definitions(mode: &Mode) -> Vec<Value> {
     }
 }
 
+/// Tool definitions trimmed for providers that bill schema tokens on every request:
+/// descriptions cut to their first sentence without asides, defaults and empty lists dropped
+pub fn compact_definitions(defs: &[Value]) -> Vec<Value> {
+    fn shorten(text: &str) -> String {
+        let first = text.split_once(". ").map_or(text, |(first, _)| first);
+        let mut out = String::new();
+        let mut depth = 0;
+        for c in first.chars() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                _ if depth == 0 => out.push(c),
+                _ => {}
+            }
+        }
+        out.split_whitespace().collect::<Vec<_>>().join(" ").trim_end_matches('.').to_string()
+    }
+    fn compact(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                map.remove("default");
+                map.remove("examples");
+                if map.get("required").and_then(|r| r.as_array()).is_some_and(|r| r.is_empty()) {
+                    map.remove("required");
+                }
+                for (key, field) in map.iter_mut() {
+                    match field {
+                        Value::String(text) if key == "description" => *text = shorten(text),
+                        _ => compact(field),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(compact),
+            _ => {}
+        }
+    }
+    defs.iter()
+        .map(|def| {
+            let mut def = def.clone();
+            compact(&mut def);
+            def
+        })
+        .collect()
+}
+
+/// Why a tool call failed, so the model can tell a retry with different arguments
+/// from a dead end
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    InvalidArguments,
+    AmbiguousMatch,
+    Stale,
+    Syntax,
+    PatchFailed,
+    NotFound,
+    Unavailable,
+    Internal,
+    Io,
+    Network,
+    CommandFailed,
+    Rejected,
+    PermissionDenied,
+    Interrupted,
+    TimeLimit,
+}
+
+impl ErrorKind {
+    fn hint(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidArguments => "Fix the arguments and call the tool again",
+            ErrorKind::AmbiguousMatch => "Include more surrounding lines so the text matches exactly once",
+            ErrorKind::Stale => "Read the lines again for the current hash, then retry",
+            ErrorKind::Syntax => "Fix the syntax error in the new content and retry",
+            ErrorKind::PatchFailed => "Read the file again and regenerate the patch against its current content",
+            ErrorKind::NotFound => "Check the path or text with read_file, grep or search_files, then retry",
+            ErrorKind::Unavailable => "Use only the tools offered in this mode",
+            ErrorKind::Internal => "Retry once; if it fails again, tell the user",
+            ErrorKind::Io => "Check the path and permissions; retrying unchanged won't help",
+            ErrorKind::Network => "The request failed; check the reference before retrying",
+            ErrorKind::CommandFailed => "Read the output for the cause before running the command again",
+            ErrorKind::Rejected => "The user declined this change; ask what they want instead of retrying",
+            ErrorKind::PermissionDenied => "The user declined; don't retry the same call, take another approach or ask",
+            ErrorKind::Interrupted => "The user interrupted this call; wait for their next instruction",
+            ErrorKind::TimeLimit => "The run is out of time; summarize instead of calling more tools",
+        }
+    }
+
+    /// The user declined or interrupted the call, rather than the tool failing
+    pub fn declined(self) -> bool {
+        matches!(self, ErrorKind::Rejected | ErrorKind::PermissionDenied | ErrorKind::Interrupted | ErrorKind::TimeLimit)
+    }
+}
+
+/// A failed tool call: the text shown in the transcript, and why it failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl ToolError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        ToolError { kind, message: message.into() }
+    }
+}
+
+pub type ToolResult = Result<String, ToolError>;
+
+/// The transcript text of a result, and its failure kind if it failed
+pub fn split_result(result: ToolResult) -> (String, Option<ErrorKind>) {
+    match result {
+        Ok(output) => (output, None),
+        Err(e) => (e.message, Some(e.kind)),
+    }
+}
+
+/// Tool result as sent to the model: failures become
+/// {"error_type", "message", "hint"} JSON, successes are passed through
+pub fn result_for_model(result: &str, error: Option<ErrorKind>) -> String {
+    match error {
+        Some(kind) => json!({
+            "error_type": kind,
+            "message": result.strip_prefix("Error: ").or_else(|| result.strip_prefix("REJECTED: ")).unwrap_or(result),
+            "hint": kind.hint(),
+        })
+        .to_string(),
+        None => result.to_string(),
+    }
+}
+
+/// Cut the middle out of a result longer than `limit` characters, keeping whole lines
+/// from both ends, with a note telling the model how to ask for less. 0 = no limit.
+pub fn limit_output(name: &str, result: &str, limit: usize) -> String {
+    let total = result.chars().count();
+    if limit == 0 || total <= limit {
+        return result.to_string();
+    }
+    let half = limit / 2;
+    let lines: Vec<&str> = result.lines().collect();
+    let (mut head, mut head_chars) = (0, 0);
+    while head < lines.len() && head_chars + lines[head].chars().count() < half {
+        head_chars += lines[head].chars().count() + 1;
+        head += 1;
+    }
+    let (mut tail, mut tail_chars) = (lines.len(), 0);
+    while tail > head && tail_chars + lines[tail - 1].chars().count() < half {
+        tail_chars += lines[tail - 1].chars().count() + 1;
+        tail -= 1;
+    }
+    let (start, end, omitted): (String, String, String) = if head == 0 && tail == lines.len() {
+        // No whole line fits (minified files, long JSON): cut by characters
+        let chars: Vec<char> = result.chars().collect();
+        (chars[..half].iter().collect(), chars[total - half..].iter().collect(), "part of a line".to_string())
+    } else {
+        (lines[..head].join("\n"), lines[tail..].join("\n"), format!("{} lines", tail - head))
+    };
+    let hint = match name {
+        "read_file" => "Read the part you need with start_line and end_line, or grep for it.",
+        "bash" => "Rerun the command with its output filtered (grep, head, tail, or sed -n for a line range).",
+        "grep" | "search_files" => "Narrow the pattern or the path.",
+        "list_symbols" => "List the symbols of a subdirectory or a single file.",
+        _ => "Ask for a narrower result, such as a line range or a grep for what you need.",
+    };
+    format!(
+        "{}\n[... {} omitted ({} of {} characters shown): tool output is limited to {} characters. {}]\n{}",
+        start,
+        omitted,
+        start.chars().count() + end.chars().count(),
+        total,
+        limit,
+        hint,
+        end
+    )
+}
+
 /// Execute a tool by name without needing a function pointer map
 /// Used for async tool execution where we can't send function pointers across threads
-pub fn execute_tool_by_name(name: &str, args_str: &str) -> String {
+pub fn execute_tool_by_name(name: &str, args_str: &str) -> ToolResult {
     let args: Value = serde_json::from_str(args_str).unwrap_or(json!({}));
 
     match name {
         "read_file" => tool_read_file(&args),
         "write_file" => tool_write_file(&args),
         "edit_file" => tool_edit_file(&args),
+        "edit_lines" => tool_edit_lines(&args),
+        "multi_edit" => tool_multi_edit(&args),
+        "apply_patch" => tool_apply_patch(&args),
+        "replace_in_files" => tool_replace_in_files(&args),
         "list_dir" => tool_list_dir(&args),
+        "repo_stats" => tool_repo_stats(&args),
+        "list_symbols" => tool_list_symbols(&args),
+        "find_definition" => tool_find_definition(&args),
         "search_files" => tool_search_files(&args),
         "grep" => tool_grep(&args),
         "bash" => tool_bash(&args),
+        "gh_fetch" => tool_gh_fetch(&args),
+        "scratch_dir" => tool_scratch_dir(),
         "view_projects" => tool_view_projects(&args),
         "update_projects" => tool_update_projects(&args),
-        _ => format!("Unknown tool: {}", name),
+        _ => Err(ToolError::new(ErrorKind::Unavailable, format!("Error: unknown tool {}", name))),
+    }
+}
+
+/// Read-only tools that can safely run concurrently with each other.
+/// Writes and bash stay serialized since they may touch the same files.
+pub fn is_parallel_safe(name: &str) -> bool {
+    matches!(name, "read_file" | "list_dir" | "repo_stats" | "list_symbols" | "find_definition" | "search_files" | "grep" | "gh_fetch" | "scratch_dir" | "view_projects")
+}
+
+/// Tools that modify files and go through diff preview/approval
+pub fn is_file_change(name: &str) -> bool {
+    matches!(name, "write_file" | "edit_file" | "edit_lines" | "multi_edit" | "apply_patch" | "replace_in_files")
+}
+
+/// A replace_in_files call without confirm, which only previews and needs no approval
+pub fn is_dry_run(name: &str, args_str: &str) -> bool {
+    name == "replace_in_files" && !serde_json::from_str::<Value>(args_str).is_ok_and(|args| args["confirm"].as_bool() == Some(true))
+}
+
+/// Preview any file-changing tool. Returns (diff_text, [(path, new_content)]).
+pub fn preview_file_change(name: &str, args_str: &str) -> Result<(String, Vec<(String, String)>), ToolError> {
+    let args: Value = serde_json::from_str(args_str).unwrap_or(json!({}));
+    let path = args["path"].as_str().unwrap_or("").to_string();
+
+    let (diff, writes) = match name {
+        "write_file" => preview_write_file(args_str).map(|(diff, content)| (diff, vec![(path, content)])),
+        "edit_file" => preview_edit_file(args_str).map(|(diff, content)| (diff, vec![(path, content)])),
+        "edit_lines" => preview_edit_lines(&args).map(|(diff, content)| (diff, vec![(path, content)])),
+        "multi_edit" => preview_multi_edit(&args).map(|(diff, content)| (diff, vec![(path, content)])),
+        "apply_patch" => preview_apply_patch(&args),
+        "replace_in_files" => preview_replace_in_files(&args),
+        _ => Err(ToolError::new(ErrorKind::InvalidArguments, format!("Error: {} does not modify files", name))),
+    }?;
+    for (path, content) in &writes {
+        check_syntax(path, content)?;
+    }
+    Ok((diff, writes))
+}
+
+/// Parse JSON, TOML, YAML and Rust before writing so the model gets the parse error
+/// instead of a broken file on disk. Files that are already unparseable are let through
+/// so multi-step fixes aren't blocked.
+pub fn check_syntax(path: &str, content: &str) -> Result<(), ToolError> {
+    let file_name = Path::new(path).file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    // JSON-with-comments configs
+    if file_name.starts_with("tsconfig") || file_name.starts_with("jsconfig") || path.contains(".vscode") {
+        return Ok(());
+    }
+
+    let parse = |text: &str| -> Option<Result<(), String>> {
+        let ext = Path::new(path).extension()?.to_string_lossy().to_lowercase();
+        Some(match ext.as_str() {
+            "json" => serde_json::from_str::<Value>(text).map(|_| ()).map_err(|e| e.to_string()),
+            "toml" => toml::from_str::<toml::Table>(text).map(|_| ()).map_err(|e| e.to_string()),
+            "yaml" | "yml" => serde_yaml::Deserializer::from_str(text)
+                .try_for_each(|doc| serde_yaml::Value::deserialize(doc).map(|_| ()))
+                .map_err(|e| e.to_string()),
+            "rs" => syn::parse_file(text).map(|_| ()).map_err(|e| {
+                let start = e.span().start();
+                format!("{} at line {} column {}", e, start.line, start.column + 1)
+            }),
+            _ => return None,
+        })
+    };
+
+    match parse(content) {
+        Some(Err(e)) => {
+            let was_valid = fs::read_to_string(path).map(|old| !matches!(parse(&old), Some(Err(_)))).unwrap_or(true);
+            if was_valid {
+                Err(ToolError::new(ErrorKind::Syntax, format!("Error: not writing {} - the result would not parse: {}", path, e.trim())))
+            } else {
+                Ok(())
+            }
+        }
+        _ => Ok(()),
     }
 }
 
 /// Preview a write_file without actually writing. Returns (diff_text, new_content).
-pub fn preview_write_file(args_str: &str) -> Result<(String, String), String> {
+pub fn preview_write_file(args_str: &str) -> Result<(String, String), ToolError> {
     let args: Value = serde_json::from_str(args_str).unwrap_or(json!({}));
     let path = args["path"].as_str().unwrap_or("");
     let content = args["content"].as_str().unwrap_or("");
 
     if path.is_empty() {
-        return Err("Error: path is required".to_string());
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: path is required"));
     }
 
     let old_content = fs::read_to_string(path).unwrap_or_default();
     let is_new = old_content.is_empty() && !Path::new(path).exists();
+    let content: &str = &crate::editorconfig::conform(path, &old_content, content.to_string());
 
     let diff = if is_new {
         let mut output = format!("Created {}\n", path);
@@ -194,39 +652,133 @@ pub fn preview_write_file(args_str: &str) -> Result<(String, String), String> {
 }
 
 /// Preview an edit_file without actually writing. Returns (diff_text, new_content).
-pub fn preview_edit_file(args_str: &str) -> Result<(String, String), String> {
+pub fn preview_edit_file(args_str: &str) -> Result<(String, String), ToolError> {
     let args: Value = serde_json::from_str(args_str).unwrap_or(json!({}));
     let path = args["path"].as_str().unwrap_or("");
     let old = args["old"].as_str().unwrap_or("");
-    let new = args["new"].as_str().unwrap_or("");
 
     if path.is_empty() {
-        return Err("Error: path is required".to_string());
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: path is required"));
     }
     if old.is_empty() {
-        return Err("Error: old text is required".to_string());
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: old text is required"));
     }
 
-    let content = fs::read_to_string(path).map_err(|e| format!("Error reading file: {}", e))?;
+    let content = fs::read_to_string(path).map_err(|e| io_error("reading file", e))?;
 
-    let count = content.matches(old).count();
-    if count == 0 {
-        return Err(format!("Error: text not found in {}", path));
-    }
-    if count > 1 {
-        return Err(format!("Error: text appears {} times in {} - be more specific", count, path));
+    let updated = edit_file_content(path, &content, &args)?;
+    let updated = crate::editorconfig::conform(path, &content, updated);
+    let mut diff_text = format_diff_with_context(path, "Edited", &content, &updated);
+    if let Some(note) = mixed_endings_note(path, &content) {
+        diff_text.push_str(&note);
     }
 
-    let updated = content.replacen(old, new, 1);
-    let diff_text = format_diff_with_context(path, "Edited", &content, &updated);
-
     Ok((diff_text, updated))
 }
 
+/// (CRLF, bare LF) line ending counts
+fn line_endings(content: &str) -> (usize, usize) {
+    let crlf = content.matches("\r\n").count();
+    (crlf, content.matches('\n').count() - crlf)
+}
+
+/// `text` converted to the file's dominant line ending
+fn with_endings_of(content: &str, text: &str) -> String {
+    let (crlf, lf) = line_endings(content);
+    let text = text.replace("\r\n", "\n");
+    if crlf > lf { text.replace('\n', "\r\n") } else { text }
+}
+
+/// Replace the single occurrence of `old`. Text the model sends with LF endings still
+/// matches a CRLF file, and replacement lines take the file's endings. Err holds the
+/// match count when it isn't exactly one.
+fn replace_unique(content: &str, old: &str, new: &str) -> Result<String, usize> {
+    replace_occurrence(content, old, new, None)
+}
+
+/// `old` with the file's line endings when it only matches that way
+fn matching_text(content: &str, old: &str) -> String {
+    if content.contains(old) { old.to_string() } else { with_endings_of(content, old) }
+}
+
+/// Like replace_unique, but `occurrence` (1-based) picks one of several matches. Err holds
+/// the match count when there is no match, several without a pick, or the pick is past the end.
+fn replace_occurrence(content: &str, old: &str, new: &str, occurrence: Option<usize>) -> Result<String, usize> {
+    let old = matching_text(content, old);
+    let starts: Vec<usize> = content.match_indices(&old).map(|(i, _)| i).collect();
+    let start = match occurrence {
+        Some(n) if n >= 1 && n <= starts.len() => starts[n - 1],
+        None if starts.len() == 1 => starts[0],
+        _ => return Err(starts.len()),
+    };
+    let (crlf, _) = line_endings(content);
+    let new = if crlf > 0 { with_endings_of(content, new) } else { new.to_string() };
+    Ok(format!("{}{}{}", &content[..start], new, &content[start + old.len()..]))
+}
+
+/// Line number and first line of every place `old` matches, for choosing between them
+pub fn match_locations(content: &str, old: &str) -> Vec<(usize, String)> {
+    let old = matching_text(content, old);
+    content
+        .match_indices(&old)
+        .map(|(i, _)| {
+            let line_start = content[..i].rfind('\n').map_or(0, |n| n + 1);
+            let line = content[line_start..].lines().next().unwrap_or("").trim().to_string();
+            (content[..i].matches('\n').count() + 1, line)
+        })
+        .collect()
+}
+
+/// edit_file's new content, or an error naming where an ambiguous `old` matches
+fn edit_file_content(path: &str, content: &str, args: &Value) -> ToolResult {
+    let old = args["old"].as_str().unwrap_or("");
+    let new = args["new"].as_str().unwrap_or("");
+    let occurrence = args["occurrence"].as_u64().map(|n| n as usize);
+    replace_occurrence(content, old, new, occurrence).map_err(|count| match (count, occurrence) {
+        (0, _) => ToolError::new(ErrorKind::NotFound, format!("Error: text not found in {}", path)),
+        (count, Some(n)) => ToolError::new(
+            ErrorKind::InvalidArguments,
+            format!("Error: occurrence {} is out of range - text appears {} times in {}", n, count, path),
+        ),
+        (count, None) => {
+            let lines: Vec<String> = match_locations(content, old).iter().map(|(line, _)| line.to_string()).collect();
+            ToolError::new(
+                ErrorKind::AmbiguousMatch,
+                format!(
+                    "Error: text appears {} times in {} (lines {}) - be more specific or pass occurrence",
+                    count,
+                    path,
+                    lines.join(", ")
+                ),
+            )
+        }
+    })
+}
+
+/// The path and match locations when an edit_file call's old text is ambiguous and no
+/// occurrence was given, so the user can pick one
+pub fn ambiguous_edit(args_str: &str) -> Option<(String, Vec<(usize, String)>)> {
+    let args: Value = serde_json::from_str(args_str).ok()?;
+    let path = args["path"].as_str()?;
+    let old = args["old"].as_str().filter(|old| !old.is_empty())?;
+    if !args["occurrence"].is_null() {
+        return None;
+    }
+    let content = fs::read_to_string(path).ok()?;
+    let locations = match_locations(&content, old);
+    (locations.len() > 1).then(|| (path.to_string(), locations))
+}
+
+/// Warning for files mixing CRLF and LF, where edits may not match as expected
+fn mixed_endings_note(path: &str, content: &str) -> Option<String> {
+    let (crlf, lf) = line_endings(content);
+    (crlf > 0 && lf > 0).then(|| format!("\nNote: {} has mixed line endings ({} CRLF, {} LF)", path, crlf, lf))
+}
+
 /// Format a unified diff with 3 lines of context and line numbers.
 /// Each line is formatted as: <marker><line_num_4_chars>│<code>
 /// Hunks are separated by "···" lines.
-fn format_diff_with_context(path: &str, action: &str, old_content: &str, new_content: &str) -> String {
+pub fn format_diff_with_context(path: &str, action: &str, old_content: &str, new_content: &str) -> String {
     let diff = similar::TextDiff::from_lines(old_content, new_content);
     let mut output = format!("{} {}\n", action, path);
 
@@ -254,26 +806,30 @@ fn format_diff_with_context(path: &str, action: &str, old_content: &str, new_con
 }
 
 /// Apply a previewed write (used after user accepts).
-pub fn apply_write(path: &str, content: &str) -> String {
+pub fn apply_write(path: &str, content: &str) -> ToolResult {
     if let Some(parent) = Path::new(path).parent() {
         if !parent.as_os_str().is_empty() {
-            if let Err(e) = fs::create_dir_all(parent) {
-                return format!("Error creating directories: {}", e);
-            }
+            fs::create_dir_all(parent).map_err(|e| io_error("creating directories", e))?;
         }
     }
-    match fs::write(path, content) {
-        Ok(_) => format!("Wrote {}", path),
-        Err(e) => format!("Error writing file: {}", e),
+    match crate::undo::write(path, content) {
+        Ok(_) => Ok(format!("Wrote {}", path)),
+        Err(e) => Err(io_error("writing file", e)),
     }
 }
 
+/// "Error reading file: ..." and the like, as not_found when the path doesn't exist
+fn io_error(action: &str, e: std::io::Error) -> ToolError {
+    let kind = if e.kind() == std::io::ErrorKind::NotFound { ErrorKind::NotFound } else { ErrorKind::Io };
+    ToolError::new(kind, format!("Error {}: {}", action, e))
+}
+
 // Coding tools
 
-fn tool_read_file(args: &Value) -> String {
+fn tool_read_file(args: &Value) -> ToolResult {
     let path = args["path"].as_str().unwrap_or("");
     if path.is_empty() {
-        return "Error: path is required".to_string();
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: path is required"));
     }
 
     let start_line = args["start_line"].as_u64().map(|n| n as usize);
@@ -281,13 +837,16 @@ fn tool_read_file(args: &Value) -> String {
 
     match fs::read_to_string(path) {
         Ok(content) => {
-            match (start_line, end_line) {
+            let output = match (start_line, end_line) {
                 (Some(start), Some(end)) => {
                     let lines: Vec<&str> = content.lines().collect();
                     let start_idx = start.saturating_sub(1); // Convert to 0-indexed
                     let end_idx = end.min(lines.len());
                     if start_idx >= lines.len() {
-                        return format!("Error: start_line {} exceeds file length ({})", start, lines.len());
+                        return Err(ToolError::new(
+                            ErrorKind::InvalidArguments,
+                            format!("Error: start_line {} exceeds file length ({})", start, lines.len()),
+                        ));
                     }
                     lines[start_idx..end_idx]
                         .iter()
@@ -300,7 +859,10 @@ fn tool_read_file(args: &Value) -> String {
                     let lines: Vec<&str> = content.lines().collect();
                     let start_idx = start.saturating_sub(1);
                     if start_idx >= lines.len() {
-                        return format!("Error: start_line {} exceeds file length ({})", start, lines.len());
+                        return Err(ToolError::new(
+                            ErrorKind::InvalidArguments,
+                            format!("Error: start_line {} exceeds file length ({})", start, lines.len()),
+                        ));
                     }
                     lines[start_idx..]
                         .iter()
@@ -319,47 +881,279 @@ fn tool_read_file(args: &Value) -> String {
                         .collect::<Vec<_>>()
                         .join("\n")
                 }
-                (None, None) => content,
+                (None, None) => return Ok(content),
+            };
+
+            // Hash of the returned lines lets edit_lines detect that they changed since
+            let lines: Vec<&str> = content.lines().collect();
+            let start_idx = start_line.unwrap_or(1).saturating_sub(1);
+            let end_idx = end_line.unwrap_or(lines.len()).min(lines.len());
+            Ok(format!(
+                "{}\n[lines {}-{} hash {}]",
+                output,
+                start_idx + 1,
+                end_idx,
+                hash_lines(&lines[start_idx..end_idx])
+            ))
+        }
+        Err(e) => Err(io_error("reading file", e)),
+    }
+}
+
+/// Short FNV-1a hash of a run of lines, shown by ranged read_file and checked by edit_lines
+fn hash_lines(lines: &[&str]) -> String {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in lines.join("\n").bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    format!("{:08x}", hash)
+}
+
+/// Compute an edit_lines change without writing. Returns (diff_text, new_content).
+fn preview_edit_lines(args: &Value) -> Result<(String, String), ToolError> {
+    let path = args["path"].as_str().unwrap_or("");
+    let operation = args["operation"].as_str().unwrap_or("replace");
+    let start = args["start_line"].as_u64().unwrap_or(0) as usize;
+    let end = args["end_line"].as_u64().map(|n| n as usize).unwrap_or(start);
+    let new = args["content"].as_str().unwrap_or("");
+
+    if path.is_empty() {
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: path is required"));
+    }
+    if start == 0 {
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: start_line is required (1-indexed)"));
+    }
+
+    let content = fs::read_to_string(path).map_err(|e| io_error("reading file", e))?;
+    let mut lines: Vec<&str> = content.split_inclusive('\n').collect();
+
+    // New lines always end in a newline unless they land at the end of a file without one
+    let mut new_text = new.to_string();
+    if !new_text.is_empty() && !new_text.ends_with('\n') {
+        new_text.push('\n');
+    }
+
+    match operation {
+        "insert" => {
+            if start > lines.len() + 1 {
+                return Err(ToolError::new(
+                    ErrorKind::InvalidArguments,
+                    format!("Error: start_line {} exceeds file length ({})", start, lines.len()),
+                ));
+            }
+            if start == lines.len() + 1 && lines.last().is_some_and(|l| !l.ends_with('\n')) {
+                new_text.insert(0, '\n');
+                new_text.pop();
+            }
+            lines.insert(start - 1, &new_text);
+        }
+        "replace" | "delete" => {
+            if end < start || end > lines.len() {
+                return Err(ToolError::new(
+                    ErrorKind::InvalidArguments,
+                    format!("Error: invalid line range {}-{} (file has {} lines)", start, end, lines.len()),
+                ));
+            }
+            let current: Vec<&str> = lines[start - 1..end].iter().map(|l| l.trim_end_matches(['\n', '\r'])).collect();
+            let expected = hash_lines(&current);
+            match args["hash"].as_str() {
+                Some(hash) if hash == expected => {}
+                Some(_) => {
+                    return Err(ToolError::new(
+                        ErrorKind::Stale,
+                        format!("Error: lines {}-{} changed since they were read - read them again to get the current hash", start, end),
+                    ));
+                }
+                None => {
+                    return Err(ToolError::new(
+                        ErrorKind::InvalidArguments,
+                        format!("Error: hash is required for {} - read_file lines {}-{} first", operation, start, end),
+                    ));
+                }
+            }
+            if end == lines.len() && !lines[end - 1].ends_with('\n') {
+                new_text.pop();
+            }
+            let replacement = if operation == "delete" { "" } else { new_text.as_str() };
+            lines.splice(start - 1..end, [replacement]);
+        }
+        other => return Err(ToolError::new(ErrorKind::InvalidArguments, format!("Error: unknown operation '{}'", other))),
+    }
+
+    let updated = crate::editorconfig::conform(path, &content, lines.concat());
+    let diff_text = format_diff_with_context(path, "Edited", &content, &updated);
+    Ok((diff_text, updated))
+}
+
+/// Apply every {old, new} edit to the file's content in order. Returns (diff_text, new_content),
+/// or the first edit that doesn't match exactly once.
+fn preview_multi_edit(args: &Value) -> Result<(String, String), ToolError> {
+    let path = args["path"].as_str().unwrap_or("");
+    if path.is_empty() {
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: path is required"));
+    }
+    let edits = match args["edits"].as_array() {
+        Some(edits) if !edits.is_empty() => edits,
+        _ => return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: edits must be a non-empty array")),
+    };
+
+    let content = fs::read_to_string(path).map_err(|e| io_error("reading file", e))?;
+    let mut updated = content.clone();
+    for (i, edit) in edits.iter().enumerate() {
+        let old = edit["old"].as_str().unwrap_or("");
+        let new = edit["new"].as_str().unwrap_or("");
+        if old.is_empty() {
+            return Err(ToolError::new(ErrorKind::InvalidArguments, format!("Error: edit {} has no old text - nothing was written", i + 1)));
+        }
+        match replace_unique(&updated, old, new) {
+            Ok(replaced) => updated = replaced,
+            Err(0) => {
+                return Err(ToolError::new(
+                    ErrorKind::NotFound,
+                    format!("Error: edit {}: text not found in {} - nothing was written", i + 1, path),
+                ));
+            }
+            Err(n) => {
+                return Err(ToolError::new(
+                    ErrorKind::AmbiguousMatch,
+                    format!("Error: edit {}: text appears {} times in {} - be more specific; nothing was written", i + 1, n, path),
+                ));
+            }
+        }
+    }
+
+    let updated = crate::editorconfig::conform(path, &content, updated);
+    let mut diff_text = format_diff_with_context(path, "Edited", &content, &updated);
+    if let Some(note) = mixed_endings_note(path, &content) {
+        diff_text.push_str(&note);
+    }
+    Ok((diff_text, updated))
+}
+
+fn tool_multi_edit(args: &Value) -> ToolResult {
+    let path = args["path"].as_str().unwrap_or("");
+    let (diff_text, updated) = preview_multi_edit(args)?;
+    check_syntax(path, &updated)?;
+    crate::undo::write(path, &updated).map_err(|e| io_error("writing file", e))?;
+    Ok(diff_text)
+}
+
+/// Apply a unified diff. Returns a per-hunk report plus diffs, and the files to write.
+/// Fails only if no hunk applies at all.
+fn preview_apply_patch(args: &Value) -> Result<(String, Vec<(String, String)>), ToolError> {
+    let patch = args["patch"].as_str().unwrap_or("");
+    if patch.trim().is_empty() {
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: patch is required"));
+    }
+    let files = crate::patch::parse(patch).map_err(|e| ToolError::new(ErrorKind::InvalidArguments, e))?;
+
+    let mut report = Vec::new();
+    let mut diffs = Vec::new();
+    let mut writes = Vec::new();
+    for file in &files {
+        let path = file.path();
+        if file.new_path.is_none() {
+            report.push(format!("{}: FAILED - deleting files isn't supported; use bash rm", path));
+            continue;
+        }
+        let content = match (&file.old_path, fs::read_to_string(path)) {
+            (_, Ok(content)) => content,
+            (None, Err(_)) => String::new(),
+            (Some(_), Err(e)) => {
+                report.push(format!("{}: FAILED - {}", path, e));
+                continue;
+            }
+        };
+
+        let (updated, results) = crate::patch::apply(&content, &file.hunks);
+        let updated = crate::editorconfig::conform(path, &content, updated);
+        report.push(format!("{}:", path));
+        for (i, result) in results.iter().enumerate() {
+            match result {
+                Ok(note) => report.push(format!("  hunk {}: {}", i + 1, note)),
+                Err(e) => report.push(format!("  hunk {}: FAILED - {}", i + 1, e)),
             }
         }
-        Err(e) => format!("Error reading file: {}", e),
+        if updated != content {
+            let action = if file.old_path.is_none() { "Created" } else { "Edited" };
+            diffs.push(format_diff_with_context(path, action, &content, &updated));
+            writes.push((path.to_string(), updated));
+        }
+    }
+
+    if writes.is_empty() {
+        return Err(ToolError::new(
+            ErrorKind::PatchFailed,
+            format!("Error: no hunks applied - nothing was written\n{}", report.join("\n")),
+        ));
+    }
+    Ok((format!("{}\n{}", report.join("\n"), diffs.join("···\n")), writes))
+}
+
+fn tool_apply_patch(args: &Value) -> ToolResult {
+    let (diff_text, writes) = preview_apply_patch(args)?;
+    for (path, content) in &writes {
+        check_syntax(path, content)?;
+    }
+    apply_writes(diff_text, &writes)
+}
+
+/// Write every previewed file, appending any failures to `diff_text`. Fails with the
+/// first failure's kind if any write did, so the model knows the change is partial.
+fn apply_writes(mut diff_text: String, writes: &[(String, String)]) -> ToolResult {
+    let mut failed = None;
+    for (path, content) in writes {
+        if let Err(e) = apply_write(path, content) {
+            diff_text.push_str(&format!("\n{} ({})", e.message, path));
+            failed.get_or_insert(e.kind);
+        }
     }
+    match failed {
+        Some(kind) => Err(ToolError::new(kind, diff_text)),
+        None => Ok(diff_text),
+    }
+}
+
+fn tool_edit_lines(args: &Value) -> ToolResult {
+    let path = args["path"].as_str().unwrap_or("");
+    let (diff_text, updated) = preview_edit_lines(args)?;
+    check_syntax(path, &updated)?;
+    crate::undo::write(path, &updated).map_err(|e| io_error("writing file", e))?;
+    Ok(diff_text)
 }
 
-fn tool_write_file(args: &Value) -> String {
+fn tool_write_file(args: &Value) -> ToolResult {
     let path = args["path"].as_str().unwrap_or("");
     let content = args["content"].as_str().unwrap_or("");
 
     if path.is_empty() {
-        return "Error: path is required".to_string();
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: path is required"));
     }
 
     // Read existing content for diff
     let old_content = fs::read_to_string(path).unwrap_or_default();
     let is_new_file = old_content.is_empty() && !Path::new(path).exists();
+    let content: &str = &crate::editorconfig::conform(path, &old_content, content.to_string());
+
+    check_syntax(path, content)?;
 
     // Create parent directories if needed
     if let Some(parent) = Path::new(path).parent() {
         if !parent.as_os_str().is_empty() {
-            if let Err(e) = fs::create_dir_all(parent) {
-                return format!("Error creating directories: {}", e);
-            }
+            fs::create_dir_all(parent).map_err(|e| io_error("creating directories", e))?;
         }
     }
 
-    match fs::write(path, content) {
-        Ok(_) => {
-            if is_new_file {
-                let mut output = format!("Created {}\n", path);
-                for line in content.lines() {
-                    output.push_str(&format!("+{}\n", line));
-                }
-                output
-            } else {
-                format_diff(path, &old_content, content)
-            }
+    crate::undo::write(path, content).map_err(|e| io_error("writing file", e))?;
+    if is_new_file {
+        let mut output = format!("Created {}\n", path);
+        for line in content.lines() {
+            output.push_str(&format!("+{}\n", line));
         }
-        Err(e) => format!("Error writing file: {}", e),
+        Ok(output)
+    } else {
+        Ok(format_diff(path, &old_content, content))
     }
 }
 
@@ -383,50 +1177,193 @@ fn format_diff(path: &str, old: &str, new: &str) -> String {
     output
 }
 
-fn tool_edit_file(args: &Value) -> String {
+fn tool_edit_file(args: &Value) -> ToolResult {
     let path = args["path"].as_str().unwrap_or("");
     let old = args["old"].as_str().unwrap_or("");
     let new = args["new"].as_str().unwrap_or("");
 
     if path.is_empty() {
-        return "Error: path is required".to_string();
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: path is required"));
     }
     if old.is_empty() {
-        return "Error: old text is required".to_string();
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: old text is required"));
     }
 
-    let content = match fs::read_to_string(path) {
-        Ok(c) => c,
-        Err(e) => return format!("Error reading file: {}", e),
-    };
+    let content = fs::read_to_string(path).map_err(|e| io_error("reading file", e))?;
+    let updated = edit_file_content(path, &content, args)?;
+    let updated = crate::editorconfig::conform(path, &content, updated);
+    check_syntax(path, &updated)?;
+    crate::undo::write(path, &updated).map_err(|e| io_error("writing file", e))?;
+
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let mut output = format!("Edited {}\n", path);
+    // Say which match was replaced, e.g. when the user picked it
+    if let Some(n) = args["occurrence"].as_u64() {
+        let locations = match_locations(&content, old);
+        if let Some((line, _)) = locations.get(n as usize - 1) {
+            output = format!("Edited {} at line {} (match {} of {})\n", path, line, n, locations.len());
+        }
+    }
+    for line in old_lines {
+        output.push_str(&format!("-{}\n", line));
+    }
+    for line in new_lines {
+        output.push_str(&format!("+{}\n", line));
+    }
+    if let Some(note) = mixed_endings_note(path, &content) {
+        output.push_str(note.trim_start());
+    }
+    Ok(output)
+}
 
-    let count = content.matches(old).count();
-    if count == 0 {
-        return format!("Error: text not found in {}", path);
+/// Most dry runs a session keeps waiting for confirmation; older ones must be run again
+const MAX_DRY_RUNS: usize = 8;
+
+/// replace_in_files calls a session has dry-run, as (pattern, replacement, glob, path, literal).
+/// A confirmed call must repeat one, and each dry run confirms once.
+#[derive(Debug, Clone, Default)]
+pub struct DryRuns(Vec<String>);
+
+impl DryRuns {
+    pub fn record(&mut self, args_str: &str) {
+        let signature = replace_signature(args_str);
+        self.0.retain(|s| *s != signature);
+        if self.0.len() == MAX_DRY_RUNS {
+            self.0.remove(0);
+        }
+        self.0.push(signature);
     }
-    if count > 1 {
-        return format!("Error: text appears {} times in {} - be more specific", count, path);
+
+    /// Whether a confirmed call repeats a recorded dry run, which it uses up
+    pub fn take(&mut self, args_str: &str) -> bool {
+        let signature = replace_signature(args_str);
+        let found = self.0.iter().position(|s| *s == signature);
+        found.map(|i| self.0.remove(i)).is_some()
     }
+}
 
-    let updated = content.replacen(old, new, 1);
-    match fs::write(path, &updated) {
-        Ok(_) => {
-            let old_lines: Vec<&str> = old.lines().collect();
-            let new_lines: Vec<&str> = new.lines().collect();
-            let mut output = format!("Edited {}\n", path);
-            for line in old_lines {
-                output.push_str(&format!("-{}\n", line));
-            }
-            for line in new_lines {
-                output.push_str(&format!("+{}\n", line));
-            }
-            output
+fn replace_signature(args_str: &str) -> String {
+    let args: Value = serde_json::from_str(args_str).unwrap_or(json!({}));
+    json!([args["pattern"], args["replacement"], args["glob"], args["path"], args["literal"]]).to_string()
+}
+
+/// Compute the replace_in_files changes without writing. Returns (diff_text, [(path, new_content)]).
+fn preview_replace_in_files(args: &Value) -> Result<(String, Vec<(String, String)>), ToolError> {
+    use ignore::WalkBuilder;
+
+    let pattern = args["pattern"].as_str().unwrap_or("");
+    let replacement = args["replacement"].as_str().unwrap_or("");
+    let file_glob = args["glob"].as_str().unwrap_or("");
+    let base_path = args["path"].as_str().unwrap_or(".");
+    let is_regex = !args["literal"].as_bool().unwrap_or(false);
+
+    if pattern.is_empty() {
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: pattern is required"));
+    }
+    if file_glob.is_empty() {
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: glob is required"));
+    }
+
+    let regex = if is_regex {
+        regex::Regex::new(pattern).map_err(|e| ToolError::new(ErrorKind::InvalidArguments, format!("Error: invalid regex: {}", e)))?
+    } else {
+        regex::Regex::new(&regex::escape(pattern)).map_err(|e| ToolError::new(ErrorKind::Internal, format!("Error: {}", e)))?
+    };
+    let matcher =
+        file_matcher(file_glob).map_err(|e| ToolError::new(ErrorKind::InvalidArguments, format!("Error: invalid glob: {}", e)))?;
+
+    let mut summary = Vec::new();
+    let mut diffs = Vec::new();
+    let mut writes = Vec::new();
+    let mut total = 0;
+
+    let walker = WalkBuilder::new(base_path)
+        .hidden(true)
+        .git_ignore(true)
+        .add_custom_ignore_filename(".vecoignore")
+        .build();
+
+    for entry in walker.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
         }
-        Err(e) => format!("Error writing file: {}", e),
+        let relative = path.strip_prefix(base_path).unwrap_or(path);
+        if !matcher.is_match(relative) {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(path) else { continue };
+
+        let count = regex.find_iter(&content).count();
+        if count == 0 {
+            continue;
+        }
+        let updated = if is_regex {
+            regex.replace_all(&content, replacement).into_owned()
+        } else {
+            regex.replace_all(&content, regex::NoExpand(replacement)).into_owned()
+        };
+        let path_str = crate::paths::display(path);
+        let updated = crate::editorconfig::conform(&path_str, &content, updated);
+        if updated == content {
+            continue;
+        }
+
+        total += count;
+        summary.push(format!("  {}: {} replacement{}", path_str, count, if count == 1 { "" } else { "s" }));
+        diffs.push(format_diff_with_context(&path_str, "Edited", &content, &updated));
+        writes.push((path_str, updated));
     }
+
+    if writes.is_empty() {
+        return Err(ToolError::new(ErrorKind::NotFound, format!("Error: '{}' not found in files matching {}", pattern, file_glob)));
+    }
+
+    let diff_text = format!(
+        "Replaced {} occurrence{} in {} file{}\n{}\n{}",
+        total,
+        if total == 1 { "" } else { "s" },
+        writes.len(),
+        if writes.len() == 1 { "" } else { "s" },
+        summary.join("\n"),
+        diffs.join("···\n"),
+    );
+
+    Ok((diff_text, writes))
 }
 
-fn tool_list_dir(args: &Value) -> String {
+fn tool_replace_in_files(args: &Value) -> ToolResult {
+    let (diff_text, writes) = preview_replace_in_files(args)?;
+    if args["confirm"].as_bool() != Some(true) {
+        return Ok(format!("Dry run, nothing written. {}\nCall again with the same arguments and confirm: true to apply.", diff_text));
+    }
+    for (path, content) in &writes {
+        check_syntax(path, content)?;
+    }
+    apply_writes(diff_text, &writes)
+}
+
+fn tool_repo_stats(args: &Value) -> ToolResult {
+    let path = args["path"].as_str().unwrap_or(".");
+    if !Path::new(path).is_dir() {
+        return Err(ToolError::new(ErrorKind::NotFound, format!("Error: {} is not a directory", path)));
+    }
+    Ok(crate::summary::repo_stats(path))
+}
+
+fn tool_list_symbols(args: &Value) -> ToolResult {
+    let path = args["path"].as_str().unwrap_or(".");
+    crate::symbols::list_symbols(path)
+}
+
+fn tool_find_definition(args: &Value) -> ToolResult {
+    let name = args["name"].as_str().unwrap_or("");
+    let path = args["path"].as_str().unwrap_or(".");
+    crate::symbols::find_definition(name, path)
+}
+
+fn tool_list_dir(args: &Value) -> ToolResult {
     let path = args["path"].as_str().unwrap_or(".");
 
     match fs::read_dir(path) {
@@ -443,31 +1380,33 @@ fn tool_list_dir(args: &Value) -> String {
                 })
                 .collect();
             items.sort();
-            items.join("\n")
+            Ok(items.join("\n"))
         }
-        Err(e) => format!("Error listing directory: {}", e),
+        Err(e) => Err(io_error("listing directory", e)),
     }
 }
 
-fn tool_search_files(args: &Value) -> String {
+fn tool_search_files(args: &Value) -> ToolResult {
     let pattern = args["pattern"].as_str().unwrap_or("");
     let base_path = args["path"].as_str().unwrap_or(".");
 
     if pattern.is_empty() {
-        return "Error: pattern is required".to_string();
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: pattern is required"));
     }
+    let matcher = file_matcher(pattern)
+        .map_err(|e| ToolError::new(ErrorKind::InvalidArguments, format!("Error: invalid glob pattern: {}", e)))?;
 
     let mut results = Vec::new();
-    search_recursive(Path::new(base_path), pattern, &mut results);
+    search_recursive(Path::new(base_path), Path::new(base_path), &matcher, &mut results);
 
     if results.is_empty() {
-        "No files found".to_string()
+        Ok("No files found".to_string())
     } else {
-        results.join("\n")
+        Ok(results.join("\n"))
     }
 }
 
-fn search_recursive(dir: &Path, pattern: &str, results: &mut Vec<String>) {
+fn search_recursive(root: &Path, dir: &Path, matcher: &globset::GlobMatcher, results: &mut Vec<String>) {
     let Ok(entries) = fs::read_dir(dir) else { return };
 
     for entry in entries.filter_map(|e| e.ok()) {
@@ -482,73 +1421,129 @@ fn search_recursive(dir: &Path, pattern: &str, results: &mut Vec<String>) {
         }
 
         if path.is_dir() {
-            search_recursive(&path, pattern, results);
-        } else if matches_pattern(&path, pattern) {
+            search_recursive(root, &path, matcher, results);
+        } else if matcher.is_match(path.strip_prefix(root).unwrap_or(&path)) {
             results.push(path.to_string_lossy().to_string());
         }
     }
 }
 
-fn matches_pattern(path: &Path, pattern: &str) -> bool {
-    let name = path.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
-    let path_str = path.to_string_lossy();
-
-    // Extract filename pattern (after last /)
-    let file_pattern = pattern.rsplit('/').next().unwrap_or(pattern);
-
-    // Check if pattern has directory component
-    let dir_pattern = if pattern.contains('/') {
-        Some(pattern.rsplitn(2, '/').nth(1).unwrap_or(""))
-    } else {
-        None
-    };
-
-    // Match directory part if specified (skip for ** which matches any)
-    if let Some(dir) = dir_pattern {
-        if !dir.is_empty() && dir != "**" && !dir.ends_with("**") {
-            // Check if path contains the directory
-            if !path_str.contains(&format!("{}/", dir)) && !path_str.starts_with(&format!("{}/", dir)) {
-                return false;
-            }
-        }
-    }
-
-    // Match filename
-    match_glob(name, file_pattern)
-}
-
-fn match_glob(name: &str, pattern: &str) -> bool {
-    glob::Pattern::new(pattern)
-        .map(|p| p.matches(name))
-        .unwrap_or(false)
+/// Compile a search_files pattern, matched against paths relative to the search root.
+/// A pattern without a slash matches file names at any depth.
+fn file_matcher(pattern: &str) -> Result<globset::GlobMatcher, globset::Error> {
+    let pattern = crate::paths::strip_dot(pattern);
+    let pattern = if pattern.contains('/') { pattern.to_string() } else { format!("**/{}", pattern) };
+    globset::GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .map(|glob| glob.compile_matcher())
 }
 
-fn tool_grep(args: &Value) -> String {
+fn tool_grep(args: &Value) -> ToolResult {
     let pattern = args["pattern"].as_str().unwrap_or("");
     let path = args["path"].as_str().unwrap_or(".");
     let context = args["context"].as_i64().unwrap_or(2) as usize;
 
     if pattern.is_empty() {
-        return "Error: pattern is required".to_string();
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: pattern is required"));
     }
 
-    let regex = match regex::Regex::new(pattern) {
-        Ok(r) => r,
-        Err(e) => return format!("Error: invalid regex: {}", e),
-    };
+    let regex = regex::Regex::new(pattern)
+        .map_err(|e| ToolError::new(ErrorKind::InvalidArguments, format!("Error: invalid regex: {}", e)))?;
 
-    let mut results = Vec::new();
-    grep_recursive(Path::new(path), &regex, context, &mut results);
+    let results = match grep_ripgrep(path, pattern, context) {
+        Some(results) => results,
+        None => {
+            let mut results = Vec::new();
+            grep_recursive(Path::new(path), &regex, context, &mut results);
+            results
+        }
+    };
 
     if results.is_empty() {
-        format!("grep '{}': no matches", pattern)
+        Ok(format!("grep '{}': no matches", pattern))
     } else {
-        format!("grep '{}':\n{}", pattern, results.join("\n"))
+        Ok(format!("grep '{}':\n{}", pattern, results.join("\n")))
     }
 }
 
+fn has_ripgrep() -> bool {
+    static AVAILABLE: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        Command::new("rg")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success())
+    })
+}
+
+/// Search with `rg --json` when it's installed. None means use the built-in search.
+fn grep_ripgrep(path: &str, pattern: &str, context: usize) -> Option<Vec<String>> {
+    if !has_ripgrep() {
+        return None;
+    }
+    let mut cmd = Command::new("rg");
+    cmd.args(["--json", "--context", &context.to_string()]);
+    let vecoignore = Path::new(path).join(".vecoignore");
+    if vecoignore.is_file() {
+        cmd.arg("--ignore-file").arg(&vecoignore);
+    }
+    cmd.args(["--regexp", pattern, "--", path]);
+    let output = cmd.output().ok()?;
+    // Exit code 1 means no matches; 2 is an error
+    if output.status.code() == Some(2) {
+        return None;
+    }
+    Some(parse_rg_json(&String::from_utf8_lossy(&output.stdout), context))
+}
+
+/// Turn rg's JSON events into the same lines grep_file produces, files sorted by path
+fn parse_rg_json(output: &str, context: usize) -> Vec<String> {
+    let mut files: Vec<(String, Vec<String>)> = Vec::new();
+    let mut last_line = 0;
+
+    for event in output.lines().filter_map(|l| serde_json::from_str::<Value>(l).ok()) {
+        let data = &event["data"];
+        match event["type"].as_str() {
+            Some("begin") => {
+                files.push((data["path"]["text"].as_str().unwrap_or("").to_string(), Vec::new()));
+                last_line = 0;
+            }
+            Some(kind @ ("match" | "context")) => {
+                // Non-UTF-8 lines come as base64 "bytes"; the built-in search skips those files too
+                let (Some(text), Some(number), Some((_, lines))) =
+                    (data["lines"]["text"].as_str(), data["line_number"].as_u64(), files.last_mut())
+                else {
+                    continue;
+                };
+                let number = number as usize;
+                if context > 0 && !lines.is_empty() && number != last_line + 1 {
+                    lines.push("--".to_string());
+                }
+                last_line = number;
+                let text = text.strip_suffix('\n').unwrap_or(text);
+                let text = text.strip_suffix('\r').unwrap_or(text);
+                let prefix = if kind == "match" { ":" } else { "-" };
+                lines.push(format!("{}:{}{}  {}", data["path"]["text"].as_str().unwrap_or(""), number, prefix, text));
+            }
+            Some("end") => {
+                if let Some((_, lines)) = files.last_mut()
+                    && context > 0
+                    && !lines.is_empty()
+                {
+                    lines.push("--".to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    files.into_iter().flat_map(|(_, lines)| lines).collect()
+}
+
 fn grep_recursive(path: &Path, regex: &regex::Regex, context: usize, results: &mut Vec<String>) {
     use ignore::WalkBuilder;
 
@@ -606,21 +1601,43 @@ fn grep_file(path: &Path, regex: &regex::Regex, context: usize, results: &mut Ve
     }
 }
 
-fn tool_bash(args: &Value) -> String {
-    execute_bash_with_paths(&serde_json::to_string(args).unwrap_or_default(), &[])
+fn tool_gh_fetch(args: &Value) -> ToolResult {
+    let reference = args["ref"].as_str().unwrap_or("");
+    match crate::github::parse_ref(reference) {
+        Some((repo, number)) => crate::github::fetch_issue(&repo, number).map_err(|e| ToolError::new(ErrorKind::Network, e)),
+        None => Err(ToolError::new(
+            ErrorKind::InvalidArguments,
+            format!("Error: '{}' is not an issue reference (expected owner/repo#123 or a URL)", reference),
+        )),
+    }
 }
 
-/// Execute bash command with additional allowed paths
-pub fn execute_bash_with_paths(args_str: &str, allowed_paths: &[String]) -> String {
+fn tool_scratch_dir() -> ToolResult {
+    match crate::scratch::get_or_create() {
+        Ok(dir) => Ok(format!("Scratch directory: {}", dir.display())),
+        Err(e) => Err(ToolError::new(ErrorKind::Io, e)),
+    }
+}
+
+fn tool_bash(args: &Value) -> ToolResult {
+    let allow_network = crate::sandbox::SandboxConfig::network_policy() == crate::sandbox::NetworkPolicy::Allow;
+    let cancel = AtomicBool::new(false);
+    execute_bash_with_paths(&serde_json::to_string(args).unwrap_or_default(), &[], allow_network, false, &cancel)
+}
+
+/// Execute bash command with additional allowed paths. Setting `cancel` kills the command.
+/// `quiet_default` applies when the call doesn't set `quiet` itself.
+pub fn execute_bash_with_paths(args_str: &str, allowed_paths: &[String], allow_network: bool, quiet_default: bool, cancel: &AtomicBool) -> ToolResult {
     let args: Value = serde_json::from_str(args_str).unwrap_or(json!({}));
     let command = args["command"].as_str().unwrap_or("");
     if command.is_empty() {
-        return "Error: command is required".to_string();
+        return Err(ToolError::new(ErrorKind::InvalidArguments, "Error: command is required"));
     }
+    let quiet = args["quiet"].as_bool().unwrap_or(quiet_default);
 
     let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
 
-    match run_sandboxed(command, &cwd, allowed_paths) {
+    match run_sandboxed(command, &cwd, allowed_paths, allow_network, cancel) {
         Ok(output) => {
             let stdout = String::from_utf8_lossy(&output.stdout);
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -634,15 +1651,88 @@ pub fn execute_bash_with_paths(args_str: &str, allowed_paths: &[String]) -> Stri
                 }
                 result.push_str(&stderr);
             }
-            if !output.status.success() {
+            if cancel.load(Ordering::SeqCst) {
+                result.push_str("\n[interrupted by user: process killed]");
+                return Err(ToolError::new(ErrorKind::Interrupted, result));
+            } else if !output.status.success() {
                 result.push_str(&format!("\n[exit code: {}]", output.status.code().unwrap_or(-1)));
+                return Err(ToolError::new(ErrorKind::CommandFailed, result));
+            } else if quiet && (!stdout.is_empty() || !stderr.is_empty()) {
+                return Ok(quiet_summary(command, &result));
             }
-            result
+            Ok(result)
         }
-        Err(e) => format!("$ {}\nError: {}", command, e),
+        Err(e) => Err(ToolError::new(ErrorKind::Internal, format!("$ {}\nError: {}", command, e))),
     }
 }
 
+/// A successful quiet command's result: the output is kept in the scratch directory
+/// rather than sent, so the model can still read it if it turns out to matter
+fn quiet_summary(command: &str, result: &str) -> String {
+    static SAVED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let lines = result.lines().count() - 1; // Not the "$ command" line
+    let saved = crate::scratch::get_or_create().ok().and_then(|dir| {
+        let path = dir.join(format!("bash-output-{}.log", SAVED.fetch_add(1, Ordering::SeqCst) + 1));
+        fs::write(&path, result).ok().map(|_| path)
+    });
+    let see = match saved {
+        Some(path) => format!("read_file {} to see them", path.display()),
+        None => "rerun with quiet false to see them".to_string(),
+    };
+    format!("$ {}\n[exit 0, {} line{} of output suppressed; {}]", command, lines, if lines == 1 { "" } else { "s" }, see)
+}
+
+/// Run a command to completion, collecting its output. If `cancel` is set while it
+/// runs, the command's whole process group is killed.
+pub fn output_cancellable(mut cmd: Command, cancel: &AtomicBool) -> std::io::Result<Output> {
+    cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    // Own process group, so children (npm, cargo, ...) die with the shell
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+
+    let mut child = cmd.spawn()?;
+    let mut stdout_pipe = child.stdout.take();
+    let mut stderr_pipe = child.stderr.take();
+    let stdout_thread = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(pipe) = stdout_pipe.as_mut() {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    });
+    let stderr_thread = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(pipe) = stderr_pipe.as_mut() {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    });
+
+    let mut killed = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if !killed && cancel.load(Ordering::SeqCst) {
+            #[cfg(unix)]
+            let _ = Command::new("kill")
+                .args(["-KILL", "--", &format!("-{}", child.id())])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+            let _ = child.kill();
+            killed = true;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout_thread.join().unwrap_or_default(),
+        stderr: stderr_thread.join().unwrap_or_default(),
+    })
+}
+
 /// Execute bash without sandbox (for debugging)
 #[allow(dead_code)]
 pub fn execute_bash_unsandboxed(args_str: &str) -> String {
@@ -668,15 +1758,21 @@ pub fn execute_bash_unsandboxed(args_str: &str) -> String {
     }
 }
 
-fn run_sandboxed(command: &str, cwd: &Path, allowed_paths: &[String]) -> std::io::Result<Output> {
+fn run_sandboxed(
+    command: &str,
+    cwd: &Path,
+    allowed_paths: &[String],
+    allow_network: bool,
+    cancel: &AtomicBool,
+) -> std::io::Result<Output> {
     #[cfg(target_os = "macos")]
-    return run_sandbox_macos(command, cwd, allowed_paths);
+    return run_sandbox_macos(command, cwd, allowed_paths, allow_network, cancel);
 
     #[cfg(target_os = "linux")]
-    return run_sandbox_linux(command, cwd, allowed_paths);
+    return run_sandbox_linux(command, cwd, allowed_paths, allow_network, cancel);
 
     #[cfg(target_os = "windows")]
-    return run_sandbox_windows(command, cwd, allowed_paths);
+    return run_sandbox_windows(command, cwd, allowed_paths, allow_network, cancel);
 
     #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
     return Err(std::io::Error::new(
@@ -686,7 +1782,13 @@ fn run_sandboxed(command: &str, cwd: &Path, allowed_paths: &[String]) -> std::io
 }
 
 #[cfg(target_os = "macos")]
-fn run_sandbox_macos(command: &str, cwd: &Path, allowed_paths: &[String]) -> std::io::Result<Output> {
+fn run_sandbox_macos(
+    command: &str,
+    cwd: &Path,
+    allowed_paths: &[String],
+    allow_network: bool,
+    cancel: &AtomicBool,
+) -> std::io::Result<Output> {
     let cwd_str = cwd.to_string_lossy();
 
     // Build extra write rules for allowed paths
@@ -696,6 +1798,13 @@ fn run_sandbox_macos(command: &str, cwd: &Path, allowed_paths: &[String]) -> std
         .collect::<Vec<_>>()
         .join("\n");
 
+    // Local sockets only unless network is allowed
+    let network_rule = if allow_network {
+        "(allow network*)"
+    } else {
+        "(allow network* (local unix))"
+    };
+
     // Sandbox profile:
     // - Allow all reads (tools need access to many system paths)
     // - Restrict writes to: cwd, /tmp, and explicitly allowed paths
@@ -714,18 +1823,23 @@ fn run_sandbox_macos(command: &str, cwd: &Path, allowed_paths: &[String]) -> std
 (allow sysctl-read)
 (allow mach-lookup)
 (allow signal)
-(allow network*)"#,
-        cwd_str, extra_write_rules
+{}"#,
+        cwd_str, extra_write_rules, network_rule
     );
 
-    Command::new("sandbox-exec")
-        .args(["-p", &profile, "bash", "-c", command])
-        .current_dir(cwd)
-        .output()
+    let mut cmd = Command::new("sandbox-exec");
+    cmd.args(["-p", &profile, "bash", "-c", command]).current_dir(cwd);
+    output_cancellable(cmd, cancel)
 }
 
 #[cfg(target_os = "linux")]
-fn run_sandbox_linux(command: &str, cwd: &Path, allowed_paths: &[String]) -> std::io::Result<Output> {
+fn run_sandbox_linux(
+    command: &str,
+    cwd: &Path,
+    allowed_paths: &[String],
+    allow_network: bool,
+    cancel: &AtomicBool,
+) -> std::io::Result<Output> {
     // Try bwrap (bubblewrap) first, fall back to basic execution with warning
     let cwd_str = cwd.to_string_lossy();
 
@@ -756,29 +1870,36 @@ fn run_sandbox_linux(command: &str, cwd: &Path, allowed_paths: &[String]) -> std
             "--bind", &cwd_str, &cwd_str,
             "--chdir", &cwd_str,
             "--unshare-all",
-            "--share-net",
-            "--die-with-parent",
-            "bash", "-c", command,
         ]);
-        cmd.output()
+        if allow_network {
+            cmd.arg("--share-net");
+        }
+        cmd.args(["--die-with-parent", "bash", "-c", command]);
+        output_cancellable(cmd, cancel)
     } else {
         // Fallback: run without sandbox but restricted to cwd
         // This is less secure but allows basic functionality
-        Command::new("bash")
-            .args(["-c", command])
-            .current_dir(cwd)
-            .output()
+        crate::sandbox::note_fallback();
+        let mut cmd = Command::new("bash");
+        cmd.args(["-c", command]).current_dir(cwd);
+        let mut output = output_cancellable(cmd, cancel)?;
+        if !allow_network {
+            let banner = "[WARNING: bwrap not found; network access could not be blocked]\n";
+            output.stderr.splice(0..0, banner.bytes());
+        }
+        Ok(output)
     }
 }
 
 #[cfg(target_os = "windows")]
-fn run_sandbox_windows(command: &str, cwd: &Path, _allowed_paths: &[String]) -> std::io::Result<Output> {
-    // Windows sandboxing is complex; for now, just run in cwd
-    // Future: could use Windows Sandbox API or AppContainer
-    Command::new("cmd")
-        .args(["/C", command])
-        .current_dir(cwd)
-        .output()
+fn run_sandbox_windows(
+    command: &str,
+    cwd: &Path,
+    allowed_paths: &[String],
+    allow_network: bool,
+    cancel: &AtomicBool,
+) -> std::io::Result<Output> {
+    crate::sandbox::windows::run(command, cwd, allowed_paths, allow_network, cancel)
 }
 
 // Coach tools
@@ -790,20 +1911,20 @@ fn projects_path() -> std::path::PathBuf {
         .join("projects.md")
 }
 
-fn tool_view_projects(_args: &Value) -> String {
+fn tool_view_projects(_args: &Value) -> ToolResult {
     let path = projects_path();
 
     match fs::read_to_string(&path) {
-        Ok(content) => content,
+        Ok(content) => Ok(content),
         Err(_) => {
             let default = "# Projects\n\n## Active\n\n## Completed\n\n## Ideas\n";
             let _ = fs::write(&path, default);
-            default.to_string()
+            Ok(default.to_string())
         }
     }
 }
 
-fn tool_update_projects(args: &Value) -> String {
+fn tool_update_projects(args: &Value) -> ToolResult {
     let content = args["content"].as_str().unwrap_or("");
     let path = projects_path();
 
@@ -812,7 +1933,251 @@ fn tool_update_projects(args: &Value) -> String {
     }
 
     match fs::write(&path, content) {
-        Ok(_) => "Projects updated successfully".to_string(),
-        Err(e) => format!("Error updating projects: {}", e),
+        Ok(_) => Ok("Projects updated successfully".to_string()),
+        Err(e) => Err(io_error("updating projects", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hal-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_search_files_patterns() {
+        let dir = temp_dir("search");
+        for file in ["src/a/x.rs", "src/b/y.rs", "src/c/z.rs", "tests/test_1_io.py", "tests/deep/test_2.py", "test_x.py"] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let search = |pattern: &str| {
+            let result = tool_search_files(&json!({ "pattern": pattern, "path": dir.to_str().unwrap() })).unwrap();
+            let mut found: Vec<String> = result
+                .lines()
+                .filter_map(|line| Path::new(line).strip_prefix(&dir).ok())
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+                .collect();
+            found.sort();
+            found
+        };
+
+        assert_eq!(search("src/{a,b}/*.rs"), ["src/a/x.rs", "src/b/y.rs"]);
+        assert_eq!(search("**/test_[0-9]*.py"), ["tests/deep/test_2.py", "tests/test_1_io.py"]);
+        assert_eq!(search("*.rs").len(), 3);
+        // * stays within one directory
+        assert!(search("src/*.rs").is_empty());
+        let invalid = tool_search_files(&json!({ "pattern": "src/{a", "path": dir.to_str().unwrap() })).unwrap_err();
+        assert_eq!(invalid.kind, ErrorKind::InvalidArguments);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_result_for_model() {
+        assert_eq!(result_for_model("Read 3 lines", None), "Read 3 lines");
+        let message = "Error: text appears 2 times in a.rs - be more specific";
+        let error: Value = serde_json::from_str(&result_for_model(message, Some(ErrorKind::AmbiguousMatch))).unwrap();
+        assert_eq!(error["error_type"], "ambiguous_match");
+        assert_eq!(error["message"], "text appears 2 times in a.rs - be more specific");
+        assert!(ErrorKind::Rejected.declined() && !ErrorKind::CommandFailed.declined());
+
+        // The kind comes from the tool, whatever its message says
+        assert_eq!(tool_read_file(&json!({ "path": "" })).unwrap_err().kind, ErrorKind::InvalidArguments);
+        assert_eq!(tool_read_file(&json!({ "path": "no/such/file" })).unwrap_err().kind, ErrorKind::NotFound);
+        assert_eq!(tool_repo_stats(&json!({ "path": "no/such/dir" })).unwrap_err().kind, ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_limit_output() {
+        let output: String = (1..=100).map(|i| format!("line {:03}\n", i)).collect();
+        assert_eq!(limit_output("bash", &output, 0), output);
+        let limited = limit_output("bash", &output, 200);
+        assert!(limited.starts_with("line 001\n"));
+        assert!(limited.trim_end().ends_with("line 100"));
+        assert!(limited.contains("lines omitted") && limited.contains("limited to 200 characters"));
+        assert!(limited.len() < 500);
+
+        let minified = "x".repeat(1000);
+        let limited = limit_output("read_file", &minified, 100);
+        assert!(limited.starts_with(&"x".repeat(50)) && limited.contains("start_line and end_line"));
+    }
+
+    #[test]
+    fn test_compact_definitions() {
+        let defs = get_tool_definitions(&Mode::Coding);
+        let compact = compact_definitions(&defs);
+        assert_eq!(compact.len(), defs.len());
+        assert!(compact.iter().map(|d| d.to_string().len()).sum::<usize>() < defs.iter().map(|d| d.to_string().len()).sum());
+
+        let read = &compact[0]["function"];
+        assert_eq!(read["name"], "read_file");
+        assert_eq!(read["description"], "Read the contents of a file");
+        assert_eq!(read["parameters"]["properties"]["start_line"]["description"], "Starting line number, 1-indexed");
+        assert_eq!(read["parameters"]["required"], json!(["path"]));
+    }
+
+    #[test]
+    fn test_edit_lines_checks_hash() {
+        let dir = temp_dir("edit-lines");
+        let path = dir.join("a.txt");
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let path_str = path.to_string_lossy().to_string();
+
+        let stale = json!({ "path": path_str, "start_line": 2, "end_line": 2, "content": "TWO", "hash": "00000000" });
+        assert_eq!(preview_edit_lines(&stale).unwrap_err().kind, ErrorKind::Stale);
+
+        let args = json!({ "path": path_str, "start_line": 2, "end_line": 2, "content": "TWO", "hash": hash_lines(&["two"]) });
+        let (_, updated) = preview_edit_lines(&args).unwrap();
+        assert_eq!(updated, "one\nTWO\nthree\n");
+
+        let insert = json!({ "path": path_str, "operation": "insert", "start_line": 4, "content": "four" });
+        let (_, updated) = preview_edit_lines(&insert).unwrap();
+        assert_eq!(updated, "one\ntwo\nthree\nfour\n");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_cancellable_kills_process_group() {
+        let cancel = std::sync::Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            flag.store(true, Ordering::SeqCst);
+        });
+        let start = std::time::Instant::now();
+        let mut cmd = Command::new("bash");
+        cmd.args(["-c", "sleep 30 & sleep 30; echo done"]);
+        let output = output_cancellable(cmd, &cancel).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_parse_rg_json_matches_builtin_format() {
+        let dir = temp_dir("rg-json");
+        let path = dir.join("a.rs");
+        fs::write(&path, "fn a() {\n    foo();\n}\n\n\n\nfn b() {\n    foo();\n}\n").unwrap();
+        let p = path.to_string_lossy();
+
+        let event = |kind: &str, line: usize, text: &str| {
+            json!({ "type": kind, "data": {
+                "path": { "text": p }, "lines": { "text": format!("{}\n", text) }, "line_number": line
+            }})
+            .to_string()
+        };
+        let output = [
+            json!({ "type": "begin", "data": { "path": { "text": p } } }).to_string(),
+            event("context", 1, "fn a() {"),
+            event("match", 2, "    foo();"),
+            event("context", 3, "}"),
+            event("context", 7, "fn b() {"),
+            event("match", 8, "    foo();"),
+            event("context", 9, "}"),
+            json!({ "type": "end", "data": { "path": { "text": p } } }).to_string(),
+        ]
+        .join("\n");
+
+        let mut builtin = Vec::new();
+        grep_file(&path, &regex::Regex::new("foo").unwrap(), 1, &mut builtin);
+        assert_eq!(parse_rg_json(&output, 1), builtin);
+    }
+
+    #[test]
+    fn test_multi_edit_is_all_or_nothing() {
+        let dir = temp_dir("multi-edit");
+        let path = dir.join("a.txt");
+        fs::write(&path, "alpha\nbeta\ngamma\n").unwrap();
+        let p = path.to_string_lossy();
+
+        let result = tool_multi_edit(&json!({ "path": p, "edits": [
+            { "old": "alpha", "new": "ALPHA" },
+            { "old": "delta", "new": "DELTA" }
+        ]}));
+        let error = result.unwrap_err();
+        assert!(error.message.starts_with("Error: edit 2"), "{}", error.message);
+        assert_eq!(error.kind, ErrorKind::NotFound);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "alpha\nbeta\ngamma\n");
+
+        let result = tool_multi_edit(&json!({ "path": p, "edits": [
+            { "old": "alpha", "new": "ALPHA" },
+            { "old": "ALPHA\nbeta", "new": "ALPHA\nBETA" }
+        ]}));
+        assert!(result.unwrap().starts_with("Edited"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ALPHA\nBETA\ngamma\n");
+    }
+
+    #[test]
+    fn test_replace_unique_keeps_crlf() {
+        let content = "fn a() {\r\n    1\r\n}\r\n";
+        assert_eq!(replace_unique(content, "{\n    1\n}", "{\n    2\n    3\n}").unwrap(), "fn a() {\r\n    2\r\n    3\r\n}\r\n");
+        assert_eq!(replace_unique(content, "    4", "x"), Err(0));
+        assert_eq!(replace_occurrence("a\nb\na\n", "a", "c", Some(2)).unwrap(), "a\nb\nc\n");
+        assert_eq!(replace_occurrence("a\nb\na\n", "a", "c", Some(3)), Err(2));
+        assert_eq!(match_locations("x = 1\ny = 2\nx = 1\n", "= 1"), [(1, "x = 1".to_string()), (3, "x = 1".to_string())]);
+        assert!(mixed_endings_note("a.rs", content).is_none());
+        assert!(mixed_endings_note("a.rs", "a\r\nb\n").unwrap().contains("1 CRLF, 1 LF"));
+    }
+
+    #[test]
+    fn test_check_syntax() {
+        let dir = temp_dir("check-syntax");
+        let json = dir.join("a.json").to_string_lossy().to_string();
+        assert!(check_syntax(&json, "{\"a\": 1}").is_ok());
+        assert!(check_syntax(&json, "{\"a\": }").is_err());
+        assert!(check_syntax(&dir.join("b.rs").to_string_lossy(), "fn main() {").is_err());
+        assert!(check_syntax(&dir.join("c.txt").to_string_lossy(), "{").is_ok());
+
+        // Already-broken files can still be edited
+        fs::write(&json, "{").unwrap();
+        assert!(check_syntax(&json, "{\"a\"").is_ok());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_replace_in_files_dry_run() {
+        let dir = temp_dir("replace-in-files");
+        fs::write(dir.join("a.rs"), "let foo = foo();\n").unwrap();
+        fs::write(dir.join("b.txt"), "foo\n").unwrap();
+
+        let args = json!({
+            "pattern": "foo",
+            "replacement": "$bar",
+            "glob": "*.rs",
+            "path": dir.to_string_lossy(),
+            "literal": true,
+        });
+        let (diff, writes) = preview_replace_in_files(&args).unwrap();
+
+        assert!(diff.starts_with("Replaced 2 occurrences in 1 file"));
+        assert_eq!(writes.len(), 1);
+        assert_eq!(writes[0].1, "let $bar = $bar();\n");
+
+        assert!(tool_replace_in_files(&args).unwrap().starts_with("Dry run, nothing written."));
+        assert_eq!(fs::read_to_string(dir.join("a.rs")).unwrap(), "let foo = foo();\n");
+        let _ = fs::remove_dir_all(&dir);
+
+        // A confirmed call needs a matching dry run, and uses it up
+        let mut confirmed = args.clone();
+        confirmed["confirm"] = json!(true);
+        let mut dry_runs = DryRuns::default();
+        assert!(!dry_runs.take(&confirmed.to_string()));
+        dry_runs.record(&args.to_string());
+        assert!(dry_runs.take(&confirmed.to_string()));
+        assert!(!dry_runs.take(&confirmed.to_string()));
+
+        // Only the latest dry runs are kept
+        for i in 0..=MAX_DRY_RUNS {
+            dry_runs.record(&json!({ "pattern": i }).to_string());
+        }
+        assert!(!dry_runs.take(&json!({ "pattern": 0 }).to_string()));
+        assert!(dry_runs.take(&json!({ "pattern": MAX_DRY_RUNS }).to_string()));
     }
 }
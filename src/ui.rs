@@ -1,4 +1,4 @@
-use crate::app::{App, AppState, DiffModal, MessageRole, PermissionModal, ProviderModal, PickerMode, MAX_PICKER_ITEMS};
+use crate::app::{App, AppState, ContinueModal, DiffModal, HistorySearch, MatchModal, PermissionKind, VersionsModal, PermissionModal, ProviderModal, PickerMode, VimMode, MAX_PICKER_ITEMS};
 use ratatui::{
     layout::{Constraint, Layout, Position, Rect},
     style::{Color, Modifier, Style},
@@ -6,6 +6,8 @@ use ratatui::{
     widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
     Frame,
 };
+use crate::render::{self, TranscriptRenderer};
+use crate::tools::ErrorKind;
 use std::sync::OnceLock;
 use syntect::highlighting::ThemeSet;
 use syntect::parsing::SyntaxSet;
@@ -14,22 +16,23 @@ use syntect::easy::HighlightLines;
 static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
 static THEME_SET: OnceLock<ThemeSet> = OnceLock::new();
 
-fn get_syntax_set() -> &'static SyntaxSet {
+pub fn get_syntax_set() -> &'static SyntaxSet {
     SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
 }
 
-fn get_theme_set() -> &'static ThemeSet {
+pub fn get_theme_set() -> &'static ThemeSet {
     THEME_SET.get_or_init(ThemeSet::load_defaults)
 }
 
-/// Highlight a diff line with syntax coloring and diff background
-fn highlight_diff_line(line: &str, path: Option<&str>) -> Vec<Span<'static>> {
-    let (bg_color, code_content) = if line.starts_with('+') {
-        (Some(Color::Rgb(30, 50, 30)), &line[1..]) // Dark green bg
-    } else if line.starts_with('-') {
-        (Some(Color::Rgb(50, 30, 30)), &line[1..]) // Dark red bg
+/// Highlight a diff line with syntax coloring and diff background. `emphasis` holds
+/// byte ranges of the code (after the +/- marker) that changed within the line.
+fn highlight_diff_line(line: &str, path: Option<&str>, emphasis: &[std::ops::Range<usize>]) -> Vec<Span<'static>> {
+    let (bg_color, emphasis_bg, code_content) = if let Some(code) = line.strip_prefix('+') {
+        (Some(Color::Rgb(30, 50, 30)), Color::Rgb(40, 95, 40), code) // Dark green bg, brighter for changed words
+    } else if let Some(code) = line.strip_prefix('-') {
+        (Some(Color::Rgb(50, 30, 30)), Color::Rgb(105, 40, 40), code) // Dark red bg
     } else {
-        (None, line)
+        (None, Color::Reset, line)
     };
 
     let prefix = if line.starts_with('+') || line.starts_with('-') {
@@ -65,22 +68,12 @@ fn highlight_diff_line(line: &str, path: Option<&str>) -> Vec<Span<'static>> {
         spans.push(Span::styled(prefix.to_string(), style));
     }
 
-    // Highlight the code content
-    match highlighter.highlight_line(code_content, ss) {
-        Ok(highlighted) => {
-            for (syntect_style, text) in highlighted {
-                let fg = Color::Rgb(
-                    syntect_style.foreground.r,
-                    syntect_style.foreground.g,
-                    syntect_style.foreground.b,
-                );
-                let mut style = Style::default().fg(fg);
-                if let Some(bg) = bg_color {
-                    style = style.bg(bg);
-                }
-                spans.push(Span::styled(text.to_string(), style));
-            }
-        }
+    // Highlight the code content, splitting pieces where emphasis starts or ends
+    let pieces: Vec<(Color, &str)> = match highlighter.highlight_line(code_content, ss) {
+        Ok(highlighted) => highlighted
+            .into_iter()
+            .map(|(style, text)| (Color::Rgb(style.foreground.r, style.foreground.g, style.foreground.b), text))
+            .collect(),
         Err(_) => {
             // Fallback: no syntax highlighting
             let fg = if line.starts_with('+') {
@@ -90,18 +83,42 @@ fn highlight_diff_line(line: &str, path: Option<&str>) -> Vec<Span<'static>> {
             } else {
                 Color::Gray
             };
+            vec![(fg, code_content)]
+        }
+    };
+    let mut offset = 0;
+    for (fg, text) in pieces {
+        let end = offset + text.len();
+        let mut cuts: Vec<usize> = emphasis
+            .iter()
+            .flat_map(|r| [r.start, r.end])
+            .filter(|&cut| cut > offset && cut < end && text.is_char_boundary(cut - offset))
+            .collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+        let mut start = offset;
+        for cut in cuts.into_iter().chain([end]) {
+            let emphasized = emphasis.iter().any(|r| r.start <= start && start < r.end);
             let mut style = Style::default().fg(fg);
-            if let Some(bg) = bg_color {
+            if emphasized {
+                style = style.bg(emphasis_bg).add_modifier(Modifier::BOLD);
+            } else if let Some(bg) = bg_color {
                 style = style.bg(bg);
             }
-            spans.push(Span::styled(code_content.to_string(), style));
+            spans.push(Span::styled(text[start - offset..cut - offset].to_string(), style));
+            start = cut;
         }
+        offset = end;
     }
 
     spans
 }
 
-pub fn draw(frame: &mut Frame, app: &mut App) {
+/// How long a request phase lasts before the thinking line names it
+const PROGRESS_AFTER: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// `busy` has one entry per open tab; `active` is the index of the tab being drawn.
+pub fn draw(frame: &mut Frame, app: &mut App, busy: &[bool], active: usize) {
     // Calculate dynamic input height based on content (use char count, not byte length)
     // Account for horizontal padding (2 chars) in width calculation
     let input_char_count = app.input.chars().count();
@@ -111,17 +128,27 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
     let chunks = Layout::vertical([
         Constraint::Length(1), // Header
         Constraint::Min(1),    // Chat
+        Constraint::Length(plan_height(app)), // Plan (while the model has one open)
         Constraint::Length(input_height), // Input (dynamic)
+        Constraint::Length(app.config.status_bar as u16), // Status bar
     ])
     .split(frame.area());
 
-    draw_header(frame, app, chunks[0]);
+    draw_header(frame, app, busy, active, chunks[0]);
     draw_chat(frame, app, chunks[1]);
-    draw_input(frame, app, chunks[2]);
+    draw_plan(frame, app, chunks[2]);
+    draw_input(frame, app, chunks[3]);
+    if app.config.status_bar {
+        let estimate = app.input_estimate().cloned();
+        draw_status_bar(frame, app, estimate.as_ref(), chunks[4]);
+    }
 
     // Draw picker popup if active
     if app.picker_active() && !app.picker_results.is_empty() {
-        draw_picker(frame, app, chunks[2]);
+        draw_picker(frame, app, chunks[3]);
+    }
+    if let Some(search) = &app.history_search {
+        draw_history_search(frame, search, chunks[3]);
     }
 
     // Draw diff modal if active
@@ -134,29 +161,106 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
         draw_permission_modal(frame, modal);
     }
 
+    // Draw tool-limit modal if active
+    if let Some(modal) = &app.continue_modal {
+        draw_continue_modal(frame, modal);
+    }
+
+    // Draw ambiguous-edit modal if active
+    if let Some(modal) = &app.match_modal {
+        draw_match_modal(frame, modal);
+    }
+    if let Some(modal) = &app.versions_modal {
+        draw_versions_modal(frame, modal);
+    }
+
     // Draw provider modal if active
     if let Some(modal) = &app.provider_modal {
-        draw_provider_modal(frame, modal, &app.config.default_provider, &app.config.providers);
+        draw_provider_modal(frame, modal, &app.provider_name, &app.config.providers);
     }
 }
 
-fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
-    let mode = match app.config.mode {
-        crate::config::Mode::Coding => "coding",
-        crate::config::Mode::Coach => "coach",
-    };
+/// Plan items shown at once; longer plans scroll to keep the current step in view
+const MAX_PLAN_ITEMS: usize = 8;
 
-    let left = Line::from(vec![
+/// The plan stays up while a step is left or the turn that finished it is still running
+fn plan_height(app: &App) -> u16 {
+    let todos = app.todos();
+    if todos.is_empty() || (!crate::todo::is_open(todos) && app.state == AppState::Idle) {
+        return 0;
+    }
+    todos.len().min(MAX_PLAN_ITEMS) as u16 + 1
+}
+
+fn draw_plan(frame: &mut Frame, app: &App, area: Rect) {
+    if area.height == 0 {
+        return;
+    }
+    let g = crate::glyphs::get();
+    let todos = app.todos();
+    let (done, total) = crate::todo::progress(todos);
+    let mut lines = vec![Line::from(vec![
+        Span::styled(" Plan ", Style::default().fg(Color::Magenta).bold()),
+        Span::styled(format!("{}/{} done", done, total), Style::default().fg(Color::Gray)),
+    ])];
+
+    let current = todos.iter().position(|t| t.status != crate::todo::Status::Done).unwrap_or(0);
+    let start = current.saturating_sub(1).min(todos.len().saturating_sub(MAX_PLAN_ITEMS));
+    for todo in todos.iter().skip(start).take(MAX_PLAN_ITEMS) {
+        let (mark, style) = match todo.status {
+            crate::todo::Status::Pending => (g.pending, Style::default()),
+            crate::todo::Status::InProgress => (g.in_progress, Style::default().fg(Color::Cyan).bold()),
+            crate::todo::Status::Done => (g.done, Style::default().fg(Color::DarkGray).add_modifier(Modifier::CROSSED_OUT)),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("   {} ", mark), style.remove_modifier(Modifier::CROSSED_OUT)),
+            Span::styled(todo.content.clone(), style),
+        ]));
+    }
+    frame.render_widget(Paragraph::new(lines), area);
+}
+
+fn draw_header(frame: &mut Frame, app: &App, busy: &[bool], active: usize, area: Rect) {
+    let mode = crate::app::mode_name(app.config.mode);
+    let g = crate::glyphs::get();
+
+    let mut left_spans = vec![
         Span::styled(format!(" hal {}", env!("CARGO_PKG_VERSION")), Style::default().fg(Color::Magenta).bold()),
-        Span::styled(" · ", Style::default().fg(Color::Gray)),
-        Span::styled(&app.config.default_provider, Style::default().fg(Color::Cyan)),
+        Span::styled(format!(" {} ", g.separator), Style::default().fg(Color::Gray)),
+        Span::styled(app.provider_name.as_str(), Style::default().fg(Color::Cyan)),
         Span::styled(format!(" [{}]", mode), Style::default().fg(Color::Gray)),
-    ]);
+    ];
+    let mut badge_len = 0;
+    if app.config.skip_permissions {
+        left_spans.push(Span::raw(" "));
+        left_spans.push(Span::styled(" YOLO ", Style::default().fg(Color::White).bg(Color::Red).bold()));
+        badge_len = 7;
+    }
 
-    let right = if let Some((prompt, completion)) = app.token_usage {
-        format!("{} in / {} out ", prompt, completion)
-    } else {
-        String::new()
+    // Tab bar: active tab highlighted, busy background tabs marked with a bullet
+    let mut tabs_len = 0;
+    if busy.len() > 1 {
+        left_spans.push(Span::styled(format!(" {}", g.separator), Style::default().fg(Color::Gray)));
+        for (i, is_busy) in busy.iter().enumerate() {
+            let label = format!(" {}{}", i + 1, if *is_busy && i != active { g.bullet } else { "" });
+            tabs_len += label.chars().count();
+            let style = if i == active {
+                Style::default().fg(Color::Magenta).bold()
+            } else {
+                Style::default().fg(Color::Gray)
+            };
+            left_spans.push(Span::styled(label, style));
+        }
+        tabs_len += 2;
+    }
+    let left = Line::from(left_spans);
+
+    let right = match (app.token_usage, app.cache_usage) {
+        (Some((prompt, completion)), Some((read, write))) => {
+            format!("{} in / {} out {} cache {} read / {} write ", prompt, completion, g.separator, read, write)
+        }
+        (Some((prompt, completion)), None) => format!("{} in / {} out ", prompt, completion),
+        _ => String::new(),
     };
 
     // Get working directory for center
@@ -165,13 +269,14 @@ fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
         .unwrap_or_default();
 
     let version = env!("CARGO_PKG_VERSION");
-    let left_len = 4 + 1 + version.len() + 3 + app.config.default_provider.len() + 2 + mode.len() + 3; // approximate + padding
+    let left_len = 4 + 1 + version.len() + 3 + app.provider_name.len() + 2 + mode.len() + 3 + tabs_len + badge_len; // approximate + padding
     let right_len = right.len();
     let available = (area.width as usize).saturating_sub(left_len + right_len + 2);
 
     // Truncate from left if too long
     let cwd = if cwd_full.len() > available && available > 3 {
-        format!("…{}", &cwd_full[cwd_full.len().saturating_sub(available - 1)..])
+        let keep = available.saturating_sub(g.ellipsis.chars().count());
+        format!("{}{}", g.ellipsis, &cwd_full[cwd_full.len().saturating_sub(keep)..])
     } else {
         cwd_full
     };
@@ -200,8 +305,93 @@ fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+/// Key hints for what the user is doing now, with sandbox state and pending approvals on the right
+fn draw_status_bar(frame: &mut Frame, app: &App, estimate: Option<&crate::usage::Estimate>, area: Rect) {
+    let g = crate::glyphs::get();
+    let hints: &[&str] = if app.diff_modal.is_some() {
+        &["Enter confirm", "Left/Right accept or reject", "Up/Down scroll", "Esc reject"]
+    } else if app.versions_modal.is_some() {
+        &["Left/Right step versions", "Up/Down scroll", "Enter restore", "Esc close"]
+    } else if app.has_modal() {
+        &["Enter confirm", "Up/Down choose", "Esc cancel"]
+    } else if app.history_search.is_some() {
+        &["Enter use", "Ctrl+R older", "Ctrl+N newer", "Esc close"]
+    } else if app.copy_mode.is_some() {
+        &["Esc leave copy mode"]
+    } else if app.state != AppState::Idle {
+        &["Esc abort", "Ctrl+T new tab", "Ctrl+C quit"]
+    } else if app.postmortem.is_some() {
+        &["Ctrl+B save diagnostics", "Ctrl+R history", "/help commands"]
+    } else {
+        &["Enter send", "@ attach file", "Ctrl+R history", "Ctrl+Y copy", "/help commands"]
+    };
+    let hint_style = Style::default().fg(Color::DarkGray);
+    let mut left = vec![Span::raw(" ")];
+    for (i, hint) in hints.iter().enumerate() {
+        if i > 0 {
+            left.push(Span::styled(format!(" {} ", g.separator), hint_style));
+        }
+        left.push(Span::styled(*hint, hint_style));
+    }
+
+    let mut right = Vec::new();
+    if app.diff_modal.is_some() || app.permission_modal.is_some() || app.match_modal.is_some() {
+        let label = match app.queued_tool_calls().saturating_sub(1) {
+            0 => "1 approval pending".to_string(),
+            n => format!("1 approval pending, {} more call{} queued", n, if n == 1 { "" } else { "s" }),
+        };
+        right.push(Span::styled(label, Style::default().fg(Color::Yellow)));
+        right.push(Span::styled(format!(" {} ", g.separator), hint_style));
+    }
+    if app.shell_attachments() > 0 {
+        let n = app.shell_attachments();
+        right.push(Span::styled(format!("{} command output{} attached", n, if n == 1 { "" } else { "s" }), Style::default().fg(Color::Cyan)));
+        right.push(Span::styled(format!(" {} ", g.separator), hint_style));
+    }
+    if let Some(estimate) = estimate.filter(|_| app.state == AppState::Idle && !app.has_modal()) {
+        let threshold = app.config.attachment_warn_tokens;
+        let largest = estimate.attachments.iter().max_by_key(|(_, tokens)| *tokens).filter(|(_, tokens)| threshold > 0 && *tokens > threshold);
+        if let Some((reference, tokens)) = largest {
+            let warning = format!("{} @{} is ~{} tokens", g.warning, reference, crate::usage::short(*tokens));
+            right.push(Span::styled(warning, Style::default().fg(Color::Yellow)));
+            right.push(Span::styled(format!(" {} ", g.separator), hint_style));
+        }
+        let mut label = format!("~{} tokens", crate::usage::short(estimate.tokens));
+        if let Some(price) = app.config.get_provider().and_then(|p| p.input_price) {
+            label.push_str(&format!(" ~${:.2}", estimate.tokens as f64 * price / 1_000_000.0));
+        }
+        right.push(Span::styled(label, hint_style));
+        if app.config.mode == crate::config::Mode::Coding {
+            right.push(Span::styled(format!(" {} ", g.separator), hint_style));
+        }
+    }
+    // Nothing is saved: the config dir is read-only
+    if !crate::config::Config::writable() {
+        right.push(Span::styled("session-only", Style::default().fg(Color::Yellow)));
+        right.push(Span::styled(format!(" {} ", g.separator), hint_style));
+    }
+    // Only coding mode runs bash
+    if app.config.mode == crate::config::Mode::Coding {
+        right.push(if crate::sandbox::isolated() {
+            Span::styled("sandboxed", Style::default().fg(Color::Green))
+        } else {
+            Span::styled("unsandboxed fallback", Style::default().fg(Color::Red).bold())
+        });
+    }
+    right.push(Span::raw(" "));
+
+    // Drop trailing hints rather than let the right side draw over them
+    let right_width: usize = right.iter().map(|s| s.width()).sum();
+    while left.len() > 2 && left.iter().map(|s| s.width()).sum::<usize>() + right_width > area.width as usize {
+        left.truncate(left.len() - 2);
+    }
+    frame.render_widget(Paragraph::new(Line::from(left)), area);
+    frame.render_widget(Paragraph::new(Line::from(right)).right_aligned(), area);
+}
+
 fn draw_chat(frame: &mut Frame, app: &mut App, area: Rect) {
     let block = Block::default()
+        .border_set(crate::glyphs::get().border)
         .borders(Borders::TOP)
         .border_style(Style::default().fg(Color::Gray));
 
@@ -216,7 +406,7 @@ fn draw_chat(frame: &mut Frame, app: &mut App, area: Rect) {
         height: block_inner.height,
     };
 
-    if app.messages.is_empty() {
+    if app.messages.is_empty() && app.state == AppState::Idle && app.notice.is_none() && app.spell_fixes().is_none() && app.pasted_paths().is_none() {
         let help = Paragraph::new(Line::from(vec![
             Span::styled("Type ", Style::default().fg(Color::Gray)),
             Span::styled("/help", Style::default().fg(Color::Magenta)),
@@ -226,140 +416,61 @@ fn draw_chat(frame: &mut Frame, app: &mut App, area: Rect) {
         return;
     }
 
-    let mut lines: Vec<Line> = Vec::new();
-
-    for msg in &app.messages {
-        match &msg.role {
-            MessageRole::User => {
-                lines.push(Line::from(""));
-                // Bright teal for user messages
-                let user_color = Color::Rgb(100, 220, 215);
-                lines.push(Line::from(vec![
-                    Span::styled("› ", Style::default().fg(user_color)),
-                    Span::styled(&msg.content, Style::default().fg(user_color)),
-                ]));
-            }
-            MessageRole::Assistant => {
-                lines.push(Line::from(""));
-                let mut in_code_block = false;
-                let mut code_lang: Option<String> = None;
-                for line in msg.content.lines() {
-                    if line.starts_with("```") {
-                        if in_code_block {
-                            in_code_block = false;
-                            code_lang = None;
-                        } else {
-                            in_code_block = true;
-                            let lang = line.trim_start_matches('`').trim();
-                            code_lang = if lang.is_empty() { None } else { Some(lang.to_string()) };
-                        }
-                        continue;
-                    }
-                    if in_code_block {
-                        let ss = get_syntax_set();
-                        let ts = get_theme_set();
-                        let syntax = code_lang.as_ref()
-                            .and_then(|l| ss.find_syntax_by_token(l))
-                            .unwrap_or_else(|| ss.find_syntax_plain_text());
-                        let theme = &ts.themes["base16-ocean.dark"];
-                        let mut highlighter = HighlightLines::new(syntax, theme);
-                        let bg = Color::Rgb(40, 44, 52);
-                        let mut spans = vec![Span::styled("  ", Style::default().bg(bg))];
-                        match highlighter.highlight_line(&format!("{}\n", line), ss) {
-                            Ok(highlighted) => {
-                                for (syntect_style, text) in highlighted {
-                                    let fg = Color::Rgb(
-                                        syntect_style.foreground.r,
-                                        syntect_style.foreground.g,
-                                        syntect_style.foreground.b,
-                                    );
-                                    spans.push(Span::styled(text.to_string(), Style::default().fg(fg).bg(bg)));
-                                }
-                            }
-                            Err(_) => {
-                                spans.push(Span::styled(line.to_string(), Style::default().fg(Color::Gray).bg(bg)));
-                            }
-                        }
-                        lines.push(Line::from(spans));
-                    } else if line.starts_with("### ") {
-                        lines.push(Line::from(Span::styled(
-                            &line[4..],
-                            Style::default().fg(Color::Magenta).italic(),
-                        )));
-                    } else if line.starts_with("## ") {
-                        lines.push(Line::from(Span::styled(
-                            &line[3..],
-                            Style::default().fg(Color::Magenta),
-                        )));
-                    } else if line.starts_with("# ") {
-                        lines.push(Line::from(Span::styled(
-                            &line[2..],
-                            Style::default().fg(Color::Magenta).bold(),
-                        )));
-                    } else if line.starts_with("- ") || line.starts_with("* ") {
-                        let mut spans = vec![Span::styled("  • ", Style::default().fg(Color::Magenta))];
-                        spans.extend(render_inline_styles(&line[2..], None));
-                        lines.push(Line::from(spans));
-                    } else if line.starts_with("**") && line.ends_with("**") {
-                        lines.push(Line::from(Span::styled(
-                            line.trim_matches('*'),
-                            Style::default().bold(),
-                        )));
-                    } else {
-                        lines.push(Line::from(render_inline_styles(line, None)));
-                    }
-                }
-            }
-            MessageRole::Tool { name, path } => {
-                if name == "write_file" || name == "edit_file" {
-                    // Render diff inline with syntax highlighting
-                    let mut result_lines = msg.content.lines();
-                    if let Some(first) = result_lines.next() {
-                        lines.push(Line::from(vec![
-                            Span::styled("  ◇ ", Style::default().fg(Color::Magenta)),
-                            Span::styled(first.to_string(), Style::default().fg(Color::Gray)),
-                        ]));
-                    }
-                    for line in result_lines {
-                        if line.is_empty() {
-                            continue;
-                        }
-                        let highlighted = highlight_diff_line(line, path.as_deref());
-                        let mut spans = vec![Span::raw("    ")];
-                        spans.extend(highlighted);
-                        lines.push(Line::from(spans));
-                    }
-                } else {
-                    let display = format_tool_result(name, path.as_deref(), &msg.content);
-                    let mut first = true;
-                    for line in display.lines() {
-                        if first {
-                            lines.push(Line::from(vec![
-                                Span::styled("  ◇ ", Style::default().fg(Color::Magenta)),
-                                Span::styled(line.to_string(), Style::default().fg(Color::Gray)),
-                            ]));
-                            first = false;
-                        } else {
-                            lines.push(Line::from(vec![
-                                Span::raw("    "),
-                                Span::styled(line.to_string(), Style::default().fg(Color::Gray)),
-                            ]));
-                        }
-                    }
-                }
+    // Render message by message so copy mode knows which lines belong to which message
+    let mut chat = ChatLines::default();
+    let mut message_ranges = Vec::with_capacity(app.messages.len());
+    for (i, msg) in app.messages.iter().enumerate() {
+        let start = chat.lines.len();
+        chat.expand_tool = app.expanded_tools.contains(&i);
+        render::render_transcript(std::slice::from_ref(msg), &mut chat);
+        message_ranges.push(start..chat.lines.len());
+    }
+    let mut lines = chat.lines;
+
+    // Bookmarked messages get a star and their note on the first non-blank line
+    for bookmark in app.bookmarks() {
+        let Some(range) = message_ranges.get(bookmark.message) else { continue };
+        if let Some(line) = lines[range.clone()].iter_mut().find(|l| l.width() > 0) {
+            line.spans.insert(0, Span::styled(format!("{} ", crate::glyphs::get().bookmark), Style::default().fg(Color::Yellow)));
+            if !bookmark.note.is_empty() {
+                line.spans.push(Span::styled(
+                    format!("  [{}]", bookmark.note),
+                    Style::default().fg(Color::Yellow).italic(),
+                ));
             }
         }
     }
 
+    // Copy mode: mark the selected message with a gutter bar
+    let selected_range = app
+        .copy_mode
+        .as_ref()
+        .map(|mode| message_ranges[mode.targets[mode.selected].message].clone());
+    if let Some(range) = &selected_range {
+        for line in &mut lines[range.clone()] {
+            line.spans.insert(0, Span::styled(crate::glyphs::get().selection, Style::default().fg(Color::Magenta)));
+        }
+    }
+
     // Add typing indicator if processing
     if app.state != AppState::Idle {
         lines.push(Line::from(""));
-        let spinner = get_spinner_frame();
-        let status_text = match &app.state {
-            AppState::Thinking => "Thinking...".to_string(),
+        let reduced_motion = app.config.reduced_motion;
+        let spinner = if reduced_motion { crate::glyphs::get().bullet } else { get_spinner_frame() };
+        let mut status_text = match &app.state {
+            // After a moment, say where the request is so a stalled connection shows
+            AppState::Thinking => match app.api_progress.current() {
+                Some((phase, _)) if reduced_motion => format!("Thinking... {}", phase),
+                Some((phase, time)) if time >= PROGRESS_AFTER => format!("Thinking... {} ({}s)", phase, time.as_secs()),
+                _ => "Thinking...".to_string(),
+            },
             AppState::ToolCall(name) => name.clone(),
             AppState::Idle => unreachable!(),
         };
+        // Whole seconds stand in for the spinner as the sign of progress
+        if reduced_motion {
+            status_text.push_str(&format!(" ({}s)", app.busy_for().as_secs()));
+        }
         lines.push(Line::from(vec![
             Span::styled(format!("{} ", spinner), Style::default().fg(Color::Magenta)),
             Span::styled(status_text, Style::default().fg(Color::Gray)),
@@ -370,15 +481,50 @@ fn draw_chat(frame: &mut Frame, app: &mut App, area: Rect) {
     if let Some(err) = &app.error {
         lines.push(Line::from(""));
         lines.push(Line::from(Span::styled(
-            format!("✗ {}", err),
+            format!("{} {}", crate::glyphs::get().failed, err),
             Style::default().fg(Color::Red),
         )));
     }
 
+    if let Some(notice) = &app.notice {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            format!("{} {}", crate::glyphs::get().ok, notice),
+            Style::default().fg(Color::Green),
+        )));
+    }
+
+    if let Some(fixes) = app.spell_fixes() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            format!(
+                "{} Possible typos: {} {} Tab fixes them, Enter sends as is",
+                crate::glyphs::get().warning,
+                crate::spell::describe(fixes),
+                crate::glyphs::get().separator
+            ),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+
+    if let Some(references) = app.pasted_paths() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            format!(
+                "{} Pasted {} {} Tab attaches as {}, keep typing to leave as text",
+                crate::glyphs::get().arrow,
+                if references.len() == 1 { "a file path".to_string() } else { format!("{} file paths", references.len()) },
+                crate::glyphs::get().separator,
+                references.join(" ")
+            ),
+            Style::default().fg(Color::Cyan),
+        )));
+    }
+
     // Calculate scroll - we want to show the bottom by default
     // Account for text wrapping when calculating content height
     let width = inner_area.width as usize;
-    let content_height: u16 = lines
+    let heights: Vec<u16> = lines
         .iter()
         .map(|line| {
             let line_width = line.width();
@@ -390,9 +536,25 @@ fn draw_chat(frame: &mut Frame, app: &mut App, area: Rect) {
                 1 + (line_width / width) as u16
             }
         })
-        .sum();
+        .collect();
+    let content_height: u16 = heights.iter().sum();
     let view_height = inner_area.height;
     let max_scroll = content_height.saturating_sub(view_height);
+
+    // Keep the copy-mode selection in view
+    if let Some(range) = selected_range {
+        let start_row: u16 = heights[..range.start].iter().sum();
+        let end_row: u16 = start_row + heights[range].iter().sum::<u16>();
+        let mut top = max_scroll.saturating_sub(app.scroll_offset.min(max_scroll));
+        if end_row > top + view_height {
+            top = end_row.saturating_sub(view_height);
+        }
+        if start_row < top {
+            top = start_row;
+        }
+        app.scroll_offset = max_scroll.saturating_sub(top);
+    }
+
     app.scroll_offset = app.scroll_offset.min(max_scroll);
     let scroll = max_scroll.saturating_sub(app.scroll_offset);
 
@@ -457,87 +619,214 @@ fn render_inline_styles(line: &str, base_color: Option<Color>) -> Vec<Span<'_>>
     spans
 }
 
-fn format_tool_result(name: &str, path: Option<&str>, result: &str) -> String {
-    if result.starts_with("Error") {
-        return result.to_string();
+/// Renders the transcript as styled ratatui lines for the chat view
+#[derive(Default)]
+struct ChatLines<'a> {
+    lines: Vec<Line<'a>>,
+    /// Show the full raw output of the next tool result instead of a one-line summary
+    expand_tool: bool,
+}
+
+impl<'a> TranscriptRenderer<'a> for ChatLines<'a> {
+    fn user(&mut self, content: &'a str) {
+        self.lines.push(Line::from(""));
+        // Bright teal for user messages
+        let user_color = Color::Rgb(100, 220, 215);
+        self.lines.push(Line::from(vec![
+            Span::styled(format!("{} ", crate::glyphs::get().prompt), Style::default().fg(user_color)),
+            Span::styled(content, Style::default().fg(user_color)),
+        ]));
     }
 
-    match name {
-        "read_file" => {
-            let lines = result.lines().count();
-            match path {
-                Some(p) => format!("Read {} ({} lines)", p, lines),
-                None => format!("Read file ({} lines)", lines),
+    fn assistant(&mut self, content: &'a str) {
+        let lines = &mut self.lines;
+        lines.push(Line::from(""));
+        let mut in_code_block = false;
+        let mut code_lang: Option<String> = None;
+        for line in content.lines() {
+            if line.starts_with("```") {
+                if in_code_block {
+                    in_code_block = false;
+                    code_lang = None;
+                } else {
+                    in_code_block = true;
+                    let lang = line.trim_start_matches('`').trim();
+                    code_lang = if lang.is_empty() { None } else { Some(lang.to_string()) };
+                }
+                continue;
             }
-        }
-        "list_dir" => {
-            let items = result.lines().count();
-            let dir = path.unwrap_or(".");
-            if items <= 8 {
-                format!("Listed {} ({})", dir, result.lines().collect::<Vec<_>>().join("  "))
+            if in_code_block {
+                let ss = get_syntax_set();
+                let ts = get_theme_set();
+                let syntax = code_lang.as_ref()
+                    .and_then(|l| ss.find_syntax_by_token(l))
+                    .unwrap_or_else(|| ss.find_syntax_plain_text());
+                let theme = &ts.themes["base16-ocean.dark"];
+                let mut highlighter = HighlightLines::new(syntax, theme);
+                let bg = Color::Rgb(40, 44, 52);
+                let mut spans = vec![Span::styled("  ", Style::default().bg(bg))];
+                match highlighter.highlight_line(&format!("{}\n", line), ss) {
+                    Ok(highlighted) => {
+                        for (syntect_style, text) in highlighted {
+                            let fg = Color::Rgb(
+                                syntect_style.foreground.r,
+                                syntect_style.foreground.g,
+                                syntect_style.foreground.b,
+                            );
+                            spans.push(Span::styled(text.to_string(), Style::default().fg(fg).bg(bg)));
+                        }
+                    }
+                    Err(_) => {
+                        spans.push(Span::styled(line.to_string(), Style::default().fg(Color::Gray).bg(bg)));
+                    }
+                }
+                lines.push(Line::from(spans));
+            } else if line.starts_with("### ") {
+                lines.push(Line::from(Span::styled(
+                    &line[4..],
+                    Style::default().fg(Color::Magenta).italic(),
+                )));
+            } else if line.starts_with("## ") {
+                lines.push(Line::from(Span::styled(
+                    &line[3..],
+                    Style::default().fg(Color::Magenta),
+                )));
+            } else if line.starts_with("# ") {
+                lines.push(Line::from(Span::styled(
+                    &line[2..],
+                    Style::default().fg(Color::Magenta).bold(),
+                )));
+            } else if line.starts_with("- ") || line.starts_with("* ") {
+                let mut spans = vec![Span::styled(format!("  {} ", crate::glyphs::get().bullet), Style::default().fg(Color::Magenta))];
+                spans.extend(render_inline_styles(&line[2..], None));
+                lines.push(Line::from(spans));
+            } else if line.starts_with("**") && line.ends_with("**") {
+                lines.push(Line::from(Span::styled(
+                    line.trim_matches('*'),
+                    Style::default().bold(),
+                )));
             } else {
-                format!("Listed {} ({} items)", dir, items)
+                lines.push(Line::from(render_inline_styles(line, None)));
             }
         }
-        "search_files" => {
-            let files: Vec<_> = result.lines().collect();
-            if files.len() <= 6 {
-                format!("Found {}", files.join(", "))
-            } else {
-                format!("Found {} files", files.len())
+    }
+
+    fn tool_summary(&mut self, name: &'a str, summary: String, output: &'a str, error: Option<ErrorKind>) {
+        // bash and grep output starts with its own header line ("$ cmd", "grep 'x':")
+        let header_lines = if name == "bash" || name == "grep" { 1 } else { 0 };
+        let body = output.lines().skip(header_lines);
+
+        let (marker, marker_style, text_style) = tool_status_style(error);
+
+        // Collapsed: first summary line plus a count of hidden lines
+        if !self.expand_tool {
+            let mut summary_lines = summary.lines();
+            let first = summary_lines.next().unwrap_or("").to_string();
+            let mut spans = vec![Span::styled(marker, marker_style), Span::styled(first, text_style)];
+            if summary_lines.next().is_some() {
+                spans.push(Span::styled(
+                    format!(" {} {} lines", crate::glyphs::get().collapsed, body.count()),
+                    Style::default().fg(Color::DarkGray),
+                ));
             }
+            self.lines.push(Line::from(spans));
+            return;
         }
-        "write_file" => {
-            // Extract just the first line (the "Wrote path" part)
-            result.lines().next().unwrap_or(result).to_string()
-        }
-        "bash" => {
-            let mut lines = result.lines();
-            let cmd = lines.next().unwrap_or("$ ?");
-            let output: Vec<_> = lines.collect();
-            if output.is_empty() {
-                cmd.to_string()
-            } else if output.len() <= 10 {
-                format!("{}\n{}", cmd, output.join("\n"))
+
+        // Expanded: summary header followed by the full raw output
+        let mut first = true;
+        for line in summary.lines().take(1).chain(body) {
+            if first {
+                self.lines.push(Line::from(vec![
+                    Span::styled(marker.clone(), marker_style),
+                    Span::styled(line.to_string(), text_style),
+                ]));
+                first = false;
             } else {
-                format!("{}\n{}\n... ({} more lines)", cmd, output[..8].join("\n"), output.len() - 8)
+                self.lines.push(Line::from(vec![
+                    Span::raw("    "),
+                    Span::styled(line.to_string(), Style::default().fg(Color::Gray)),
+                ]));
             }
         }
-        "grep" => {
-            // First line is "grep 'pattern':" - extract pattern
-            let mut lines = result.lines();
-            let header = lines.next().unwrap_or("");
-            let pattern = header.strip_prefix("grep '")
-                .and_then(|s| s.strip_suffix("':"))
-                .unwrap_or("?");
-            let matches: Vec<_> = lines.filter(|l| !l.is_empty() && *l != "--").collect();
-            if matches.is_empty() || result.contains("no matches") {
-                format!("Grep '{}': no matches", pattern)
-            } else if matches.len() <= 4 {
-                format!("Grep '{}':\n{}", pattern, matches.iter().map(|l| {
-                    if l.len() > 60 { format!("{}...", &l[..60]) } else { l.to_string() }
-                }).collect::<Vec<_>>().join("\n"))
-            } else {
-                format!("Grep '{}': {} matches\n{}\n...", pattern, matches.len(), matches[..3].iter().map(|l| {
-                    if l.len() > 60 { format!("{}...", &l[..60]) } else { l.to_string() }
-                }).collect::<Vec<_>>().join("\n"))
-            }
+    }
+
+    fn tool_diff(&mut self, _name: &'a str, path: Option<&'a str>, header: &'a str, diff: Vec<&'a str>, error: Option<ErrorKind>) {
+        let (marker, marker_style, text_style) = tool_status_style(error);
+        let mut spans = vec![Span::styled(marker, marker_style), Span::styled(header.to_string(), text_style)];
+        // Lockfiles and generated files: stat line only until expanded (o in copy mode)
+        if !self.expand_tool && crate::render::is_low_signal_diff(path, &diff) {
+            spans.push(Span::styled(
+                format!(" {} {} lines, collapsed", crate::glyphs::get().collapsed, crate::render::diff_stat(&diff)),
+                Style::default().fg(Color::DarkGray),
+            ));
+            self.lines.push(Line::from(spans));
+            return;
         }
-        _ => {
-            if result.len() > 60 {
-                format!("{}...", &result[..60])
-            } else {
-                result.to_string()
+
+        // Render diff inline with syntax highlighting
+        self.lines.push(Line::from(spans));
+        let g = crate::glyphs::get();
+        let emphasis = crate::render::word_emphasis(&diff);
+        for (line, emphasis) in diff.iter().zip(&emphasis) {
+            let mut spans = vec![Span::raw("    ")];
+            match crate::render::split_diff_line(line) {
+                Some((marker, number, code)) => spans.extend(numbered_diff_line(marker, number, code, path, emphasis)),
+                None if *line == "···" => spans.extend(highlight_diff_line(g.hunks, path, &[])),
+                None => spans.extend(highlight_diff_line(line, path, &[])),
             }
+            self.lines.push(Line::from(spans));
         }
     }
 }
 
+/// A preview diff line as "+  12│code": marker, line number and gutter, then the highlighted code
+fn numbered_diff_line(marker: char, number: &str, code: &str, path: Option<&str>, emphasis: &[std::ops::Range<usize>]) -> Vec<Span<'static>> {
+    let gutter = format!("{}{}", number, crate::glyphs::get().gutter);
+    if marker == ' ' {
+        let mut spans = vec![Span::styled(format!(" {}", gutter), Style::default().fg(Color::DarkGray))];
+        spans.extend(highlight_diff_line(code, path, emphasis));
+        return spans;
+    }
+    let mut spans = highlight_diff_line(&format!("{}{}", marker, code), path, emphasis);
+    let bg = spans.first().and_then(|s| s.style.bg).unwrap_or(Color::Reset);
+    spans.insert(1, Span::styled(gutter, Style::default().fg(Color::DarkGray).bg(bg)));
+    spans
+}
+
+/// Marker and styles for a tool result line: failures in red, calls the user declined in yellow
+fn tool_status_style(error: Option<ErrorKind>) -> (String, Style, Style) {
+    let g = crate::glyphs::get();
+    let (marker, marker_style, text_style) = match error {
+        Some(kind) if kind.declined() => (g.declined, Style::default().fg(Color::Yellow), Style::default().fg(Color::Yellow)),
+        Some(_) => (g.failed, Style::default().fg(Color::Red), Style::default().fg(Color::Red)),
+        None => (g.tool, Style::default().fg(Color::Magenta), Style::default().fg(Color::Gray)),
+    };
+    (format!("  {} ", marker), marker_style, text_style)
+}
+
 fn draw_input(frame: &mut Frame, app: &App, area: Rect) {
-    let block = Block::default()
+    let mut block = Block::default()
+        .border_set(crate::glyphs::get().border)
         .borders(Borders::TOP)
         .border_style(Style::default().fg(Color::Gray));
 
+    // Copy mode / vim mode indicator on the right of the border
+    if let Some(mode) = &app.copy_mode {
+        let label = format!(
+            " COPY {} {sep} y copy {sep} o expand {sep} Esc exit ",
+            mode.targets[mode.selected].label,
+            sep = crate::glyphs::get().separator
+        );
+        block = block.title_top(Line::from(Span::styled(label, Style::default().fg(Color::Magenta))).right_aligned());
+    } else if app.config.keybindings == crate::config::Keybindings::Vim {
+        let (label, color) = match app.vim_mode {
+            VimMode::Normal => (" NORMAL ", Color::Magenta),
+            VimMode::Insert => (" INSERT ", Color::Cyan),
+        };
+        block = block.title_top(Line::from(Span::styled(label, Style::default().fg(color))).right_aligned());
+    }
+
     let block_inner = block.inner(area);
     frame.render_widget(block, area);
 
@@ -549,12 +838,12 @@ fn draw_input(frame: &mut Frame, app: &App, area: Rect) {
         height: block_inner.height,
     };
 
-    let prefix = "› ";
+    let prefix = format!("{} ", crate::glyphs::get().prompt);
     let prefix_width = 2; // display width, not byte length
     let width = inner.width as usize;
 
     // Build wrapped lines manually to preserve prefix styling
-    let lines = wrap_input_lines(prefix, &app.input, prefix_width, width);
+    let lines = wrap_input_lines(&prefix, &app.input, prefix_width, width);
     let para = Paragraph::new(Text::from(lines));
     frame.render_widget(para, inner);
 
@@ -613,7 +902,7 @@ fn calculate_wrapped_cursor(cursor: usize, prefix_len: usize, width: usize) -> (
 }
 
 fn calculate_input_height(input_len: usize, width: usize) -> u16 {
-    let prefix_len = 2; // "› "
+    let prefix_len = 2; // Prompt glyph and a space
     if width <= prefix_len {
         return 2;
     }
@@ -650,22 +939,25 @@ fn draw_picker(frame: &mut Frame, app: &App, input_area: Rect) {
         .picker_results
         .iter()
         .enumerate()
-        .map(|(i, item)| {
+        .map(|(i, (item, matched))| {
             let style = if i == app.picker_selected {
                 Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD)
             } else {
                 Style::default().fg(Color::Gray)
             };
-            let prefix = if i == app.picker_selected { "› " } else { "  " };
-            ListItem::new(Line::from(vec![
-                Span::styled(prefix, style),
-                Span::styled(format!("{}{}", item_prefix, item), style),
-            ]))
+            let highlight = style.fg(Color::Yellow).add_modifier(Modifier::UNDERLINED);
+            let prefix = if i == app.picker_selected { selected_prefix() } else { "  ".to_string() };
+            let mut spans = vec![Span::styled(prefix, style), Span::styled(item_prefix, style)];
+            spans.extend(item.chars().enumerate().map(|(j, c)| {
+                Span::styled(c.to_string(), if matched.contains(&j) { highlight } else { style })
+            }));
+            ListItem::new(Line::from(spans))
         })
         .collect();
 
     let list = List::new(items).block(
         Block::default()
+            .border_set(crate::glyphs::get().border)
             .borders(Borders::ALL)
             .border_style(Style::default().fg(Color::Gray))
             .title(title)
@@ -676,13 +968,76 @@ fn draw_picker(frame: &mut Frame, app: &App, input_area: Rect) {
     frame.render_widget(list, area);
 }
 
-fn get_spinner_frame() -> char {
-    const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+fn draw_history_search(frame: &mut Frame, search: &HistorySearch, input_area: Rect) {
+    let height = (search.results.len().max(1) as u16).min(input_area.y.saturating_sub(1)) + 2;
+    let width = input_area.width.saturating_sub(4);
+    let area = Rect {
+        x: input_area.x + 2,
+        y: input_area.y.saturating_sub(height),
+        width,
+        height,
+    };
+    let text_width = width.saturating_sub(4) as usize;
+    let g = crate::glyphs::get();
+
+    // Newest match at the bottom, next to the input, like a shell's reverse search
+    let mut items: Vec<ListItem> = search
+        .results
+        .iter()
+        .enumerate()
+        .map(|(i, (prompt, matched))| {
+            let style = if i == search.selected {
+                Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Gray)
+            };
+            let highlight = style.fg(Color::Yellow).add_modifier(Modifier::UNDERLINED);
+            let prefix = if i == search.selected { selected_prefix() } else { "  ".to_string() };
+            let mut spans = vec![Span::styled(prefix, style)];
+            spans.extend(prompt.chars().take(text_width).enumerate().map(|(j, c)| {
+                let c = if c == '\n' { g.newline } else { c };
+                Span::styled(c.to_string(), if matched.contains(&j) { highlight } else { style })
+            }));
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    if items.is_empty() {
+        items.push(ListItem::new(Span::styled("  no matching prompts", Style::default().fg(Color::DarkGray))));
+    }
+    items.reverse();
+
+    let list = List::new(items).block(
+        Block::default()
+            .border_set(crate::glyphs::get().border)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Gray))
+            .title(format!(" reverse-i-search: {}{}", search.query, g.cursor))
+            .title_style(Style::default().fg(Color::Magenta))
+            .title_bottom(
+                Line::from(Span::styled(
+                    format!(" Enter insert {sep} Ctrl+R older {sep} Esc cancel ", sep = g.separator),
+                    Style::default().fg(Color::DarkGray),
+                ))
+                    .right_aligned(),
+            ),
+    );
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(list, area);
+}
+
+/// "› " (or its ASCII stand-in) marking the selected item of a list
+fn selected_prefix() -> String {
+    format!("{} ", crate::glyphs::get().prompt)
+}
+
+fn get_spinner_frame() -> &'static str {
+    let frames = crate::glyphs::get().spinner;
     let ms = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
         .as_millis();
-    FRAMES[(ms / 80) as usize % FRAMES.len()]
+    frames[(ms / 80) as usize % frames.len()]
 }
 
 fn draw_permission_modal(frame: &mut Frame, modal: &PermissionModal) {
@@ -709,7 +1064,10 @@ fn draw_permission_modal(frame: &mut Frame, modal: &PermissionModal) {
         )),
         Line::from(""),
         Line::from(vec![
-            Span::styled("Path: ", Style::default().fg(Color::Gray)),
+            Span::styled(
+                if modal.kind == PermissionKind::Network { "Command: " } else { "Path: " },
+                Style::default().fg(Color::Gray),
+            ),
             Span::styled(&modal.path, Style::default().fg(Color::Yellow)),
         ]),
         Line::from(Span::styled(&modal.reason, Style::default().fg(Color::Gray))),
@@ -723,11 +1081,12 @@ fn draw_permission_modal(frame: &mut Frame, modal: &PermissionModal) {
         } else {
             Style::default().fg(Color::White)
         };
-        let prefix = if i == modal.selected { "› " } else { "  " };
+        let prefix = if i == modal.selected { selected_prefix() } else { "  ".to_string() };
         lines.push(Line::from(Span::styled(format!("{}{}", prefix, option), style)));
     }
 
     let block = Block::default()
+        .border_set(crate::glyphs::get().border)
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::Magenta))
         .title(" Sandbox ")
@@ -740,6 +1099,110 @@ fn draw_permission_modal(frame: &mut Frame, modal: &PermissionModal) {
     frame.render_widget(para, modal_area);
 }
 
+fn draw_continue_modal(frame: &mut Frame, modal: &ContinueModal) {
+    let area = frame.area();
+
+    let width = 60.min(area.width.saturating_sub(4));
+    let height = 9.min(area.height.saturating_sub(4));
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+    let modal_area = Rect { x, y, width, height };
+
+    frame.render_widget(Clear, modal_area);
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!("This turn has reached {} — continue?", modal.reached),
+            Style::default().fg(Color::Magenta).bold(),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("Spent this turn: ", Style::default().fg(Color::Gray)),
+            Span::styled(
+                format!(
+                    "{} tool call{}, {} in / {} out tokens{}, {}m {}s",
+                    modal.tool_calls,
+                    if modal.tool_calls == 1 { "" } else { "s" },
+                    modal.tokens.0,
+                    modal.tokens.1,
+                    modal.cost.map(|cost| format!(" (~${:.2})", cost)).unwrap_or_default(),
+                    modal.elapsed.as_secs() / 60,
+                    modal.elapsed.as_secs() % 60
+                ),
+                Style::default().fg(Color::Yellow),
+            ),
+        ]),
+        Line::from(""),
+    ];
+
+    for (i, option) in modal.options.iter().enumerate() {
+        let style = if i == modal.selected {
+            Style::default().fg(Color::Magenta).bold()
+        } else {
+            Style::default().fg(Color::White)
+        };
+        let prefix = if i == modal.selected { selected_prefix() } else { "  ".to_string() };
+        lines.push(Line::from(Span::styled(format!("{}{}", prefix, option), style)));
+    }
+
+    let block = Block::default()
+        .border_set(crate::glyphs::get().border)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Magenta))
+        .title(" Turn limit ")
+        .title_style(Style::default().fg(Color::Magenta));
+
+    let para = Paragraph::new(Text::from(lines))
+        .block(block)
+        .wrap(Wrap { trim: false });
+
+    frame.render_widget(para, modal_area);
+}
+
+fn draw_match_modal(frame: &mut Frame, modal: &MatchModal) {
+    let area = frame.area();
+    let options = modal.options();
+
+    let width = 80.min(area.width.saturating_sub(4));
+    let height = (options.len() as u16 + 6).min(area.height.saturating_sub(4));
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+    let modal_area = Rect { x, y, width, height };
+
+    frame.render_widget(Clear, modal_area);
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!("The edit matches {} places in {}", modal.locations.len(), modal.path),
+            Style::default().fg(Color::Yellow).bold(),
+        )),
+        Line::from(Span::styled("Which one should change?", Style::default().fg(Color::Gray))),
+        Line::from(""),
+    ];
+
+    for (i, option) in options.iter().enumerate() {
+        let style = if i == modal.selected {
+            Style::default().fg(Color::Yellow).bold()
+        } else {
+            Style::default().fg(Color::White)
+        };
+        let prefix = if i == modal.selected { selected_prefix() } else { "  ".to_string() };
+        lines.push(Line::from(Span::styled(format!("{}{}", prefix, option), style)));
+    }
+
+    let block = Block::default()
+        .border_set(crate::glyphs::get().border)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow))
+        .title(" Ambiguous edit ")
+        .title_style(Style::default().fg(Color::Yellow));
+
+    // Unwrapped so each match stays on one row
+    let para = Paragraph::new(Text::from(lines)).block(block);
+
+    frame.render_widget(para, modal_area);
+}
+
 fn draw_provider_modal(frame: &mut Frame, modal: &ProviderModal, active: &str, providers: &std::collections::HashMap<String, crate::config::Provider>) {
     let area = frame.area();
 
@@ -765,7 +1228,7 @@ fn draw_provider_modal(frame: &mut Frame, modal: &ProviderModal, active: &str, p
         let is_active = name == active;
         let model = providers.get(name).map(|p| p.model.as_str()).unwrap_or("?");
         let selected = i == modal.selected;
-        let prefix = if selected { "› " } else { "  " };
+        let prefix = if selected { selected_prefix() } else { "  ".to_string() };
         let name_style = if selected {
             Style::default().fg(Color::Magenta).bold()
         } else {
@@ -782,6 +1245,7 @@ fn draw_provider_modal(frame: &mut Frame, modal: &ProviderModal, active: &str, p
     }
 
     let block = Block::default()
+        .border_set(crate::glyphs::get().border)
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::Magenta))
         .title(" Model ")
@@ -794,6 +1258,59 @@ fn draw_provider_modal(frame: &mut Frame, modal: &ProviderModal, active: &str, p
     frame.render_widget(para, modal_area);
 }
 
+/// The review modal shows old and new side by side from this inner width
+const SIDE_BY_SIDE_MIN_WIDTH: u16 = 160;
+
+/// A file's versions this session, each shown as a diff against the one before it
+fn draw_versions_modal(frame: &mut Frame, modal: &VersionsModal) {
+    let area = frame.area();
+    let modal_area = Rect { x: 2, y: 2, width: area.width.saturating_sub(4), height: area.height.saturating_sub(4) };
+    frame.render_widget(Clear, modal_area);
+
+    let block = Block::default()
+        .border_set(crate::glyphs::get().border)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan))
+        .title(format!(" {} ", modal.path))
+        .title_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(modal_area);
+    frame.render_widget(block, modal_area);
+
+    let chunks = Layout::vertical([Constraint::Length(2), Constraint::Min(1), Constraint::Length(1)]).split(inner);
+
+    let version = &modal.versions[modal.selected];
+    let latest = if modal.selected + 1 == modal.versions.len() { ", latest" } else { "" };
+    let header = Line::from(vec![
+        Span::styled(format!("Version {} of {}: ", modal.selected + 1, modal.versions.len()), Style::default().fg(Color::Cyan).bold()),
+        Span::styled(format!("{} ({}{})", version.label, crate::session::relative_time(version.time), latest), Style::default().fg(Color::Gray)),
+    ]);
+    frame.render_widget(Paragraph::new(header), chunks[0]);
+
+    let gutter_style = Style::default().fg(Color::DarkGray);
+    let diff = modal.diff();
+    // First line is the "Edited path" header, already in the title
+    let lines: Vec<Line> = diff
+        .lines()
+        .skip(1)
+        .map(|line| match crate::render::split_diff_line(line) {
+            Some((marker, number, code)) => {
+                let mut spans = vec![Span::styled(format!("{} ", number), gutter_style)];
+                spans.extend(highlight_diff_line(&format!("{}{}", marker, code), Some(&modal.path), &[]));
+                Line::from(spans)
+            }
+            None if line == "···" => Line::from(Span::styled(format!("  {}", crate::glyphs::get().hunks), gutter_style)),
+            None => Line::from(Span::styled(line.to_string(), Style::default().fg(Color::Gray))),
+        })
+        .collect();
+    let lines = if lines.is_empty() { vec![Line::from(Span::styled("No changes from the previous version", gutter_style))] } else { lines };
+    let max_scroll = (lines.len() as u16).saturating_sub(chunks[1].height);
+    frame.render_widget(Paragraph::new(Text::from(lines)).scroll((modal.scroll.min(max_scroll), 0)), chunks[1]);
+
+    let sep = crate::glyphs::get().separator;
+    let footer = format!("Left/Right step {sep} Up/Down scroll {sep} Enter restore this version {sep} Esc close");
+    frame.render_widget(Paragraph::new(Span::styled(footer, gutter_style)), chunks[2]);
+}
+
 fn draw_diff_modal(frame: &mut Frame, modal: &DiffModal) {
     let area = frame.area();
 
@@ -809,6 +1326,7 @@ fn draw_diff_modal(frame: &mut Frame, modal: &DiffModal) {
     frame.render_widget(Clear, modal_area);
 
     let block = Block::default()
+        .border_set(crate::glyphs::get().border)
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::Magenta))
         .title(" Review Changes ")
@@ -835,51 +1353,71 @@ fn draw_diff_modal(frame: &mut Frame, modal: &DiffModal) {
             first.to_string(),
             Style::default().fg(Color::Gray),
         )));
+        if let Some(warning) = &modal.warning {
+            lines.push(Line::from(Span::styled(
+                format!("{} {}", crate::glyphs::get().warning, warning),
+                Style::default().fg(Color::Yellow),
+            )));
+        }
         lines.push(Line::from(""));
     }
 
     let gutter_style = Style::default().fg(Color::DarkGray);
+    let hunk_line = || Line::from(Span::styled(format!("  {}", crate::glyphs::get().hunks), gutter_style));
+    // Parse format: <marker><line_num>│<code>, with the line number as a gutter before the code
+    let diff_line = |marker: char, number: &str, code: &str, emphasis: &[std::ops::Range<usize>]| {
+        let mut spans = vec![Span::styled(format!("{} ", number), gutter_style)];
+        // Context lines get a space for alignment with +/-
+        spans.extend(highlight_diff_line(&format!("{}{}", marker, code), Some(&modal.path), emphasis));
+        Line::from(spans)
+    };
 
-    for line in diff_lines {
-        if line == "···" {
-            // Hunk separator
-            lines.push(Line::from(Span::styled(
-                "  ···".to_string(),
-                gutter_style,
-            )));
-            continue;
-        }
-        if line.is_empty() {
-            lines.push(Line::from(""));
+    let diff_lines: Vec<&str> = diff_lines.collect();
+    let emphasis = crate::render::word_emphasis(&diff_lines);
+    let side_by_side = chunks[0].width >= SIDE_BY_SIDE_MIN_WIDTH;
+    let mut right: Vec<Line> = vec![Line::from(""); lines.len()];
+    let (mut removed, mut added): (Vec<Line>, Vec<Line>) = (Vec::new(), Vec::new());
+
+    for (line, emphasis) in diff_lines.iter().zip(&emphasis) {
+        let parsed = crate::render::split_diff_line(line);
+        // Side by side: removed lines on the left, the lines that replaced them on the right
+        if side_by_side {
+            match parsed {
+                Some(('-', number, code)) => removed.push(diff_line('-', number, code, emphasis)),
+                Some(('+', number, code)) => added.push(diff_line('+', number, code, emphasis)),
+                _ => {
+                    let rows = removed.len().max(added.len());
+                    removed.resize(rows, Line::from(""));
+                    added.resize(rows, Line::from(""));
+                    lines.append(&mut removed);
+                    right.append(&mut added);
+                    let (left_line, right_line) = match parsed {
+                        Some((marker, number, code)) => (diff_line(marker, number, code, &[]), diff_line(marker, number, code, &[])),
+                        None if *line == "···" => (hunk_line(), hunk_line()),
+                        None => (Line::from(line.to_string()), Line::from("")),
+                    };
+                    lines.push(left_line);
+                    right.push(right_line);
+                }
+            }
             continue;
         }
 
-        // Parse format: <marker><line_num>│<code>
-        if let Some(sep_pos) = line.find('│') {
-            let marker_char = line.as_bytes()[0];
-            let line_num_str = &line[1..sep_pos];
-            let code = &line[sep_pos + '│'.len_utf8()..];
-
-            let mut spans = Vec::new();
-
-            // Line number gutter
-            spans.push(Span::styled(format!("{} ", line_num_str), gutter_style));
-
-            // Reconstruct diff line for highlight_diff_line
-            let diff_line = match marker_char {
-                b'+' => format!("+{}", code),
-                b'-' => format!("-{}", code),
-                _ => format!(" {}", code), // context: add space for alignment with +/-
-            };
-            spans.extend(highlight_diff_line(&diff_line, Some(&modal.path)));
-
-            lines.push(Line::from(spans));
-        } else {
+        match parsed {
+            Some((marker, number, code)) => lines.push(diff_line(marker, number, code, emphasis)),
+            None if *line == "···" => lines.push(hunk_line()),
+            None if line.is_empty() => lines.push(Line::from("")),
             // Fallback for lines without the expected format
-            let highlighted = highlight_diff_line(line, Some(&modal.path));
-            lines.push(Line::from(highlighted));
+            None => lines.push(Line::from(highlight_diff_line(line, Some(&modal.path), &[]))),
         }
     }
+    if side_by_side {
+        let rows = removed.len().max(added.len());
+        removed.resize(rows, Line::from(""));
+        added.resize(rows, Line::from(""));
+        lines.append(&mut removed);
+        right.append(&mut added);
+    }
 
     // Calculate scroll for diff content
     let content_height = lines.len() as u16;
@@ -887,15 +1425,23 @@ fn draw_diff_modal(frame: &mut Frame, modal: &DiffModal) {
     let max_scroll = content_height.saturating_sub(view_height);
     let scroll = max_scroll.saturating_sub(modal.scroll_offset.min(max_scroll));
 
-    let diff_para = Paragraph::new(Text::from(lines))
-        .wrap(Wrap { trim: false })
-        .scroll((scroll, 0));
-
-    frame.render_widget(diff_para, chunks[0]);
+    if side_by_side {
+        // Unwrapped so the two columns stay row-aligned
+        let columns = Layout::horizontal([Constraint::Fill(1), Constraint::Length(1), Constraint::Fill(1)]).split(chunks[0]);
+        frame.render_widget(Paragraph::new(Text::from(lines)).scroll((scroll, 0)), columns[0]);
+        let divider = vec![Line::from(crate::glyphs::get().gutter.to_string()); columns[1].height as usize];
+        frame.render_widget(Paragraph::new(divider).style(gutter_style), columns[1]);
+        frame.render_widget(Paragraph::new(Text::from(right)).scroll((scroll, 0)), columns[2]);
+    } else {
+        let diff_para = Paragraph::new(Text::from(lines))
+            .wrap(Wrap { trim: false })
+            .scroll((scroll, 0));
+        frame.render_widget(diff_para, chunks[0]);
+    }
 
     // Separator
     let sep = Paragraph::new(Line::from(
-        "─".repeat(chunks[1].width as usize),
+        crate::glyphs::get().rule.repeat(chunks[1].width as usize),
     ))
     .style(Style::default().fg(Color::Gray));
     frame.render_widget(sep, chunks[1]);
@@ -911,7 +1457,7 @@ fn draw_diff_modal(frame: &mut Frame, modal: &DiffModal) {
         } else {
             Style::default().fg(Color::Gray)
         };
-        let prefix = if i == modal.selected { "› " } else { "  " };
+        let prefix = if i == modal.selected { selected_prefix() } else { "  ".to_string() };
         option_spans.push(Span::styled(format!("{}{}", prefix, option), style));
     }
 
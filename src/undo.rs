@@ -0,0 +1,306 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// One applied file change, with what the file held before it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Change {
+    path: String,
+    before: Option<String>, // None if hal created the file
+    after: String,
+    time: i64,
+}
+
+struct Journal {
+    changes: Vec<Change>,
+    started: i64, // Names this run's files under .hal/undo/
+    seq: usize,
+    backups: Vec<PathBuf>, // .hal-backup files to remove when the session ends
+}
+
+static KEEP_BACKUPS: AtomicBool = AtomicBool::new(false);
+
+/// Keep a `<file>.hal-backup` of each file's pre-image until the session ends
+pub fn set_keep_backups(enabled: bool) {
+    KEEP_BACKUPS.store(enabled, Ordering::Relaxed);
+}
+
+fn journal() -> &'static Mutex<Journal> {
+    static JOURNAL: OnceLock<Mutex<Journal>> = OnceLock::new();
+    JOURNAL.get_or_init(|| {
+        Mutex::new(Journal {
+            changes: Vec::new(),
+            started: chrono::Utc::now().timestamp(),
+            seq: 0,
+            backups: Vec::new(),
+        })
+    })
+}
+
+fn undo_dir() -> PathBuf {
+    // Keep tests from journaling into the repo
+    if cfg!(test) {
+        return std::env::temp_dir().join(format!("hal-test-undo-{}", std::process::id()));
+    }
+    PathBuf::from(".hal").join("undo")
+}
+
+/// Create .hal/undo/, keeping it out of git
+fn ensure_undo_dir() -> io::Result<()> {
+    let dir = undo_dir();
+    if !dir.exists() {
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(".gitignore"), "*\n")?;
+    }
+    Ok(())
+}
+
+fn entry_path(started: i64, seq: usize) -> PathBuf {
+    undo_dir().join(format!("{}-{:04}.json", started, seq))
+}
+
+fn backup_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.hal-backup", path))
+}
+
+/// Write through a temp file in the same directory renamed over the target, so a crash
+/// mid-write leaves the old file or the new one, never a truncated one
+fn write_atomic(path: &str, content: &str) -> io::Result<()> {
+    // Replace what a symlink points to, not the link itself
+    let path = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path)?,
+        _ => PathBuf::from(path),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let tmp = dir.join(format!(".{}.hal-tmp-{}", name.to_string_lossy(), std::process::id()));
+
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        if let Ok(meta) = fs::metadata(&path) {
+            fs::set_permissions(&tmp, meta.permissions())?;
+        }
+        fs::rename(&tmp, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Write a file, recording its previous content so /undo can restore it. Every tool
+/// that changes files goes through here.
+pub fn write(path: &str, content: &str) -> io::Result<()> {
+    if let Some(lease) = crate::lease::held(path) {
+        return Err(io::Error::other(format!("{}; try again once it's done", lease.describe())));
+    }
+    let before = match fs::read_to_string(path) {
+        Ok(before) => Some(Some(before)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Some(None),
+        Err(_) => None, // Unreadable (e.g. binary): written, but can't be undone
+    };
+    if matches!(&before, Some(Some(before)) if before == content) {
+        return Ok(()); // Nothing to write or journal
+    }
+
+    // The first pre-image of each file this session, kept beside it
+    if KEEP_BACKUPS.load(Ordering::Relaxed)
+        && let Some(Some(before)) = &before
+    {
+        let backup = backup_path(path);
+        let mut journal = journal().lock().unwrap_or_else(|e| e.into_inner());
+        if !journal.backups.contains(&backup) {
+            fs::write(&backup, before)?;
+            journal.backups.push(backup);
+        }
+    }
+    write_atomic(path, content)?;
+
+    if let Some(before) = before {
+        let change = Change {
+            path: path.to_string(),
+            before,
+            after: content.to_string(),
+            time: chrono::Utc::now().timestamp(),
+        };
+        let mut journal = journal().lock().unwrap_or_else(|e| e.into_inner());
+        journal.seq += 1;
+        // The on-disk copy is for manual recovery if hal itself goes away
+        if ensure_undo_dir().is_ok()
+            && let Ok(json) = serde_json::to_string(&change)
+        {
+            let _ = fs::write(entry_path(journal.started, journal.seq), json);
+        }
+        journal.changes.push(change);
+    }
+    Ok(())
+}
+
+/// Revert the most recent change. Refuses if the file was edited since hal wrote it.
+pub fn undo() -> Result<String, String> {
+    let mut journal = journal().lock().unwrap_or_else(|e| e.into_inner());
+    let change = journal.changes.last().cloned().ok_or("Nothing to undo")?;
+
+    let current = fs::read_to_string(&change.path).ok();
+    if current.as_deref() != Some(change.after.as_str()) {
+        return Err(format!("{} changed since hal wrote it; not reverting", change.path));
+    }
+
+    let message = match &change.before {
+        Some(before) => {
+            write_atomic(&change.path, before).map_err(|e| format!("Failed to restore {}: {}", change.path, e))?;
+            format!("Reverted {}", change.path)
+        }
+        None => {
+            fs::remove_file(&change.path).map_err(|e| format!("Failed to remove {}: {}", change.path, e))?;
+            format!("Removed {} (created by hal)", change.path)
+        }
+    };
+
+    let _ = fs::remove_file(entry_path(journal.started, journal.seq));
+    journal.seq -= 1;
+    journal.changes.pop();
+
+    // Back at the pre-image: the backup has nothing left to protect
+    if !journal.changes.iter().any(|c| c.path == change.path) {
+        let backup = backup_path(&change.path);
+        if let Some(i) = journal.backups.iter().position(|b| *b == backup) {
+            let _ = fs::remove_file(journal.backups.remove(i));
+        }
+    }
+    Ok(message)
+}
+
+/// Delete this session's .hal-backup files; call on exit
+pub fn remove_backups() {
+    let mut journal = journal().lock().unwrap_or_else(|e| e.into_inner());
+    for backup in journal.backups.drain(..) {
+        let _ = fs::remove_file(backup);
+    }
+}
+
+/// One state of a file during this session
+#[derive(Debug, Clone, PartialEq)]
+pub struct Version {
+    pub content: Option<String>, // None before hal created the file
+    pub time: i64,               // When hal wrote it, or when hal replaced it for earlier states
+    pub label: &'static str,
+}
+
+/// Every state of `path` the journal has seen: the original, each write by hal, and
+/// any edit made outside hal between two of them. Oldest first.
+pub fn versions(path: &str) -> Vec<Version> {
+    let journal = journal().lock().unwrap_or_else(|e| e.into_inner());
+    let key = crate::paths::display(Path::new(path));
+    let mut versions: Vec<Version> = Vec::new();
+    for change in journal.changes.iter().filter(|c| crate::paths::display(Path::new(&c.path)) == key) {
+        let label = match versions.last() {
+            None => Some("original"),
+            Some(last) if last.content != change.before => Some("edited outside hal"),
+            Some(_) => None,
+        };
+        if let Some(label) = label {
+            versions.push(Version { content: change.before.clone(), time: change.time, label });
+        }
+        versions.push(Version { content: Some(change.after.clone()), time: change.time, label: "written by hal" });
+    }
+    versions
+}
+
+/// Files changed since hal started, in order of first change, with change counts
+pub fn changes() -> Vec<(String, usize, bool)> {
+    let journal = journal().lock().unwrap_or_else(|e| e.into_inner());
+    let mut files: Vec<(String, usize, bool)> = Vec::new();
+    for change in &journal.changes {
+        match files.iter_mut().find(|(p, _, _)| *p == change.path) {
+            Some(entry) => entry.1 += 1,
+            None => files.push((change.path.clone(), 1, change.before.is_none())),
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The journal is global; tests that write through it take turns
+    static JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("hal-test-write-atomic-{}", std::process::id()));
+        fs::create_dir_all(dir.join("taken")).unwrap();
+        let file = dir.join("file.txt");
+        fs::write(&file, "old\n").unwrap();
+
+        write_atomic(file.to_str().unwrap(), "new\n").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new\n");
+
+        // A directory can't be renamed over; the temp file is cleaned up
+        assert!(write_atomic(dir.join("taken").to_str().unwrap(), "x").is_err());
+        assert!(dir.join("taken").is_dir());
+        let names: Vec<_> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().to_string()).collect();
+        assert!(!names.iter().any(|n| n.contains(".hal-tmp-")), "{:?}", names);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_undo_reverts_newest_first() {
+        let _lock = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("hal-test-undo-order-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.txt").to_string_lossy().to_string();
+        let b = dir.join("b.txt").to_string_lossy().to_string();
+        fs::write(&b, "b1\n").unwrap();
+
+        write(&a, "a1\n").unwrap();
+        write(&b, "b2\n").unwrap();
+        write(&a, "a2\n").unwrap();
+
+        // Edited outside hal since: refused, and the change stays journaled
+        fs::write(&a, "mine\n").unwrap();
+        assert!(undo().unwrap_err().contains("changed since hal wrote it"));
+        fs::write(&a, "a2\n").unwrap();
+
+        assert_eq!(undo().unwrap(), format!("Reverted {}", a));
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a1\n");
+        assert_eq!(undo().unwrap(), format!("Reverted {}", b));
+        assert_eq!(fs::read_to_string(&b).unwrap(), "b1\n");
+        assert_eq!(undo().unwrap(), format!("Removed {} (created by hal)", a));
+        assert!(!Path::new(&a).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_versions() {
+        let _lock = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("hal-test-versions-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt").to_string_lossy().to_string();
+        fs::write(&path, "one\n").unwrap();
+
+        write(&path, "two\n").unwrap();
+        write(&path, "three\n").unwrap();
+        fs::write(&path, "edited\n").unwrap();
+        write(&path, "four\n").unwrap();
+
+        let states: Vec<_> = versions(&path).into_iter().map(|v| (v.content.unwrap_or_default(), v.label)).collect();
+        let expected = [
+            ("one\n", "original"),
+            ("two\n", "written by hal"),
+            ("three\n", "written by hal"),
+            ("edited\n", "edited outside hal"),
+            ("four\n", "written by hal"),
+        ];
+        assert_eq!(states, expected.map(|(content, label)| (content.to_string(), label)));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
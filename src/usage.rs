@@ -0,0 +1,276 @@
+use crate::session::Turn;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Rough bytes per token, for /usage, /stats and the estimate shown while typing
+const BYTES_PER_TOKEN: usize = 4;
+/// Largest attachments listed by /usage
+const MAX_LISTED_ATTACHMENTS: usize = 5;
+/// An attached image, which providers bill by resolution rather than file size
+const IMAGE_TOKENS: usize = 1_500;
+
+/// Where a piece of the prompt came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    System,
+    User,
+    Attachment(String), // path, directory or issue reference
+    Assistant,
+    ToolCall,
+    ToolResult(String), // tool name
+}
+
+/// One piece of the history: its estimated size and how many requests have sent it so far
+#[derive(Debug)]
+pub struct Part {
+    pub source: Source,
+    pub tokens: usize,
+    pub sent: usize,
+}
+
+impl Part {
+    /// Prompt tokens this part has cost across every request that carried it
+    pub fn cost(&self) -> usize {
+        self.tokens * self.sent
+    }
+}
+
+/// Aggregate cost of one tool's results
+#[derive(Debug, PartialEq)]
+pub struct ToolCost {
+    pub name: String,
+    pub calls: usize,
+    pub tokens: usize,  // In the history now
+    pub largest: usize, // Biggest single result
+    pub cost: usize,    // Sent so far, summed over requests
+}
+
+/// Split the API history into attributed parts. A message at index i was sent with
+/// every request whose turn recorded more than i messages.
+pub fn parts(api_messages: &[Value], turns: &[Turn]) -> Vec<Part> {
+    let attachment = regex::Regex::new(r#"(?s)<(file|directory|github) (?:path|ref)="([^"]*)"[^>]*>.*?</(?:file|directory|github)>|<draft>.*?</draft>"#)
+        .expect("valid regex");
+    let mut tool_names: HashMap<&str, &str> = HashMap::new();
+    let mut parts = Vec::new();
+
+    for (i, message) in api_messages.iter().enumerate() {
+        let sent = turns.iter().filter(|t| t.api_message > i).count();
+        let mut push = |source: Source, text: &str| {
+            if !text.is_empty() {
+                parts.push(Part { source, tokens: tokens_for(text.len()), sent });
+            }
+        };
+
+        match message["role"].as_str().unwrap_or("") {
+            "system" => push(Source::System, message["content"].as_str().unwrap_or("")),
+            "user" => {
+                // Image parts aren't estimated; their base64 size says little about tokens
+                let text = match &message["content"] {
+                    Value::Array(content) => content.iter().filter_map(|c| c["text"].as_str()).collect::<Vec<_>>().join("\n"),
+                    content => content.as_str().unwrap_or("").to_string(),
+                };
+                let mut rest = text.clone();
+                for block in attachment.captures_iter(&text) {
+                    let label = block.get(2).map_or("draft", |m| m.as_str()).to_string();
+                    push(Source::Attachment(label), &block[0]);
+                    rest = rest.replacen(&block[0], "", 1);
+                }
+                push(Source::User, rest.trim());
+            }
+            "assistant" => {
+                push(Source::Assistant, message["content"].as_str().unwrap_or(""));
+                for call in message["tool_calls"].as_array().into_iter().flatten() {
+                    if let (Some(id), Some(name)) = (call["id"].as_str(), call["function"]["name"].as_str()) {
+                        tool_names.insert(id, name);
+                    }
+                    push(Source::ToolCall, &call["function"].to_string());
+                }
+            }
+            "tool" => {
+                let name = message["tool_call_id"].as_str().and_then(|id| tool_names.get(id)).unwrap_or(&"unknown");
+                push(Source::ToolResult(name.to_string()), message["content"].as_str().unwrap_or(""));
+            }
+            _ => {}
+        }
+    }
+    parts
+}
+
+/// Tool results grouped by tool, most expensive first
+pub fn tool_costs(parts: &[Part]) -> Vec<ToolCost> {
+    let mut costs: Vec<ToolCost> = Vec::new();
+    for part in parts {
+        let Source::ToolResult(name) = &part.source else { continue };
+        let index = match costs.iter().position(|c| c.name == *name) {
+            Some(index) => index,
+            None => {
+                costs.push(ToolCost { name: name.clone(), calls: 0, tokens: 0, largest: 0, cost: 0 });
+                costs.len() - 1
+            }
+        };
+        let cost = &mut costs[index];
+        cost.calls += 1;
+        cost.tokens += part.tokens;
+        cost.largest = cost.largest.max(part.tokens);
+        cost.cost += part.cost();
+    }
+    costs.sort_by(|a, b| b.cost.cmp(&a.cost).then(b.tokens.cmp(&a.tokens)));
+    costs
+}
+
+/// /usage: prompt size by source, or per tool with `tools`. `schema_bytes` is the
+/// size of the tool schemas sent with each request.
+pub fn report(api_messages: &[Value], turns: &[Turn], schema_bytes: usize, tools: bool) -> String {
+    let parts = parts(api_messages, turns);
+    let requests = turns.len();
+    let in_history: usize = parts.iter().map(|p| p.tokens).sum();
+    let sent: usize = parts.iter().map(Part::cost).sum::<usize>() + tokens_for(schema_bytes) * requests;
+    let mut lines = vec![
+        format!(
+            "**Usage** — ~{} tokens in the history; ~{} prompt tokens sent over {} requests (estimated at {} bytes per token)",
+            in_history, sent, requests, BYTES_PER_TOKEN
+        ),
+        String::new(),
+    ];
+
+    if tools {
+        let costs = tool_costs(&parts);
+        if costs.is_empty() {
+            lines.push("No tool results yet.".to_string());
+        }
+        for cost in costs {
+            lines.push(format!(
+                "- `{}`: {} calls, ~{} tokens in history (largest ~{}), ~{} sent so far",
+                cost.name, cost.calls, cost.tokens, cost.largest, cost.cost
+            ));
+        }
+        return lines.join("\n");
+    }
+
+    let sum = |matches: &dyn Fn(&Source) -> bool| {
+        let matching = parts.iter().filter(|p| matches(&p.source));
+        matching.fold((0, 0), |(tokens, cost), p| (tokens + p.tokens, cost + p.cost()))
+    };
+    let mut line = |label: &str, (tokens, cost): (usize, usize)| {
+        if tokens > 0 {
+            lines.push(format!("- {}: ~{} tokens, ~{} sent so far", label, tokens, cost));
+        }
+    };
+    line("System prompt", sum(&|s| *s == Source::System));
+    line("Tool schemas", (tokens_for(schema_bytes), tokens_for(schema_bytes) * requests));
+    line("Your messages", sum(&|s| *s == Source::User));
+    line("Attachments", sum(&|s| matches!(s, Source::Attachment(_))));
+    line("Assistant replies", sum(&|s| *s == Source::Assistant));
+    line("Tool calls", sum(&|s| *s == Source::ToolCall));
+    line("Tool results", sum(&|s| matches!(s, Source::ToolResult(_))));
+
+    let mut attachments: Vec<&Part> = parts.iter().filter(|p| matches!(p.source, Source::Attachment(_))).collect();
+    attachments.sort_by_key(|p| std::cmp::Reverse(p.cost()));
+    if !attachments.is_empty() {
+        lines.push(String::new());
+        lines.push("Largest attachments:".to_string());
+        for part in attachments.iter().take(MAX_LISTED_ATTACHMENTS) {
+            if let Source::Attachment(label) = &part.source {
+                lines.push(format!("- `{}`: ~{} tokens, ~{} sent so far", label, part.tokens, part.cost()));
+            }
+        }
+    }
+    if let Some(top) = tool_costs(&parts).first() {
+        lines.push(String::new());
+        lines.push(format!("Most expensive tool: `{}` (~{} sent so far) — `/usage tools` for all", top.name, top.cost));
+    }
+    lines.join("\n")
+}
+
+/// Rough size of the next request, shown while the message is typed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub tokens: usize,                     // History, tool schemas, the message and its attachments
+    pub attachments: Vec<(String, usize)>, // Each @ reference and its tokens
+}
+
+/// Estimates the request the typed message would make, cheaply enough to run on every
+/// edit: the history is measured once per message count and each @ reference once per
+/// history (the whole file even for a line range). Directories aren't walked.
+#[derive(Default)]
+pub struct Estimator {
+    history: (usize, usize),                    // Message count and its size in bytes
+    references: HashMap<String, Option<usize>>, // Tokens per reference; None when it isn't a file
+}
+
+impl Estimator {
+    pub fn estimate(&mut self, input: &str, api_messages: &[Value], schema_bytes: usize) -> Estimate {
+        if self.history.0 != api_messages.len() {
+            // Tools may have changed the referenced files since they were sized
+            self.references.clear();
+            self.history = (api_messages.len(), api_messages.iter().map(|m| m.to_string().len()).sum());
+        }
+        let mut attachments = Vec::new();
+        for word in input.split_whitespace() {
+            let Some(reference) = word.strip_prefix('@').filter(|r| !r.is_empty() && !r.starts_with("gh:")) else { continue };
+            let tokens = *self.references.entry(reference.to_string()).or_insert_with(|| reference_tokens(reference));
+            if let Some(tokens) = tokens {
+                attachments.push((reference.to_string(), tokens));
+            }
+        }
+        let tokens = tokens_for(self.history.1 + schema_bytes + input.len()) + attachments.iter().map(|(_, t)| t).sum::<usize>();
+        Estimate { tokens, attachments }
+    }
+}
+
+/// Size of the file an @ reference attaches, if it names one
+fn reference_tokens(reference: &str) -> Option<usize> {
+    // A trailing :start-end picks lines from the file
+    let file = if Path::new(reference).is_file() { reference } else { reference.rsplit_once(':').map_or(reference, |(f, _)| f) };
+    match fs::metadata(file) {
+        Ok(_) if crate::image::is_image(file) => Some(IMAGE_TOKENS),
+        Ok(meta) if meta.is_file() => Some(tokens_for(meta.len() as usize)),
+        _ => None,
+    }
+}
+
+/// "850" or "12.3k"
+pub fn short(tokens: usize) -> String {
+    if tokens < 1000 { tokens.to_string() } else { format!("{:.1}k", tokens as f64 / 1000.0) }
+}
+
+/// Estimated tokens for `bytes` of text
+pub fn tokens_for(bytes: usize) -> usize {
+    bytes.div_ceil(BYTES_PER_TOKEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_costs() {
+        let messages = vec![
+            json!({"role": "system", "content": "sys"}),
+            json!({"role": "user", "content": "look at `a.rs`\n\n<file path=\"a.rs\">\nfn main() {}\n</file>"}),
+            json!({"role": "assistant", "content": null, "tool_calls": [
+                {"id": "1", "function": {"name": "grep", "arguments": "{}"}},
+                {"id": "2", "function": {"name": "read_file", "arguments": "{}"}}
+            ]}),
+            json!({"role": "tool", "tool_call_id": "1", "content": "x".repeat(40)}),
+            json!({"role": "tool", "tool_call_id": "2", "content": "y".repeat(400)}),
+            json!({"role": "assistant", "content": "done"}),
+        ];
+        let turn = |api_message| Turn { api_message, model: String::new(), seed: None, fingerprint: None, time: 0 };
+        let turns = [turn(2), turn(5)];
+
+        let parts = parts(&messages, &turns);
+        assert!(parts.iter().any(|p| p.source == Source::Attachment("a.rs".to_string()) && p.sent == 2));
+        let costs = tool_costs(&parts);
+        assert_eq!(costs[0], ToolCost { name: "read_file".to_string(), calls: 1, tokens: 100, largest: 100, cost: 100 });
+        assert_eq!(costs[1].name, "grep");
+
+        let estimate = Estimator::default().estimate("see @Cargo.toml:1-3 and @missing.rs @src", &messages[..1], 40);
+        assert_eq!(estimate.attachments.iter().map(|(r, _)| r.as_str()).collect::<Vec<_>>(), ["Cargo.toml:1-3"]);
+        assert!(estimate.tokens > estimate.attachments[0].1);
+        assert_eq!(short(12_345), "12.3k");
+    }
+}